@@ -1,19 +1,20 @@
 use async_trait::async_trait;
 use primitives::{
     adapter::{
-        Adapter, AdapterErrorKind, AdapterResult, DummyAdapterOptions, Error as AdapterError,
-        Session,
+        Adapter, AdapterErrorKind, AdapterResult, ChannelLogs, ChannelState, DummyAdapterOptions,
+        Error as AdapterError, Session,
     },
     channel_validator::ChannelValidator,
     config::Config,
-    Channel, ToETHChecksum, ValidatorId,
+    ChainId, Channel, ChannelId, ToETHChecksum, ValidatorId,
 };
 use std::collections::HashMap;
 use std::fmt;
 
 #[derive(Debug, Clone)]
 pub struct DummyAdapter {
-    identity: ValidatorId,
+    /// The current identity, followed by the previous ones
+    identities: Vec<ValidatorId>,
     config: Config,
     // Auth tokens that we have verified (tokenId => session)
     session_tokens: HashMap<String, ValidatorId>,
@@ -27,8 +28,12 @@ impl ChannelValidator for DummyAdapter {}
 
 impl DummyAdapter {
     pub fn init(opts: DummyAdapterOptions, config: &Config) -> Self {
+        let identities = std::iter::once(opts.dummy_identity)
+            .chain(opts.dummy_previous_identities)
+            .collect();
+
         Self {
-            identity: opts.dummy_identity,
+            identities,
             config: config.to_owned(),
             session_tokens: opts.dummy_auth,
             authorization_tokens: opts.dummy_auth_tokens,
@@ -50,25 +55,30 @@ impl AdapterErrorKind for Error {}
 impl Adapter for DummyAdapter {
     type AdapterError = Error;
 
-    fn unlock(&mut self) -> AdapterResult<(), Self::AdapterError> {
+    async fn unlock(&mut self) -> AdapterResult<(), Self::AdapterError> {
         Ok(())
     }
 
-    fn whoami(&self) -> &ValidatorId {
-        &self.identity
+    fn identities(&self) -> &[ValidatorId] {
+        &self.identities
     }
 
-    fn sign(&self, state_root: &str) -> AdapterResult<String, Self::AdapterError> {
+    async fn sign<'a>(
+        &'a self,
+        channel: &'a Channel,
+        state_root: &'a str,
+    ) -> AdapterResult<String, Self::AdapterError> {
         let signature = format!(
             "Dummy adapter signature for {} by {}",
             state_root,
-            self.whoami().to_checksum()
+            self.whoami(channel).to_checksum()
         );
         Ok(signature)
     }
 
     fn verify(
         &self,
+        _channel: &Channel,
         signer: &ValidatorId,
         _state_root: &str,
         signature: &str,
@@ -87,11 +97,32 @@ impl Adapter for DummyAdapter {
         &'a self,
         channel: &'a Channel,
     ) -> AdapterResult<bool, Self::AdapterError> {
-        DummyAdapter::is_channel_valid(&self.config, self.whoami(), channel)
+        DummyAdapter::is_channel_valid(&self.config, self.whoami(channel), channel)
             .map(|_| true)
             .map_err(AdapterError::InvalidChannel)
     }
 
+    /// All of the channels are considered `Active`
+    async fn channel_states<'a>(
+        &'a self,
+        _chain_id: ChainId,
+        channels: &'a [ChannelId],
+    ) -> AdapterResult<HashMap<ChannelId, ChannelState>, Self::AdapterError> {
+        Ok(channels
+            .iter()
+            .map(|channel_id| (*channel_id, ChannelState::Active))
+            .collect())
+    }
+
+    /// There are no on-chain logs, so the `from_block` is never confirmed
+    async fn channel_logs(
+        &self,
+        _chain_id: ChainId,
+        _from_block: u64,
+    ) -> AdapterResult<ChannelLogs, Self::AdapterError> {
+        Ok(ChannelLogs::default())
+    }
+
     async fn session_from_token<'a>(
         &'a self,
         token: &'a str,
@@ -113,11 +144,13 @@ impl Adapter for DummyAdapter {
         }
     }
 
-    fn get_auth(&self, _validator: &ValidatorId) -> AdapterResult<String, Self::AdapterError> {
-        let who = self
-            .session_tokens
-            .iter()
-            .find(|(_, id)| *id == &self.identity);
+    async fn get_auth<'a>(
+        &'a self,
+        channel: &'a Channel,
+        _validator: &'a ValidatorId,
+    ) -> AdapterResult<String, Self::AdapterError> {
+        let whoami = self.whoami(channel);
+        let who = self.session_tokens.iter().find(|(_, id)| *id == whoami);
         match who {
             Some((id, _)) => {
                 let auth = self.authorization_tokens.get(id).expect("id should exist");
@@ -125,7 +158,7 @@ impl Adapter for DummyAdapter {
             }
             None => Err(AdapterError::Authentication(format!(
                 "no auth token for this identity: {}",
-                self.identity
+                whoami
             ))),
         }
     }
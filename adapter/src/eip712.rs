@@ -0,0 +1,295 @@
+//! [EIP-712](https://eips.ethereum.org/EIPS/eip-712) typed data for signing the state roots & the authentication tokens
+use crate::ethereum::Payload;
+use primitives::{config::ChainConfig, ChainId, ChannelId};
+use serde_json::{json, Map, Value};
+use tiny_keccak::Keccak;
+use web3::{
+    ethabi::{encode, token::Token},
+    types::{Address, U256},
+};
+
+pub const DOMAIN_NAME: &str = "AdEx Validator";
+pub const DOMAIN_VERSION: &str = "1";
+
+const DOMAIN_FIELDS: &[(&str, &str)] = &[
+    ("name", "string"),
+    ("version", "string"),
+    ("chainId", "uint256"),
+    ("verifyingContract", "address"),
+];
+
+/// The domain of the typed data, i.e. the core contract of the chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Domain {
+    pub chain_id: ChainId,
+    pub verifying_contract: [u8; 20],
+}
+
+impl From<&ChainConfig> for Domain {
+    fn from(chain: &ChainConfig) -> Self {
+        Self {
+            chain_id: chain.chain_id,
+            verifying_contract: chain.core_address,
+        }
+    }
+}
+
+impl Domain {
+    fn separator(&self) -> [u8; 32] {
+        let tokens = [
+            Token::FixedBytes(type_hash("EIP712Domain", DOMAIN_FIELDS).to_vec()),
+            Token::FixedBytes(keccak256(DOMAIN_NAME.as_bytes()).to_vec()),
+            Token::FixedBytes(keccak256(DOMAIN_VERSION.as_bytes()).to_vec()),
+            Token::Uint(self.chain_id.as_u64().into()),
+            Token::Address(Address::from_slice(&self.verifying_contract)),
+        ];
+
+        keccak256(&encode(&tokens))
+    }
+}
+
+/// A struct which can be signed as the primary type of the typed data
+pub trait TypedMessage {
+    const PRIMARY_TYPE: &'static str;
+    /// The (name, type) of the struct members in order
+    const FIELDS: &'static [(&'static str, &'static str)];
+
+    /// The encoded values of the struct members in the order of `FIELDS`
+    fn encode_data(&self) -> Vec<Token>;
+
+    /// The JSON `message` of the typed data
+    fn message(&self) -> Value;
+
+    fn hash_struct(&self) -> [u8; 32] {
+        let type_hash = Token::FixedBytes(type_hash(Self::PRIMARY_TYPE, Self::FIELDS).to_vec());
+        let tokens: Vec<Token> = std::iter::once(type_hash)
+            .chain(self.encode_data())
+            .collect();
+
+        keccak256(&encode(&tokens))
+    }
+}
+
+/// The `StateRoot` of a channel, i.e. the `keccak256(channelId, balanceRoot)`
+pub struct StateRoot<'a> {
+    pub channel_id: &'a ChannelId,
+    pub state_root: &'a [u8; 32],
+}
+
+impl TypedMessage for StateRoot<'_> {
+    const PRIMARY_TYPE: &'static str = "StateRoot";
+    const FIELDS: &'static [(&'static str, &'static str)] =
+        &[("channelId", "bytes32"), ("stateRoot", "bytes32")];
+
+    fn encode_data(&self) -> Vec<Token> {
+        vec![
+            Token::FixedBytes(self.channel_id.to_vec()),
+            Token::FixedBytes(self.state_root.to_vec()),
+        ]
+    }
+
+    fn message(&self) -> Value {
+        json!({
+            "channelId": format!("0x{}", hex::encode(self.channel_id.as_ref())),
+            "stateRoot": format!("0x{}", hex::encode(self.state_root)),
+        })
+    }
+}
+
+/// The `Payload` of an Ethereum Web Token, a missing `identity` is encoded as the zero address
+pub struct Authentication<'a>(pub &'a Payload);
+
+impl TypedMessage for Authentication<'_> {
+    const PRIMARY_TYPE: &'static str = "Authentication";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("id", "string"),
+        ("era", "int256"),
+        ("address", "string"),
+        ("identity", "address"),
+    ];
+
+    fn encode_data(&self) -> Vec<Token> {
+        vec![
+            Token::FixedBytes(keccak256(self.0.id.as_bytes()).to_vec()),
+            Token::Int(int256(self.0.era)),
+            Token::FixedBytes(keccak256(self.0.address.as_bytes()).to_vec()),
+            Token::Address(self.identity()),
+        ]
+    }
+
+    fn message(&self) -> Value {
+        json!({
+            "id": self.0.id,
+            "era": self.0.era,
+            "address": self.0.address,
+            "identity": format!("{:?}", self.identity()),
+        })
+    }
+}
+
+impl Authentication<'_> {
+    fn identity(&self) -> Address {
+        self.0
+            .identity
+            .as_ref()
+            .map(|identity| Address::from_slice(identity.inner()))
+            .unwrap_or_else(Address::zero)
+    }
+}
+
+/// The hash that is signed, i.e. `keccak256("\x19\x01" ‖ domainSeparator ‖ hashStruct(message))`
+pub fn hash_to_sign(domain: &Domain, message: &impl TypedMessage) -> [u8; 32] {
+    let mut result = Keccak::new_keccak256();
+    result.update(&[0x19, 0x01]);
+    result.update(&domain.separator());
+    result.update(&message.hash_struct());
+
+    let mut res: [u8; 32] = [0; 32];
+    result.finalize(&mut res);
+
+    res
+}
+
+/// The typed data JSON, as expected by `eth_signTypedData_v4`
+pub fn typed_data<M: TypedMessage>(domain: &Domain, message: &M) -> Value {
+    let mut types = Map::new();
+    types.insert("EIP712Domain".to_string(), type_fields(DOMAIN_FIELDS));
+    types.insert(M::PRIMARY_TYPE.to_string(), type_fields(M::FIELDS));
+
+    json!({
+        "types": types,
+        "primaryType": M::PRIMARY_TYPE,
+        "domain": {
+            "name": DOMAIN_NAME,
+            "version": DOMAIN_VERSION,
+            "chainId": domain.chain_id,
+            "verifyingContract": format!("0x{}", hex::encode(domain.verifying_contract)),
+        },
+        "message": message.message(),
+    })
+}
+
+/// `keccak256("{name}({type} {name},...)")`
+fn type_hash(primary_type: &str, fields: &[(&str, &str)]) -> [u8; 32] {
+    let members = fields
+        .iter()
+        .map(|(name, field_type)| format!("{} {}", field_type, name))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    keccak256(format!("{}({})", primary_type, members).as_bytes())
+}
+
+fn type_fields(fields: &[(&str, &str)]) -> Value {
+    fields
+        .iter()
+        .map(|(name, field_type)| json!({ "name": name, "type": field_type }))
+        .collect()
+}
+
+/// Two's complement of the `i64`
+fn int256(value: i64) -> U256 {
+    if value >= 0 {
+        U256::from(value as u64)
+    } else {
+        !U256::from((-(value + 1)) as u64)
+    }
+}
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut result = Keccak::new_keccak256();
+    result.update(data);
+
+    let mut res: [u8; 32] = [0; 32];
+    result.finalize(&mut res);
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex::FromHex;
+
+    const CORE_ADDRESS: &str = "333420fc6a897356e69b62417cd17ff012177d2b";
+
+    fn domain() -> Domain {
+        Domain {
+            chain_id: ChainId::MAINNET,
+            verifying_contract: <[u8; 20]>::from_hex(CORE_ADDRESS).expect("Valid address"),
+        }
+    }
+
+    #[test]
+    fn hashes_the_state_root() {
+        let channel_id =
+            ChannelId::from_hex("061d5e2a67d0a9a10f1c732bca12a676d83f79663a396f7d87b3e30b9b411088")
+                .expect("Valid ChannelId");
+        let state_root = <[u8; 32]>::from_hex(
+            "b1a4fc6c1a1e1ab908a487e504006edcebea297f61b4b8ce6cad3b4e2e5f1b4c",
+        )
+        .expect("Valid state root");
+        let message = StateRoot {
+            channel_id: &channel_id,
+            state_root: &state_root,
+        };
+
+        assert_eq!(
+            "03ba8da7d3753b154dcd1c1dc42aa16c54b78bf16584234b70d0b0f908309827",
+            hex::encode(hash_to_sign(&domain(), &message))
+        );
+
+        // the same contract address on another chain is another domain
+        let other_chain = Domain {
+            chain_id: ChainId::new(5),
+            ..domain()
+        };
+        assert_ne!(
+            hash_to_sign(&domain(), &message),
+            hash_to_sign(&other_chain, &message)
+        );
+    }
+
+    #[test]
+    fn hashes_the_authentication_with_negative_era_and_no_identity() {
+        let payload = Payload {
+            id: "awesomeValidator".to_string(),
+            era: -5,
+            address: "0x2bDeAFAE53940669DaA6F519373f686c1f3d3393".to_string(),
+            identity: None,
+        };
+
+        assert_eq!(
+            "2ec3a9ad0e0c7f052e62d23fa791fc40afb6e5a829a89b97cd136e2e515bd888",
+            hex::encode(hash_to_sign(&domain(), &Authentication(&payload)))
+        );
+    }
+
+    #[test]
+    fn typed_data_has_the_domain_and_the_message() {
+        let payload = Payload {
+            id: "awesomeValidator".to_string(),
+            era: 100_000,
+            address: "0x2bDeAFAE53940669DaA6F519373f686c1f3d3393".to_string(),
+            identity: None,
+        };
+        let typed_data = typed_data(&domain(), &Authentication(&payload));
+
+        assert_eq!("Authentication", typed_data["primaryType"]);
+        assert_eq!(
+            format!("0x{}", CORE_ADDRESS),
+            typed_data["domain"]["verifyingContract"]
+        );
+        assert_eq!(1, typed_data["domain"]["chainId"]);
+        assert_eq!(
+            Some(4),
+            typed_data["types"]["Authentication"]
+                .as_array()
+                .map(Vec::len)
+        );
+        assert_eq!(
+            "0x0000000000000000000000000000000000000000",
+            typed_data["message"]["identity"]
+        );
+    }
+}
@@ -0,0 +1,542 @@
+use super::{Error, ADEXCORE_ABI};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use lazy_static::lazy_static;
+use primitives::{
+    adapter::{ChannelEvent, ChannelLog, ChannelLogs, ChannelState},
+    config::{ChainConfig, Config},
+    ChainId, ChannelId,
+};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tiny_keccak::Keccak;
+use web3::{
+    contract::tokens::Tokenizable,
+    contract::{Contract, Options},
+    transports::Http,
+    types::{BlockId, BlockNumber, FilterBuilder, Log, H256, U256},
+    Web3,
+};
+
+/// The maximum number of blocks searched for logs at once
+const MAX_LOGS_BLOCK_RANGE: u64 = 10_000;
+/// The maximum number of concurrent `states` calls of a single lookup
+const MAX_CONCURRENT_STATE_CALLS: usize = 16;
+
+lazy_static! {
+    static ref LOG_CHANNEL_OPEN: H256 = event_topic("LogChannelOpen(bytes32)");
+    static ref LOG_CHANNEL_WITHDRAW: H256 = event_topic("LogChannelWithdraw(bytes32,uint256)");
+    static ref LOG_CHANNEL_WITHDRAW_EXPIRED: H256 =
+        event_topic("LogChannelWithdrawExpired(bytes32,uint256)");
+}
+
+/// The `ChainStateCache`s of all the configured chains
+#[derive(Debug, Clone)]
+pub(crate) struct Chains(HashMap<ChainId, ChainStateCache>);
+
+impl Chains {
+    pub(crate) fn init(config: &Config) -> Result<Self, Error> {
+        let ttl = Duration::from_millis(config.channel_state_cache_ttl as u64);
+
+        config
+            .chains
+            .iter()
+            .map(|chain| ChainStateCache::new(chain, ttl).map(|cache| (chain.chain_id, cache)))
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    pub(crate) fn get(&self, chain_id: ChainId) -> Result<&ChainStateCache, Error> {
+        self.0
+            .get(&chain_id)
+            .ok_or(Error::UnsupportedChain(chain_id))
+    }
+}
+
+/// Cache of the `AdExCore` channel states on a single chain.
+/// The states are read `confirmations` blocks behind the latest one,
+/// so a channel opened in a block that might still be reorged is not `Active` yet.
+/// `Unknown` states are never cached, as the channel might be opened at any moment.
+#[derive(Debug, Clone)]
+pub(crate) struct ChainStateCache {
+    web3: Web3<Http>,
+    chain: ChainConfig,
+    ttl: Duration,
+    states: Arc<RwLock<HashMap<ChannelId, (ChannelState, Instant)>>>,
+}
+
+impl ChainStateCache {
+    pub(crate) fn new(chain: &ChainConfig, ttl: Duration) -> Result<Self, Error> {
+        let transport = Http::new(&chain.rpc).map_err(Error::Web3)?;
+
+        Ok(Self {
+            web3: Web3::new(transport),
+            chain: chain.to_owned(),
+            ttl,
+            states: Default::default(),
+        })
+    }
+
+    pub(crate) fn chain(&self) -> &ChainConfig {
+        &self.chain
+    }
+
+    pub(crate) async fn state(&self, channel_id: &ChannelId) -> Result<ChannelState, Error> {
+        let states = self.states(&[*channel_id]).await?;
+
+        Ok(states[channel_id])
+    }
+
+    /// Returns the cached states and queries the rest of the `channels`
+    /// from the same confirmed block.
+    pub(crate) async fn states(
+        &self,
+        channels: &[ChannelId],
+    ) -> Result<HashMap<ChannelId, ChannelState>, Error> {
+        let now = Instant::now();
+        let mut states = HashMap::with_capacity(channels.len());
+        let mut missing = vec![];
+
+        {
+            let cached = self.states.read().expect("The lock should not be poisoned");
+            for channel_id in channels {
+                match cached.get(channel_id) {
+                    Some((state, cached_at)) if now.duration_since(*cached_at) < self.ttl => {
+                        states.insert(*channel_id, *state);
+                    }
+                    _ => missing.push(*channel_id),
+                }
+            }
+        }
+
+        if missing.is_empty() {
+            return Ok(states);
+        }
+
+        let contract = Contract::from_json(
+            self.web3.eth(),
+            self.chain.core_address.into(),
+            &ADEXCORE_ABI,
+        )
+        .map_err(Error::ContractInitialization)?;
+        let block = self.confirmed_block().await?;
+
+        // `buffered` keeps the order of the `missing` channels
+        let queried: Vec<U256> = stream::iter(missing.iter())
+            .map(|channel_id| {
+                contract.query::<U256, _, _, _>(
+                    "states",
+                    H256(**channel_id).into_token(),
+                    None,
+                    Options::default(),
+                    block,
+                )
+            })
+            .buffered(MAX_CONCURRENT_STATE_CALLS)
+            .try_collect()
+            .await
+            .map_err(Error::ContractQuerying)?;
+
+        let mut cached = self
+            .states
+            .write()
+            .expect("The lock should not be poisoned");
+        let ttl = self.ttl;
+        cached.retain(|_, (_, cached_at)| now.duration_since(*cached_at) < ttl);
+
+        for (channel_id, state) in missing.into_iter().zip(queried) {
+            let state = channel_state(state);
+            if state != ChannelState::Unknown {
+                cached.insert(channel_id, (state, now));
+            }
+
+            states.insert(channel_id, state);
+        }
+
+        Ok(states)
+    }
+
+    /// The channel logs of the confirmed blocks from `from_block` on,
+    /// at most `MAX_LOGS_BLOCK_RANGE` blocks at once.
+    /// The cached states are updated with the states after the logged events.
+    pub(crate) async fn channel_logs(&self, from_block: u64) -> Result<ChannelLogs, Error> {
+        let confirmed = self.confirmed_block_number().await?;
+        if from_block > confirmed {
+            return Ok(ChannelLogs::default());
+        }
+        let to_block = confirmed.min(from_block + MAX_LOGS_BLOCK_RANGE - 1);
+
+        let filter = FilterBuilder::default()
+            .address(vec![self.chain.core_address.into()])
+            .topics(
+                Some(vec![
+                    *LOG_CHANNEL_OPEN,
+                    *LOG_CHANNEL_WITHDRAW,
+                    *LOG_CHANNEL_WITHDRAW_EXPIRED,
+                ]),
+                None,
+                None,
+                None,
+            )
+            .from_block(BlockNumber::Number(from_block.into()))
+            .to_block(BlockNumber::Number(to_block.into()))
+            .build();
+
+        let logs: Vec<ChannelLog> = self
+            .web3
+            .eth()
+            .logs(filter)
+            .await
+            .map_err(Error::Web3)?
+            .into_iter()
+            .filter_map(channel_log)
+            .collect();
+
+        let now = Instant::now();
+        let mut cached = self
+            .states
+            .write()
+            .expect("The lock should not be poisoned");
+        for log in logs.iter() {
+            cached.insert(log.channel_id, (log.event.state(), now));
+        }
+
+        Ok(ChannelLogs {
+            logs,
+            to_block: Some(to_block),
+        })
+    }
+
+    async fn confirmed_block(&self) -> Result<BlockId, Error> {
+        if self.chain.confirmations == 0 {
+            return Ok(BlockId::Number(BlockNumber::Latest));
+        }
+
+        let confirmed = self.confirmed_block_number().await?;
+
+        Ok(BlockId::Number(BlockNumber::Number(confirmed.into())))
+    }
+
+    async fn confirmed_block_number(&self) -> Result<u64, Error> {
+        let latest = self.web3.eth().block_number().await.map_err(Error::Web3)?;
+
+        Ok(latest.as_u64().saturating_sub(self.chain.confirmations))
+    }
+}
+
+/// Skips the logs of unknown events and the pending ones
+fn channel_log(log: Log) -> Option<ChannelLog> {
+    let topic = log.topics.get(0)?;
+    let event = if topic == &*LOG_CHANNEL_OPEN {
+        ChannelEvent::Open
+    } else if topic == &*LOG_CHANNEL_WITHDRAW {
+        ChannelEvent::Withdraw
+    } else if topic == &*LOG_CHANNEL_WITHDRAW_EXPIRED {
+        ChannelEvent::WithdrawExpired
+    } else {
+        return None;
+    };
+
+    // the channel id is the first topic if indexed, otherwise the first word of the data
+    let channel_id = match log.topics.get(1) {
+        Some(channel_id) => channel_id.0,
+        None => <[u8; 32]>::try_from(log.data.0.get(..32)?).ok()?,
+    };
+
+    Some(ChannelLog {
+        channel_id: ChannelId::from(channel_id),
+        event,
+        block_number: log.block_number?.as_u64(),
+    })
+}
+
+fn event_topic(signature: &str) -> H256 {
+    let mut keccak = Keccak::new_keccak256();
+    keccak.update(signature.as_bytes());
+
+    let mut topic = [0_u8; 32];
+    keccak.finalize(&mut topic);
+
+    H256(topic)
+}
+
+/// The `ChannelState` enum of the `AdExCore` contract
+fn channel_state(state: U256) -> ChannelState {
+    if state == 1.into() {
+        ChannelState::Active
+    } else if state == 2.into() {
+        ChannelState::Expired
+    } else {
+        ChannelState::Unknown
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use primitives::config::configuration;
+    use serde_json::{json, Value};
+    use wiremock::{
+        matchers::{body_string_contains, method},
+        Mock, MockServer, Request, Respond, ResponseTemplate,
+    };
+
+    /// Responds to the JSON-RPC request with the `result`
+    struct RpcResult(Value);
+
+    impl Respond for RpcResult {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            let request: Value =
+                serde_json::from_slice(&request.body).expect("Should be a JSON-RPC request");
+
+            ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "result": self.0
+            }))
+        }
+    }
+
+    /// The ABI encoded `ChannelState`
+    fn encoded_state(state: u8) -> Value {
+        let mut encoded = [0_u8; 32];
+        encoded[31] = state;
+
+        json!(format!("0x{}", hex::encode(encoded)))
+    }
+
+    fn setup_cache(node: &MockServer, confirmations: u64) -> ChainStateCache {
+        let config = configuration("development", None).expect("failed parse config");
+        let chain = ChainConfig {
+            rpc: node.uri(),
+            confirmations,
+            ..config.chains[0].clone()
+        };
+
+        ChainStateCache::new(&chain, Duration::from_millis(60_000))
+            .expect("failed to init the cache")
+    }
+
+    #[tokio::test]
+    async fn caches_the_states_of_the_opened_channels() {
+        let node = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("eth_call"))
+            .respond_with(RpcResult(encoded_state(1)))
+            // only the first lookup reaches the node
+            .expect(2)
+            .mount(&node)
+            .await;
+
+        let cache = setup_cache(&node, 0);
+        let channels = [ChannelId::from([1; 32]), ChannelId::from([2; 32])];
+
+        for _ in 0..2 {
+            let states = cache
+                .states(&channels)
+                .await
+                .expect("Should get the states");
+
+            assert_eq!(2, states.len());
+            assert!(states.values().all(|state| state == &ChannelState::Active));
+        }
+    }
+
+    #[tokio::test]
+    async fn does_not_cache_the_unknown_states() {
+        let node = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("eth_call"))
+            .respond_with(RpcResult(encoded_state(0)))
+            .expect(2)
+            .mount(&node)
+            .await;
+
+        let cache = setup_cache(&node, 0);
+        let channel_id = ChannelId::from([1; 32]);
+
+        for _ in 0..2 {
+            let state = cache
+                .state(&channel_id)
+                .await
+                .expect("Should get the state");
+
+            assert_eq!(ChannelState::Unknown, state);
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_the_states_from_the_confirmed_block() {
+        let node = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("eth_blockNumber"))
+            .respond_with(RpcResult(json!("0x10")))
+            .expect(1)
+            .mount(&node)
+            .await;
+        // 0x10 - 3 confirmations
+        Mock::given(method("POST"))
+            .and(body_string_contains("eth_call"))
+            .and(body_string_contains("\"0xd\""))
+            .respond_with(RpcResult(encoded_state(2)))
+            .expect(1)
+            .mount(&node)
+            .await;
+
+        let cache = setup_cache(&node, 3);
+        let state = cache
+            .state(&ChannelId::from([1; 32]))
+            .await
+            .expect("Should get the state");
+
+        assert_eq!(ChannelState::Expired, state);
+    }
+
+    #[tokio::test]
+    async fn gets_the_channel_logs_of_the_confirmed_blocks() {
+        let node = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("eth_blockNumber"))
+            .respond_with(RpcResult(json!("0x20")))
+            .mount(&node)
+            .await;
+
+        let open = "0x13c5d35090070a7e2a5e8eaab30efb0db4dd2a622fc0e1ed61960bbff1580ef3";
+        let withdraw_expired = "0x220fe94f8612c2099cdde52d125687c1f3ac71f882c139f664e5c99e405795d2";
+        let log = |topics: Vec<String>, data: String, block: &str| {
+            json!({
+                "address": "0x333420fc6a897356e69b62417cd17ff012177d2b",
+                "topics": topics,
+                "data": data,
+                "blockNumber": block,
+                "blockHash": format!("0x{}", "ab".repeat(32)),
+                "transactionHash": format!("0x{}", "cd".repeat(32)),
+                "transactionIndex": "0x0",
+                "logIndex": "0x0",
+                "removed": false
+            })
+        };
+        let logs = json!([
+            // indexed channel id
+            log(
+                vec![open.to_string(), format!("0x{}", "01".repeat(32))],
+                "0x".to_string(),
+                "0x11"
+            ),
+            // channel id in the data
+            log(
+                vec![withdraw_expired.to_string()],
+                format!("0x{}{}", "02".repeat(32), "00".repeat(32)),
+                "0x1e"
+            ),
+            // not a channel event
+            log(
+                vec![format!("0x{}", "ff".repeat(32))],
+                "0x".to_string(),
+                "0x1e"
+            ),
+        ]);
+        // 0x20 - 2 confirmations
+        Mock::given(method("POST"))
+            .and(body_string_contains("eth_getLogs"))
+            .and(body_string_contains("\"fromBlock\":\"0x10\""))
+            .and(body_string_contains("\"toBlock\":\"0x1e\""))
+            .respond_with(RpcResult(logs))
+            .expect(1)
+            .mount(&node)
+            .await;
+
+        let cache = setup_cache(&node, 2);
+        let channel_logs = cache
+            .channel_logs(16)
+            .await
+            .expect("Should get the channel logs");
+
+        let expected = ChannelLogs {
+            logs: vec![
+                ChannelLog {
+                    channel_id: ChannelId::from([1; 32]),
+                    event: ChannelEvent::Open,
+                    block_number: 17,
+                },
+                ChannelLog {
+                    channel_id: ChannelId::from([2; 32]),
+                    event: ChannelEvent::WithdrawExpired,
+                    block_number: 30,
+                },
+            ],
+            to_block: Some(30),
+        };
+        assert_eq!(expected, channel_logs);
+
+        // the states after the events are cached, so the node is not called
+        let states = cache
+            .states(&[ChannelId::from([1; 32]), ChannelId::from([2; 32])])
+            .await
+            .expect("Should get the cached states");
+        assert_eq!(ChannelState::Active, states[&ChannelId::from([1; 32])]);
+        assert_eq!(ChannelState::Expired, states[&ChannelId::from([2; 32])]);
+
+        // the block is not confirmed yet
+        let channel_logs = cache
+            .channel_logs(31)
+            .await
+            .expect("Should get the channel logs");
+        assert_eq!(ChannelLogs::default(), channel_logs);
+    }
+
+    #[tokio::test]
+    async fn keeps_a_cache_for_each_chain() {
+        let mainnet = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("eth_call"))
+            .respond_with(RpcResult(encoded_state(1)))
+            .expect(1)
+            .mount(&mainnet)
+            .await;
+        let goerli = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("eth_call"))
+            .respond_with(RpcResult(encoded_state(2)))
+            .expect(1)
+            .mount(&goerli)
+            .await;
+
+        let mut config = configuration("development", None).expect("failed parse config");
+        let chain = config.chains[0].clone();
+        config.chains = vec![
+            ChainConfig {
+                rpc: mainnet.uri(),
+                ..chain.clone()
+            },
+            ChainConfig {
+                chain_id: ChainId::new(5),
+                rpc: goerli.uri(),
+                ..chain
+            },
+        ];
+        let chains = Chains::init(&config).expect("failed to init the chains");
+
+        // the same channel id is looked up on each chain
+        let channel_id = ChannelId::from([1; 32]);
+        for (chain_id, expected) in &[
+            (ChainId::MAINNET, ChannelState::Active),
+            (ChainId::new(5), ChannelState::Expired),
+        ] {
+            let state = chains
+                .get(*chain_id)
+                .expect("Should be a configured chain")
+                .state(&channel_id)
+                .await
+                .expect("Should get the state");
+
+            assert_eq!(expected, &state);
+        }
+
+        assert!(matches!(
+            chains.get(ChainId::new(100)),
+            Err(Error::UnsupportedChain(chain_id)) if chain_id == ChainId::new(100)
+        ));
+    }
+}
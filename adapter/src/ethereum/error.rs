@@ -1,5 +1,6 @@
+use crate::remote::RemoteSignerError;
 use primitives::adapter::{AdapterErrorKind, Error as AdapterError};
-use primitives::ChannelId;
+use primitives::{ChainId, ChannelId};
 use std::fmt;
 
 #[derive(Debug)]
@@ -15,6 +16,8 @@ pub enum Error {
         actual: ChannelId,
     },
     ChannelInactive(ChannelId),
+    /// The chain is not one of the `Config.chains`
+    UnsupportedChain(ChainId),
     /// Signing of the message failed
     SignMessage(EwtSigningError),
     VerifyMessage(EwtVerifyError),
@@ -22,6 +25,8 @@ pub enum Error {
     ContractQuerying(web3::contract::Error),
     /// Error occurred during verification of Signature and/or StateRoot and/or Address
     VerifyAddress(VerifyError),
+    /// Communicating with the remote signer failed or it returned an error
+    RemoteSigner(RemoteSignerError),
 }
 
 impl std::error::Error for Error {}
@@ -39,11 +44,13 @@ impl fmt::Display for Error {
                 RelayerClient(err) => write!(f, "Relayer client: {}", err),
                 InvalidChannelId { expected, actual} => write!(f, "The hashed EthereumChannel.id ({}) is not the same as the Channel.id ({}) that was provided", expected, actual),
                 ChannelInactive(channel_id) => write!(f, "Channel ({}) is not Active on the ethereum network", channel_id),
+                UnsupportedChain(chain_id) => write!(f, "Chain ({}) is not supported", chain_id),
                 SignMessage(err) => write!(f, "Signing message: {}", err),
                 VerifyMessage(err) => write!(f, "Verifying message: {}", err),
                 ContractInitialization(err) => write!(f, "Contract initialization: {}", err),
                 ContractQuerying(err) => write!(f, "Contract querying: {}", err),
-                VerifyAddress(err) => write!(f, "Verifying address: {}", err)
+                VerifyAddress(err) => write!(f, "Verifying address: {}", err),
+                RemoteSigner(err) => write!(f, "Remote signer: {}", err),
             }
     }
 }
@@ -54,6 +61,8 @@ impl fmt::Display for Error {
 pub enum VerifyError {
     PublicKeyRecovery(ethstore::ethkey::Error),
     StateRootDecoding(hex::FromHexError),
+    /// Only state roots of 32 bytes can be signed as EIP-712 typed data
+    StateRootLength(usize),
     SignatureDecoding(hex::FromHexError),
     SignatureNotPrefixed,
 }
@@ -67,6 +76,9 @@ impl fmt::Display for VerifyError {
                 write!(f, "Recovering the public key from the signature: {}", err)
             }
             StateRootDecoding(err) => write!(f, "Decoding state root: {}", err),
+            StateRootLength(length) => {
+                write!(f, "State root should be 32 bytes long, got: {}", length)
+            }
             SignatureDecoding(err) => write!(f, "Decoding signature: {}", err),
             SignatureNotPrefixed => write!(f, "Signature is not prefixed with `0x`"),
         }
@@ -81,6 +93,8 @@ impl From<VerifyError> for AdapterError<Error> {
 
 #[derive(Debug)]
 pub enum KeystoreError {
+    /// At least one keystore is required
+    NoKeystores,
     /// `address` key is missing from the keystore file
     AddressMissing,
     /// The `address` key in the keystore file is not a valid `ValidatorId`
@@ -95,7 +109,7 @@ impl std::error::Error for KeystoreError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         use KeystoreError::*;
         match self {
-            AddressMissing => None,
+            NoKeystores | AddressMissing => None,
             AddressInvalid(err) => Some(err),
             ReadingFile(err) => Some(err),
             Deserialization(err) => Some(err),
@@ -108,6 +122,7 @@ impl fmt::Display for KeystoreError {
         use KeystoreError::*;
 
         match self {
+            NoKeystores => write!(f, "No keystore files were provided"),
             AddressMissing => write!(f, "\"address\" key missing in keystore file"),
             AddressInvalid(err) => write!(f, "\"address\" is invalid: {}", err),
             ReadingFile(err) => write!(f, "Reading keystore file: {}", err),
@@ -116,6 +131,12 @@ impl fmt::Display for KeystoreError {
     }
 }
 
+impl From<RemoteSignerError> for AdapterError<Error> {
+    fn from(err: RemoteSignerError) -> Self {
+        AdapterError::Adapter(Error::RemoteSigner(err).into())
+    }
+}
+
 impl From<KeystoreError> for AdapterError<Error> {
     fn from(err: KeystoreError) -> Self {
         AdapterError::Adapter(Error::Keystore(err).into())
@@ -150,6 +171,13 @@ impl From<EwtSigningError> for AdapterError<Error> {
 
 #[derive(Debug)]
 pub enum EwtVerifyError {
+    HeaderDecoding(base64::DecodeError),
+    HeaderDeserialization(serde_json::Error),
+    /// The `alg` of the header is neither `ETH` nor `EIP712`
+    UnsupportedAlgorithm(String),
+    /// The `EIP712` tokens should have the `chainId` of their domain in the header
+    ChainIdMissing,
+    UnsupportedChain(ChainId),
     AddressRecovery(ethstore::ethkey::Error),
     SignatureDecoding(base64::DecodeError),
     PayloadDecoding(base64::DecodeError),
@@ -162,6 +190,11 @@ impl fmt::Display for EwtVerifyError {
         use EwtVerifyError::*;
 
         match self {
+            HeaderDecoding(err) => write!(f, "Header decoding: {}", err),
+            HeaderDeserialization(err) => write!(f, "Header deserialization: {}", err),
+            UnsupportedAlgorithm(alg) => write!(f, "Unsupported algorithm: {}", alg),
+            ChainIdMissing => write!(f, "Header chainId is missing"),
+            UnsupportedChain(chain_id) => write!(f, "Unsupported chain: {}", chain_id),
             AddressRecovery(err) => write!(f, "Address recovery: {}", err),
             SignatureDecoding(err) => write!(f, "Signature decoding: {}", err),
             PayloadDecoding(err) => write!(f, "Payload decoding: {}", err),
@@ -17,13 +17,17 @@ use web3::{
 
 pub use self::dummy::DummyAdapter;
 pub use self::ethereum::EthereumAdapter;
+pub use self::remote::RemoteAdapter;
 
 pub mod dummy;
+pub mod eip712;
 pub mod ethereum;
+pub mod remote;
 
 pub enum AdapterTypes {
     DummyAdapter(Box<DummyAdapter>),
     EthereumAdapter(Box<EthereumAdapter>),
+    RemoteAdapter(Box<RemoteAdapter>),
 }
 
 pub fn get_signable_state_root(
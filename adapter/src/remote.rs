@@ -0,0 +1,680 @@
+use crate::eip712::{self, Authentication, Domain, StateRoot, TypedMessage};
+use crate::ethereum::{
+    chain_domain, ewt_message, ewt_token, session_from_ewt, validate_channel_on_chain,
+    verify_signature, Chains, Error, Payload, RelayerClient, VerifyError,
+};
+use async_trait::async_trait;
+use primitives::{
+    adapter::{
+        Adapter, AdapterResult, ChannelLogs, ChannelState, Error as AdapterError,
+        RemoteSignerOptions, Session, SignatureScheme,
+    },
+    channel_validator::ChannelValidator,
+    config::Config,
+    ChainId, Channel, ChannelId, ToETHChecksum, ValidatorId,
+};
+use reqwest::{Certificate, Client, Identity};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::time::Duration;
+
+pub use error::*;
+
+mod error;
+
+/// Ethereum adapter which never has access to the private key.
+/// Signing is delegated to a remote signer over JSON-RPC
+/// (`eth_accounts`, `eth_sign` & `eth_signTypedData_v4`),
+/// everything else is the same as in the `EthereumAdapter`.
+#[derive(Debug, Clone)]
+pub struct RemoteAdapter {
+    identities: Vec<ValidatorId>,
+    config: Config,
+    signer: RemoteSignerClient,
+    unlocked: bool,
+    chains: Chains,
+    relayer: RelayerClient,
+}
+
+// Enables RemoteAdapter to be able to
+// check if a channel is valid
+impl ChannelValidator for RemoteAdapter {}
+
+impl RemoteAdapter {
+    /// Connects to the remote signer and uses its accounts as the adapter identities.
+    /// The first account is the current identity.
+    /// The requests to the signer time out after the `fetch_timeout` of the `config`.
+    pub async fn init(
+        opts: RemoteSignerOptions,
+        config: &Config,
+    ) -> AdapterResult<RemoteAdapter, Error> {
+        let timeout = Duration::from_millis(config.fetch_timeout.into());
+        let signer = RemoteSignerClient::new(&opts, timeout)?;
+
+        let identities = signer.accounts().await?;
+        if identities.is_empty() {
+            return Err(RemoteSignerError::NoAccounts.into());
+        }
+
+        let chains = Chains::init(config)?;
+        let relayer =
+            RelayerClient::new(&config.ethereum_adapter_relayer).map_err(Error::RelayerClient)?;
+
+        Ok(Self {
+            identities,
+            config: config.to_owned(),
+            signer,
+            unlocked: false,
+            chains,
+            relayer,
+        })
+    }
+}
+
+#[async_trait]
+impl Adapter for RemoteAdapter {
+    type AdapterError = Error;
+
+    /// The keys never leave the remote signer,
+    /// so we only check that the signer still manages our accounts.
+    async fn unlock(&mut self) -> AdapterResult<(), Self::AdapterError> {
+        let accounts = self.signer.accounts().await?;
+        if let Some(missing) = self
+            .identities
+            .iter()
+            .find(|identity| !accounts.contains(identity))
+        {
+            return Err(RemoteSignerError::AccountMissing(*missing).into());
+        }
+
+        self.unlocked = true;
+
+        Ok(())
+    }
+
+    fn identities(&self) -> &[ValidatorId] {
+        &self.identities
+    }
+
+    async fn sign<'a>(
+        &'a self,
+        channel: &'a Channel,
+        state_root: &'a str,
+    ) -> AdapterResult<String, Self::AdapterError> {
+        if !self.unlocked {
+            return Err(AdapterError::LockedWallet);
+        }
+
+        let whoami = self.whoami(channel);
+        let domain = chain_domain(&self.config, channel.chain_id)?;
+        let message = hex::decode(state_root).map_err(VerifyError::StateRootDecoding)?;
+        let scheme = SignatureScheme::for_channel(channel, &self.config);
+        let signature = match scheme {
+            SignatureScheme::EthSign => self.signer.eth_sign(whoami, &message).await?,
+            SignatureScheme::Eip712 => {
+                let state_root = <[u8; 32]>::try_from(message.as_slice())
+                    .map_err(|_| VerifyError::StateRootLength(message.len()))?;
+                let state_root = StateRoot {
+                    channel_id: &channel.id,
+                    state_root: &state_root,
+                };
+
+                self.signer
+                    .sign_typed_data(whoami, &domain, &state_root)
+                    .await?
+            }
+        };
+
+        // don't propagate signatures which the other validators will reject
+        if !verify_signature(scheme, &domain, &channel.id, whoami, state_root, &signature)? {
+            return Err(RemoteSignerError::InvalidSignature.into());
+        }
+
+        Ok(signature)
+    }
+
+    /// `state_root` is hex string which **should not** be `0x` prefixed
+    /// `sig` is hex string wihch **should be** `0x` prefixed
+    fn verify(
+        &self,
+        channel: &Channel,
+        signer: &ValidatorId,
+        state_root: &str,
+        sig: &str,
+    ) -> AdapterResult<bool, Self::AdapterError> {
+        let scheme = SignatureScheme::for_channel(channel, &self.config);
+        let domain = chain_domain(&self.config, channel.chain_id)?;
+
+        verify_signature(scheme, &domain, &channel.id, signer, state_root, sig)
+    }
+
+    async fn validate_channel<'a>(
+        &'a self,
+        channel: &'a Channel,
+    ) -> AdapterResult<bool, Self::AdapterError> {
+        validate_channel_on_chain(&self.config, &self.chains, self.whoami(channel), channel).await
+    }
+
+    async fn channel_states<'a>(
+        &'a self,
+        chain_id: ChainId,
+        channels: &'a [ChannelId],
+    ) -> AdapterResult<HashMap<ChannelId, ChannelState>, Self::AdapterError> {
+        let chain_state = self.chains.get(chain_id)?;
+
+        chain_state.states(channels).await.map_err(Into::into)
+    }
+
+    async fn channel_logs(
+        &self,
+        chain_id: ChainId,
+        from_block: u64,
+    ) -> AdapterResult<ChannelLogs, Self::AdapterError> {
+        let chain_state = self.chains.get(chain_id)?;
+
+        chain_state
+            .channel_logs(from_block)
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Creates a `Session` from a provided Token by calling the Contract.
+    /// Does **not** cache the (`Token`, `Session`) pair.
+    async fn session_from_token<'a>(
+        &'a self,
+        token: &'a str,
+    ) -> AdapterResult<Session, Self::AdapterError> {
+        session_from_ewt(&self.relayer, &self.config.chains, self.identities(), token).await
+    }
+
+    async fn get_auth<'a>(
+        &'a self,
+        channel: &'a Channel,
+        validator: &'a ValidatorId,
+    ) -> AdapterResult<String, Self::AdapterError> {
+        if !self.unlocked {
+            return Err(AdapterError::LockedWallet);
+        }
+
+        let whoami = self.whoami(channel);
+        let payload = Payload::for_validator(validator, whoami);
+        let domain = match SignatureScheme::for_channel(channel, &self.config) {
+            SignatureScheme::EthSign => None,
+            SignatureScheme::Eip712 => Some(chain_domain(&self.config, channel.chain_id)?),
+        };
+        let message = ewt_message(&payload, domain.as_ref())?;
+        let signature = match &domain {
+            None => self.signer.eth_sign(whoami, message.as_bytes()).await?,
+            Some(domain) => {
+                self.signer
+                    .sign_typed_data(whoami, domain, &Authentication(&payload))
+                    .await?
+            }
+        };
+        let signature = signature.strip_prefix("0x").unwrap_or(&signature);
+
+        ewt_token(&message, signature).map_err(Into::into)
+    }
+}
+
+#[derive(Serialize)]
+struct RpcRequest {
+    jsonrpc: &'static str,
+    id: u64,
+    method: String,
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Clone)]
+struct RemoteSignerClient {
+    client: Client,
+    signer_url: String,
+    request_id: Arc<AtomicU64>,
+}
+
+impl RemoteSignerClient {
+    fn new(opts: &RemoteSignerOptions, timeout: Duration) -> Result<Self, RemoteSignerError> {
+        let mut builder = Client::builder().timeout(timeout);
+
+        if let Some(identity_file) = &opts.client_identity_file {
+            let identity = fs::read(identity_file).map_err(RemoteSignerError::ReadingFile)?;
+            let identity = Identity::from_pkcs12_der(&identity, &opts.client_identity_pwd)
+                .map_err(RemoteSignerError::Client)?;
+
+            builder = builder.identity(identity);
+        }
+
+        if let Some(ca_certificate_file) = &opts.ca_certificate_file {
+            let certificate =
+                fs::read(ca_certificate_file).map_err(RemoteSignerError::ReadingFile)?;
+            let certificate =
+                Certificate::from_pem(&certificate).map_err(RemoteSignerError::Client)?;
+
+            builder = builder.add_root_certificate(certificate);
+        }
+
+        let client = builder.build().map_err(RemoteSignerError::Client)?;
+
+        Ok(Self {
+            client,
+            signer_url: opts.signer_url.to_owned(),
+            request_id: Arc::new(AtomicU64::new(1)),
+        })
+    }
+
+    async fn accounts(&self) -> Result<Vec<ValidatorId>, RemoteSignerError> {
+        self.call("eth_accounts", json!([])).await
+    }
+
+    /// Signs the `message` prefixed with `\x19Ethereum Signed Message:\n{length}`.
+    /// The returned signature is `0x` prefixed
+    async fn eth_sign(
+        &self,
+        address: &ValidatorId,
+        message: &[u8],
+    ) -> Result<String, RemoteSignerError> {
+        let params = json!([address.to_checksum(), format!("0x{}", hex::encode(message))]);
+
+        self.call("eth_sign", params).await
+    }
+
+    /// Signs the `message` as EIP-712 typed data of the `domain`.
+    /// The returned signature is `0x` prefixed
+    async fn sign_typed_data(
+        &self,
+        address: &ValidatorId,
+        domain: &Domain,
+        message: &impl TypedMessage,
+    ) -> Result<String, RemoteSignerError> {
+        let typed_data = eip712::typed_data(domain, message);
+        let params = json!([address.to_checksum(), typed_data]);
+
+        self.call("eth_signTypedData_v4", params).await
+    }
+
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<T, RemoteSignerError> {
+        let request = RpcRequest {
+            jsonrpc: "2.0",
+            id: self.request_id.fetch_add(1, Ordering::Relaxed),
+            method: method.to_string(),
+            params,
+        };
+
+        let response: RpcResponse<T> = self
+            .client
+            .post(&self.signer_url)
+            .json(&request)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(RemoteSignerError::Client)?
+            .json()
+            .await
+            .map_err(RemoteSignerError::Client)?;
+
+        match response {
+            RpcResponse {
+                error: Some(error), ..
+            } => Err(RemoteSignerError::Rpc(error)),
+            RpcResponse {
+                result: Some(result),
+                ..
+            } => Ok(result),
+            _ => Err(RemoteSignerError::EmptyResponse),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ethereum::{ewt_verify, hash_message};
+    use ethstore::{
+        ethkey::{public_to_address, sign, KeyPair, Message, Password, Secret, Signature},
+        SafeAccount,
+    };
+    use primitives::config::configuration;
+    use primitives::util::tests::prep_db::{
+        DUMMY_CHANNEL, DUMMY_VALIDATOR_FOLLOWER, DUMMY_VALIDATOR_LEADER,
+    };
+    use primitives::ValidatorDesc;
+    use std::convert::TryFrom;
+    use wiremock::{matchers::method, Mock, MockServer, Request, Respond, ResponseTemplate};
+
+    /// Local mock of the remote signer
+    struct MockSigner {
+        accounts: Vec<(ValidatorId, Secret)>,
+    }
+
+    impl MockSigner {
+        async fn start(secrets: Vec<Secret>) -> MockServer {
+            let accounts = secrets
+                .into_iter()
+                .map(|secret| {
+                    let key_pair = KeyPair::from_secret(secret.clone()).expect("Valid secret");
+                    let address = public_to_address(key_pair.public());
+
+                    (ValidatorId::from(&address.0), secret)
+                })
+                .collect();
+
+            let server = MockServer::start().await;
+
+            Mock::given(method("POST"))
+                .respond_with(MockSigner { accounts })
+                .mount(&server)
+                .await;
+
+            server
+        }
+    }
+
+    impl Respond for MockSigner {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            let request: Value =
+                serde_json::from_slice(&request.body).expect("Should be a JSON-RPC request");
+
+            let result = match request["method"].as_str() {
+                Some("eth_accounts") => json!(self
+                    .accounts
+                    .iter()
+                    .map(|(address, _)| address.to_checksum())
+                    .collect::<Vec<_>>()),
+                Some("eth_sign") => {
+                    let address = request["params"][0].as_str().expect("Should have address");
+                    let data = request["params"][1].as_str().expect("Should have data");
+
+                    let data = hex::decode(&data[2..]).expect("Data should be 0x prefixed hex");
+                    let message = Message::from(hash_message(&data));
+
+                    json!(format!("0x{}", self.sign(address, &message)))
+                }
+                Some("eth_signTypedData_v4") => {
+                    let address = request["params"][0].as_str().expect("Should have address");
+                    let message = Message::from(typed_data_hash(&request["params"][1]));
+
+                    json!(format!("0x{}", self.sign(address, &message)))
+                }
+                _ => {
+                    return ResponseTemplate::new(200).set_body_json(json!({
+                        "jsonrpc": "2.0",
+                        "id": request["id"],
+                        "error": { "code": -32601, "message": "Method not found" }
+                    }))
+                }
+            };
+
+            ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "result": result
+            }))
+        }
+    }
+
+    impl MockSigner {
+        fn sign(&self, address: &str, message: &Message) -> Signature {
+            let secret = self
+                .accounts
+                .iter()
+                .find(|(account, _)| account.to_checksum() == address)
+                .map(|(_, secret)| secret)
+                .expect("Should sign only with the signer accounts");
+
+            sign(secret, message)
+                .expect("Should sign the message")
+                .into_electrum()
+                .into()
+        }
+    }
+
+    /// Rebuilds the typed message from the `eth_signTypedData_v4` JSON and hashes it
+    fn typed_data_hash(typed_data: &Value) -> [u8; 32] {
+        fn bytes<T: for<'a> TryFrom<&'a [u8]>>(value: &Value) -> T {
+            let hex = value.as_str().expect("Should be a string");
+            let bytes = hex::decode(&hex[2..]).expect("Should be 0x prefixed hex");
+
+            T::try_from(bytes.as_slice())
+                .ok()
+                .expect("Should have the right length")
+        }
+
+        let domain = Domain {
+            chain_id: serde_json::from_value(typed_data["domain"]["chainId"].clone())
+                .expect("Should have chainId"),
+            verifying_contract: bytes(&typed_data["domain"]["verifyingContract"]),
+        };
+        let message = &typed_data["message"];
+
+        match typed_data["primaryType"].as_str() {
+            Some("StateRoot") => {
+                let channel_id = ChannelId::from(bytes::<[u8; 32]>(&message["channelId"]));
+                let state_root: [u8; 32] = bytes(&message["stateRoot"]);
+
+                eip712::hash_to_sign(
+                    &domain,
+                    &StateRoot {
+                        channel_id: &channel_id,
+                        state_root: &state_root,
+                    },
+                )
+            }
+            Some("Authentication") => {
+                let identity: [u8; 20] = bytes(&message["identity"]);
+                let payload = Payload {
+                    id: message["id"].as_str().expect("Should have id").to_string(),
+                    era: message["era"].as_i64().expect("Should have era"),
+                    address: message["address"]
+                        .as_str()
+                        .expect("Should have address")
+                        .to_string(),
+                    identity: Some(identity)
+                        .filter(|identity| identity != &[0; 20])
+                        .map(|identity| ValidatorId::from(&identity)),
+                };
+
+                eip712::hash_to_sign(&domain, &Authentication(&payload))
+            }
+            other => panic!("Unexpected primary type: {:?}", other),
+        }
+    }
+
+    /// The secret of the test keystore (`0x2bDeAFAE53940669DaA6F519373f686c1f3d3393`)
+    fn keystore_secret() -> Secret {
+        let keystore =
+            fs::read_to_string("./test/resources/keystore.json").expect("Should read the keystore");
+        let password: Password = "adexvalidator".to_string().into();
+        let account = SafeAccount::from_file(
+            serde_json::from_str(&keystore).expect("Should deserialize the keystore"),
+            None,
+            &Some(password.clone()),
+        )
+        .expect("Should unlock the keystore");
+
+        account
+            .crypto
+            .secret(&password)
+            .expect("Should decrypt the secret")
+    }
+
+    fn rotated_secret() -> Secret {
+        Secret::from_unsafe_slice(&[0x11; 32]).expect("Valid secret")
+    }
+
+    async fn setup_remote_adapter(signer: &MockServer) -> RemoteAdapter {
+        let config = configuration("development", None).expect("failed parse config");
+        let options = RemoteSignerOptions {
+            signer_url: signer.uri(),
+            client_identity_file: None,
+            client_identity_pwd: String::new(),
+            ca_certificate_file: None,
+        };
+
+        RemoteAdapter::init(options, &config)
+            .await
+            .expect("should init remote adapter")
+    }
+
+    #[tokio::test]
+    async fn should_get_whoami_and_sign_with_the_remote_signer() {
+        let signer = MockSigner::start(vec![keystore_secret()]).await;
+        let mut remote_adapter = setup_remote_adapter(&signer).await;
+
+        assert_eq!(
+            remote_adapter.whoami(&DUMMY_CHANNEL).to_string(),
+            "0x2bDeAFAE53940669DaA6F519373f686c1f3d3393",
+            "failed to get correct whoami"
+        );
+
+        let message = "2bdeafae53940669daa6f519373f686c";
+        match remote_adapter.sign(&DUMMY_CHANNEL, message).await {
+            Err(AdapterError::LockedWallet) => {}
+            other => panic!("Expected a LockedWallet error, got: {:?}", other),
+        }
+
+        remote_adapter
+            .unlock()
+            .await
+            .expect("should unlock remote adapter");
+
+        // the same signature as the one of the `EthereumAdapter` with the same keystore
+        let expected_response =
+            "0x625fd46f82c4cfd135ea6a8534e85dbf50beb157046dce59d2e97aacdf4e38381d1513c0e6f002b2f05c05458038b187754ff38cc0658dfc9ba854cccfb6e13e1b";
+        let signature = remote_adapter
+            .sign(&DUMMY_CHANNEL, message)
+            .await
+            .expect("failed to sign message");
+        assert_eq!(expected_response, signature, "invalid signature");
+
+        let verify = remote_adapter
+            .verify(
+                &DUMMY_CHANNEL,
+                remote_adapter.whoami(&DUMMY_CHANNEL),
+                message,
+                &signature,
+            )
+            .expect("Failed to verify signature");
+        assert!(verify, "invalid signature verification");
+    }
+
+    #[tokio::test]
+    async fn should_sign_with_the_previous_identity_for_its_channels() {
+        let signer = MockSigner::start(vec![rotated_secret(), keystore_secret()]).await;
+        let mut remote_adapter = setup_remote_adapter(&signer).await;
+        remote_adapter
+            .unlock()
+            .await
+            .expect("should unlock remote adapter");
+
+        let current = remote_adapter.identities()[0];
+        let previous = ValidatorId::try_from("0x2bDeAFAE53940669DaA6F519373f686c1f3d3393")
+            .expect("Valid ValidatorId");
+        assert_eq!(&[current, previous], remote_adapter.identities());
+
+        let mut previous_channel = DUMMY_CHANNEL.clone();
+        previous_channel.spec.validators = (
+            ValidatorDesc {
+                id: previous,
+                ..DUMMY_VALIDATOR_LEADER.clone()
+            },
+            DUMMY_VALIDATOR_FOLLOWER.clone(),
+        )
+            .into();
+
+        assert_eq!(&previous, remote_adapter.whoami(&previous_channel));
+        // none of the identities validates it, so the current identity is used
+        assert_eq!(&current, remote_adapter.whoami(&DUMMY_CHANNEL));
+
+        let message = "2bdeafae53940669daa6f519373f686c";
+        for (channel, signer) in &[(&previous_channel, previous), (&DUMMY_CHANNEL, current)] {
+            let signature = remote_adapter
+                .sign(channel, message)
+                .await
+                .expect("failed to sign message");
+
+            let verify = remote_adapter
+                .verify(channel, signer, message, &signature)
+                .expect("Failed to verify signature");
+            assert!(verify, "should be signed by {}", signer);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_get_auth_and_session_from_the_token() {
+        let signer = MockSigner::start(vec![keystore_secret()]).await;
+        let mut remote_adapter = setup_remote_adapter(&signer).await;
+        remote_adapter
+            .unlock()
+            .await
+            .expect("should unlock remote adapter");
+
+        let whoami = *remote_adapter.whoami(&DUMMY_CHANNEL);
+        let token = remote_adapter
+            .get_auth(&DUMMY_CHANNEL, &whoami)
+            .await
+            .expect("Should get the auth token");
+
+        let parts: Vec<&str> = token.split('.').collect();
+        let verification = ewt_verify(parts[0], parts[1], parts[2], &remote_adapter.config.chains)
+            .expect("Failed to verify ewt token");
+        assert_eq!(whoami, verification.from);
+        assert_eq!(whoami.to_checksum(), verification.payload.id);
+
+        let session = remote_adapter
+            .session_from_token(&token)
+            .await
+            .expect("Should create a session from the token");
+        assert_eq!(whoami, session.uid);
+    }
+
+    #[tokio::test]
+    async fn should_sign_typed_data_with_the_remote_signer() {
+        let signer = MockSigner::start(vec![keystore_secret()]).await;
+        let mut remote_adapter = setup_remote_adapter(&signer).await;
+        remote_adapter
+            .unlock()
+            .await
+            .expect("should unlock remote adapter");
+
+        let mut channel = DUMMY_CHANNEL.clone();
+        channel.spec.signature_scheme = Some(SignatureScheme::Eip712);
+        let whoami = *remote_adapter.whoami(&channel);
+
+        let state_root = "b1a4fc6c1a1e1ab908a487e504006edcebea297f61b4b8ce6cad3b4e2e5f1b4c";
+        let signature = remote_adapter
+            .sign(&channel, state_root)
+            .await
+            .expect("failed to sign typed data");
+        let verify = remote_adapter
+            .verify(&channel, &whoami, state_root, &signature)
+            .expect("Failed to verify signature");
+        assert!(verify, "invalid signature verification");
+
+        let token = remote_adapter
+            .get_auth(&channel, &whoami)
+            .await
+            .expect("Should get the auth token");
+        let session = remote_adapter
+            .session_from_token(&token)
+            .await
+            .expect("Should create a session from the typed data token");
+        assert_eq!(whoami, session.uid);
+    }
+}
@@ -0,0 +1,51 @@
+use primitives::ValidatorId;
+use serde::Deserialize;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum RemoteSignerError {
+    /// Reading the client identity or the CA certificate file failed
+    ReadingFile(std::io::Error),
+    Client(reqwest::Error),
+    /// The remote signer responded with a JSON-RPC error
+    Rpc(RpcError),
+    /// The remote signer responded with neither a result nor an error
+    EmptyResponse,
+    /// The remote signer doesn't manage any accounts
+    NoAccounts,
+    /// The remote signer doesn't manage the account of the adapter (anymore)
+    AccountMissing(ValidatorId),
+    /// The returned signature was not made with the key of the adapter account
+    InvalidSignature,
+}
+
+impl fmt::Display for RemoteSignerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use RemoteSignerError::*;
+
+        match self {
+            ReadingFile(err) => write!(f, "Reading TLS file: {}", err),
+            Client(err) => write!(f, "Client: {}", err),
+            Rpc(err) => write!(f, "JSON-RPC error: {}", err),
+            EmptyResponse => write!(f, "JSON-RPC response has neither a result nor an error"),
+            NoAccounts => write!(f, "The signer doesn't have any accounts"),
+            AccountMissing(address) => {
+                write!(f, "The signer doesn't have the account {}", address)
+            }
+            InvalidSignature => write!(f, "The signature is not made by the adapter account"),
+        }
+    }
+}
+
+/// The `error` object of a JSON-RPC response
+#[derive(Debug, Clone, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (code: {})", self.message, self.code)
+    }
+}
@@ -5,9 +5,10 @@ use adex_primitives::{
     supermarket::units_for_slot,
     supermarket::units_for_slot::response::{AdUnit, Campaign},
     targeting::{self, input},
+    token::{default_tokens, StaticPriceProvider, TokenInfo, TokenRegistry},
     BigNum, ChannelId, SpecValidators, ValidatorId, IPFS,
 };
-use async_std::{sync::RwLock, task::block_on};
+use async_std::sync::RwLock;
 use chrono::{DateTime, Utc};
 use lazy_static::lazy_static;
 use num_integer::Integer;
@@ -26,6 +27,9 @@ use thiserror::Error;
 use units_for_slot::response::UnitsWithPrice;
 use url::Url;
 
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
 const IPFS_GATEWAY: &str = "https://ipfs.moonicorn.network/ipfs/";
 
 // How much time to wait before sending out an impression event
@@ -51,6 +55,10 @@ pub struct Options {
     pub publisher_addr: ValidatorId,
     // All passed tokens must be of the same price and decimals, so that the amounts can be accurately compared
     pub whitelisted_tokens: Vec<String>,
+    /// The decimals & USD prices of the whitelisted tokens, used by `getPriceInUsd` in the targeting rules.
+    /// Defaults to the stablecoins of `default_tokens`
+    #[serde(default = "default_tokens")]
+    pub tokens: Vec<TokenInfo>,
     pub width: Option<u64>,
     pub height: Option<u64>,
     pub navigator_language: Option<String>,
@@ -66,8 +74,10 @@ impl Options {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct HistoryEntry {
+    #[serde(with = "chrono::serde::ts_milliseconds")]
     time: DateTime<Utc>,
     unit_id: IPFS,
     campaign_id: ChannelId,
@@ -282,6 +292,7 @@ pub struct Manager {
     /// It always trims to HISTORY_LIMIT, removing the oldest (firstly inserted) elements from the History
     history: Arc<RwLock<VecDeque<HistoryEntry>>>,
     client: reqwest::Client,
+    token_registry: TokenRegistry,
     logger: Logger,
 }
 
@@ -292,11 +303,14 @@ impl Manager {
         logger: Logger,
     ) -> Result<Self, Error> {
         let client = reqwest::Client::builder().build()?;
+        let token_registry =
+            TokenRegistry::new(options.tokens.clone(), Arc::new(StaticPriceProvider));
 
         Ok(Self {
             options,
             history: Arc::new(RwLock::new(history)),
             client,
+            token_registry,
             logger,
         })
     }
@@ -383,20 +397,23 @@ impl Manager {
         })
     }
 
-    async fn is_campaign_sticky(&self, campaign_id: ChannelId) -> bool {
+    fn is_campaign_sticky(&self, history: &VecDeque<HistoryEntry>, campaign_id: ChannelId) -> bool {
         if self.options.disabled_sticky {
             false
         } else {
             let stickiness_threshold = Utc::now() - *IMPRESSION_STICKINESS_TIME;
 
-            self.history
-                .read()
-                .await
+            history
                 .iter()
                 .any(|h| h.time > stickiness_threshold && h.campaign_id == campaign_id)
         }
     }
 
+    /// The history entries, from Old to New, e.g. for persisting them between sessions
+    pub async fn history(&self) -> VecDeque<HistoryEntry> {
+        self.history.read().await.clone()
+    }
+
     pub async fn get_market_demand_resp(
         &self,
     ) -> Result<units_for_slot::response::Response, Error> {
@@ -460,18 +477,22 @@ impl Manager {
         let random: f64 = rng.gen::<f64>() * (0x80000000_u64 as f64 - 1.0);
         let seed = BigNum::from(random as u64);
 
+        let history = self.history.read().await.clone();
+
         // Apply targeting, now with adView.* variables, and sort the resulting ad units
         let mut units_with_price: Vec<(UnitsWithPrice, ChannelId)> = campaigns
             .iter()
             .map(|campaign| {
-                // since we are in a Iterator.map(), we can't use async, so we block
-                if block_on(self.is_campaign_sticky(campaign.channel.id)) {
+                if self.is_campaign_sticky(&history, campaign.channel.id) {
                     return vec![];
                 }
 
                 let campaign_id = campaign.channel.id;
 
-                let mut unit_input = targeting_input.clone().with_market_channel(campaign.channel.clone());
+                let mut unit_input = targeting_input
+                    .clone()
+                    .with_market_channel(campaign.channel.clone())
+                    .with_deposit_asset_price(&self.token_registry);
 
                 campaign
                     .units_with_price
@@ -515,8 +536,6 @@ impl Manager {
         let auction_winner = units_with_price.get(0);
 
         if let Some((unit_with_price, campaign_id)) = auction_winner {
-            let history = self.history.read().await.clone();
-
             let new_entry = HistoryEntry {
                 time: Utc::now(),
                 unit_id: unit_with_price.unit.id.clone(),
@@ -580,6 +599,8 @@ impl Manager {
     }
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct NextAdUnit {
     pub unit: AdUnit,
     pub price: BigNum,
@@ -597,7 +618,21 @@ pub struct StickyAdUnit {
 #[cfg(test)]
 mod test {
     use super::*;
-    use adex_primitives::util::tests::prep_db::DUMMY_IPFS;
+    use chrono::TimeZone;
+
+    // the fixtures of `adex_primitives::util::tests` aren't built without the `test-util` feature
+    lazy_static! {
+        static ref DUMMY_IPFS: [IPFS; 2] = [
+            IPFS::try_from("QmcUVX7fvoLMM93uN2bD3wGTH8MXSxeL8hojYfL2Lhp7mR")
+                .expect("Valid IPFS V0"),
+            IPFS::try_from("Qmasg8FrbuSQpjFu3kRnZF9beg8rEBFrqgi1uXDRwCbX5f")
+                .expect("Valid IPFS V0"),
+        ];
+        static ref DUMMY_CHANNEL_ID: ChannelId =
+            "0x061d5e2a67d0a9a10f1c732bca12a676d83f79663a396f7d87b3e30b9b411088"
+                .parse()
+                .expect("Valid channel id");
+    }
 
     fn get_ad_unit(media_mime: &str) -> AdUnit {
         AdUnit {
@@ -627,6 +662,40 @@ mod test {
         assert_eq!("http://123".to_string(), normalize_url("http://123"));
     }
 
+    #[test]
+    fn history_entry_json() {
+        let entry = HistoryEntry {
+            time: Utc.timestamp_millis(1_600_000_000_123),
+            unit_id: DUMMY_IPFS[0].clone(),
+            campaign_id: *DUMMY_CHANNEL_ID,
+            slot_id: DUMMY_IPFS[1].clone(),
+        };
+
+        let json = serde_json::to_value(&entry).expect("Should serialize");
+        assert_eq!(serde_json::json!(1_600_000_000_123_i64), json["time"]);
+
+        let deserialized: HistoryEntry = serde_json::from_value(json).expect("Should deserialize");
+        assert_eq!(entry.time, deserialized.time);
+        assert_eq!(entry.unit_id, deserialized.unit_id);
+        assert_eq!(entry.campaign_id, deserialized.campaign_id);
+        assert_eq!(entry.slot_id, deserialized.slot_id);
+    }
+
+    #[test]
+    fn options_without_tokens_price_the_stablecoins() {
+        let options: Options = serde_json::from_value(serde_json::json!({
+            "marketURL": "https://market.adex.network",
+            "marketSlot": DUMMY_IPFS[1],
+            "publisherAddr": "0xB7d3F81E857692d13e9D63b232A90F4A1793189E",
+            "whitelistedTokens": ["0x6B175474E89094C44Da98b954EedeAC495271d0F"],
+            "disabledVideo": false,
+            "disabledSticky": false,
+        }))
+        .expect("Should deserialize the options");
+
+        assert_eq!(default_tokens(), options.tokens);
+    }
+
     mod randomized_sort_pos {
 
         use super::*;
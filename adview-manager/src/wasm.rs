@@ -0,0 +1,130 @@
+//! JavaScript bindings of the [`Manager`] for the `wasm32-unknown-unknown` build.
+//!
+//! The Market is requested with `fetch` and the history is persisted in the `localStorage`,
+//! so the impression stickiness and the frequency caps are kept across page loads.
+//! The values are passed to and from JavaScript as plain objects, in the same format as their JSON.
+use crate::{HistoryEntry, Manager, Options};
+use js_sys::{Promise, JSON};
+use serde::{de::DeserializeOwned, Serialize};
+use slog::{o, Drain, Level, Logger, Never, OwnedKVList, Record, KV};
+use std::{collections::VecDeque, fmt, fmt::Write, rc::Rc};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::future_to_promise;
+use web_sys::{console, Storage};
+
+/// The `localStorage` key of the history
+const HISTORY_KEY: &str = "adexHistory";
+
+#[wasm_bindgen(js_name = Manager)]
+pub struct JsManager {
+    manager: Rc<Manager>,
+}
+
+#[wasm_bindgen(js_class = Manager)]
+impl JsManager {
+    /// Creates a `Manager` from the `Options` object with the history from the `localStorage`
+    #[wasm_bindgen(constructor)]
+    pub fn new(options: JsValue) -> Result<JsManager, JsValue> {
+        let options: Options = from_js(&options)?;
+        let logger = Logger::root(ConsoleDrain, o!());
+
+        let manager = Manager::new(options, load_history(), logger).map_err(js_error)?;
+
+        Ok(Self {
+            manager: Rc::new(manager),
+        })
+    }
+
+    /// Resolves with the next ad unit or `null` and persists the updated history
+    #[wasm_bindgen(js_name = getNextAdUnit)]
+    pub fn get_next_ad_unit(&self) -> Promise {
+        let manager = self.manager.clone();
+
+        future_to_promise(async move {
+            let next_ad_unit = manager.get_next_ad_unit().await.map_err(js_error)?;
+            save_history(&manager.history().await);
+
+            to_js(&next_ad_unit)
+        })
+    }
+
+    /// Resolves with the history entries, from Old to New
+    pub fn history(&self) -> Promise {
+        let manager = self.manager.clone();
+
+        future_to_promise(async move { to_js(&manager.history().await) })
+    }
+}
+
+fn from_js<T: DeserializeOwned>(value: &JsValue) -> Result<T, JsValue> {
+    let json = String::from(JSON::stringify(value)?);
+
+    serde_json::from_str(&json).map_err(js_error)
+}
+
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    let json = serde_json::to_string(value).map_err(js_error)?;
+
+    JSON::parse(&json)
+}
+
+fn js_error(error: impl fmt::Display) -> JsValue {
+    js_sys::Error::new(&error.to_string()).into()
+}
+
+/// `None` when the `localStorage` is disabled, e.g. for third-party iframes
+fn local_storage() -> Option<Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+/// An empty history if there is no `localStorage` or if the stored history is invalid
+fn load_history() -> VecDeque<HistoryEntry> {
+    local_storage()
+        .and_then(|storage| storage.get_item(HISTORY_KEY).ok()?)
+        .and_then(|history| serde_json::from_str(&history).ok())
+        .unwrap_or_default()
+}
+
+/// Without a `localStorage` the history is only kept by the `Manager`
+fn save_history(history: &VecDeque<HistoryEntry>) {
+    if let (Some(storage), Ok(history)) = (local_storage(), serde_json::to_string(history)) {
+        // it fails only if the storage quota is exceeded
+        let _ = storage.set_item(HISTORY_KEY, &history);
+    }
+}
+
+/// Logs the records and their key-values to the browser console
+struct ConsoleDrain;
+
+impl Drain for ConsoleDrain {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record<'_>, values: &OwnedKVList) -> Result<(), Never> {
+        let mut message = record.msg().to_string();
+        let mut serializer = MessageSerializer(&mut message);
+        // writing to a `String` doesn't fail
+        let _ = record.kv().serialize(record, &mut serializer);
+        let _ = values.serialize(record, &mut serializer);
+
+        let message = JsValue::from_str(&message);
+        match record.level() {
+            Level::Critical | Level::Error => console::error_1(&message),
+            Level::Warning => console::warn_1(&message),
+            Level::Info => console::info_1(&message),
+            Level::Debug | Level::Trace => console::debug_1(&message),
+        }
+
+        Ok(())
+    }
+}
+
+struct MessageSerializer<'a>(&'a mut String);
+
+impl slog::Serializer for MessageSerializer<'_> {
+    fn emit_arguments(&mut self, key: slog::Key, value: &fmt::Arguments<'_>) -> slog::Result {
+        write!(self.0, ", {}: {}", key, value)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+//! Runs in a browser, e.g. with `wasm-pack test --headless --firefox adview-manager -- --features wasm`
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use adview_manager::wasm::JsManager;
+use js_sys::{Function, Reflect, JSON};
+use serde_json::{json, Value};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+use wasm_bindgen_test::*;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+const HISTORY_KEY: &str = "adexHistory";
+const UNIT_ID: &str = "QmcUVX7fvoLMM93uN2bD3wGTH8MXSxeL8hojYfL2Lhp7mR";
+const SLOT_ID: &str = "Qmasg8FrbuSQpjFu3kRnZF9beg8rEBFrqgi1uXDRwCbX5f";
+const CAMPAIGN_ID: &str = "0x061d5e2a67d0a9a10f1c732bca12a676d83f79663a396f7d87b3e30b9b411088";
+
+fn options() -> JsValue {
+    to_js(&json!({
+        "marketURL": "https://market.adex.network",
+        "marketSlot": SLOT_ID,
+        "publisherAddr": "0xB7d3F81E857692d13e9D63b232A90F4A1793189E",
+        "whitelistedTokens": ["0x89d24A6b4CcB1B6fAA2625fE562bDD9a23260359"],
+        "disabledVideo": false,
+        "disabledSticky": false,
+    }))
+}
+
+/// The Market response with a single campaign, which wins the auction
+fn units_for_slot() -> Value {
+    json!({
+        "targetingInputBase": {
+            "adSlotId": SLOT_ID,
+            "adSlotType": "legacy_300x100",
+            "publisherId": "0xB7d3F81E857692d13e9D63b232A90F4A1793189E",
+            "country": "BG",
+            "eventType": "IMPRESSION",
+            "secondsSinceEpoch": 1_600_000_000,
+            "adSlot.categories": [],
+            "adSlot.hostname": "adex.network",
+        },
+        "acceptedReferrers": [],
+        "fallbackUnit": null,
+        "campaigns": [{
+            "id": CAMPAIGN_ID,
+            "creator": "0x033ed90e0fec3f3ea1c9b005c724d704501e0196",
+            "depositAsset": "0x89d24A6b4CcB1B6fAA2625fE562bDD9a23260359",
+            "depositAmount": "1000",
+            "spec": {
+                "withdrawPeriodStart": 4_073_414_400_000_u64,
+                "created": 1_600_000_000_000_u64,
+                "validators": [
+                    { "id": "0xce07CbB7e054514D590a0262C93070D838bFBA2e", "url": "http://localhost:8005", "fee": "100" },
+                    { "id": "0xC91763D7F14ac5c5dDfBCD012e0D2A61ab9bDED3", "url": "http://localhost:8006", "fee": "100" },
+                ],
+            },
+            "targetingRules": [],
+            "unitsWithPrice": [{
+                "unit": {
+                    "id": UNIT_ID,
+                    "mediaUrl": "ipfs://QmWWQSuPMS6aXCbZKpEjPHPUZN2NjB3YrhJTHsV4X3vb2t",
+                    "mediaMime": "image/jpeg",
+                    "targetUrl": "https://adex.network",
+                },
+                "price": "10",
+            }],
+        }],
+    })
+}
+
+/// Replaces the global `fetch`, so every request resolves with the `response` JSON
+fn mock_market(response: &Value) {
+    let fetch = Function::new_no_args(&format!(
+        "return Promise.resolve(new Response(JSON.stringify({}), {{ status: 200 }}));",
+        response
+    ));
+
+    Reflect::set(&js_sys::global(), &"fetch".into(), &fetch).expect("Should mock fetch");
+}
+
+fn local_storage() -> web_sys::Storage {
+    web_sys::window()
+        .and_then(|window| window.local_storage().ok()?)
+        .expect("Should have a localStorage")
+}
+
+fn to_js(value: &Value) -> JsValue {
+    JSON::parse(&value.to_string()).expect("Should parse the JSON")
+}
+
+fn from_js(value: &JsValue) -> Value {
+    let json = String::from(JSON::stringify(value).expect("Should stringify"));
+
+    serde_json::from_str(&json).expect("Should be valid JSON")
+}
+
+#[wasm_bindgen_test]
+async fn persists_the_history_of_the_next_ad_unit() {
+    local_storage()
+        .clear()
+        .expect("Should clear the localStorage");
+    mock_market(&units_for_slot());
+
+    let manager = JsManager::new(options()).expect("Should create the Manager");
+    let next_ad_unit = JsFuture::from(manager.get_next_ad_unit())
+        .await
+        .expect("Should get the next ad unit");
+
+    let next_ad_unit = from_js(&next_ad_unit);
+    assert_eq!(json!(UNIT_ID), next_ad_unit["unit"]["id"]);
+    assert_eq!(json!("10"), next_ad_unit["price"]);
+
+    let stored = local_storage()
+        .get_item(HISTORY_KEY)
+        .expect("Should read the localStorage")
+        .expect("Should store the history");
+    let stored: Value = serde_json::from_str(&stored).expect("Should be a valid history");
+    let history = from_js(
+        &JsFuture::from(manager.history())
+            .await
+            .expect("Should get the history"),
+    );
+
+    assert_eq!(history, stored);
+    assert_eq!(1, stored.as_array().map(Vec::len).unwrap_or_default());
+    assert_eq!(json!(UNIT_ID), stored[0]["unitId"]);
+    assert_eq!(json!(CAMPAIGN_ID), stored[0]["campaignId"]);
+    assert_eq!(json!(SLOT_ID), stored[0]["slotId"]);
+
+    // the history is loaded by a new `Manager`, so the impression is sticky
+    let manager = JsManager::new(options()).expect("Should create the Manager");
+    let history = from_js(
+        &JsFuture::from(manager.history())
+            .await
+            .expect("Should get the history"),
+    );
+    assert_eq!(stored, history);
+
+    let sticky_ad_unit = JsFuture::from(manager.get_next_ad_unit())
+        .await
+        .expect("Should get the next ad unit");
+    let sticky_ad_unit = from_js(&sticky_ad_unit);
+    assert_eq!(json!(UNIT_ID), sticky_ad_unit["unit"]["id"]);
+    assert_eq!(json!("0"), sticky_ad_unit["price"]);
+}
+
+#[wasm_bindgen_test]
+async fn ignores_an_invalid_stored_history() {
+    local_storage()
+        .set_item(HISTORY_KEY, "not a history")
+        .expect("Should write the localStorage");
+
+    let manager = JsManager::new(options()).expect("Should create the Manager");
+    let history = JsFuture::from(manager.history())
+        .await
+        .expect("Should get the history");
+
+    assert_eq!(json!([]), from_js(&history));
+}
@@ -0,0 +1,75 @@
+//! Compares evaluating the targeting rules of a channel for every event
+//! with `eval_with_callback` and with the `CompiledRules`
+use chrono::Utc;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use primitives::{
+    targeting::{
+        eval_with_callback, input, text::parse_rules, CompiledRules, Error, Input, Output, Rule,
+    },
+    util::tests::prep_db::{DUMMY_CHANNEL, IDS},
+};
+
+const RULES: &str = r#"
+    onlyShowIf(adSlotType in ["legacy_300x100", "legacy_728x90", "legacy_160x600"]);
+    onlyShowIf(country nin ["US", "CA", "GB"]);
+    set price.IMPRESSION = bn("1000000000000000") * 3n;
+    if eventType == "CLICK" and userAgentOS == "Android" then set price.CLICK = get("price.IMPRESSION") * 10n;
+    if userAgentOS in ["Windows", "Mac OS X"] or secondsSinceEpoch > 1600000000 then set boost = 1.5;
+    set price.IMPRESSION = max(get("price.IMPRESSION"), campaignBudget / 1000000n);
+    onlyShowIf(not startsWith(adSlotId, "blocked_") and between(1, 10, 5))
+"#;
+
+fn input() -> Input {
+    Input {
+        ad_view: None,
+        global: input::Global {
+            ad_slot_id: "QmcUVX7fvoLMM93uN2bD3wGTH8MXSxeL8hojYfL2Lhp7mR".to_string(),
+            ad_slot_type: "legacy_300x100".to_string(),
+            publisher_id: IDS["publisher"],
+            country: Some("BG".to_string()),
+            event_type: "IMPRESSION".to_string(),
+            seconds_since_epoch: Utc::now(),
+            user_agent_os: Some("Windows".to_string()),
+            user_agent_browser_family: None,
+        },
+        ad_unit_id: None,
+        channel: None,
+        balances: None,
+        ad_slot: None,
+        deposit_asset_price: None,
+    }
+    .with_channel(DUMMY_CHANNEL.clone())
+}
+
+fn on_type_error(error: Error, rule: Rule) {
+    black_box((error, rule));
+}
+
+fn targeting(c: &mut Criterion) {
+    let rules = parse_rules(RULES).expect("Should parse the rules");
+    let compiled = CompiledRules::new(&rules);
+    let input = input();
+
+    let mut group = c.benchmark_group("targeting");
+    group.bench_function("eval_with_callback", |b| {
+        b.iter(|| {
+            let mut output = Output::from(&*DUMMY_CHANNEL);
+            eval_with_callback(&rules, black_box(&input), &mut output, Some(on_type_error));
+            output
+        })
+    });
+    group.bench_function("compiled", |b| {
+        b.iter(|| {
+            let mut output = Output::from(&*DUMMY_CHANNEL);
+            compiled.eval_with_callback(black_box(&input), &mut output, Some(on_type_error));
+            output
+        })
+    });
+    group.bench_function("compile", |b| {
+        b.iter(|| CompiledRules::new(black_box(&rules)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, targeting);
+criterion_main!(benches);
@@ -1,6 +1,6 @@
 use crate::channel::ChannelError;
 use crate::channel_validator::ChannelValidator;
-use crate::{Channel, DomainError, ValidatorId};
+use crate::{ChainId, Channel, ChannelId, Config, DomainError, ValidatorId};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -51,8 +51,78 @@ impl<AE: AdapterErrorKind> From<DomainError> for Error<AE> {
     }
 }
 
+/// The scheme used for signing the state roots & the authentication tokens
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SignatureScheme {
+    /// The message is prefixed with `\x19Ethereum Signed Message:\n{length}`
+    EthSign,
+    /// [EIP-712](https://eips.ethereum.org/EIPS/eip-712) domain separated typed data
+    Eip712,
+}
+
+impl Default for SignatureScheme {
+    fn default() -> Self {
+        SignatureScheme::EthSign
+    }
+}
+
+impl SignatureScheme {
+    /// The scheme set in the `ChannelSpec`, otherwise the one from the `Config`
+    pub fn for_channel(channel: &Channel, config: &Config) -> Self {
+        channel
+            .spec
+            .signature_scheme
+            .unwrap_or(config.signature_scheme)
+    }
+}
+
+/// The state of a channel in the `AdExCore` contract
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelState {
+    /// The channel was never opened
+    Unknown,
+    Active,
+    Expired,
+}
+
+/// The lifecycle events of a channel, logged by the `AdExCore` contract
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelEvent {
+    Open,
+    Withdraw,
+    WithdrawExpired,
+}
+
+impl ChannelEvent {
+    /// The state of the channel after the event
+    pub fn state(&self) -> ChannelState {
+        match self {
+            ChannelEvent::Open | ChannelEvent::Withdraw => ChannelState::Active,
+            ChannelEvent::WithdrawExpired => ChannelState::Expired,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelLog {
+    pub channel_id: ChannelId,
+    pub event: ChannelEvent,
+    pub block_number: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChannelLogs {
+    /// The logs in the order they were emitted
+    pub logs: Vec<ChannelLog>,
+    /// The last block searched for logs, `None` when the `from_block` is not confirmed yet
+    pub to_block: Option<u64>,
+}
+
 pub struct DummyAdapterOptions {
     pub dummy_identity: ValidatorId,
+    /// Rotated out identities, which are still used for the channels validated by them
+    pub dummy_previous_identities: Vec<ValidatorId>,
     pub dummy_auth: HashMap<String, ValidatorId>,
     pub dummy_auth_tokens: HashMap<String, String>,
 }
@@ -63,6 +133,18 @@ pub struct KeystoreOptions {
     pub keystore_pwd: String,
 }
 
+#[derive(Debug, Clone)]
+pub struct RemoteSignerOptions {
+    /// The JSON-RPC endpoint of the remote signer
+    pub signer_url: String,
+    /// PKCS #12 archive with the client certificate & key used for mutual TLS
+    pub client_identity_file: Option<String>,
+    pub client_identity_pwd: String,
+    /// PEM encoded CA certificate for verifying the remote signer,
+    /// if it's not issued by one of the system trusted roots
+    pub ca_certificate_file: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
     pub era: i64,
@@ -74,17 +156,37 @@ pub trait Adapter: ChannelValidator + Send + Sync + fmt::Debug + Clone {
     type AdapterError: AdapterErrorKind + 'static;
 
     /// Unlock adapter
-    fn unlock(&mut self) -> AdapterResult<(), Self::AdapterError>;
-
-    /// Get Adapter whoami
-    fn whoami(&self) -> &ValidatorId;
+    async fn unlock(&mut self) -> AdapterResult<(), Self::AdapterError>;
+
+    /// All the identities of the Adapter, there is always at least one.
+    /// The first one is the current identity, the rest are rotated out identities
+    /// which are kept for the channels that are still validated by them.
+    fn identities(&self) -> &[ValidatorId];
+
+    /// Get Adapter whoami for the `channel`, i.e. the first of the `identities` which validates it.
+    /// If none of them validates the channel, the current identity is returned.
+    fn whoami(&self, channel: &Channel) -> &ValidatorId {
+        let identities = self.identities();
+
+        identities
+            .iter()
+            .find(|identity| channel.spec.validators.find(identity).is_some())
+            .unwrap_or(&identities[0])
+    }
 
-    /// Signs the provided state_root
-    fn sign(&self, state_root: &str) -> AdapterResult<String, Self::AdapterError>;
+    /// Signs the provided state_root of the `channel` with the key of `whoami(channel)`,
+    /// using the `SignatureScheme` of the channel
+    async fn sign<'a>(
+        &'a self,
+        channel: &'a Channel,
+        state_root: &'a str,
+    ) -> AdapterResult<String, Self::AdapterError>;
 
-    /// Verify, based on the signature & state_root, that the signer is the same
+    /// Verify, based on the signature & state_root of the `channel`, that the signer is the same.
+    /// Only signatures of the `SignatureScheme` of the channel are accepted.
     fn verify(
         &self,
+        channel: &Channel,
         signer: &ValidatorId,
         state_root: &str,
         signature: &str,
@@ -96,12 +198,33 @@ pub trait Adapter: ChannelValidator + Send + Sync + fmt::Debug + Clone {
         channel: &'a Channel,
     ) -> AdapterResult<bool, Self::AdapterError>;
 
+    /// The on-chain states of the channels on the `chain_id` chain, looked up in bulk.
+    /// Every one of the `channels` is present in the returned map.
+    async fn channel_states<'a>(
+        &'a self,
+        chain_id: ChainId,
+        channels: &'a [ChannelId],
+    ) -> AdapterResult<HashMap<ChannelId, ChannelState>, Self::AdapterError>;
+
+    /// The channel lifecycle logs on the `chain_id` chain from the `from_block` (inclusive) on.
+    /// Only confirmed blocks are searched and the search range might be limited,
+    /// so it should be repeated from the block after `to_block` until there is no `to_block`.
+    async fn channel_logs(
+        &self,
+        chain_id: ChainId,
+        from_block: u64,
+    ) -> AdapterResult<ChannelLogs, Self::AdapterError>;
+
     /// Get user session from token
     async fn session_from_token<'a>(
         &'a self,
         token: &'a str,
     ) -> AdapterResult<Session, Self::AdapterError>;
 
-    /// Gets authentication for specific validator
-    fn get_auth(&self, validator_id: &ValidatorId) -> AdapterResult<String, Self::AdapterError>;
+    /// Gets authentication as `whoami(channel)` for specific validator of the `channel`
+    async fn get_auth<'a>(
+        &'a self,
+        channel: &'a Channel,
+        validator_id: &'a ValidatorId,
+    ) -> AdapterResult<String, Self::AdapterError>;
 }
@@ -1,6 +1,9 @@
 use crate::ChannelId;
 use crate::DomainError;
+use chrono::{serde::ts_milliseconds_option, DateTime, Duration, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
 
 pub const ANALYTICS_QUERY_LIMIT: u32 = 200;
 
@@ -47,6 +50,29 @@ pub struct AnalyticsQuery {
     #[serde(default = "default_timeframe")]
     pub timeframe: String,
     pub segment_by_channel: Option<String>,
+    /// The start of a custom range (inclusive), a timestamp in **milliseconds**.
+    /// Defaults to `end` minus the period of the `timeframe`
+    #[serde(default, with = "ts_milliseconds_option")]
+    pub start: Option<DateTime<Utc>>,
+    /// The end of a custom range (exclusive), a timestamp in **milliseconds**. Defaults to now
+    #[serde(default, with = "ts_milliseconds_option")]
+    pub end: Option<DateTime<Utc>>,
+    /// The size of the time buckets, a number of minutes, hours or days, e.g. `15m`, `6h` or `1d`.
+    /// Defaults to the bucket size of the `timeframe`
+    pub bucket: Option<String>,
+    /// The IANA timezone in which the buckets are aligned, e.g. `1d` buckets start at its midnight.
+    /// Defaults to `UTC`
+    pub timezone: Option<String>,
+}
+
+/// The time range and buckets of an `AnalyticsQuery`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeFrame {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// The bucket size in seconds
+    pub bucket: i64,
+    pub timezone: Tz,
 }
 
 impl AnalyticsQuery {
@@ -75,6 +101,165 @@ impl AnalyticsQuery {
                 "invalid limit {}, maximum value 200",
                 self.limit
             )))
+        } else {
+            self.time_frame(Utc::now()).map(|_| ())
+        }
+    }
+
+    /// Only `start`, `end`, `bucket` & `timezone` are validated,
+    /// the `timeframe` provides the defaults for the first three
+    pub fn time_frame(&self, now: DateTime<Utc>) -> Result<TimeFrame, DomainError> {
+        let (timeframe_bucket, period) = timeframe_bucket_and_period(&self.timeframe)
+            .ok_or_else(|| DomainError::InvalidArgument("invalid timeframe".to_string()))?;
+
+        let end = self.end.unwrap_or(now);
+        let start = self
+            .start
+            .unwrap_or_else(|| end - Duration::seconds(period));
+        let bucket = match &self.bucket {
+            Some(bucket) => parse_bucket(bucket).ok_or_else(|| {
+                DomainError::InvalidArgument(format!(
+                    "invalid bucket {}, it should be a number of minutes, hours or days, e.g. 15m, 6h or 1d",
+                    bucket
+                ))
+            })?,
+            None => timeframe_bucket,
+        };
+        let timezone = match &self.timezone {
+            Some(timezone) => timezone.parse::<Tz>().map_err(|_| {
+                DomainError::InvalidArgument(format!("invalid timezone {}", timezone))
+            })?,
+            None => Tz::UTC,
+        };
+
+        if start >= end {
+            return Err(DomainError::InvalidArgument(
+                "invalid range, start should be before end".to_string(),
+            ));
+        }
+
+        // the first and last buckets can be partial
+        let buckets = (end - start).num_seconds() / bucket + 2;
+        if buckets > ANALYTICS_QUERY_LIMIT.into() {
+            return Err(DomainError::InvalidArgument(format!(
+                "invalid range, it contains {} buckets, maximum value {}",
+                buckets, ANALYTICS_QUERY_LIMIT
+            )));
+        }
+
+        Ok(TimeFrame {
+            start,
+            end,
+            bucket,
+            timezone,
+        })
+    }
+}
+
+/// The bucket size and the period of the `timeframe`, in seconds
+fn timeframe_bucket_and_period(timeframe: &str) -> Option<(i64, i64)> {
+    let minute = 60;
+    let hour = 60 * minute;
+    let day = 24 * hour;
+
+    match timeframe {
+        "year" => Some((30 * day, 365 * day)),
+        "month" => Some((day, 30 * day)),
+        "week" => Some((6 * hour, 7 * day)),
+        "day" => Some((hour, day)),
+        "hour" => Some((minute, hour)),
+        _ => None,
+    }
+}
+
+/// A bucket of e.g. `15m`, `6h` or `1d` in seconds
+fn parse_bucket(bucket: &str) -> Option<i64> {
+    let unit = match bucket.chars().last()? {
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return None,
+    };
+    let amount = bucket[..bucket.len() - 1]
+        .parse::<i64>()
+        .ok()
+        .filter(|amount| *amount > 0)?;
+
+    amount.checked_mul(unit)
+}
+
+/// The breakdowns of the events kept in the hourly analytics rollup
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum AnalyticsDimension {
+    AdUnit,
+    AdSlot,
+    Country,
+    Hostname,
+}
+
+impl AnalyticsDimension {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnalyticsDimension::AdUnit => "adUnit",
+            AnalyticsDimension::AdSlot => "adSlot",
+            AnalyticsDimension::Country => "country",
+            AnalyticsDimension::Hostname => "hostname",
+        }
+    }
+}
+
+impl fmt::Display for AnalyticsDimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AnalyticsDimension {
+    type Err = DomainError;
+
+    fn from_str(dimension: &str) -> Result<Self, Self::Err> {
+        match dimension {
+            "adUnit" => Ok(AnalyticsDimension::AdUnit),
+            "adSlot" => Ok(AnalyticsDimension::AdSlot),
+            "country" => Ok(AnalyticsDimension::Country),
+            "hostname" => Ok(AnalyticsDimension::Hostname),
+            _ => Err(DomainError::InvalidArgument(format!(
+                "invalid dimension {}",
+                dimension
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdvancedAnalyticsQuery {
+    #[serde(default = "default_event_type")]
+    pub event_type: String,
+    /// The start of the range (inclusive), a timestamp in **milliseconds**. All-time if not set
+    #[serde(default, with = "ts_milliseconds_option")]
+    pub start: Option<DateTime<Utc>>,
+    /// The end of the range (exclusive), a timestamp in **milliseconds**
+    #[serde(default, with = "ts_milliseconds_option")]
+    pub end: Option<DateTime<Utc>>,
+    /// Only the reports of this dimension
+    pub dimension: Option<AnalyticsDimension>,
+}
+
+impl AdvancedAnalyticsQuery {
+    pub fn is_valid(&self) -> Result<(), DomainError> {
+        let valid_event_types = ["IMPRESSION", "CLICK"];
+
+        if !valid_event_types.contains(&self.event_type.as_str()) {
+            Err(DomainError::InvalidArgument(format!(
+                "invalid event_type, possible values are: {}",
+                valid_event_types.join(" ,")
+            )))
+        } else if matches!((self.start, self.end), (Some(start), Some(end)) if start >= end) {
+            Err(DomainError::InvalidArgument(
+                "invalid range, start should be before end".to_string(),
+            ))
         } else {
             Ok(())
         }
@@ -96,3 +281,91 @@ fn default_metric() -> String {
 fn default_timeframe() -> String {
     "hour".into()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn query(timeframe: &str) -> AnalyticsQuery {
+        AnalyticsQuery {
+            limit: default_limit(),
+            event_type: default_event_type(),
+            metric: default_metric(),
+            timeframe: timeframe.to_string(),
+            segment_by_channel: None,
+            start: None,
+            end: None,
+            bucket: None,
+            timezone: None,
+        }
+    }
+
+    #[test]
+    fn time_frame_defaults_to_the_timeframe() {
+        let now = Utc.ymd(2020, 10, 10).and_hms(12, 0, 0);
+        let time_frame = query("week").time_frame(now).expect("Should be valid");
+
+        assert_eq!(
+            TimeFrame {
+                start: Utc.ymd(2020, 10, 3).and_hms(12, 0, 0),
+                end: now,
+                bucket: 6 * 60 * 60,
+                timezone: Tz::UTC,
+            },
+            time_frame
+        );
+    }
+
+    #[test]
+    fn custom_range_bucket_and_timezone() {
+        let now = Utc.ymd(2020, 10, 10).and_hms(12, 0, 0);
+        let query = AnalyticsQuery {
+            start: Some(Utc.ymd(2020, 9, 1).and_hms(0, 0, 0)),
+            end: Some(Utc.ymd(2020, 10, 1).and_hms(0, 0, 0)),
+            bucket: Some("1d".to_string()),
+            timezone: Some("Europe/Sofia".to_string()),
+            ..query("hour")
+        };
+        let time_frame = query.time_frame(now).expect("Should be valid");
+
+        assert_eq!(Utc.ymd(2020, 9, 1).and_hms(0, 0, 0), time_frame.start);
+        assert_eq!(Utc.ymd(2020, 10, 1).and_hms(0, 0, 0), time_frame.end);
+        assert_eq!(24 * 60 * 60, time_frame.bucket);
+        assert_eq!(Tz::Europe__Sofia, time_frame.timezone);
+        assert!(query.is_valid().is_ok());
+    }
+
+    #[test]
+    fn invalid_time_frames() {
+        let now = Utc.ymd(2020, 10, 10).and_hms(12, 0, 0);
+
+        for bucket in &["", "m", "0h", "-1d", "15s", "1.5h"] {
+            let query = AnalyticsQuery {
+                bucket: Some(bucket.to_string()),
+                ..query("day")
+            };
+            assert!(query.time_frame(now).is_err(), "bucket {}", bucket);
+        }
+
+        let invalid_timezone = AnalyticsQuery {
+            timezone: Some("Mars/Olympus_Mons".to_string()),
+            ..query("day")
+        };
+        assert!(invalid_timezone.time_frame(now).is_err());
+
+        let reversed = AnalyticsQuery {
+            start: Some(now),
+            end: Some(now - Duration::hours(1)),
+            ..query("day")
+        };
+        assert!(reversed.time_frame(now).is_err());
+
+        let too_many_buckets = AnalyticsQuery {
+            start: Some(now - Duration::days(365)),
+            bucket: Some("1h".to_string()),
+            ..query("day")
+        };
+        assert!(too_many_buckets.time_frame(now).is_err());
+    }
+}
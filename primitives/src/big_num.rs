@@ -245,21 +245,113 @@ where
 #[cfg(feature = "postgres")]
 pub mod postgres {
     use super::BigNum;
-    use bytes::BytesMut;
-    use postgres_types::{FromSql, IsNull, ToSql, Type};
+    use bytes::{Buf, BufMut, BytesMut};
+    use num::BigUint;
+    use postgres_types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
     use std::error::Error;
+    use std::str::FromStr;
+
+    /// The `NUMERIC` digits are in base 10 000, i.e. 4 decimal digits each
+    const NUMERIC_DIGIT_LEN: usize = 4;
+    const NUMERIC_POS: u16 = 0x0000;
+
+    /// Decodes the binary `NUMERIC` format: the number of digits, the weight of the first digit,
+    /// the sign and the display scale, followed by the base 10 000 digits.
+    /// Only non-negative integers are valid amounts.
+    fn from_numeric(mut raw: &[u8]) -> Result<BigNum, Box<dyn Error + Sync + Send>> {
+        if raw.len() < 8 {
+            return Err("invalid NUMERIC, the header is too short".into());
+        }
+
+        let ndigits = raw.get_i16();
+        let weight = raw.get_i16();
+        let sign = raw.get_u16();
+        let _dscale = raw.get_u16();
+
+        if sign != NUMERIC_POS {
+            return Err("invalid NUMERIC, only non-negative amounts are supported".into());
+        }
+        if ndigits < 0 || raw.len() != ndigits as usize * 2 {
+            return Err("invalid NUMERIC, the digits don't match their count".into());
+        }
+
+        let mut decimal = String::with_capacity((ndigits as usize + 1) * NUMERIC_DIGIT_LEN);
+        for position in 0..ndigits {
+            let digit = raw.get_u16();
+
+            if i32::from(position) > i32::from(weight) {
+                // the fraction, which is only valid if it's zero
+                if digit != 0 {
+                    return Err("invalid NUMERIC, only integer amounts are supported".into());
+                }
+            } else {
+                decimal.push_str(&format!("{:04}", digit));
+            }
+        }
+        // the trailing zero digits aren't stored
+        for _ in ndigits..weight.saturating_add(1) {
+            decimal.push_str("0000");
+        }
+
+        if decimal.is_empty() {
+            return Ok(BigNum::from(0));
+        }
+
+        Ok(BigNum(BigUint::from_str(&decimal)?))
+    }
+
+    fn to_numeric(
+        big_num: &BigNum,
+        w: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        let decimal = big_num.0.to_str_radix(10);
+        // left-pad the decimal to whole base 10 000 digits
+        let padding = (NUMERIC_DIGIT_LEN - decimal.len() % NUMERIC_DIGIT_LEN) % NUMERIC_DIGIT_LEN;
+        let padded = format!("{}{}", "0".repeat(padding), decimal);
+
+        let mut digits = padded
+            .as_bytes()
+            .chunks(NUMERIC_DIGIT_LEN)
+            .map(|chunk| {
+                std::str::from_utf8(chunk)
+                    .expect("Should be ASCII digits")
+                    .parse::<u16>()
+                    .expect("Should be a base 10 000 digit")
+            })
+            .collect::<Vec<_>>();
+
+        let weight = digits.len() as i16 - 1;
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+
+        w.put_i16(digits.len() as i16);
+        // zero has no digits and a weight of 0
+        w.put_i16(if digits.is_empty() { 0 } else { weight });
+        w.put_u16(NUMERIC_POS);
+        w.put_u16(0);
+        for digit in digits {
+            w.put_u16(digit);
+        }
+
+        Ok(IsNull::No)
+    }
 
     impl<'a> FromSql<'a> for BigNum {
         fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<BigNum, Box<dyn Error + Sync + Send>> {
             use std::convert::TryInto;
 
+            if *ty == Type::NUMERIC {
+                return from_numeric(raw);
+            }
+
             let str_slice = <&str as FromSql>::from_sql(ty, raw)?;
 
             Ok(str_slice.try_into()?)
         }
 
         fn accepts(ty: &Type) -> bool {
-            matches!(*ty, Type::TEXT | Type::VARCHAR)
+            matches!(*ty, Type::TEXT | Type::VARCHAR | Type::NUMERIC)
         }
     }
 
@@ -269,19 +361,84 @@ pub mod postgres {
             ty: &Type,
             w: &mut BytesMut,
         ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+            if *ty == Type::NUMERIC {
+                return to_numeric(self, w);
+            }
+
             <String as ToSql>::to_sql(&self.0.to_string(), ty, w)
         }
 
         fn accepts(ty: &Type) -> bool {
-            matches!(*ty, Type::TEXT | Type::VARCHAR)
+            matches!(*ty, Type::TEXT | Type::VARCHAR | Type::NUMERIC)
         }
 
-        fn to_sql_checked(
-            &self,
-            ty: &Type,
-            out: &mut BytesMut,
-        ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
-            <String as ToSql>::to_sql_checked(&self.0.to_string(), ty, out)
+        to_sql_checked!();
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        fn round_trip(big_num: &BigNum) -> BigNum {
+            let mut buf = BytesMut::new();
+            big_num
+                .to_sql(&Type::NUMERIC, &mut buf)
+                .expect("Should encode");
+
+            BigNum::from_sql(&Type::NUMERIC, &buf).expect("Should decode")
+        }
+
+        #[test]
+        fn numeric_encoding() {
+            let mut buf = BytesMut::new();
+            BigNum::from(10_000)
+                .to_sql(&Type::NUMERIC, &mut buf)
+                .expect("Should encode");
+            // a single digit `1` with a weight of 1, i.e. 1 * 10 000^1
+            assert_eq!(&[0, 1, 0, 1, 0, 0, 0, 0, 0, 1][..], &buf[..]);
+
+            let mut buf = BytesMut::new();
+            BigNum::from(0)
+                .to_sql(&Type::NUMERIC, &mut buf)
+                .expect("Should encode");
+            assert_eq!(&[0, 0, 0, 0, 0, 0, 0, 0][..], &buf[..]);
+        }
+
+        #[test]
+        fn numeric_round_trip() {
+            let max_uint256 = BigNum::from_str(
+                "115792089237316195423570985008687907853269984665640564039457584007913129639935",
+            )
+            .expect("Should parse");
+
+            for big_num in vec![
+                BigNum::from(0),
+                BigNum::from(1),
+                BigNum::from(10_000),
+                BigNum::from(123_456_789),
+                BigNum::from(10u64.pow(18)),
+                max_uint256,
+            ] {
+                assert_eq!(big_num, round_trip(&big_num));
+            }
+        }
+
+        #[test]
+        fn numeric_with_a_zero_fraction() {
+            // 12.0000 with a display scale of 4
+            let raw = [0, 2, 0, 0, 0, 0, 0, 4, 0, 12, 0, 0];
+            assert_eq!(
+                BigNum::from(12),
+                BigNum::from_sql(&Type::NUMERIC, &raw).expect("Should decode")
+            );
+
+            // 12.5
+            let raw = [0, 2, 0, 0, 0, 0, 0, 1, 0, 12, 19, 136];
+            assert!(BigNum::from_sql(&Type::NUMERIC, &raw).is_err());
+
+            // -12
+            let raw = [0, 1, 0, 0, 0x40, 0, 0, 0, 0, 12];
+            assert!(BigNum::from_sql(&Type::NUMERIC, &raw).is_err());
         }
     }
 }
@@ -8,7 +8,10 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_hex::{SerHex, StrictPfx};
 
-use crate::{targeting::Rules, AdUnit, BigNum, EventSubmission, ValidatorDesc, ValidatorId};
+use crate::{
+    adapter::SignatureScheme, targeting::Rules, AdUnit, BigNum, EventSubmission, ValidatorDesc,
+    ValidatorId,
+};
 use hex::{FromHex, FromHexError};
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Copy, Clone, Hash)]
@@ -86,10 +89,49 @@ impl FromStr for ChannelId {
     }
 }
 
+/// The [EIP-155](https://eips.ethereum.org/EIPS/eip-155) chain ID of the network the channel is opened on
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Hash)]
+#[serde(transparent)]
+pub struct ChainId(u64);
+
+impl ChainId {
+    /// Ethereum mainnet, the chain of the channels which don't specify one
+    pub const MAINNET: ChainId = ChainId(1);
+
+    pub fn new(chain_id: u64) -> Self {
+        Self(chain_id)
+    }
+
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for ChainId {
+    fn default() -> Self {
+        Self::MAINNET
+    }
+}
+
+impl From<u64> for ChainId {
+    fn from(chain_id: u64) -> Self {
+        Self(chain_id)
+    }
+}
+
+impl fmt::Display for ChainId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct Channel {
     pub id: ChannelId,
+    /// The chain of the `AdExCore` contract the channel is opened with
+    #[serde(default)]
+    pub chain_id: ChainId,
     pub creator: ValidatorId,
     pub deposit_asset: String,
     pub deposit_amount: BigNum,
@@ -188,6 +230,10 @@ pub struct ChannelSpec {
     pub ad_units: Vec<AdUnit>,
     #[serde(default)]
     pub targeting_rules: Rules,
+    /// The scheme used by the validators for signing the state & authenticating (optional).
+    /// If missing, the scheme from the validator `Config` is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature_scheme: Option<SignatureScheme>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
@@ -308,6 +354,8 @@ pub enum ChannelError {
     UnlistedValidator,
     UnlistedCreator,
     UnlistedAsset,
+    /// When the `channel.chain_id` is not one of the configured chains
+    UnsupportedChain(ChainId),
     MinimumDepositNotMet,
     MinimumValidatorFeeNotMet,
     FeeConstraintViolated,
@@ -322,6 +370,9 @@ impl fmt::Display for ChannelError {
             ChannelError::UnlistedValidator => write!(f, "validators are not in the whitelist"),
             ChannelError::UnlistedCreator => write!(f, "channel.creator is not whitelisted"),
             ChannelError::UnlistedAsset => write!(f, "channel.depositAsset is not whitelisted"),
+            ChannelError::UnsupportedChain(chain_id) => {
+                write!(f, "channel.chainId ({}) is not supported", chain_id)
+            }
             ChannelError::MinimumDepositNotMet => {
                 write!(f, "channel.depositAmount is less than MINIMAL_DEPOSIT")
             }
@@ -384,12 +435,13 @@ mod test {
 
 #[cfg(feature = "postgres")]
 pub mod postgres {
-    use super::ChannelId;
+    use super::{ChainId, ChannelId};
     use super::{Channel, ChannelSpec};
     use crate::targeting::Rules;
     use bytes::BytesMut;
     use hex::FromHex;
     use postgres_types::{accepts, to_sql_checked, FromSql, IsNull, Json, ToSql, Type};
+    use std::convert::TryFrom;
     use std::error::Error;
     use tokio_postgres::Row;
 
@@ -397,6 +449,7 @@ pub mod postgres {
         fn from(row: &Row) -> Self {
             Self {
                 id: row.get("id"),
+                chain_id: row.get("chain_id"),
                 creator: row.get("creator"),
                 deposit_asset: row.get("deposit_asset"),
                 deposit_amount: row.get("deposit_amount"),
@@ -442,6 +495,29 @@ pub mod postgres {
         to_sql_checked!();
     }
 
+    impl<'a> FromSql<'a> for ChainId {
+        fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+            let chain_id = <i64 as FromSql>::from_sql(ty, raw)?;
+
+            Ok(ChainId(u64::try_from(chain_id)?))
+        }
+
+        accepts!(INT8);
+    }
+
+    impl ToSql for ChainId {
+        fn to_sql(
+            &self,
+            ty: &Type,
+            w: &mut BytesMut,
+        ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+            <i64 as ToSql>::to_sql(&i64::try_from(self.0)?, ty, w)
+        }
+
+        accepts!(INT8);
+        to_sql_checked!();
+    }
+
     impl ToSql for ChannelSpec {
         fn to_sql(
             &self,
@@ -454,4 +530,31 @@ pub mod postgres {
         accepts!(JSONB);
         to_sql_checked!();
     }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn chain_id_out_of_the_int8_range() {
+            let mut buf = BytesMut::new();
+            ChainId::new(5)
+                .to_sql(&Type::INT8, &mut buf)
+                .expect("Should encode");
+            assert_eq!(
+                ChainId::new(5),
+                ChainId::from_sql(&Type::INT8, &buf).expect("Should decode")
+            );
+
+            assert!(ChainId::new(u64::MAX)
+                .to_sql(&Type::INT8, &mut BytesMut::new())
+                .is_err());
+
+            let mut buf = BytesMut::new();
+            (-1_i64)
+                .to_sql(&Type::INT8, &mut buf)
+                .expect("Should encode");
+            assert!(ChainId::from_sql(&Type::INT8, &buf).is_err());
+        }
+    }
 }
@@ -46,7 +46,11 @@ pub trait ChannelValidator {
             return Err(ChannelError::UnlistedCreator);
         }
 
-        if !asset_listed(&channel, &config.token_address_whitelist) {
+        let chain = config
+            .chain(channel.chain_id)
+            .ok_or(ChannelError::UnsupportedChain(channel.chain_id))?;
+
+        if !asset_listed(&channel, &chain.token_address_whitelist) {
             return Err(ChannelError::UnlistedAsset);
         }
 
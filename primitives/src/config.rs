@@ -1,17 +1,19 @@
+use crate::adapter::SignatureScheme;
 use crate::event_submission::RateLimit;
-use crate::{BigNum, ValidatorId};
+use crate::token::{default_tokens, TokenInfo};
+use crate::{BigNum, ChainId, ValidatorId};
+use hex::FromHex;
 use lazy_static::lazy_static;
-use serde::{Deserialize, Serialize};
+use serde::{de::Error as _, Deserialize, Serialize};
 use serde_hex::{SerHex, StrictPfx};
 use std::fs;
+use url::Url;
 
 lazy_static! {
-    static ref DEVELOPMENT_CONFIG: Config =
-        toml::from_str(include_str!("../../docs/config/dev.toml"))
-            .expect("Failed to parse dev.toml config file");
-    static ref PRODUCTION_CONFIG: Config =
-        toml::from_str(include_str!("../../docs/config/prod.toml"))
-            .expect("Failed to parse prod.toml config file");
+    static ref DEVELOPMENT_CONFIG: Config = parse(include_str!("../../docs/config/dev.toml"))
+        .expect("Failed to parse dev.toml config file");
+    static ref PRODUCTION_CONFIG: Config = parse(include_str!("../../docs/config/prod.toml"))
+        .expect("Failed to parse prod.toml config file");
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -34,12 +36,154 @@ pub struct Config {
     pub creators_whitelist: Vec<ValidatorId>,
     pub minimal_deposit: BigNum,
     pub minimal_fee: BigNum,
-    pub token_address_whitelist: Vec<String>,
-    #[serde(with = "SerHex::<StrictPfx>")]
-    pub ethereum_core_address: [u8; 20],
-    pub ethereum_network: String,
     pub ethereum_adapter_relayer: String,
+    #[serde(default = "default_channel_state_cache_ttl")]
+    pub channel_state_cache_ttl: u32, // in milliseconds
+    #[serde(default = "default_ethereum_sync_interval")]
+    pub ethereum_sync_interval: u32, // in milliseconds
+    /// How often the analytics buffer is flushed to Postgres, in milliseconds
+    #[serde(default = "default_analytics_flush_interval")]
+    pub analytics_flush_interval: u32,
+    /// How many days the approved event aggregates are kept before they're compacted into daily summaries
+    #[serde(default = "default_event_aggregates_retention_days")]
+    pub event_aggregates_retention_days: u32,
+    /// How often the event aggregates are compacted, in milliseconds
+    #[serde(default = "default_event_aggregates_compaction_interval")]
+    pub event_aggregates_compaction_interval: u32,
     pub validators_whitelist: Vec<ValidatorId>,
+    /// The scheme used for the channels without a `SignatureScheme` in their spec
+    #[serde(default)]
+    pub signature_scheme: SignatureScheme,
+    /// The chains on which channels are validated
+    #[serde(default)]
+    pub chains: Vec<ChainConfig>,
+    /// The known deposit assets, used for converting amounts to USD,
+    /// the `default_tokens` if not set
+    #[serde(default = "default_tokens")]
+    pub tokens: Vec<TokenInfo>,
+    /// A JSON file with the USD prices of the tokens with `PriceSource::Feed`
+    #[serde(default)]
+    pub price_feed_file: Option<String>,
+    /// The built-in supermarket answering `units-for-slot`, disabled if not set
+    #[serde(default)]
+    pub supermarket: Option<SupermarketConfig>,
+    /// Only read from the configs without `chains`
+    #[serde(flatten, skip_serializing)]
+    legacy_chain: LegacyChainConfig,
+}
+
+impl Config {
+    /// The configuration of the chain with `chain_id`, if it's supported
+    pub fn chain(&self, chain_id: ChainId) -> Option<&ChainConfig> {
+        self.chains.iter().find(|chain| chain.chain_id == chain_id)
+    }
+}
+
+// The defaults of the fields which were added later, so the older configs are still valid
+
+fn default_channel_state_cache_ttl() -> u32 {
+    60_000
+}
+
+fn default_ethereum_sync_interval() -> u32 {
+    60_000
+}
+
+fn default_analytics_flush_interval() -> u32 {
+    60_000
+}
+
+fn default_event_aggregates_retention_days() -> u32 {
+    90
+}
+
+fn default_event_aggregates_compaction_interval() -> u32 {
+    3_600_000
+}
+
+fn default_confirmations() -> u64 {
+    12
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all(serialize = "SCREAMING_SNAKE_CASE"))]
+pub struct ChainConfig {
+    pub chain_id: ChainId,
+    /// The JSON-RPC endpoint of an ethereum node of the chain
+    pub rpc: String,
+    #[serde(with = "SerHex::<StrictPfx>")]
+    pub core_address: [u8; 20],
+    pub token_address_whitelist: Vec<String>,
+    /// The on-chain channel states are read this many blocks behind the latest one
+    #[serde(default = "default_confirmations")]
+    pub confirmations: u64,
+    /// The first block searched for channel logs by the Sentry chain watcher
+    #[serde(default)]
+    pub sync_from_block: u64,
+}
+
+/// The single chain of the configs written before `chains`, which is always chain `1`
+#[derive(Deserialize, Debug, Clone, Default)]
+struct LegacyChainConfig {
+    #[serde(default)]
+    ethereum_network: Option<String>,
+    #[serde(default)]
+    ethereum_core_address: Option<String>,
+    #[serde(default)]
+    token_address_whitelist: Vec<String>,
+    #[serde(default)]
+    ethereum_confirmations: Option<u64>,
+    #[serde(default)]
+    ethereum_sync_from_block: Option<u64>,
+}
+
+impl LegacyChainConfig {
+    /// `None` if the config doesn't have the legacy chain fields
+    fn into_chain(self) -> Result<Option<ChainConfig>, toml::de::Error> {
+        let (rpc, core_address) = match (self.ethereum_network, self.ethereum_core_address) {
+            (Some(rpc), Some(core_address)) => (rpc, core_address),
+            _ => return Ok(None),
+        };
+
+        let core_address = <[u8; 20]>::from_hex(core_address.trim_start_matches("0x"))
+            .map_err(|err| toml::de::Error::custom(format!("ethereum_core_address: {}", err)))?;
+
+        Ok(Some(ChainConfig {
+            chain_id: ChainId::MAINNET,
+            rpc,
+            core_address,
+            token_address_whitelist: self.token_address_whitelist,
+            confirmations: self
+                .ethereum_confirmations
+                .unwrap_or_else(default_confirmations),
+            sync_from_block: self.ethereum_sync_from_block.unwrap_or_default(),
+        }))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all(serialize = "SCREAMING_SNAKE_CASE"))]
+pub struct SupermarketConfig {
+    /// The market from which the ad slots and their fallback ad units are fetched
+    pub market_url: Url,
+    /// The other sentries from which campaigns are aggregated, besides the channels of this one
+    pub sentry_urls: Vec<Url>,
+    /// How long (in milliseconds) the aggregated campaigns are cached
+    pub campaigns_cache_ttl: u32,
+    /// The timeout (in milliseconds) of the requests to the market and the other sentries
+    #[serde(default = "default_supermarket_fetch_timeout")]
+    pub fetch_timeout: u32,
+    /// The timeout (in milliseconds) of connecting to the market and the other sentries
+    #[serde(default = "default_supermarket_connect_timeout")]
+    pub connect_timeout: u32,
+}
+
+fn default_supermarket_fetch_timeout() -> u32 {
+    10_000
+}
+
+fn default_supermarket_connect_timeout() -> u32 {
+    3_000
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -47,13 +191,28 @@ pub enum ConfigError {
     InvalidFile(String),
 }
 
+/// Parses the config, turning the single chain of the older configs into `chains`
+fn parse(config: &str) -> Result<Config, toml::de::Error> {
+    let mut config: Config = toml::from_str(config)?;
+    let legacy_chain = std::mem::take(&mut config.legacy_chain).into_chain()?;
+
+    if config.chains.is_empty() {
+        config.chains.extend(legacy_chain);
+    }
+
+    if config.chains.is_empty() {
+        return Err(toml::de::Error::custom(
+            "missing `chains`, or `ethereum_network` and `ethereum_core_address` for a single chain",
+        ));
+    }
+
+    Ok(config)
+}
+
 pub fn configuration(environment: &str, config_file: Option<&str>) -> Result<Config, ConfigError> {
     match config_file {
         Some(config_file) => match fs::read_to_string(config_file) {
-            Ok(config) => match toml::from_str(&config) {
-                Ok(data) => data,
-                Err(e) => Err(ConfigError::InvalidFile(e.to_string())),
-            },
+            Ok(config) => parse(&config).map_err(|e| ConfigError::InvalidFile(e.to_string())),
             Err(e) => Err(ConfigError::InvalidFile(format!(
                 "Unable to read provided config file {} {}",
                 config_file, e
@@ -65,3 +224,71 @@ pub fn configuration(environment: &str, config_file: Option<&str>) -> Result<Con
         },
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The `docs/config` files from before `chains` and the other added fields
+    const OLDER_PROD: &str = include_str!("../test/resources/older_prod.toml");
+    const OLDER_DEV: &str = include_str!("../test/resources/older_dev.toml");
+
+    #[test]
+    fn parses_the_configs_written_before_chains() {
+        let prod = parse(OLDER_PROD).expect("Should parse the older prod.toml");
+        let dev = parse(OLDER_DEV).expect("Should parse the older dev.toml");
+
+        for config in [&prod, &dev].iter() {
+            assert_eq!(1, config.chains.len());
+
+            let chain = &config.chains[0];
+            assert_eq!(ChainId::MAINNET, chain.chain_id);
+            assert_eq!("http://localhost:8545", chain.rpc);
+            assert_eq!(
+                "333420fc6a897356e69b62417cd17ff012177d2b",
+                hex::encode(chain.core_address)
+            );
+            assert_eq!(12, chain.confirmations);
+            assert_eq!(0, chain.sync_from_block);
+
+            assert_eq!(60_000, config.channel_state_cache_ttl);
+            assert_eq!(60_000, config.ethereum_sync_interval);
+            assert_eq!(60_000, config.analytics_flush_interval);
+            assert_eq!(90, config.event_aggregates_retention_days);
+            assert_eq!(3_600_000, config.event_aggregates_compaction_interval);
+            assert_eq!(default_tokens(), config.tokens);
+        }
+
+        assert_eq!(
+            vec![
+                "0x89d24A6b4CcB1B6fAA2625fE562bDD9a23260359".to_string(),
+                "0x6B175474E89094C44Da98b954EedeAC495271d0F".to_string(),
+            ],
+            prod.chains[0].token_address_whitelist
+        );
+        assert!(dev.chains[0].token_address_whitelist.is_empty());
+    }
+
+    #[test]
+    fn prefers_the_chains_over_the_single_chain_fields() {
+        let config = format!(
+            "ethereum_network = 'http://legacy:8545'\n{}",
+            include_str!("../../docs/config/prod.toml")
+        );
+        let config = parse(&config).expect("Should parse the config");
+
+        assert_eq!(1, config.chains.len());
+        assert_eq!("http://localhost:8545", config.chains[0].rpc);
+    }
+
+    #[test]
+    fn requires_a_chain() {
+        let without_chain: String = OLDER_PROD
+            .lines()
+            .filter(|line| !line.starts_with("ethereum_network"))
+            .map(|line| format!("{}\n", line))
+            .collect();
+
+        assert!(parse(&without_chain).is_err());
+    }
+}
@@ -1,13 +1,9 @@
-use crypto::{digest::Digest, sha3::Sha3};
+use tiny_keccak::keccak256;
 
 pub fn checksum(address: &str) -> String {
     let address = address.trim_start_matches("0x").to_lowercase();
 
-    let address_hash = {
-        let mut hasher = Sha3::keccak256();
-        hasher.input(address.as_bytes());
-        hasher.result_str()
-    };
+    let address_hash = hex::encode(keccak256(address.as_bytes()));
 
     address
         .char_indices()
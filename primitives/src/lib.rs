@@ -18,11 +18,13 @@ pub mod merkle_tree;
 pub mod sentry;
 pub mod supermarket;
 pub mod targeting;
+pub mod token;
 
 pub mod util {
     pub use api::ApiUrl;
 
     pub mod api;
+    #[cfg(feature = "test-util")]
     pub mod tests {
         use slog::{o, Discard, Drain, Logger};
 
@@ -36,6 +38,7 @@ pub mod util {
         }
     }
 
+    #[cfg(feature = "logging")]
     pub mod logging;
 }
 pub mod analytics;
@@ -46,7 +49,7 @@ pub use self::ad_slot::AdSlot;
 pub use self::ad_unit::AdUnit;
 pub use self::balances_map::BalancesMap;
 pub use self::big_num::BigNum;
-pub use self::channel::{Channel, ChannelId, ChannelSpec, SpecValidator, SpecValidators};
+pub use self::channel::{ChainId, Channel, ChannelId, ChannelSpec, SpecValidator, SpecValidators};
 pub use self::config::Config;
 pub use self::event_submission::EventSubmission;
 pub use self::ipfs::IPFS;
@@ -1,5 +1,8 @@
+use crate::analytics::AnalyticsDimension;
+use crate::supermarket::Status;
+use crate::targeting::Input;
 use crate::validator::MessageTypes;
-use crate::{BigNum, Channel, ChannelId, ValidatorId};
+use crate::{BalancesMap, BigNum, Channel, ChannelId, ValidatorId};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -100,7 +103,7 @@ pub struct Earner {
     pub promilles: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct EventAggregate {
     pub channel_id: ChannelId,
@@ -123,6 +126,25 @@ pub struct ChannelListResponse {
     pub total_pages: u64,
     pub total: u64,
     pub page: u64,
+    /// The `Status` of each of the `channels`
+    #[serde(default)]
+    pub statuses: HashMap<ChannelId, Status>,
+    /// The `cursor` of the next page, `None` if this is the last page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<channel_list::ChannelListCursor>,
+}
+
+/// The balances of the last approved `NewState` of a channel
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct ChannelBalancesResponse {
+    pub balances: BalancesMap,
+}
+
+/// A single earner of the `ChannelBalancesResponse`, used for the CSV and NDJSON exports
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Balance {
+    pub earner: ValidatorId,
+    pub amount: BigNum,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
@@ -140,6 +162,15 @@ pub struct SuccessResponse {
     pub success: bool,
 }
 
+/// The response of `POST /channel` and `POST /channel/validate`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChannelValidationResponse {
+    pub success: bool,
+    /// The targeting rules which are valid, but have no effect
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ValidatorMessage {
     pub from: ValidatorId,
@@ -151,6 +182,45 @@ pub struct ValidatorMessage {
 #[serde(rename_all = "camelCase")]
 pub struct ValidatorMessageResponse {
     pub validator_messages: Vec<ValidatorMessage>,
+    /// The `cursor` of the next page, `None` if this is the last page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<validator_message_list::ValidatorMessagesCursor>,
+}
+
+/// A real-time update of a channel, streamed by the sentry
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ChannelUpdate {
+    /// A validator message was received
+    #[serde(rename_all = "camelCase")]
+    ValidatorMessage {
+        channel_id: ChannelId,
+        from: ValidatorId,
+        received: DateTime<Utc>,
+        msg: MessageTypes,
+    },
+    /// The validator reported the channel as exhausted
+    #[serde(rename_all = "camelCase")]
+    Exhausted {
+        channel_id: ChannelId,
+        validator: ValidatorId,
+    },
+    /// An event aggregate was flushed to the database
+    #[serde(rename_all = "camelCase")]
+    EventAggregate {
+        channel_id: ChannelId,
+        created: DateTime<Utc>,
+    },
+}
+
+impl ChannelUpdate {
+    pub fn channel_id(&self) -> &ChannelId {
+        match self {
+            ChannelUpdate::ValidatorMessage { channel_id, .. } => channel_id,
+            ChannelUpdate::Exhausted { channel_id, .. } => channel_id,
+            ChannelUpdate::EventAggregate { channel_id, .. } => channel_id,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -159,6 +229,13 @@ pub struct EventAggregateResponse {
     pub events: Vec<EventAggregate>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetingExplainRequest {
+    /// The channel and the price of its `deposit_asset` are set by the sentry
+    pub input: Input,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ValidationErrorResponse {
@@ -204,6 +281,38 @@ pub enum ChannelReport {
     HostnamePay,
 }
 
+impl PublisherReport {
+    /// The dimension of the analytics rollup from which the report is built
+    pub fn dimension(&self) -> AnalyticsDimension {
+        match self {
+            PublisherReport::AdUnit => AnalyticsDimension::AdUnit,
+            PublisherReport::AdSlot | PublisherReport::AdSlotPay => AnalyticsDimension::AdSlot,
+            PublisherReport::Country => AnalyticsDimension::Country,
+            PublisherReport::Hostname => AnalyticsDimension::Hostname,
+        }
+    }
+
+    /// Whether the report sums the payouts instead of counting the events
+    pub fn is_payout(&self) -> bool {
+        matches!(self, PublisherReport::AdSlotPay)
+    }
+}
+
+impl ChannelReport {
+    /// The dimension of the analytics rollup from which the report is built
+    pub fn dimension(&self) -> AnalyticsDimension {
+        match self {
+            ChannelReport::AdUnit => AnalyticsDimension::AdUnit,
+            ChannelReport::Hostname | ChannelReport::HostnamePay => AnalyticsDimension::Hostname,
+        }
+    }
+
+    /// Whether the report sums the payouts instead of counting the events
+    pub fn is_payout(&self) -> bool {
+        matches!(self, ChannelReport::HostnamePay)
+    }
+}
+
 impl fmt::Display for ChannelReport {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
@@ -214,10 +323,108 @@ impl fmt::Display for ChannelReport {
     }
 }
 
+pub mod validator_message_list {
+    use crate::DomainError;
+    use chrono::{DateTime, TimeZone, Utc};
+    use serde::{Deserialize, Serialize};
+    use std::convert::TryFrom;
+    use std::fmt;
+    use std::str::FromStr;
+
+    /// The position of the last message of a page, formatted as `<received>:<id>`,
+    /// where `received` is in milliseconds, e.g. `1564383600000:42`.
+    /// The `id` orders the messages received at the same time.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(try_from = "String", into = "String")]
+    pub struct ValidatorMessagesCursor {
+        pub received: DateTime<Utc>,
+        pub id: i64,
+    }
+
+    impl fmt::Display for ValidatorMessagesCursor {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}:{}", self.received.timestamp_millis(), self.id)
+        }
+    }
+
+    impl FromStr for ValidatorMessagesCursor {
+        type Err = DomainError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let invalid = || DomainError::InvalidArgument(format!("invalid cursor {}", s));
+            let parts: Vec<&str> = s.splitn(2, ':').collect();
+
+            let (received, id) = match parts.as_slice() {
+                [received, id] => (*received, *id),
+                _ => return Err(invalid()),
+            };
+
+            let received = received.parse().map_err(|_| invalid())?;
+
+            Ok(Self {
+                received: Utc
+                    .timestamp_millis_opt(received)
+                    .single()
+                    .ok_or_else(invalid)?,
+                id: id.parse().map_err(|_| invalid())?,
+            })
+        }
+    }
+
+    impl TryFrom<String> for ValidatorMessagesCursor {
+        type Error = DomainError;
+
+        fn try_from(cursor: String) -> Result<Self, Self::Error> {
+            cursor.parse()
+        }
+    }
+
+    impl From<ValidatorMessagesCursor> for String {
+        fn from(cursor: ValidatorMessagesCursor) -> Self {
+            cursor.to_string()
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn cursor_round_trip() {
+            let cursor = ValidatorMessagesCursor {
+                received: Utc.timestamp_millis(1_564_383_600_120),
+                id: 42,
+            };
+
+            assert_eq!("1564383600120:42", cursor.to_string());
+            assert_eq!(Ok(cursor.clone()), cursor.to_string().parse());
+
+            let out_of_range = format!("{}:42", i64::MAX);
+            for invalid in vec![
+                "",
+                "1564383600120",
+                "1564383600120:",
+                "now:42",
+                ":42",
+                &out_of_range,
+            ] {
+                assert!(
+                    invalid.parse::<ValidatorMessagesCursor>().is_err(),
+                    "{}",
+                    invalid
+                );
+            }
+        }
+    }
+}
+
 pub mod channel_list {
-    use crate::ValidatorId;
+    use crate::{supermarket::StatusKind, BigNum, Channel, ChannelId, DomainError, ValidatorId};
     use chrono::{serde::ts_seconds, DateTime, Utc};
     use serde::{Deserialize, Serialize};
+    use std::convert::TryFrom;
+    use std::fmt;
+    use std::str::FromStr;
 
     #[derive(Debug, Serialize, Deserialize)]
     pub struct ChannelListQuery {
@@ -230,6 +437,145 @@ pub mod channel_list {
         pub creator: Option<String>,
         /// filters the channels containing a specific validator if provided
         pub validator: Option<ValidatorId>,
+        /// filters the channels by their current `Status`, e.g. `Active` or `Unsound`
+        pub status: Option<StatusKind>,
+        /// filters the channels by their deposit asset
+        #[serde(rename = "depositAsset")]
+        pub deposit_asset: Option<String>,
+        /// filters the channels with at least one ad unit of this type, e.g. `legacy_300x250`
+        #[serde(rename = "adUnitType")]
+        pub ad_unit_type: Option<String>,
+        #[serde(default)]
+        pub sort: ChannelListSort,
+        /// continues the list after the `next` cursor of the previous response, `page` is ignored
+        pub cursor: Option<ChannelListCursor>,
+    }
+
+    /// All the currently valid channels, as if there was no query
+    impl Default for ChannelListQuery {
+        fn default() -> Self {
+            Self {
+                page: default_page(),
+                valid_until_ge: Utc::now(),
+                creator: None,
+                validator: None,
+                status: None,
+                deposit_asset: None,
+                ad_unit_type: None,
+                sort: ChannelListSort::default(),
+                cursor: None,
+            }
+        }
+    }
+
+    /// The channels are listed in descending order, with their id as a tiebreaker
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub enum ChannelListSort {
+        /// By the `created` of their spec, the newest first
+        Created,
+        /// By their `deposit_amount`, the largest first
+        Deposit,
+    }
+
+    impl Default for ChannelListSort {
+        fn default() -> Self {
+            ChannelListSort::Created
+        }
+    }
+
+    impl fmt::Display for ChannelListSort {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                ChannelListSort::Created => write!(f, "created"),
+                ChannelListSort::Deposit => write!(f, "deposit"),
+            }
+        }
+    }
+
+    /// The position of the last channel of a page, formatted as `<sort>:<value>:<channel id>`,
+    /// e.g. `created:1564383600000:0x061d...`.
+    /// Unlike the `page`, the following pages don't change when new channels are inserted.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(try_from = "String", into = "String")]
+    pub struct ChannelListCursor {
+        pub sort: ChannelListSort,
+        /// The `created` timestamp in milliseconds or the `deposit_amount` of the channel
+        pub value: BigNum,
+        pub id: ChannelId,
+    }
+
+    impl ChannelListCursor {
+        /// The cursor of the page following the `channel`
+        pub fn after(sort: ChannelListSort, channel: &Channel) -> Self {
+            let value = match sort {
+                ChannelListSort::Created => {
+                    BigNum::from(channel.spec.created.timestamp_millis().max(0) as u64)
+                }
+                ChannelListSort::Deposit => channel.deposit_amount.clone(),
+            };
+
+            Self {
+                sort,
+                value,
+                id: channel.id,
+            }
+        }
+    }
+
+    impl fmt::Display for ChannelListCursor {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "{}:{}:{}",
+                self.sort,
+                self.value.to_str_radix(10),
+                self.id
+            )
+        }
+    }
+
+    impl FromStr for ChannelListCursor {
+        type Err = DomainError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let invalid = || DomainError::InvalidArgument(format!("invalid cursor {}", s));
+            let parts: Vec<&str> = s.splitn(3, ':').collect();
+
+            let (sort, value, id) = match parts.as_slice() {
+                [sort, value, id] => (*sort, *value, *id),
+                _ => return Err(invalid()),
+            };
+
+            let sort = match sort {
+                "created" => ChannelListSort::Created,
+                "deposit" => ChannelListSort::Deposit,
+                _ => return Err(invalid()),
+            };
+            if value.is_empty() || !value.chars().all(|c| c.is_ascii_digit()) {
+                return Err(invalid());
+            }
+
+            Ok(Self {
+                sort,
+                value: BigNum::from_str(value).map_err(|_| invalid())?,
+                id: ChannelId::from_str(id).map_err(|_| invalid())?,
+            })
+        }
+    }
+
+    impl TryFrom<String> for ChannelListCursor {
+        type Error = DomainError;
+
+        fn try_from(cursor: String) -> Result<Self, Self::Error> {
+            cursor.parse()
+        }
+    }
+
+    impl From<ChannelListCursor> for String {
+        fn from(cursor: ChannelListCursor) -> Self {
+            cursor.to_string()
+        }
     }
 
     #[derive(Debug, Deserialize)]
@@ -241,12 +587,48 @@ pub mod channel_list {
     fn default_page() -> u64 {
         0
     }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+        use crate::util::tests::prep_db::DUMMY_CHANNEL;
+
+        #[test]
+        fn cursor_round_trip() {
+            for sort in vec![ChannelListSort::Created, ChannelListSort::Deposit] {
+                let cursor = ChannelListCursor::after(sort, &DUMMY_CHANNEL);
+
+                assert_eq!(Ok(cursor.clone()), cursor.to_string().parse());
+            }
+
+            let cursor = ChannelListCursor::after(ChannelListSort::Deposit, &DUMMY_CHANNEL);
+            assert_eq!(
+                format!("deposit:1000:{}", DUMMY_CHANNEL.id),
+                cursor.to_string()
+            );
+        }
+
+        #[test]
+        fn invalid_cursors() {
+            let id = DUMMY_CHANNEL.id.to_string();
+
+            for cursor in vec![
+                "".to_string(),
+                "created:1000".to_string(),
+                format!("updated:1000:{}", id),
+                format!("created:-1000:{}", id),
+                "created:1000:0x1234".to_string(),
+            ] {
+                assert!(cursor.parse::<ChannelListCursor>().is_err(), "{}", cursor);
+            }
+        }
+    }
 }
 
 #[cfg(feature = "postgres")]
 mod postgres {
     use super::{
-        ApproveStateValidatorMessage, HeartbeatValidatorMessage, NewStateValidatorMessage,
+        ApproveStateValidatorMessage, Balance, HeartbeatValidatorMessage, NewStateValidatorMessage,
         ValidatorMessage,
     };
     use crate::sentry::EventAggregate;
@@ -266,6 +648,15 @@ mod postgres {
         }
     }
 
+    impl From<&Row> for Balance {
+        fn from(row: &Row) -> Self {
+            Self {
+                earner: row.get("earner"),
+                amount: row.get("amount"),
+            }
+        }
+    }
+
     impl From<&Row> for ValidatorMessage {
         fn from(row: &Row) -> Self {
             Self {
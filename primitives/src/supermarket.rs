@@ -1,4 +1,5 @@
 use crate::{BalancesMap, Channel};
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Campaign {
@@ -17,7 +18,7 @@ impl Campaign {
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub enum Status {
     // Active and Ready
     Active,
@@ -25,6 +26,7 @@ pub enum Status {
     Initializing,
     Waiting,
     Finalized(Finalized),
+    #[serde(rename_all = "camelCase")]
     Unsound {
         disconnected: bool,
         offline: bool,
@@ -33,7 +35,31 @@ pub enum Status {
     },
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+impl Status {
+    pub fn kind(&self) -> StatusKind {
+        match self {
+            Status::Active => StatusKind::Active,
+            Status::Pending => StatusKind::Pending,
+            Status::Initializing => StatusKind::Initializing,
+            Status::Waiting => StatusKind::Waiting,
+            Status::Finalized(_) => StatusKind::Finalized,
+            Status::Unsound { .. } => StatusKind::Unsound,
+        }
+    }
+}
+
+/// The `Status` without its details, e.g. for filtering channels by their status
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum StatusKind {
+    Active,
+    Pending,
+    Initializing,
+    Waiting,
+    Finalized,
+    Unsound,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum Finalized {
     Expired,
     Exhausted,
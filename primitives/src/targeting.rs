@@ -1,13 +1,17 @@
 use crate::{channel::Pricing, BigNum, Channel};
 
 pub use eval::*;
+use serde::Serialize;
 use serde_json::Number;
 use std::collections::HashMap;
 
 pub use input::{field::GetField, Input};
 
+pub mod check;
 mod eval;
 pub mod input;
+pub mod text;
+pub mod trace;
 
 pub fn get_pricing_bounds(channel: &Channel, event_type: &str) -> Pricing {
     channel
@@ -31,7 +35,8 @@ pub fn get_pricing_bounds(channel: &Channel, event_type: &str) -> Pricing {
         })
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Output {
     /// Whether to show the ad
     /// Default: true
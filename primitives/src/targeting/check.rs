@@ -0,0 +1,825 @@
+//! Static checking of the targeting `Rule`s
+//!
+//! Infers the type of every `Function` and `Value` of the rules without evaluating them,
+//! so that the errors which would otherwise only show up as a `TypeError` or `UnknownVariable`
+//! while evaluating the rules can be reported, together with the path of the failing rule,
+//! when a channel is created.
+use super::{
+    input::field::{self, Field},
+    Function, Rule, Value,
+};
+use crate::{BigNum, Channel};
+use chrono_tz::Tz;
+use regex::Regex;
+use std::{convert::TryFrom, fmt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Bool,
+    Number,
+    String,
+    Array,
+    BigNum,
+    /// The type is known only when evaluating the rule, e.g. the element of an array
+    Any,
+}
+
+impl Type {
+    fn name(self) -> &'static str {
+        match self {
+            Type::Bool => "bool",
+            Type::Number => "number",
+            Type::String => "string",
+            Type::Array => "array",
+            Type::BigNum => "bigNum",
+            Type::Any => "any",
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnknownVariable(String),
+    TypeMismatch {
+        expected: &'static str,
+        found: Type,
+    },
+    /// The rule is used as a value, but it doesn't return one, e.g. `set` or `onlyShowIf`
+    NoValue,
+    InvalidBigNum(String),
+    InvalidTimezone(String),
+    InvalidRegex(String),
+    /// A rule which never sets `show` or any other output variable.
+    /// It's only a warning, since the rules are still valid
+    NoEffect,
+}
+
+impl ErrorKind {
+    /// The warnings are reported, but they don't make the rules invalid
+    pub fn is_warning(&self) -> bool {
+        matches!(self, ErrorKind::NoEffect)
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::UnknownVariable(variable) => write!(f, "unknown variable `{}`", variable),
+            ErrorKind::TypeMismatch { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            ErrorKind::NoValue => write!(f, "expected a value, but the rule doesn't return one"),
+            ErrorKind::InvalidBigNum(value) => write!(f, "`{}` is not a valid bigNum", value),
+            ErrorKind::InvalidTimezone(value) => write!(f, "`{}` is not a known timezone", value),
+            ErrorKind::InvalidRegex(value) => write!(f, "`{}` is not a valid regex", value),
+            ErrorKind::NoEffect => write!(
+                f,
+                "the rule never sets `show` or another output variable, so it has no effect"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckError {
+    /// The path of the failing rule in the JSON of the rules, e.g. `[0].onlyShowIf.gt[1]`
+    pub path: String,
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.kind)
+    }
+}
+
+impl std::error::Error for CheckError {}
+
+/// Checks the rules and returns the warnings if the rules are valid,
+/// otherwise all of the found errors and warnings
+pub fn check(rules: &[Rule]) -> Result<Vec<CheckError>, Vec<CheckError>> {
+    let mut checker = Checker::default();
+    checker.check_rules(rules, "");
+
+    checker.into_result()
+}
+
+/// Checks both the `targetingRules` and the `spec.targetingRules` of the channel
+pub fn check_channel(channel: &Channel) -> Result<Vec<CheckError>, Vec<CheckError>> {
+    let mut checker = Checker::default();
+    checker.check_rules(&channel.targeting_rules, "targetingRules");
+    checker.check_rules(&channel.spec.targeting_rules, "spec.targetingRules");
+
+    checker.into_result()
+}
+
+/// The type of the input or output variable, if there is such a variable
+pub fn variable_type(variable: &str) -> Option<Type> {
+    match variable {
+        "show" => Some(Type::Bool),
+        "boost" => Some(Type::Number),
+        price if price.starts_with("price.") => Some(Type::BigNum),
+        _ => variable
+            .parse::<Field>()
+            .ok()
+            .map(|field| field_type(&field)),
+    }
+}
+
+fn field_type(field: &Field) -> Type {
+    match field {
+        Field::AdView(ad_view) => match ad_view {
+            field::AdView::SecondsSinceCampaignImpression => Type::Number,
+            field::AdView::HasCustomPreferences => Type::Bool,
+            field::AdView::NavigatorLanguage => Type::String,
+        },
+        Field::Global(global) => match global {
+            field::Global::SecondsSinceEpoch => Type::Number,
+            field::Global::AdSlotId
+            | field::Global::AdSlotType
+            | field::Global::PublisherId
+            | field::Global::Country
+            | field::Global::EventType
+            | field::Global::UserAgentOS
+            | field::Global::UserAgentBrowserFamily => Type::String,
+        },
+        Field::AdUnit(field::AdUnit::AdUnitId) => Type::String,
+        Field::Channel(channel) => match channel {
+            field::Channel::AdvertiserId | field::Channel::CampaignId => Type::String,
+            field::Channel::CampaignSecondsActive | field::Channel::CampaignSecondsDuration => {
+                Type::Number
+            }
+            field::Channel::CampaignBudget
+            | field::Channel::EventMinPrice
+            | field::Channel::EventMaxPrice => Type::BigNum,
+        },
+        Field::Balances(_) => Type::BigNum,
+        Field::AdSlot(ad_slot) => match ad_slot {
+            field::AdSlot::Categories => Type::Array,
+            field::AdSlot::Hostname => Type::String,
+            field::AdSlot::AlexaRank => Type::Number,
+        },
+    }
+}
+
+pub(super) fn value_type(value: &Value) -> Type {
+    match value {
+        Value::Bool(_) => Type::Bool,
+        Value::Number(_) => Type::Number,
+        Value::String(_) => Type::String,
+        Value::Array(_) => Type::Array,
+        Value::BigNum(_) => Type::BigNum,
+    }
+}
+
+/// The name of the function in the JSON of the rules
+fn function_name(function: &Function) -> &'static str {
+    match function {
+        Function::MulDiv(..) => "mulDiv",
+        Function::Div(..) => "div",
+        Function::Mul(..) => "mul",
+        Function::Mod(..) => "mod",
+        Function::Add(..) => "add",
+        Function::Sub(..) => "sub",
+        Function::Max(..) => "max",
+        Function::Min(..) => "min",
+        Function::If(..) => "if",
+        Function::IfNot(..) => "ifNot",
+        Function::IfElse(..) => "ifElse",
+        Function::And(..) => "and",
+        Function::Or(..) => "or",
+        Function::Xor(..) => "xor",
+        Function::Not(..) => "not",
+        Function::Lt(..) => "lt",
+        Function::Lte(..) => "lte",
+        Function::Gt(..) => "gt",
+        Function::Gte(..) => "gte",
+        Function::Eq(..) => "eq",
+        Function::Neq(..) => "neq",
+        Function::In(..) => "in",
+        Function::Nin(..) => "nin",
+        Function::At(..) => "at",
+        Function::Between(..) => "between",
+        Function::Split(..) => "split",
+        Function::StartsWith(..) => "startsWith",
+        Function::EndsWith(..) => "endsWith",
+        Function::OnlyShowIf(..) => "onlyShowIf",
+        Function::GetPriceInUsd(..) => "getPriceInUsd",
+        Function::Intersects(..) => "intersects",
+        Function::HourOfDay(..) => "hourOfDay",
+        Function::DayOfWeek(..) => "dayOfWeek",
+        Function::EqIgnoreCase(..) => "eqIgnoreCase",
+        Function::RegexMatch(..) => "regexMatch",
+        Function::HashBucket(..) => "hashBucket",
+        Function::Do(..) => "do",
+        Function::Get(..) => "get",
+        Function::Set(..) => "set",
+        Function::Bn(..) => "bn",
+    }
+}
+
+/// Whether the rule can set `show` or any other output variable when evaluated,
+/// the branches which are never taken because of a literal condition are skipped
+fn has_effect(rule: &Rule) -> bool {
+    let function = match rule {
+        Rule::Value(_) => return false,
+        Rule::Function(function) => function,
+    };
+
+    match function {
+        Function::Set(..) | Function::OnlyShowIf(..) => true,
+        Function::If(condition, then) => match condition.as_ref() {
+            Rule::Value(Value::Bool(false)) => false,
+            _ => has_effect(condition) || has_effect(then),
+        },
+        Function::IfNot(condition, then) => match condition.as_ref() {
+            Rule::Value(Value::Bool(true)) => false,
+            _ => has_effect(condition) || has_effect(then),
+        },
+        Function::IfElse(condition, then, otherwise) => match condition.as_ref() {
+            Rule::Value(Value::Bool(true)) => has_effect(then),
+            Rule::Value(Value::Bool(false)) => has_effect(otherwise),
+            _ => has_effect(condition) || has_effect(then) || has_effect(otherwise),
+        },
+        Function::Not(rule) | Function::GetPriceInUsd(rule) | Function::Do(rule) => {
+            has_effect(rule)
+        }
+        Function::MulDiv(first, second, third) | Function::Between(first, second, third) => {
+            has_effect(first) || has_effect(second) || has_effect(third)
+        }
+        Function::Div(lhs, rhs)
+        | Function::Mul(lhs, rhs)
+        | Function::Mod(lhs, rhs)
+        | Function::Add(lhs, rhs)
+        | Function::Sub(lhs, rhs)
+        | Function::Max(lhs, rhs)
+        | Function::Min(lhs, rhs)
+        | Function::And(lhs, rhs)
+        | Function::Or(lhs, rhs)
+        | Function::Xor(lhs, rhs)
+        | Function::Lt(lhs, rhs)
+        | Function::Lte(lhs, rhs)
+        | Function::Gt(lhs, rhs)
+        | Function::Gte(lhs, rhs)
+        | Function::Eq(lhs, rhs)
+        | Function::Neq(lhs, rhs)
+        | Function::In(lhs, rhs)
+        | Function::Nin(lhs, rhs)
+        | Function::At(lhs, rhs)
+        | Function::Split(lhs, rhs)
+        | Function::StartsWith(lhs, rhs)
+        | Function::EndsWith(lhs, rhs)
+        | Function::Intersects(lhs, rhs)
+        | Function::HourOfDay(lhs, rhs)
+        | Function::DayOfWeek(lhs, rhs)
+        | Function::EqIgnoreCase(lhs, rhs)
+        | Function::RegexMatch(lhs, rhs)
+        | Function::HashBucket(lhs, rhs) => has_effect(lhs) || has_effect(rhs),
+        Function::Get(_) | Function::Bn(_) => false,
+    }
+}
+
+/// An argument of a function, with its already inferred type
+#[derive(Clone)]
+struct Arg<'a> {
+    rule: &'a Rule,
+    ty: Type,
+    path: String,
+}
+
+#[derive(Default)]
+struct Checker {
+    errors: Vec<CheckError>,
+}
+
+impl Checker {
+    fn into_result(self) -> Result<Vec<CheckError>, Vec<CheckError>> {
+        if self.errors.iter().all(|error| error.kind.is_warning()) {
+            Ok(self.errors)
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn error(&mut self, path: &str, kind: ErrorKind) {
+        self.errors.push(CheckError {
+            path: path.to_string(),
+            kind,
+        })
+    }
+
+    fn check_rules(&mut self, rules: &[Rule], path: &str) {
+        for (index, rule) in rules.iter().enumerate() {
+            let path = format!("{}[{}]", path, index);
+
+            self.infer(rule, &path);
+
+            if !has_effect(rule) {
+                self.error(&path, ErrorKind::NoEffect);
+            }
+        }
+    }
+
+    /// Infers the type of the rule, `None` if it doesn't return a value.
+    /// On errors `Type::Any` is returned, so the error is not reported again by the parent rules.
+    fn infer(&mut self, rule: &Rule, path: &str) -> Option<Type> {
+        let function = match rule {
+            Rule::Value(value) => return Some(value_type(value)),
+            Rule::Function(function) => function,
+        };
+
+        let name = function_name(function);
+        let arg_path = |index: usize| format!("{}.{}[{}]", path, name, index);
+        let single_path = || format!("{}.{}", path, name);
+
+        let ty = match function {
+            Function::MulDiv(value, multiplier, divisor) => {
+                let value = self.arg(value, arg_path(0));
+                let multiplier = self.arg(multiplier, arg_path(1));
+                let product = self.math(value, multiplier);
+                let divisor = self.arg(divisor, arg_path(2));
+                let product = Arg {
+                    rule,
+                    ty: product,
+                    path: path.to_string(),
+                };
+
+                self.math(product, divisor)
+            }
+            Function::Div(lhs, rhs)
+            | Function::Mul(lhs, rhs)
+            | Function::Mod(lhs, rhs)
+            | Function::Add(lhs, rhs)
+            | Function::Sub(lhs, rhs)
+            | Function::Max(lhs, rhs)
+            | Function::Min(lhs, rhs) => {
+                let (lhs, rhs) = (self.arg(lhs, arg_path(0)), self.arg(rhs, arg_path(1)));
+
+                self.math(lhs, rhs)
+            }
+            Function::Lt(lhs, rhs)
+            | Function::Lte(lhs, rhs)
+            | Function::Gt(lhs, rhs)
+            | Function::Gte(lhs, rhs) => {
+                let (lhs, rhs) = (self.arg(lhs, arg_path(0)), self.arg(rhs, arg_path(1)));
+                self.math(lhs, rhs);
+
+                Type::Bool
+            }
+            Function::Between(start, end, value) => {
+                let start = self.arg(start, arg_path(0));
+                let end = self.arg(end, arg_path(1));
+                let value = self.arg(value, arg_path(2));
+                self.math(value.clone(), start);
+                self.math(value, end);
+
+                Type::Bool
+            }
+            Function::Eq(lhs, rhs) | Function::Neq(lhs, rhs) => {
+                let (lhs, rhs) = (self.arg(lhs, arg_path(0)), self.arg(rhs, arg_path(1)));
+                self.equality(lhs, rhs);
+
+                Type::Bool
+            }
+            Function::If(condition, then) | Function::IfNot(condition, then) => {
+                self.expect(condition, arg_path(0), Type::Bool);
+
+                return self.infer(then, &arg_path(1));
+            }
+            Function::IfElse(condition, then, otherwise) => {
+                self.expect(condition, arg_path(0), Type::Bool);
+                let then = self.infer(then, &arg_path(1));
+                let otherwise = self.infer(otherwise, &arg_path(2));
+
+                return match (then, otherwise) {
+                    (Some(then), Some(otherwise)) if then == otherwise => Some(then),
+                    (Some(_), Some(_)) => Some(Type::Any),
+                    (Some(ty), None) | (None, Some(ty)) => Some(ty),
+                    (None, None) => None,
+                };
+            }
+            Function::And(lhs, rhs) | Function::Or(lhs, rhs) | Function::Xor(lhs, rhs) => {
+                self.expect(lhs, arg_path(0), Type::Bool);
+                self.expect(rhs, arg_path(1), Type::Bool);
+
+                Type::Bool
+            }
+            Function::Not(rule) => {
+                self.expect(rule, single_path(), Type::Bool);
+
+                Type::Bool
+            }
+            Function::Intersects(lhs, rhs) => {
+                self.expect(lhs, arg_path(0), Type::Array);
+                self.expect(rhs, arg_path(1), Type::Array);
+
+                Type::Bool
+            }
+            Function::In(array, value) | Function::Nin(array, value) => {
+                self.expect(array, arg_path(0), Type::Array);
+                self.arg(value, arg_path(1));
+
+                Type::Bool
+            }
+            Function::At(array, index) => {
+                self.expect(array, arg_path(0), Type::Array);
+                self.expect(index, arg_path(1), Type::Number);
+
+                Type::Any
+            }
+            Function::Split(string, separator) => {
+                self.expect(string, arg_path(0), Type::String);
+                self.expect(separator, arg_path(1), Type::String);
+
+                Type::Array
+            }
+            Function::StartsWith(string, affix) | Function::EndsWith(string, affix) => {
+                self.expect(string, arg_path(0), Type::String);
+                self.expect(affix, arg_path(1), Type::String);
+
+                Type::Bool
+            }
+            Function::HourOfDay(seconds, timezone) | Function::DayOfWeek(seconds, timezone) => {
+                self.expect(seconds, arg_path(0), Type::Number);
+                self.expect(timezone, arg_path(1), Type::String);
+
+                if let Rule::Value(Value::String(timezone)) = timezone.as_ref() {
+                    if timezone.parse::<Tz>().is_err() {
+                        self.error(&arg_path(1), ErrorKind::InvalidTimezone(timezone.clone()));
+                    }
+                }
+
+                Type::Number
+            }
+            Function::EqIgnoreCase(lhs, rhs) => {
+                self.expect(lhs, arg_path(0), Type::String);
+                self.expect(rhs, arg_path(1), Type::String);
+
+                Type::Bool
+            }
+            Function::RegexMatch(string, regex) => {
+                self.expect(string, arg_path(0), Type::String);
+                self.expect(regex, arg_path(1), Type::String);
+
+                if let Rule::Value(Value::String(regex)) = regex.as_ref() {
+                    if Regex::new(regex).is_err() {
+                        self.error(&arg_path(1), ErrorKind::InvalidRegex(regex.clone()));
+                    }
+                }
+
+                Type::Bool
+            }
+            Function::HashBucket(string, buckets) => {
+                self.expect(string, arg_path(0), Type::String);
+                self.expect(buckets, arg_path(1), Type::Number);
+
+                Type::Number
+            }
+            Function::OnlyShowIf(condition) => {
+                self.expect(condition, single_path(), Type::Bool);
+
+                return None;
+            }
+            Function::GetPriceInUsd(amount) => {
+                let amount = self.arg(amount, single_path());
+                self.bignum(amount);
+
+                Type::Number
+            }
+            Function::Do(rule) => return self.infer(rule, &single_path()),
+            Function::Get(variable) => match variable_type(variable) {
+                Some(ty) => ty,
+                None => {
+                    self.error(
+                        &single_path(),
+                        ErrorKind::UnknownVariable(variable.to_string()),
+                    );
+
+                    Type::Any
+                }
+            },
+            Function::Set(variable, rule) => {
+                match variable.as_str() {
+                    "show" => self.expect(rule, arg_path(1), Type::Bool),
+                    "boost" => self.expect(rule, arg_path(1), Type::Number),
+                    "price.IMPRESSION" | "price.CLICK" => {
+                        let price = self.arg(rule, arg_path(1));
+                        self.bignum(price);
+                    }
+                    _ => {
+                        self.error(
+                            &arg_path(0),
+                            ErrorKind::UnknownVariable(variable.to_string()),
+                        );
+                        self.infer(rule, &arg_path(1));
+                    }
+                }
+
+                return None;
+            }
+            Function::Bn(value) => {
+                if BigNum::try_from(value.clone()).is_err() {
+                    self.error(
+                        &single_path(),
+                        ErrorKind::InvalidBigNum(display_value(value)),
+                    );
+                }
+
+                Type::BigNum
+            }
+        };
+
+        Some(ty)
+    }
+
+    /// Infers the type of an argument, which should return a value
+    fn arg<'a>(&mut self, rule: &'a Rule, path: String) -> Arg<'a> {
+        let ty = match self.infer(rule, &path) {
+            Some(ty) => ty,
+            None => {
+                self.error(&path, ErrorKind::NoValue);
+
+                Type::Any
+            }
+        };
+
+        Arg { rule, ty, path }
+    }
+
+    fn expect(&mut self, rule: &Rule, path: String, expected: Type) {
+        let arg = self.arg(rule, path);
+
+        if arg.ty != expected && arg.ty != Type::Any {
+            self.mismatch(expected.name(), arg);
+        }
+    }
+
+    /// The argument should be convertible to a `BigNum`,
+    /// for literal values we also check if the conversion succeeds
+    fn bignum(&mut self, arg: Arg<'_>) {
+        match arg.ty {
+            Type::BigNum | Type::Any => {}
+            Type::String | Type::Number => {
+                if let Rule::Value(value) = arg.rule {
+                    if BigNum::try_from(value.clone()).is_err() {
+                        self.error(&arg.path, ErrorKind::InvalidBigNum(display_value(value)));
+                    }
+                }
+            }
+            Type::Bool | Type::Array => self.mismatch("bigNum, number or string", arg),
+        }
+    }
+
+    /// The arithmetic & comparison functions work either with 2 numbers
+    /// or convert both arguments to `BigNum`s, if one of them is a `BigNum`
+    fn math(&mut self, lhs: Arg<'_>, rhs: Arg<'_>) -> Type {
+        match (lhs.ty, rhs.ty) {
+            (Type::BigNum, _) => {
+                self.bignum(rhs);
+
+                Type::BigNum
+            }
+            (_, Type::BigNum) => {
+                self.bignum(lhs);
+
+                Type::BigNum
+            }
+            (Type::Number, Type::Number) => Type::Number,
+            (Type::Any, _) | (_, Type::Any) => Type::Any,
+            (Type::Number, _) => {
+                self.mismatch("number or bigNum", rhs);
+
+                Type::Any
+            }
+            _ => {
+                self.mismatch("number or bigNum", lhs);
+
+                Type::Any
+            }
+        }
+    }
+
+    fn equality(&mut self, lhs: Arg<'_>, rhs: Arg<'_>) {
+        match (lhs.ty, rhs.ty) {
+            (Type::BigNum, _) => self.bignum(rhs),
+            (_, Type::BigNum) => self.bignum(lhs),
+            (Type::Any, _) | (_, Type::Any) => {}
+            (lhs_ty, rhs_ty) if lhs_ty == rhs_ty => {}
+            (Type::Bool, _) => self.mismatch("bool", rhs),
+            (Type::Number, _) => self.mismatch("number or bigNum", rhs),
+            (Type::String, _) => self.mismatch("string or bigNum", rhs),
+            (Type::Array, _) => self.mismatch("array", rhs),
+        }
+    }
+
+    fn mismatch(&mut self, expected: &'static str, arg: Arg<'_>) {
+        self.error(
+            &arg.path,
+            ErrorKind::TypeMismatch {
+                expected,
+                found: arg.ty,
+            },
+        )
+    }
+}
+
+fn display_value(value: &Value) -> String {
+    let json: serde_json::Value = value.clone().into();
+
+    json.to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::tests::prep_db::DUMMY_CHANNEL;
+    use serde_json::json;
+
+    fn rules(json: serde_json::Value) -> Vec<Rule> {
+        serde_json::from_value(json).expect("Should deserialize rules")
+    }
+
+    fn errors(json: serde_json::Value) -> Vec<String> {
+        match check(&rules(json)) {
+            Ok(_warnings) => vec![],
+            Err(errors) => errors.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    fn warnings(json: serde_json::Value) -> Vec<String> {
+        check(&rules(json))
+            .expect("Should be valid")
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn valid_rules_pass() {
+        let valid = json!([
+            { "onlyShowIf": { "nin": [{ "get": "adSlot.categories" }, "IAB25"] } },
+            { "onlyShowIf": { "gt": [{ "get": "adView.secondsSinceCampaignImpression" }, 900] } },
+            { "onlyShowIf": { "lt": [{ "get": "campaignTotalSpent" }, { "bn": "1000000" }] } },
+            { "set": ["boost", { "mul": [{ "get": "boost" }, 2] }] },
+            { "set": ["price.IMPRESSION", { "mulDiv": [{ "get": "price.IMPRESSION" }, 3, "2"] }] },
+            { "if": [
+                { "startsWith": [{ "get": "adSlotType" }, "legacy_"] },
+                { "set": ["show", { "eq": [{ "get": "country" }, "BG"] }] }
+            ] },
+            { "onlyShowIf": { "gte": [{ "getPriceInUsd": { "get": "eventMinPrice" } }, 0.01] } },
+            { "onlyShowIf": { "lt": [{ "hourOfDay": [{ "get": "secondsSinceEpoch" }, "Europe/Sofia"] }, 18] } },
+            { "onlyShowIf": { "eqIgnoreCase": [{ "get": "userAgentOS" }, "android"] } },
+            { "onlyShowIf": { "lt": [{ "hashBucket": [{ "get": "publisherId" }, 100] }, 10] } },
+            { "ifElse": [
+                { "get": "adView.hasCustomPreferences" },
+                { "set": ["show", false] },
+                { "set": ["boost", { "at": [[0.5, 1.5], 1] }] }
+            ] },
+        ]);
+
+        assert_eq!(Vec::<String>::new(), errors(valid));
+    }
+
+    #[test]
+    fn reports_unknown_variables_with_their_path() {
+        let json = json!([
+            { "onlyShowIf": { "eq": [{ "get": "countryCode" }, "BG"] } },
+            { "set": ["hide", true] },
+        ]);
+
+        assert_eq!(
+            vec![
+                "[0].onlyShowIf.eq[0].get: unknown variable `countryCode`",
+                "[1].set[0]: unknown variable `hide`",
+            ],
+            errors(json)
+        );
+    }
+
+    #[test]
+    fn reports_type_mismatches() {
+        let json = json!([
+            { "set": ["show", 1] },
+            { "onlyShowIf": { "and": [true, { "get": "adSlot.hostname" }] } },
+            { "set": ["boost", { "add": [{ "get": "adSlotId" }, 1] }] },
+            { "onlyShowIf": { "in": [{ "get": "country" }, "BG"] } },
+            { "onlyShowIf": { "eq": [{ "get": "adView.hasCustomPreferences" }, "true"] } },
+        ]);
+
+        assert_eq!(
+            vec![
+                "[0].set[1]: expected bool, found number",
+                "[1].onlyShowIf.and[1]: expected bool, found string",
+                "[2].set[1].add[0]: expected number or bigNum, found string",
+                "[3].onlyShowIf.in[0]: expected array, found string",
+                "[4].onlyShowIf.eq[1]: expected bool, found string",
+            ],
+            errors(json)
+        );
+    }
+
+    #[test]
+    fn reports_invalid_bignum_literals() {
+        let json = json!([
+            { "set": ["price.IMPRESSION", { "bn": "1.5" }] },
+            { "onlyShowIf": { "gt": [{ "get": "campaignBudget" }, "a lot"] } },
+            { "set": ["price.CLICK", -1] },
+        ]);
+
+        assert_eq!(
+            vec![
+                "[0].set[1].bn: `\"1.5\"` is not a valid bigNum",
+                "[1].onlyShowIf.gt[1]: `\"a lot\"` is not a valid bigNum",
+                "[2].set[1]: `-1` is not a valid bigNum",
+            ],
+            errors(json)
+        );
+    }
+
+    #[test]
+    fn reports_invalid_timezone_and_regex_literals() {
+        let json = json!([
+            { "onlyShowIf": { "lt": [{ "hourOfDay": [{ "get": "secondsSinceEpoch" }, "Mars/Olympus"] }, 12] } },
+            { "onlyShowIf": { "regexMatch": [{ "get": "adSlot.hostname" }, "(unclosed"] } },
+            { "onlyShowIf": { "regexMatch": [{ "get": "adSlot.hostname" }, "\\.example\\.com$"] } },
+        ]);
+
+        assert_eq!(
+            vec![
+                "[0].onlyShowIf.lt[0].hourOfDay[1]: `Mars/Olympus` is not a known timezone",
+                "[1].onlyShowIf.regexMatch[1]: `(unclosed` is not a valid regex",
+            ],
+            errors(json)
+        );
+    }
+
+    #[test]
+    fn reports_rules_without_a_value_used_as_values() {
+        let json = json!([{ "onlyShowIf": { "not": { "set": ["show", false] } } }]);
+
+        assert_eq!(
+            vec!["[0].onlyShowIf.not: expected a value, but the rule doesn't return one"],
+            errors(json)
+        );
+    }
+
+    #[test]
+    fn warns_about_rules_which_never_set_show() {
+        let json = json!([
+            { "eq": [{ "get": "country" }, "BG"] },
+            { "if": [false, { "set": ["show", false] }] },
+            { "ifElse": [true, { "set": ["show", false] }, { "get": "show" }] },
+        ]);
+
+        assert_eq!(
+            vec![
+                "[0]: the rule never sets `show` or another output variable, so it has no effect",
+                "[1]: the rule never sets `show` or another output variable, so it has no effect",
+            ],
+            warnings(json)
+        );
+
+        // the warnings are reported together with the errors
+        let json = json!([
+            { "eq": [{ "get": "country" }, "BG"] },
+            { "set": ["boost", true] },
+        ]);
+
+        assert_eq!(
+            vec![
+                "[0]: the rule never sets `show` or another output variable, so it has no effect",
+                "[1].set[1]: expected number, found bool",
+            ],
+            errors(json)
+        );
+    }
+
+    #[test]
+    fn checks_both_rules_of_the_channel() {
+        let mut channel = DUMMY_CHANNEL.clone();
+        channel.targeting_rules = crate::targeting::Rules(rules(json!([
+            { "onlyShowIf": { "get": "adView.navigatorLanguage" } }
+        ])));
+        channel.spec.targeting_rules = crate::targeting::Rules(rules(json!([
+            { "set": ["boost", true] }
+        ])));
+
+        let errors = check_channel(&channel).expect_err("Should have errors");
+        let paths: Vec<_> = errors.iter().map(|error| error.path.as_str()).collect();
+
+        assert_eq!(
+            vec![
+                "targetingRules[0].onlyShowIf",
+                "spec.targetingRules[0].set[1]"
+            ],
+            paths
+        );
+        assert!(check_channel(&DUMMY_CHANNEL).is_ok());
+    }
+}
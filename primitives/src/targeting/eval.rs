@@ -1,5 +1,8 @@
 use crate::BigNum;
+use chrono::{DateTime, Datelike, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
 use lazy_static::lazy_static;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::{value::Value as SerdeValue, Number};
 use std::{
@@ -8,38 +11,29 @@ use std::{
     fmt,
     ops::{Add, Div, Mul, Rem, Sub},
     str::FromStr,
+    sync::Mutex,
 };
 
 pub use rules::Rules;
 
 use super::{
     input::{channel::Getter as ChannelGetter, Get},
+    trace::{Trace, Tracer},
     Input, Output,
 };
 
+pub use compiled::CompiledRules;
+
+mod compiled;
 #[cfg(test)]
 #[path = "eval_test.rs"]
 mod test;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Error {
     TypeError,
     UnknownVariable,
 }
-pub const DAI_ADDR: &str = "0x89d24A6b4CcB1B6fAA2625fE562bDD9a23260359";
-pub const USDT_ADDR: &str = "0xdac17f958d2ee523a2206206994597c13d831ec7";
-pub const USDC_ADDR: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
-
-lazy_static! {
-    pub static ref DEPOSIT_ASSETS_MAP: HashMap<String, BigNum> = {
-        let mut assets = HashMap::new();
-        assets.insert(DAI_ADDR.into(), BigNum::from(10u64.pow(18)));
-        assets.insert(USDT_ADDR.into(), BigNum::from(10u64.pow(6)));
-        assets.insert(USDC_ADDR.into(), BigNum::from(10u64.pow(18)));
-
-        assets
-    };
-}
 
 trait Eval {
     fn eval(self, input: &Input, output: &mut Output) -> Result<Option<Value>, Error>;
@@ -47,19 +41,19 @@ trait Eval {
 
 impl Eval for Value {
     fn eval(self, input: &Input, output: &mut Output) -> Result<Option<Value>, Error> {
-        eval(input, output, &Rule::Value(self))
+        eval(input, output, &Rule::Value(self), &mut Tracer::default())
     }
 }
 
 impl Eval for Function {
     fn eval(self, input: &Input, output: &mut Output) -> Result<Option<Value>, Error> {
-        eval(input, output, &Rule::Function(self))
+        eval(input, output, &Rule::Function(self), &mut Tracer::default())
     }
 }
 
 impl Eval for &Rule {
     fn eval(self, input: &Input, output: &mut Output) -> Result<Option<Value>, Error> {
-        eval(input, output, self)
+        eval(input, output, self, &mut Tracer::default())
     }
 }
 
@@ -159,7 +153,7 @@ pub enum Rule {
 
 impl Rule {
     pub fn eval(&self, input: &Input, output: &mut Output) -> Result<Option<Value>, Error> {
-        eval(input, output, self)
+        eval(input, output, self, &mut Tracer::default())
     }
 }
 
@@ -268,6 +262,20 @@ pub enum Function {
     OnlyShowIf(Box<Rule>),
     GetPriceInUsd(Box<Rule>),
     Intersects(Box<Rule>, Box<Rule>),
+    /// The hour of the day (0 - 23) of a timestamp in seconds (first value)
+    /// in an IANA timezone (second value), e.g. `Europe/Sofia`
+    HourOfDay(Box<Rule>, Box<Rule>),
+    /// The day of the week (0 - 6, starting from Sunday like in JS) of a timestamp in seconds (first value)
+    /// in an IANA timezone (second value)
+    DayOfWeek(Box<Rule>, Box<Rule>),
+    /// Are the strings equal when ignoring their case
+    EqIgnoreCase(Box<Rule>, Box<Rule>),
+    /// Does the string (first value) match the regex (second value)
+    /// Note: the regex syntax has no look-arounds and backreferences
+    RegexMatch(Box<Rule>, Box<Rule>),
+    /// Deterministically puts the string (first value) in one of the buckets (second value),
+    /// i.e. returns a number from 0 to `buckets - 1`, e.g. for A/B splits
+    HashBucket(Box<Rule>, Box<Rule>),
     /// Evaluates rule
     Do(Box<Rule>),
     Get(String),
@@ -442,6 +450,26 @@ impl Function {
     pub fn new_get_price_in_usd(amount: impl Into<Rule>) -> Self {
         Self::GetPriceInUsd(Box::new(amount.into()))
     }
+
+    pub fn new_hour_of_day(seconds: impl Into<Rule>, timezone: impl Into<Rule>) -> Self {
+        Self::HourOfDay(Box::new(seconds.into()), Box::new(timezone.into()))
+    }
+
+    pub fn new_day_of_week(seconds: impl Into<Rule>, timezone: impl Into<Rule>) -> Self {
+        Self::DayOfWeek(Box::new(seconds.into()), Box::new(timezone.into()))
+    }
+
+    pub fn new_eq_ignore_case(lhs: impl Into<Rule>, rhs: impl Into<Rule>) -> Self {
+        Self::EqIgnoreCase(Box::new(lhs.into()), Box::new(rhs.into()))
+    }
+
+    pub fn new_regex_match(string: impl Into<Rule>, regex: impl Into<Rule>) -> Self {
+        Self::RegexMatch(Box::new(string.into()), Box::new(regex.into()))
+    }
+
+    pub fn new_hash_bucket(string: impl Into<Rule>, buckets: impl Into<Rule>) -> Self {
+        Self::HashBucket(Box::new(string.into()), Box::new(buckets.into()))
+    }
 }
 
 impl Value {
@@ -502,449 +530,276 @@ impl TryFrom<Value> for BigNum {
 ///     - BigNum
 /// - Mutates output
 /// - Throws an error
-fn eval(input: &Input, output: &mut Output, rule: &Rule) -> Result<Option<Value>, Error> {
+fn eval(
+    input: &Input,
+    output: &mut Output,
+    rule: &Rule,
+    tracer: &mut Tracer,
+) -> Result<Option<Value>, Error> {
     let function = match rule {
         Rule::Value(value) => return Ok(Some(value.clone())),
         Rule::Function(function) => function,
     };
 
+    if !tracer.enabled {
+        return eval_function(input, output, function, tracer);
+    }
+
+    tracer.enter(output, function);
+    let result = eval_function(input, output, function, tracer);
+    tracer.exit(output, rule, &result);
+
+    result
+}
+
+fn eval_function(
+    input: &Input,
+    output: &mut Output,
+    function: &Function,
+    tracer: &mut Tracer,
+) -> Result<Option<Value>, Error> {
     // basic operators
     let value = match function {
         Function::MulDiv(first_rule, second_rule, third_rule) => {
-            let product = Function::Mul(first_rule.clone(), second_rule.clone())
-                .eval(input, output)?
-                .ok_or(Error::TypeError)?;
+            let product = eval(
+                input,
+                output,
+                &Rule::Function(Function::Mul(first_rule.clone(), second_rule.clone())),
+                tracer,
+            )?
+            .ok_or(Error::TypeError)?;
             let product_rule = Rule::Value(product);
             let boxed_rule = Box::new(product_rule);
-            Function::Div(boxed_rule, third_rule.clone()).eval(input, output)?
+            eval(
+                input,
+                output,
+                &Rule::Function(Function::Div(boxed_rule, third_rule.clone())),
+                tracer,
+            )?
         }
         Function::Div(first_rule, second_rule) => {
-            let first_eval = first_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-            let second_eval = second_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-
-            let value = match (first_eval, second_eval) {
-                (Value::BigNum(bignum), second_value) => {
-                    let second_bignum = BigNum::try_from(second_value)?;
+            let first_eval = eval(input, output, first_rule, tracer)?.ok_or(Error::TypeError)?;
+            let second_eval = eval(input, output, second_rule, tracer)?.ok_or(Error::TypeError)?;
 
-                    Value::BigNum(bignum.div(second_bignum))
-                }
-                (lhs_value, Value::BigNum(rhs_bignum)) => {
-                    let lhs_bignum = BigNum::try_from(lhs_value)?;
-
-                    Value::BigNum(lhs_bignum.div(rhs_bignum))
-                }
-                (Value::Number(lhs), Value::Number(rhs)) => {
-                    Value::Number(math_operator(lhs, rhs, MathOperator::Division)?)
-                }
-                _ => return Err(Error::TypeError),
-            };
-
-            Some(value)
+            Some(math(first_eval, second_eval, MathOperator::Division)?)
         }
         Function::Mul(first_rule, second_rule) => {
-            let first_eval = first_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-            let second_eval = second_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-
-            let value = match (first_eval, second_eval) {
-                (Value::BigNum(bignum), rhs_value) => {
-                    let rhs_bignum = BigNum::try_from(rhs_value)?;
-
-                    Value::BigNum(bignum.mul(rhs_bignum))
-                }
-                (lhs_value, Value::BigNum(rhs_bignum)) => {
-                    let lhs_bignum = BigNum::try_from(lhs_value)?;
+            let first_eval = eval(input, output, first_rule, tracer)?.ok_or(Error::TypeError)?;
+            let second_eval = eval(input, output, second_rule, tracer)?.ok_or(Error::TypeError)?;
 
-                    Value::BigNum(lhs_bignum.mul(rhs_bignum))
-                }
-                (Value::Number(lhs), Value::Number(rhs)) => {
-                    Value::Number(math_operator(lhs, rhs, MathOperator::Multiplication)?)
-                }
-                _ => return Err(Error::TypeError),
-            };
-
-            Some(value)
+            Some(math(first_eval, second_eval, MathOperator::Multiplication)?)
         }
         Function::Mod(first_rule, second_rule) => {
-            let first_eval = first_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-            let second_eval = second_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-
-            let value = match (first_eval, second_eval) {
-                (Value::BigNum(bignum), rhs_value) => {
-                    let rhs_bignum = BigNum::try_from(rhs_value)?;
-
-                    Value::BigNum(bignum.rem(rhs_bignum))
-                }
-                (lhs_value, Value::BigNum(rhs_bignum)) => {
-                    let lhs_bignum = BigNum::try_from(lhs_value)?;
+            let first_eval = eval(input, output, first_rule, tracer)?.ok_or(Error::TypeError)?;
+            let second_eval = eval(input, output, second_rule, tracer)?.ok_or(Error::TypeError)?;
 
-                    Value::BigNum(lhs_bignum.rem(rhs_bignum))
-                }
-                (Value::Number(lhs), Value::Number(rhs)) => {
-                    Value::Number(math_operator(lhs, rhs, MathOperator::Modulus)?)
-                }
-                _ => return Err(Error::TypeError),
-            };
-
-            Some(value)
+            Some(math(first_eval, second_eval, MathOperator::Modulus)?)
         }
         Function::Add(first_rule, second_rule) => {
-            let first_eval = first_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-            let second_eval = second_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-
-            let value = match (first_eval, second_eval) {
-                (Value::BigNum(bignum), rhs_value) => {
-                    let rhs_bignum = BigNum::try_from(rhs_value)?;
+            let first_eval = eval(input, output, first_rule, tracer)?.ok_or(Error::TypeError)?;
+            let second_eval = eval(input, output, second_rule, tracer)?.ok_or(Error::TypeError)?;
 
-                    Value::BigNum(bignum.add(rhs_bignum))
-                }
-                (lhs_value, Value::BigNum(rhs_bignum)) => {
-                    let lhs_bignum = BigNum::try_from(lhs_value)?;
-
-                    Value::BigNum(lhs_bignum.add(rhs_bignum))
-                }
-                (Value::Number(lhs), Value::Number(rhs)) => {
-                    Value::Number(math_operator(lhs, rhs, MathOperator::Addition)?)
-                }
-                _ => return Err(Error::TypeError),
-            };
-
-            Some(value)
+            Some(math(first_eval, second_eval, MathOperator::Addition)?)
         }
         Function::Sub(first_rule, second_rule) => {
-            let first_eval = first_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-            let second_eval = second_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-
-            let value = match (first_eval, second_eval) {
-                (Value::BigNum(bignum), rhs_value) => {
-                    let rhs_bignum = BigNum::try_from(rhs_value)?;
-
-                    Value::BigNum(bignum.sub(rhs_bignum))
-                }
-                (lhs_value, Value::BigNum(rhs_bignum)) => {
-                    let lhs_bignum = BigNum::try_from(lhs_value)?;
-
-                    Value::BigNum(lhs_bignum.sub(rhs_bignum))
-                }
-                (Value::Number(lhs), Value::Number(rhs)) => {
-                    Value::Number(math_operator(lhs, rhs, MathOperator::Subtraction)?)
-                }
-                _ => return Err(Error::TypeError),
-            };
+            let first_eval = eval(input, output, first_rule, tracer)?.ok_or(Error::TypeError)?;
+            let second_eval = eval(input, output, second_rule, tracer)?.ok_or(Error::TypeError)?;
 
-            Some(value)
+            Some(math(first_eval, second_eval, MathOperator::Subtraction)?)
         }
         Function::Max(first_rule, second_rule) => {
-            let first_eval = first_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-            let second_eval = second_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-
-            let value = match (first_eval, second_eval) {
-                (Value::BigNum(bignum), rhs_value) => {
-                    let rhs_bignum = BigNum::try_from(rhs_value)?;
-
-                    Value::BigNum(bignum.max(rhs_bignum))
-                }
-                (lhs_value, Value::BigNum(rhs_bignum)) => {
-                    let lhs_bignum = BigNum::try_from(lhs_value)?;
-
-                    Value::BigNum(lhs_bignum.max(rhs_bignum))
-                }
-                (Value::Number(lhs), Value::Number(rhs)) => {
-                    Value::Number(math_operator(lhs, rhs, MathOperator::Max)?)
-                }
-                _ => return Err(Error::TypeError),
-            };
+            let first_eval = eval(input, output, first_rule, tracer)?.ok_or(Error::TypeError)?;
+            let second_eval = eval(input, output, second_rule, tracer)?.ok_or(Error::TypeError)?;
 
-            Some(value)
+            Some(math(first_eval, second_eval, MathOperator::Max)?)
         }
         Function::Min(first_rule, second_rule) => {
-            let first_eval = first_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-            let second_eval = second_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-
-            let value = match (first_eval, second_eval) {
-                (Value::BigNum(bignum), rhs_value) => {
-                    let rhs_bignum = BigNum::try_from(rhs_value)?;
-
-                    Value::BigNum(bignum.min(rhs_bignum))
-                }
-                (lhs_value, Value::BigNum(rhs_bignum)) => {
-                    let lhs_bignum = BigNum::try_from(lhs_value)?;
-
-                    Value::BigNum(lhs_bignum.min(rhs_bignum))
-                }
-                (Value::Number(lhs), Value::Number(rhs)) => {
-                    Value::Number(math_operator(lhs, rhs, MathOperator::Min)?)
-                }
-                _ => return Err(Error::TypeError),
-            };
+            let first_eval = eval(input, output, first_rule, tracer)?.ok_or(Error::TypeError)?;
+            let second_eval = eval(input, output, second_rule, tracer)?.ok_or(Error::TypeError)?;
 
-            Some(value)
+            Some(math(first_eval, second_eval, MathOperator::Min)?)
         }
         Function::If(first_rule, second_rule) => {
-            let eval_if = eval(input, output, first_rule)?
+            let eval_if = eval(input, output, first_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_bool()?;
 
             if eval_if {
-                eval(input, output, second_rule)?
+                eval(input, output, second_rule, tracer)?
             } else {
                 None
             }
         }
         Function::IfNot(if_rule, else_rule) => {
-            let eval_if = eval(input, output, if_rule)?
+            let eval_if = eval(input, output, if_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_bool()?;
 
             if !eval_if {
-                eval(input, output, else_rule)?
+                eval(input, output, else_rule, tracer)?
             } else {
                 None
             }
         }
         Function::IfElse(if_rule, then_rule, else_rule) => {
-            let eval_if = eval(input, output, if_rule)?
+            let eval_if = eval(input, output, if_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_bool()?;
 
             if eval_if {
-                eval(input, output, then_rule)?
+                eval(input, output, then_rule, tracer)?
             } else {
-                eval(input, output, else_rule)?
+                eval(input, output, else_rule, tracer)?
             }
         }
         Function::And(first_rule, second_rule) => {
-            let a = eval(input, output, first_rule)?
+            let a = eval(input, output, first_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_bool()?;
-            let b = eval(input, output, second_rule)?
+            let b = eval(input, output, second_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_bool()?;
 
             Some(Value::Bool(a && b))
         }
         Function::Or(first_rule, second_rule) => {
-            let a = eval(input, output, first_rule)?
+            let a = eval(input, output, first_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_bool()?;
-            let b = eval(input, output, second_rule)?
+            let b = eval(input, output, second_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_bool()?;
 
             Some(Value::Bool(a || b))
         }
         Function::Xor(first_rule, second_rule) => {
-            let a = eval(input, output, first_rule)?
+            let a = eval(input, output, first_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_bool()?;
-            let b = eval(input, output, second_rule)?
+            let b = eval(input, output, second_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_bool()?;
 
             Some(Value::Bool(a ^ b))
         }
         Function::Not(first_rule) => {
-            let a = eval(input, output, first_rule)?
+            let a = eval(input, output, first_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_bool()?;
 
             Some(Value::Bool(!a))
         }
         Function::Lt(first_rule, second_rule) => {
-            let first_eval = first_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-            let second_eval = second_rule.eval(input, output)?.ok_or(Error::TypeError)?;
+            let first_eval = eval(input, output, first_rule, tracer)?.ok_or(Error::TypeError)?;
+            let second_eval = eval(input, output, second_rule, tracer)?.ok_or(Error::TypeError)?;
 
-            let value = match (first_eval, second_eval) {
-                (Value::BigNum(bignum), rhs_value) => {
-                    let rhs_bignum = BigNum::try_from(rhs_value)?;
-
-                    Value::Bool(bignum.lt(&rhs_bignum))
-                }
-                (lhs_value, Value::BigNum(rhs_bignum)) => {
-                    let lhs_bignum = BigNum::try_from(lhs_value)?;
-
-                    Value::Bool(lhs_bignum.lt(&rhs_bignum))
-                }
-                (Value::Number(lhs), Value::Number(rhs)) => {
-                    Value::Bool(compare_numbers(lhs, rhs, ComparisonOperator::Lt)?)
-                }
-                _ => return Err(Error::TypeError),
-            };
-
-            Some(value)
+            Some(Value::Bool(compare(
+                first_eval,
+                second_eval,
+                ComparisonOperator::Lt,
+            )?))
         }
         Function::Lte(first_rule, second_rule) => {
-            let first_eval = first_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-            let second_eval = second_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-
-            let value = match (first_eval, second_eval) {
-                (Value::BigNum(bignum), rhs_value) => {
-                    let rhs_bignum = BigNum::try_from(rhs_value)?;
+            let first_eval = eval(input, output, first_rule, tracer)?.ok_or(Error::TypeError)?;
+            let second_eval = eval(input, output, second_rule, tracer)?.ok_or(Error::TypeError)?;
 
-                    Value::Bool(bignum.le(&rhs_bignum))
-                }
-                (lhs_value, Value::BigNum(rhs_bignum)) => {
-                    let lhs_bignum = BigNum::try_from(lhs_value)?;
-
-                    Value::Bool(lhs_bignum.le(&rhs_bignum))
-                }
-                (Value::Number(lhs), Value::Number(rhs)) => {
-                    Value::Bool(compare_numbers(lhs, rhs, ComparisonOperator::Lte)?)
-                }
-                _ => return Err(Error::TypeError),
-            };
-
-            Some(value)
+            Some(Value::Bool(compare(
+                first_eval,
+                second_eval,
+                ComparisonOperator::Lte,
+            )?))
         }
         Function::Gt(first_rule, second_rule) => {
-            let first_eval = first_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-            let second_eval = second_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-
-            let value = match (first_eval, second_eval) {
-                (Value::BigNum(bignum), rhs_value) => {
-                    let rhs_bignum = BigNum::try_from(rhs_value)?;
+            let first_eval = eval(input, output, first_rule, tracer)?.ok_or(Error::TypeError)?;
+            let second_eval = eval(input, output, second_rule, tracer)?.ok_or(Error::TypeError)?;
 
-                    Value::Bool(bignum.gt(&rhs_bignum))
-                }
-                (lhs_value, Value::BigNum(rhs_bignum)) => {
-                    let lhs_bignum = BigNum::try_from(lhs_value)?;
-
-                    Value::Bool(lhs_bignum.gt(&rhs_bignum))
-                }
-                (Value::Number(lhs), Value::Number(rhs)) => {
-                    Value::Bool(compare_numbers(lhs, rhs, ComparisonOperator::Gt)?)
-                }
-                _ => return Err(Error::TypeError),
-            };
-
-            Some(value)
+            Some(Value::Bool(compare(
+                first_eval,
+                second_eval,
+                ComparisonOperator::Gt,
+            )?))
         }
         Function::Gte(first_rule, second_rule) => {
-            let first_eval = first_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-            let second_eval = second_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-
-            let value = match (first_eval, second_eval) {
-                (Value::BigNum(bignum), rhs_value) => {
-                    let rhs_bignum = BigNum::try_from(rhs_value)?;
+            let first_eval = eval(input, output, first_rule, tracer)?.ok_or(Error::TypeError)?;
+            let second_eval = eval(input, output, second_rule, tracer)?.ok_or(Error::TypeError)?;
 
-                    Value::Bool(bignum.ge(&rhs_bignum))
-                }
-                (lhs_value, Value::BigNum(rhs_bignum)) => {
-                    let lhs_bignum = BigNum::try_from(lhs_value)?;
-
-                    Value::Bool(lhs_bignum.ge(&rhs_bignum))
-                }
-                (Value::Number(lhs), Value::Number(rhs)) => {
-                    Value::Bool(compare_numbers(lhs, rhs, ComparisonOperator::Gte)?)
-                }
-                _ => return Err(Error::TypeError),
-            };
-
-            Some(value)
+            Some(Value::Bool(compare(
+                first_eval,
+                second_eval,
+                ComparisonOperator::Gte,
+            )?))
         }
         Function::Eq(first_rule, second_rule) => {
-            let first_eval = first_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-            let second_eval = second_rule.eval(input, output)?.ok_or(Error::TypeError)?;
-
-            let value = match (first_eval, second_eval) {
-                (Value::BigNum(bignum), rhs_value) => {
-                    let rhs_bignum = BigNum::try_from(rhs_value)?;
-
-                    Value::Bool(bignum.eq(&rhs_bignum))
-                }
-                (lhs_value, Value::BigNum(rhs_bignum)) => {
-                    let lhs_bignum = BigNum::try_from(lhs_value)?;
+            let first_eval = eval(input, output, first_rule, tracer)?.ok_or(Error::TypeError)?;
+            let second_eval = eval(input, output, second_rule, tracer)?.ok_or(Error::TypeError)?;
 
-                    Value::Bool(lhs_bignum.eq(&rhs_bignum))
-                }
-                (Value::Number(lhs), Value::Number(rhs)) => {
-                    Value::Bool(compare_numbers(lhs, rhs, ComparisonOperator::Eq)?)
-                }
-                (Value::Bool(lhs), Value::Bool(rhs)) => Value::Bool(lhs == rhs),
-                (Value::String(lhs), Value::String(rhs)) => Value::Bool(lhs == rhs),
-                (Value::Array(lhs), Value::Array(rhs)) => {
-                    if lhs.len() != rhs.len() {
-                        Value::Bool(false)
-                    } else {
-                        let are_same = lhs.iter().zip(rhs.iter()).all(|(a, b)| a == b);
-                        Value::Bool(are_same)
-                    }
-                }
-                _ => return Err(Error::TypeError),
-            };
-
-            Some(value)
+            Some(Value::Bool(equals(first_eval, second_eval)?))
         }
         Function::Neq(first_rule, second_rule) => {
-            let is_equal = Function::Eq(first_rule.clone(), second_rule.clone())
-                .eval(input, output)?
-                .ok_or(Error::TypeError)?
-                .try_bool()?;
+            let is_equal = eval(
+                input,
+                output,
+                &Rule::Function(Function::Eq(first_rule.clone(), second_rule.clone())),
+                tracer,
+            )?
+            .ok_or(Error::TypeError)?
+            .try_bool()?;
             Some(Value::Bool(!is_equal))
         }
         Function::Intersects(first_rule, second_rule) => {
-            let a = eval(input, output, first_rule)?
+            let a = eval(input, output, first_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_array()?;
-            let b = eval(input, output, second_rule)?
+            let b = eval(input, output, second_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_array()?;
 
             Some(Value::Bool(a.iter().any(|x| b.contains(x))))
         }
         Function::In(array_value, search_value) => {
-            let a = eval(input, output, array_value)?
+            let a = eval(input, output, array_value, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_array()?;
-            let b = eval(input, output, search_value)?.ok_or(Error::TypeError)?;
+            let b = eval(input, output, search_value, tracer)?.ok_or(Error::TypeError)?;
 
             Some(Value::Bool(a.contains(&b)))
         }
         Function::Nin(array_value, search_value) => {
-            let is_in = Function::In(array_value.clone(), search_value.clone())
-                .eval(input, output)?
-                .ok_or(Error::TypeError)?
-                .try_bool()?;
+            let is_in = eval(
+                input,
+                output,
+                &Rule::Function(Function::In(array_value.clone(), search_value.clone())),
+                tracer,
+            )?
+            .ok_or(Error::TypeError)?
+            .try_bool()?;
             Some(Value::Bool(!is_in))
         }
         Function::Between(min_rule, max_rule, value_rule) => {
-            let is_gte_start = Function::Gte(value_rule.clone(), min_rule.clone())
-                .eval(input, output)?
-                .ok_or(Error::TypeError)?
-                .try_bool()?;
+            let value = eval(input, output, value_rule, tracer)?.ok_or(Error::TypeError)?;
+            let min = eval(input, output, min_rule, tracer)?.ok_or(Error::TypeError)?;
+            let is_gte_start = compare(value.clone(), min, ComparisonOperator::Gte)?;
 
-            let is_lte_end = Function::Lte(value_rule.clone(), max_rule.clone())
-                .eval(input, output)?
-                .ok_or(Error::TypeError)?
-                .try_bool()?;
+            let max = eval(input, output, max_rule, tracer)?.ok_or(Error::TypeError)?;
+            let is_lte_end = compare(value, max, ComparisonOperator::Lte)?;
 
             Some(Value::Bool(is_gte_start && is_lte_end))
         }
         Function::At(array_rule, index_rule) => {
-            let mut array_value = array_rule
-                .eval(input, output)?
+            let array_value = eval(input, output, array_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_array()?;
-            let index_value = index_rule
-                .eval(input, output)?
+            let index_value = eval(input, output, index_rule, tracer)?
                 .ok_or(Error::TypeError)?
-                .try_number()?
-                .as_u64()
-                .ok_or(Error::TypeError)?;
-            let index = usize::try_from(index_value).map_err(|_| Error::TypeError)?;
+                .try_number()?;
 
-            if array_value.get(index).is_none() {
-                return Err(Error::TypeError);
-            } else {
-                Some(array_value.swap_remove(index))
-            }
+            Some(at(array_value, index_value)?)
         }
         Function::Split(string_rule, pattern_rule) => {
-            let string_value = string_rule
-                .eval(input, output)?
+            let string_value = eval(input, output, string_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_string()?;
-            let pattern_value = pattern_rule
-                .eval(input, output)?
+            let pattern_value = eval(input, output, pattern_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_string()?;
 
@@ -956,106 +811,104 @@ fn eval(input: &Input, output: &mut Output, rule: &Rule) -> Result<Option<Value>
             Some(Value::Array(after_split))
         }
         Function::StartsWith(string_rule, starts_with_rule) => {
-            let string_value = string_rule
-                .eval(input, output)?
+            let string_value = eval(input, output, string_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_string()?;
-            let starts_with_value = starts_with_rule
-                .eval(input, output)?
+            let starts_with_value = eval(input, output, starts_with_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_string()?;
 
             Some(Value::Bool(string_value.starts_with(&starts_with_value)))
         }
         Function::EndsWith(string_rule, ends_with_rule) => {
-            let string_value = string_rule
-                .eval(input, output)?
+            let string_value = eval(input, output, string_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_string()?;
-            let ends_with_value = ends_with_rule
-                .eval(input, output)?
+            let ends_with_value = eval(input, output, ends_with_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_string()?;
 
             Some(Value::Bool(string_value.ends_with(&ends_with_value)))
         }
+        Function::HourOfDay(seconds_rule, timezone_rule) => {
+            let seconds = eval(input, output, seconds_rule, tracer)?
+                .ok_or(Error::TypeError)?
+                .try_number()?;
+            let timezone = eval(input, output, timezone_rule, tracer)?
+                .ok_or(Error::TypeError)?
+                .try_string()?;
+
+            Some(Value::new_number(local_time(seconds, &timezone)?.hour()))
+        }
+        Function::DayOfWeek(seconds_rule, timezone_rule) => {
+            let seconds = eval(input, output, seconds_rule, tracer)?
+                .ok_or(Error::TypeError)?
+                .try_number()?;
+            let timezone = eval(input, output, timezone_rule, tracer)?
+                .ok_or(Error::TypeError)?
+                .try_string()?;
+            let weekday = local_time(seconds, &timezone)?.weekday();
+
+            Some(Value::new_number(weekday.num_days_from_sunday()))
+        }
+        Function::EqIgnoreCase(first_rule, second_rule) => {
+            let a = eval(input, output, first_rule, tracer)?
+                .ok_or(Error::TypeError)?
+                .try_string()?;
+            let b = eval(input, output, second_rule, tracer)?
+                .ok_or(Error::TypeError)?
+                .try_string()?;
+
+            Some(Value::Bool(a.to_lowercase() == b.to_lowercase()))
+        }
+        Function::RegexMatch(string_rule, regex_rule) => {
+            let string_value = eval(input, output, string_rule, tracer)?
+                .ok_or(Error::TypeError)?
+                .try_string()?;
+            let regex_value = eval(input, output, regex_rule, tracer)?
+                .ok_or(Error::TypeError)?
+                .try_string()?;
+
+            Some(Value::Bool(regex_match(&string_value, &regex_value)?))
+        }
+        Function::HashBucket(string_rule, buckets_rule) => {
+            let string_value = eval(input, output, string_rule, tracer)?
+                .ok_or(Error::TypeError)?
+                .try_string()?;
+            let buckets = eval(input, output, buckets_rule, tracer)?
+                .ok_or(Error::TypeError)?
+                .try_number()?;
+
+            Some(hash_bucket(&string_value, buckets)?)
+        }
         Function::OnlyShowIf(rule) => {
-            let eval = rule
-                .eval(input, output)?
+            let show = eval(input, output, rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_bool()?;
-            let new_rule = Box::new(Rule::Value(Value::Bool(eval)));
+            let new_rule = Box::new(Rule::Value(Value::Bool(show)));
 
-            Function::Set(String::from("show"), new_rule).eval(input, output)?
+            eval(
+                input,
+                output,
+                &Rule::Function(Function::Set(String::from("show"), new_rule)),
+                tracer,
+            )?
         }
         Function::GetPriceInUsd(amount_rule) => {
-            let amount = amount_rule
-                .eval(input, output)?
+            let amount = eval(input, output, amount_rule, tracer)?
                 .ok_or(Error::TypeError)?
                 .try_bignum()?;
 
-            // if there is no way to get the deposit_asset, then fail with UnknownVariable
-            // since we can't calculate the price in USD
-            let deposit_asset = match &input.channel {
-                Some(Get::Getter(ChannelGetter::Full(full_channel))) => {
-                    Ok(full_channel.channel.deposit_asset.clone())
-                }
-                Some(Get::Getter(ChannelGetter::Market(channel))) => {
-                    Ok(channel.deposit_asset.clone())
-                }
-                // In case of a Values - we don't have the deposit_asset on hand so we fail in that case
-                // In case of None we also fail
-                _ => Err(Error::UnknownVariable),
-            }?;
-
-            let divisor = DEPOSIT_ASSETS_MAP
-                .get(&deposit_asset)
-                .ok_or(Error::TypeError)?;
-            let amount_in_usd = amount.div(divisor).to_f64().ok_or(Error::TypeError)?;
-            let amount_as_number = Number::from_f64(amount_in_usd).ok_or(Error::TypeError)?;
-            Some(Value::Number(amount_as_number))
-        }
-        Function::Do(first_rule) => eval(input, output, first_rule)?,
+            Some(price_in_usd(input, &amount)?)
+        }
+        Function::Do(first_rule) => eval(input, output, first_rule, tracer)?,
         Function::Set(key, rule) => {
             // Output variables can be set any number of times by different rules, except `show`
             // if `show` is at any point set to `false`, we stop executing rules and don't show the ad.
-            match key.as_str() {
-                "boost" => {
-                    let boost_num = rule
-                        .eval(input, output)?
-                        .ok_or(Error::TypeError)?
-                        .try_number()?;
-
-                    output.boost = boost_num.as_f64().ok_or(Error::TypeError)?;
-                }
-                "show" => {
-                    let show_value = rule
-                        .eval(input, output)?
-                        .ok_or(Error::TypeError)?
-                        .try_bool()?;
+            let variable = OutputVariable::from_key(key).ok_or(Error::UnknownVariable)?;
+            let value = eval(input, output, rule, tracer)?.ok_or(Error::TypeError)?;
 
-                    output.show = show_value;
-                }
-                "price.IMPRESSION" => {
-                    let price = rule
-                        .eval(input, output)?
-                        .ok_or(Error::TypeError)?
-                        .try_bignum()?;
-
-                    // we do not care about any other old value
-                    output.price.insert("IMPRESSION".to_string(), price);
-                }
-                "price.CLICK" => {
-                    let price = rule
-                        .eval(input, output)?
-                        .ok_or(Error::TypeError)?
-                        .try_bignum()?;
-
-                    // we do not care about any other old value
-                    output.price.insert("CLICK".to_string(), price);
-                }
-                _ => return Err(Error::UnknownVariable),
-            }
+            variable.set(output, value)?;
 
             return Ok(None);
         }
@@ -1074,6 +927,197 @@ fn eval(input: &Input, output: &mut Output, rule: &Rule) -> Result<Option<Value>
     Ok(value)
 }
 
+/// `div`, `mul`, `mod`, `add`, `sub`, `max` and `min`
+fn math(lhs: Value, rhs: Value, operator: MathOperator) -> Result<Value, Error> {
+    let (lhs, rhs) = match (lhs, rhs) {
+        (Value::BigNum(bignum), rhs_value) => (bignum, BigNum::try_from(rhs_value)?),
+        (lhs_value, Value::BigNum(rhs_bignum)) => (BigNum::try_from(lhs_value)?, rhs_bignum),
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            return Ok(Value::Number(math_operator(lhs, rhs, operator)?))
+        }
+        _ => return Err(Error::TypeError),
+    };
+
+    let bignum = match operator {
+        MathOperator::Division => lhs.div(rhs),
+        MathOperator::Multiplication => lhs.mul(rhs),
+        MathOperator::Modulus => lhs.rem(rhs),
+        MathOperator::Addition => lhs.add(rhs),
+        MathOperator::Subtraction => lhs.sub(rhs),
+        MathOperator::Max => lhs.max(rhs),
+        MathOperator::Min => lhs.min(rhs),
+    };
+
+    Ok(Value::BigNum(bignum))
+}
+
+/// `lt`, `lte`, `gt` and `gte`
+fn compare(lhs: Value, rhs: Value, operator: ComparisonOperator) -> Result<bool, Error> {
+    match (lhs, rhs) {
+        (Value::BigNum(bignum), rhs_value) => {
+            let rhs_bignum = BigNum::try_from(rhs_value)?;
+
+            Ok(handle_comparisons(bignum, rhs_bignum, operator))
+        }
+        (lhs_value, Value::BigNum(rhs_bignum)) => {
+            let lhs_bignum = BigNum::try_from(lhs_value)?;
+
+            Ok(handle_comparisons(lhs_bignum, rhs_bignum, operator))
+        }
+        (Value::Number(lhs), Value::Number(rhs)) => compare_numbers(lhs, rhs, operator),
+        _ => Err(Error::TypeError),
+    }
+}
+
+/// `eq`, which unlike the rest of the comparisons works for all types
+fn equals(lhs: Value, rhs: Value) -> Result<bool, Error> {
+    match (lhs, rhs) {
+        (Value::Bool(lhs), Value::Bool(rhs)) => Ok(lhs == rhs),
+        (Value::String(lhs), Value::String(rhs)) => Ok(lhs == rhs),
+        (Value::Array(lhs), Value::Array(rhs)) => Ok(lhs == rhs),
+        (lhs, rhs) => compare(lhs, rhs, ComparisonOperator::Eq),
+    }
+}
+
+fn at(mut array: Vec<Value>, index: Number) -> Result<Value, Error> {
+    let index = index.as_u64().ok_or(Error::TypeError)?;
+    let index = usize::try_from(index).map_err(|_| Error::TypeError)?;
+
+    if array.get(index).is_none() {
+        Err(Error::TypeError)
+    } else {
+        Ok(array.swap_remove(index))
+    }
+}
+
+fn price_in_usd(input: &Input, amount: &BigNum) -> Result<Value, Error> {
+    // if there is no way to get the deposit_asset, then fail with UnknownVariable
+    // since we can't calculate the price in USD
+    match &input.channel {
+        Some(Get::Getter(ChannelGetter::Full(_))) | Some(Get::Getter(ChannelGetter::Market(_))) => {
+        }
+        // In case of a Values - we don't have the deposit_asset on hand so we fail in that case
+        // In case of None we also fail
+        _ => return Err(Error::UnknownVariable),
+    };
+
+    // the price is set from the `TokenRegistry`, it's missing for unknown tokens
+    let amount_in_usd = input
+        .deposit_asset_price
+        .as_ref()
+        .and_then(|price| price.amount_in_usd(amount))
+        .ok_or(Error::TypeError)?;
+    let amount_as_number = Number::from_f64(amount_in_usd).ok_or(Error::TypeError)?;
+
+    Ok(Value::Number(amount_as_number))
+}
+
+/// The time of a timestamp in seconds in an IANA timezone, e.g. `Europe/Sofia`
+fn local_time(seconds: Number, timezone: &str) -> Result<DateTime<Tz>, Error> {
+    let seconds = seconds.as_i64().ok_or(Error::TypeError)?;
+    let timezone = timezone.parse::<Tz>().map_err(|_| Error::TypeError)?;
+    let time = Utc
+        .timestamp_opt(seconds, 0)
+        .single()
+        .ok_or(Error::TypeError)?;
+
+    Ok(time.with_timezone(&timezone))
+}
+
+/// The bucket is the 32-bit FNV-1a hash of the UTF-8 bytes of the string modulo the buckets,
+/// so that the JS implementation puts the string in the same bucket
+fn hash_bucket(string: &str, buckets: Number) -> Result<Value, Error> {
+    let buckets = buckets
+        .as_u64()
+        .filter(|buckets| *buckets > 0)
+        .ok_or(Error::TypeError)?;
+    let hash = string.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    });
+
+    Ok(Value::new_number(u64::from(hash) % buckets))
+}
+
+/// The most regexes kept compiled, the cache is cleared once it's full
+const REGEX_CACHE_SIZE: usize = 256;
+
+lazy_static! {
+    /// The compiled regexes of `regexMatch` by their pattern
+    static ref REGEX_CACHE: Mutex<HashMap<String, Regex>> = Mutex::new(HashMap::new());
+}
+
+/// The regex is compiled only the first time the pattern is matched.
+/// An invalid regex is a `TypeError`
+fn regex_match(string: &str, pattern: &str) -> Result<bool, Error> {
+    let cached = REGEX_CACHE
+        .lock()
+        .expect("Should lock the regex cache")
+        .get(pattern)
+        .cloned();
+
+    let regex = match cached {
+        Some(regex) => regex,
+        None => {
+            let regex = Regex::new(pattern).map_err(|_| Error::TypeError)?;
+
+            let mut cache = REGEX_CACHE.lock().expect("Should lock the regex cache");
+            if cache.len() >= REGEX_CACHE_SIZE {
+                cache.clear();
+            }
+            cache.insert(pattern.to_string(), regex.clone());
+
+            regex
+        }
+    };
+
+    Ok(regex.is_match(string))
+}
+
+/// The `Output` variables which can be `set`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputVariable {
+    Boost,
+    Show,
+    ImpressionPrice,
+    ClickPrice,
+}
+
+impl OutputVariable {
+    fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "boost" => Some(Self::Boost),
+            "show" => Some(Self::Show),
+            "price.IMPRESSION" => Some(Self::ImpressionPrice),
+            "price.CLICK" => Some(Self::ClickPrice),
+            _ => None,
+        }
+    }
+
+    fn set(self, output: &mut Output, value: Value) -> Result<(), Error> {
+        match self {
+            Self::Boost => {
+                let boost_num = value.try_number()?;
+
+                output.boost = boost_num.as_f64().ok_or(Error::TypeError)?;
+            }
+            Self::Show => output.show = value.try_bool()?,
+            // we do not care about any other old value
+            Self::ImpressionPrice => {
+                output
+                    .price
+                    .insert("IMPRESSION".to_string(), value.try_bignum()?);
+            }
+            Self::ClickPrice => {
+                output
+                    .price
+                    .insert("CLICK".to_string(), value.try_bignum()?);
+            }
+        }
+
+        Ok(())
+    }
+}
+
 /// Stops (i.e. it short-circuits) evaluating `Rule`s when `Output.show` becomes `false`
 pub fn eval_multiple(
     rules: &[Rule],
@@ -1093,6 +1137,28 @@ pub fn eval_multiple(
     results
 }
 
+/// Evaluates the `Rule`s like `eval_multiple`, but also records each evaluated `Function`
+/// with its evaluated arguments, result and the changes to the `Output`
+pub fn eval_with_trace(rules: &[Rule], input: &Input, output: &mut Output) -> Trace {
+    let mut tracer = Tracer::enabled();
+    let mut trace = Trace {
+        rules: vec![],
+        hidden_by_rule: None,
+    };
+
+    for (index, rule) in rules.iter().enumerate() {
+        let result = eval(input, output, rule, &mut tracer);
+        trace.rules.push(tracer.rule_step(rule, &result));
+
+        if !output.show {
+            trace.hidden_by_rule = Some(index);
+            break;
+        }
+    }
+
+    trace
+}
+
 pub fn eval_with_callback<F: Fn(Error, Rule)>(
     rules: &[Rule],
     input: &Input,
@@ -1116,6 +1182,7 @@ pub fn eval_with_callback<F: Fn(Error, Rule)>(
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum MathOperator {
     Division,
     Multiplication,
@@ -1126,6 +1193,7 @@ enum MathOperator {
     Min,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ComparisonOperator {
     /// First value is greater than second value
     Gt,
@@ -0,0 +1,776 @@
+//! The targeting `Rule`s compiled once per channel, instead of walking the `Rule`s for every event:
+//! - the variables of `get` and `set` are resolved when compiling
+//! - `bn` and the functions of constant arguments are folded into constants
+//! - of the operands of `and` & `or`, the one which can neither fail nor change the `Output`
+//!   is evaluated last, so it's skipped when the other one already decides the result
+//!
+//! The evaluation of `CompiledRules` has the same results and errors as `eval_multiple`.
+use super::{
+    at, compare, equals, hash_bucket, local_time, math, price_in_usd, regex_match,
+    ComparisonOperator, Error, Function, MathOperator, OutputVariable, Rule, Value,
+};
+use crate::targeting::{
+    check::{value_type, Type},
+    input::field::{self, Field},
+    GetField, Input, Output,
+};
+use chrono::{Datelike, Timelike};
+use regex::Regex;
+use std::str::FromStr;
+
+/// The `Rule`s compiled for evaluating them many times
+#[derive(Debug, Clone, Default)]
+pub struct CompiledRules {
+    /// The original `Rule` is kept for the errors
+    rules: Vec<(Rule, Node)>,
+}
+
+impl CompiledRules {
+    pub fn new(rules: &[Rule]) -> Self {
+        Self {
+            rules: rules
+                .iter()
+                .map(|rule| (rule.clone(), compile(rule)))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Stops (i.e. it short-circuits) evaluating `Rule`s when `Output.show` becomes `false`,
+    /// see `eval_multiple`
+    pub fn eval_multiple(
+        &self,
+        input: &Input,
+        output: &mut Output,
+    ) -> Vec<Result<Option<Value>, (Error, Rule)>> {
+        let mut results = vec![];
+
+        for (rule, node) in self.rules.iter() {
+            results.push(node.eval(input, output).map_err(|err| (err, rule.clone())));
+
+            if !output.show {
+                break;
+            }
+        }
+
+        results
+    }
+
+    /// See `eval_with_callback`
+    pub fn eval_with_callback<F: Fn(Error, Rule)>(
+        &self,
+        input: &Input,
+        output: &mut Output,
+        on_type_error: Option<F>,
+    ) {
+        for (rule, node) in self.rules.iter() {
+            match (node.eval(input, output), on_type_error.as_ref()) {
+                (Err(Error::TypeError), Some(on_type_error)) => {
+                    on_type_error(Error::TypeError, rule.clone())
+                }
+                // skip any other case, including Error::UnknownVariable
+                _ => {}
+            }
+
+            if !output.show {
+                return;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Logic {
+    And,
+    Or,
+}
+
+/// The functions which only evaluate all of their arguments and then compute their value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    Math(MathOperator),
+    Compare(ComparisonOperator),
+    Eq,
+    Not,
+    Xor,
+    Intersects,
+    In,
+    At,
+    Split,
+    StartsWith,
+    EndsWith,
+    HourOfDay,
+    DayOfWeek,
+    EqIgnoreCase,
+    RegexMatch,
+    HashBucket,
+}
+
+impl Operation {
+    /// The type of each argument, checked right after it's evaluated,
+    /// so that the errors are in the same order as when evaluating the `Rule`
+    fn arg_types(self) -> &'static [Type] {
+        match self {
+            Operation::Math(_) | Operation::Compare(_) | Operation::Eq => &[Type::Any, Type::Any],
+            Operation::Not => &[Type::Bool],
+            Operation::Xor => &[Type::Bool, Type::Bool],
+            Operation::Intersects => &[Type::Array, Type::Array],
+            Operation::In => &[Type::Array, Type::Any],
+            Operation::At => &[Type::Array, Type::Number],
+            Operation::Split
+            | Operation::StartsWith
+            | Operation::EndsWith
+            | Operation::EqIgnoreCase
+            | Operation::RegexMatch => &[Type::String, Type::String],
+            Operation::HourOfDay | Operation::DayOfWeek => &[Type::Number, Type::String],
+            Operation::HashBucket => &[Type::String, Type::Number],
+        }
+    }
+
+    /// The type of the result if the operation can't fail for arguments of these types
+    fn infallible_type(self, arg_types: &[Type]) -> Option<Type> {
+        match (self, arg_types) {
+            (Operation::Not, [Type::Bool]) | (Operation::Xor, [Type::Bool, Type::Bool]) => {
+                Some(Type::Bool)
+            }
+            (Operation::Eq, [lhs, rhs]) if lhs == rhs && *lhs != Type::Any => Some(Type::Bool),
+            (Operation::Compare(_), [Type::Number, Type::Number])
+            | (Operation::Compare(_), [Type::BigNum, Type::BigNum]) => Some(Type::Bool),
+            (Operation::In, [Type::Array, _])
+            | (Operation::Intersects, [Type::Array, Type::Array])
+            | (Operation::StartsWith, [Type::String, Type::String])
+            | (Operation::EndsWith, [Type::String, Type::String])
+            | (Operation::EqIgnoreCase, [Type::String, Type::String]) => Some(Type::Bool),
+            (Operation::Split, [Type::String, Type::String]) => Some(Type::Array),
+            _ => None,
+        }
+    }
+
+    /// The arguments are already checked with `arg_types`
+    fn apply(self, args: Vec<Value>) -> Result<Value, Error> {
+        let mut args = args.into_iter();
+        let mut arg = || args.next().ok_or(Error::TypeError);
+
+        let value = match self {
+            Operation::Math(operator) => math(arg()?, arg()?, operator)?,
+            Operation::Compare(operator) => Value::Bool(compare(arg()?, arg()?, operator)?),
+            Operation::Eq => Value::Bool(equals(arg()?, arg()?)?),
+            Operation::Not => Value::Bool(!arg()?.try_bool()?),
+            Operation::Xor => Value::Bool(arg()?.try_bool()? ^ arg()?.try_bool()?),
+            Operation::Intersects => {
+                let a = arg()?.try_array()?;
+                let b = arg()?.try_array()?;
+
+                Value::Bool(a.iter().any(|x| b.contains(x)))
+            }
+            Operation::In => {
+                let array = arg()?.try_array()?;
+
+                Value::Bool(array.contains(&arg()?))
+            }
+            Operation::At => at(arg()?.try_array()?, arg()?.try_number()?)?,
+            Operation::Split => {
+                let string = arg()?.try_string()?;
+                let pattern = arg()?.try_string()?;
+
+                Value::Array(string.split(&pattern).map(Value::new_string).collect())
+            }
+            Operation::StartsWith => {
+                let string = arg()?.try_string()?;
+
+                Value::Bool(string.starts_with(&arg()?.try_string()?))
+            }
+            Operation::EndsWith => {
+                let string = arg()?.try_string()?;
+
+                Value::Bool(string.ends_with(&arg()?.try_string()?))
+            }
+            Operation::HourOfDay => {
+                let seconds = arg()?.try_number()?;
+
+                Value::new_number(local_time(seconds, &arg()?.try_string()?)?.hour())
+            }
+            Operation::DayOfWeek => {
+                let seconds = arg()?.try_number()?;
+                let weekday = local_time(seconds, &arg()?.try_string()?)?.weekday();
+
+                Value::new_number(weekday.num_days_from_sunday())
+            }
+            Operation::EqIgnoreCase => {
+                let a = arg()?.try_string()?;
+                let b = arg()?.try_string()?;
+
+                Value::Bool(a.to_lowercase() == b.to_lowercase())
+            }
+            Operation::RegexMatch => {
+                let string = arg()?.try_string()?;
+
+                Value::Bool(regex_match(&string, &arg()?.try_string()?)?)
+            }
+            Operation::HashBucket => {
+                let string = arg()?.try_string()?;
+
+                hash_bucket(&string, arg()?.try_number()?)?
+            }
+        };
+
+        Ok(value)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Const(Value),
+    /// Fails when evaluated, e.g. a `bn` of a string which is not a number
+    Fail(Error),
+    Input(Field),
+    /// The variables which are not an `Input` `Field`, i.e. the `Output` ones
+    Output(String),
+    Apply(Operation, Vec<Node>),
+    If(Box<Node>, Box<Node>),
+    IfNot(Box<Node>, Box<Node>),
+    IfElse(Box<Node>, Box<Node>, Box<Node>),
+    /// `second` is skipped when `short_circuit` is set and `first` decides the result
+    Logic {
+        logic: Logic,
+        first: Box<Node>,
+        second: Box<Node>,
+        short_circuit: bool,
+    },
+    Set(OutputVariable, Box<Node>),
+    GetPriceInUsd(Box<Node>),
+    /// The `regexMatch` of a literal regex, which is compiled only once
+    RegexMatch(Box<Node>, Regex),
+    /// The value is evaluated once and then compared to both bounds
+    Between {
+        value: Box<Node>,
+        min: Box<Node>,
+        max: Box<Node>,
+    },
+}
+
+fn binary(operation: Operation, first: &Rule, second: &Rule) -> Node {
+    apply(operation, vec![compile(first), compile(second)])
+}
+
+/// Folds the operation into a constant if all of its arguments are constants
+fn apply(operation: Operation, args: Vec<Node>) -> Node {
+    let constants = args
+        .iter()
+        .zip(operation.arg_types())
+        .map(|(arg, arg_type)| match arg {
+            Node::Const(value) => Some(check(value.clone(), *arg_type)),
+            _ => None,
+        })
+        .collect::<Option<Result<Vec<_>, _>>>();
+
+    match constants {
+        Some(Ok(values)) if !may_panic(operation, &values) => fold(operation.apply(values)),
+        Some(Err(error)) => Node::Fail(error),
+        _ => Node::Apply(operation, args),
+    }
+}
+
+/// The `BigNum` division by zero and subtraction below zero panic,
+/// so they are left for the evaluation, which might not even reach them
+fn may_panic(operation: Operation, values: &[Value]) -> bool {
+    let is_bignum = values.iter().any(|value| matches!(value, Value::BigNum(_)));
+
+    match operation {
+        Operation::Math(MathOperator::Division)
+        | Operation::Math(MathOperator::Modulus)
+        | Operation::Math(MathOperator::Subtraction) => is_bignum,
+        _ => false,
+    }
+}
+
+fn logic(logic: Logic, first: Node, second: Node) -> Node {
+    if let (Node::Const(first), Node::Const(second)) = (&first, &second) {
+        let result = first.clone().try_bool().and_then(|first| {
+            let second = second.clone().try_bool()?;
+
+            Ok(Value::Bool(logic.apply(first, second)))
+        });
+
+        return fold(result);
+    }
+
+    let is_bool = |node: &Node| node.infallible_type() == Some(Type::Bool);
+
+    let (first, second, short_circuit) = if is_bool(&second) {
+        (first, second, true)
+    } else if is_bool(&first) {
+        // `first` can neither fail nor change the `Output`, so evaluating it last is the same
+        (second, first, true)
+    } else {
+        (first, second, false)
+    };
+
+    Node::Logic {
+        logic,
+        first: Box::new(first),
+        second: Box::new(second),
+        short_circuit,
+    }
+}
+
+fn between(value: Node, min: Node, max: Node) -> Node {
+    if let (Node::Const(value), Node::Const(min), Node::Const(max)) = (&value, &min, &max) {
+        return fold(between_bounds(value.clone(), min.clone(), || {
+            Ok(max.clone())
+        }));
+    }
+
+    Node::Between {
+        value: Box::new(value),
+        min: Box::new(min),
+        max: Box::new(max),
+    }
+}
+
+/// `max` is only evaluated after `value` is compared to `min`, like when evaluating the `Rule`
+fn between_bounds(
+    value: Value,
+    min: Value,
+    max: impl FnOnce() -> Result<Value, Error>,
+) -> Result<Value, Error> {
+    let is_gte_start = compare(value.clone(), min, ComparisonOperator::Gte)?;
+    let is_lte_end = compare(value, max()?, ComparisonOperator::Lte)?;
+
+    Ok(Value::Bool(is_gte_start && is_lte_end))
+}
+
+fn fold(result: Result<Value, Error>) -> Node {
+    match result {
+        Ok(value) => Node::Const(value),
+        Err(error) => Node::Fail(error),
+    }
+}
+
+fn check(value: Value, value_type: Type) -> Result<Value, Error> {
+    match value_type {
+        Type::Any => Ok(value),
+        Type::Bool => value.try_bool().map(Value::Bool),
+        Type::Number => value.try_number().map(Value::Number),
+        Type::String => value.try_string().map(Value::String),
+        Type::Array => value.try_array().map(Value::Array),
+        Type::BigNum => value.try_bignum().map(Value::BigNum),
+    }
+}
+
+fn compile(rule: &Rule) -> Node {
+    let function = match rule {
+        Rule::Value(value) => return Node::Const(value.clone()),
+        Rule::Function(function) => function,
+    };
+
+    match function {
+        Function::MulDiv(first, second, third) => {
+            let product = binary(Operation::Math(MathOperator::Multiplication), first, second);
+
+            apply(
+                Operation::Math(MathOperator::Division),
+                vec![product, compile(third)],
+            )
+        }
+        Function::Div(first, second) => {
+            binary(Operation::Math(MathOperator::Division), first, second)
+        }
+        Function::Mul(first, second) => {
+            binary(Operation::Math(MathOperator::Multiplication), first, second)
+        }
+        Function::Mod(first, second) => {
+            binary(Operation::Math(MathOperator::Modulus), first, second)
+        }
+        Function::Add(first, second) => {
+            binary(Operation::Math(MathOperator::Addition), first, second)
+        }
+        Function::Sub(first, second) => {
+            binary(Operation::Math(MathOperator::Subtraction), first, second)
+        }
+        Function::Max(first, second) => binary(Operation::Math(MathOperator::Max), first, second),
+        Function::Min(first, second) => binary(Operation::Math(MathOperator::Min), first, second),
+        Function::If(condition, then) => {
+            Node::If(Box::new(compile(condition)), Box::new(compile(then)))
+        }
+        Function::IfNot(condition, then) => {
+            Node::IfNot(Box::new(compile(condition)), Box::new(compile(then)))
+        }
+        Function::IfElse(condition, then, otherwise) => Node::IfElse(
+            Box::new(compile(condition)),
+            Box::new(compile(then)),
+            Box::new(compile(otherwise)),
+        ),
+        Function::And(first, second) => logic(Logic::And, compile(first), compile(second)),
+        Function::Or(first, second) => logic(Logic::Or, compile(first), compile(second)),
+        Function::Xor(first, second) => binary(Operation::Xor, first, second),
+        Function::Not(rule) => apply(Operation::Not, vec![compile(rule)]),
+        Function::Lt(first, second) => {
+            binary(Operation::Compare(ComparisonOperator::Lt), first, second)
+        }
+        Function::Lte(first, second) => {
+            binary(Operation::Compare(ComparisonOperator::Lte), first, second)
+        }
+        Function::Gt(first, second) => {
+            binary(Operation::Compare(ComparisonOperator::Gt), first, second)
+        }
+        Function::Gte(first, second) => {
+            binary(Operation::Compare(ComparisonOperator::Gte), first, second)
+        }
+        Function::Eq(first, second) => binary(Operation::Eq, first, second),
+        Function::Neq(first, second) => {
+            apply(Operation::Not, vec![binary(Operation::Eq, first, second)])
+        }
+        Function::Intersects(first, second) => binary(Operation::Intersects, first, second),
+        Function::In(array, value) => binary(Operation::In, array, value),
+        Function::Nin(array, value) => {
+            apply(Operation::Not, vec![binary(Operation::In, array, value)])
+        }
+        Function::Between(min, max, value) => between(compile(value), compile(min), compile(max)),
+        Function::At(array, index) => binary(Operation::At, array, index),
+        Function::Split(string, pattern) => binary(Operation::Split, string, pattern),
+        Function::StartsWith(string, prefix) => binary(Operation::StartsWith, string, prefix),
+        Function::EndsWith(string, suffix) => binary(Operation::EndsWith, string, suffix),
+        Function::HourOfDay(seconds, timezone) => binary(Operation::HourOfDay, seconds, timezone),
+        Function::DayOfWeek(seconds, timezone) => binary(Operation::DayOfWeek, seconds, timezone),
+        Function::EqIgnoreCase(lhs, rhs) => binary(Operation::EqIgnoreCase, lhs, rhs),
+        Function::RegexMatch(string, regex) => {
+            let literal = match regex.as_ref() {
+                Rule::Value(Value::String(regex)) => Regex::new(regex).ok(),
+                _ => None,
+            };
+
+            match literal {
+                Some(regex) => Node::RegexMatch(Box::new(compile(string)), regex),
+                // an invalid regex fails, after the string is evaluated
+                None => binary(Operation::RegexMatch, string, regex),
+            }
+        }
+        Function::HashBucket(string, buckets) => binary(Operation::HashBucket, string, buckets),
+        Function::OnlyShowIf(rule) => Node::Set(OutputVariable::Show, Box::new(compile(rule))),
+        Function::GetPriceInUsd(amount) => Node::GetPriceInUsd(Box::new(compile(amount))),
+        Function::Do(rule) => compile(rule),
+        Function::Set(key, rule) => match OutputVariable::from_key(key) {
+            Some(variable) => Node::Set(variable, Box::new(compile(rule))),
+            None => Node::Fail(Error::UnknownVariable),
+        },
+        // the `Output` variables are not valid `Field`s
+        Function::Get(key) => match Field::from_str(key) {
+            Ok(field) => Node::Input(field),
+            Err(_) => Node::Output(key.clone()),
+        },
+        Function::Bn(value) => fold(value.clone().try_bignum().map(Value::BigNum)),
+    }
+}
+
+impl Logic {
+    fn apply(self, first: bool, second: bool) -> bool {
+        match self {
+            Logic::And => first && second,
+            Logic::Or => first || second,
+        }
+    }
+
+    /// Whether the result is known from the first operand
+    fn is_decided_by(self, first: bool) -> bool {
+        match self {
+            Logic::And => !first,
+            Logic::Or => first,
+        }
+    }
+}
+
+impl Node {
+    /// The type of the node if its evaluation can neither fail nor change the `Output`
+    fn infallible_type(&self) -> Option<Type> {
+        match self {
+            Node::Const(value) => Some(value_type(value)),
+            // the `Global` fields which are always set
+            Node::Input(Field::Global(global)) => match global {
+                field::Global::AdSlotId
+                | field::Global::AdSlotType
+                | field::Global::PublisherId
+                | field::Global::EventType => Some(Type::String),
+                field::Global::SecondsSinceEpoch => Some(Type::Number),
+                _ => None,
+            },
+            Node::Apply(operation, args) => {
+                let arg_types = args
+                    .iter()
+                    .map(Node::infallible_type)
+                    .collect::<Option<Vec<_>>>()?;
+
+                operation.infallible_type(&arg_types)
+            }
+            Node::Logic { first, second, .. } => {
+                match (first.infallible_type()?, second.infallible_type()?) {
+                    (Type::Bool, Type::Bool) => Some(Type::Bool),
+                    _ => None,
+                }
+            }
+            Node::Between { value, min, max } => {
+                let comparison = Operation::Compare(ComparisonOperator::Gte);
+                let value = value.infallible_type()?;
+
+                comparison.infallible_type(&[value, min.infallible_type()?])?;
+                comparison.infallible_type(&[value, max.infallible_type()?])
+            }
+            _ => None,
+        }
+    }
+
+    fn eval(&self, input: &Input, output: &mut Output) -> Result<Option<Value>, Error> {
+        let value = match self {
+            Node::Const(value) => value.clone(),
+            Node::Fail(error) => return Err(*error),
+            Node::Input(field) => input.get(field).ok_or(Error::UnknownVariable)?,
+            Node::Output(key) => output.try_get(key)?,
+            Node::Apply(operation, args) => {
+                let mut values = Vec::with_capacity(args.len());
+
+                for (arg, arg_type) in args.iter().zip(operation.arg_types()) {
+                    values.push(check(arg.value(input, output)?, *arg_type)?);
+                }
+
+                operation.apply(values)?
+            }
+            Node::If(condition, then) => {
+                return if condition.value(input, output)?.try_bool()? {
+                    then.eval(input, output)
+                } else {
+                    Ok(None)
+                };
+            }
+            Node::IfNot(condition, then) => {
+                return if !condition.value(input, output)?.try_bool()? {
+                    then.eval(input, output)
+                } else {
+                    Ok(None)
+                };
+            }
+            Node::IfElse(condition, then, otherwise) => {
+                return if condition.value(input, output)?.try_bool()? {
+                    then.eval(input, output)
+                } else {
+                    otherwise.eval(input, output)
+                };
+            }
+            Node::Logic {
+                logic,
+                first,
+                second,
+                short_circuit,
+            } => {
+                let first = first.value(input, output)?.try_bool()?;
+
+                if *short_circuit && logic.is_decided_by(first) {
+                    Value::Bool(first)
+                } else {
+                    let second = second.value(input, output)?.try_bool()?;
+
+                    Value::Bool(logic.apply(first, second))
+                }
+            }
+            Node::Set(variable, node) => {
+                let value = node.value(input, output)?;
+                variable.set(output, value)?;
+
+                return Ok(None);
+            }
+            Node::GetPriceInUsd(amount) => {
+                let amount = amount.value(input, output)?.try_bignum()?;
+
+                price_in_usd(input, &amount)?
+            }
+            Node::RegexMatch(string, regex) => {
+                let string = string.value(input, output)?.try_string()?;
+
+                Value::Bool(regex.is_match(&string))
+            }
+            Node::Between { value, min, max } => {
+                let value = value.value(input, output)?;
+                let min = min.value(input, output)?;
+
+                between_bounds(value, min, || max.value(input, output))?
+            }
+        };
+
+        Ok(Some(value))
+    }
+
+    /// Fails with `Error::TypeError` if the node has no value, e.g. a `set`
+    fn value(&self, input: &Input, output: &mut Output) -> Result<Value, Error> {
+        self.eval(input, output)?.ok_or(Error::TypeError)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        targeting::{eval_multiple, input, text::parse_rules},
+        util::tests::prep_db::{DUMMY_CHANNEL, IDS},
+    };
+    use chrono::{TimeZone, Utc};
+
+    fn get_input(ad_view: Option<input::AdView>) -> Input {
+        Input {
+            ad_view,
+            global: input::Global {
+                ad_slot_id: "ad_slot_id Value".to_string(),
+                ad_slot_type: "legacy_300x100".to_string(),
+                publisher_id: IDS["leader"],
+                country: Some("BG".to_string()),
+                event_type: "IMPRESSION".to_string(),
+                seconds_since_epoch: Utc.ymd(2020, 11, 6).and_hms(12, 0, 0),
+                user_agent_os: None,
+                user_agent_browser_family: Some("Firefox".to_string()),
+            },
+            channel: None,
+            balances: None,
+            ad_unit_id: None,
+            ad_slot: None,
+            deposit_asset_price: None,
+        }
+        .with_channel(DUMMY_CHANNEL.clone())
+    }
+
+    /// Evaluates the rules both compiled and with `eval_multiple` and compares the results
+    fn assert_same_evaluation(rules: &str, input: &Input) {
+        let rules = parse_rules(rules).expect("Should parse the rules");
+
+        let mut output = Output::from(&*DUMMY_CHANNEL);
+        let expected = eval_multiple(&rules, input, &mut output);
+
+        let mut compiled_output = Output::from(&*DUMMY_CHANNEL);
+        let compiled = CompiledRules::new(&rules).eval_multiple(input, &mut compiled_output);
+
+        assert_eq!(expected, compiled);
+        assert_eq!(output.show, compiled_output.show);
+        assert_eq!(output.boost, compiled_output.boost);
+        assert_eq!(output.price, compiled_output.price);
+    }
+
+    #[test]
+    fn evaluates_like_the_rules() {
+        let rules = r#"
+            set boost = 2;
+            set price.IMPRESSION = bn("1000") * 3n;
+            set price.CLICK = max(get("price.IMPRESSION"), 5000n) / 2n;
+            onlyShowIf(adSlotType in ["legacy_300x100", "legacy_728x90"]);
+            if country nin ["US", "CA"] then set boost = 1.5;
+            if eventType == "CLICK" and userAgentBrowserFamily == "Chrome" then set show = false;
+            if adView.secondsSinceCampaignImpression < 300 or eventType == "CLICK" then set show = false;
+            set price.IMPRESSION = get("price.IMPRESSION") + campaignBudget / 1000000n;
+            onlyShowIf(not (startsWith(adSlotId, "ad_slot") xor endsWith(adSlotId, "Value")))
+        "#;
+
+        assert_same_evaluation(rules, &get_input(None));
+
+        let ad_view = input::AdView {
+            seconds_since_campaign_impression: 200,
+            has_custom_preferences: false,
+            navigator_language: "bg".to_string(),
+        };
+        assert_same_evaluation(rules, &get_input(Some(ad_view)));
+    }
+
+    #[test]
+    fn fails_like_the_rules() {
+        let rules = r#"
+            set price.IMPRESSION = bn("not a number");
+            set unknown = 1;
+            get("unknown");
+            set boost = userAgentOS;
+            set show = eventType == 1 and adView.hasCustomPreferences;
+            set show = 1 < "2";
+            at([1, 2], 5);
+            not 1
+        "#;
+
+        assert_same_evaluation(rules, &get_input(None));
+    }
+
+    #[test]
+    fn folds_constants() {
+        let rules = parse_rules(r#"bn("1000") * 3n; 1 + 2 == 3 and true; 2 + "1""#)
+            .expect("Should parse the rules");
+        let compiled = CompiledRules::new(&rules);
+
+        let nodes = compiled
+            .rules
+            .iter()
+            .map(|(_, node)| node)
+            .collect::<Vec<_>>();
+        assert!(matches!(nodes[0], Node::Const(Value::BigNum(_))));
+        assert!(matches!(nodes[1], Node::Const(Value::Bool(true))));
+        assert!(matches!(nodes[2], Node::Fail(Error::TypeError)));
+
+        // it would panic when compiling the channel's rules
+        let rule: Rule = "if false then 1n - 2n"
+            .parse()
+            .expect("Should parse the rule");
+        let compiled = CompiledRules::new(&[rule]);
+        let mut output = Output::from(&*DUMMY_CHANNEL);
+        assert_eq!(
+            vec![Ok(None)],
+            compiled.eval_multiple(&get_input(None), &mut output)
+        );
+    }
+
+    #[test]
+    fn evaluates_the_between_value_once() {
+        let rules = r#"
+            between(1600000000, 1700000000, secondsSinceEpoch);
+            between(1, "3", secondsSinceEpoch);
+            between("1", 3, adView.navigatorLanguage)
+        "#;
+        assert_same_evaluation(rules, &get_input(None));
+
+        let node = compile(
+            &"between(1600000000, 1700000000, secondsSinceEpoch)"
+                .parse()
+                .expect("Should parse the rule"),
+        );
+        assert!(matches!(node, Node::Between { .. }));
+        assert_eq!(Some(Type::Bool), node.infallible_type());
+
+        let node = compile(&"between(1, 3, 2)".parse().expect("Should parse the rule"));
+        assert!(matches!(node, Node::Const(Value::Bool(true))));
+    }
+
+    #[test]
+    fn short_circuits_only_the_infallible_operands() {
+        let input = get_input(None);
+        let mut output = Output::from(&*DUMMY_CHANNEL);
+
+        // `eventType == "CLICK"` is evaluated last, after the missing `adView` variable fails the rule
+        let node = compile(
+            &"eventType == \"CLICK\" and adView.hasCustomPreferences"
+                .parse()
+                .expect("Should parse the rule"),
+        );
+        assert!(matches!(node, Node::Logic { short_circuit: true, .. }));
+        assert_eq!(Err(Error::UnknownVariable), node.eval(&input, &mut output));
+
+        // `eventType == "IMPRESSION"` would decide the result, but the first operand still fails
+        let node = compile(
+            &"adView.hasCustomPreferences or eventType == \"IMPRESSION\""
+                .parse()
+                .expect("Should parse the rule"),
+        );
+        assert!(matches!(node, Node::Logic { short_circuit: true, .. }));
+        assert_eq!(Err(Error::UnknownVariable), node.eval(&input, &mut output));
+
+        // `country` is optional, so neither operand is skipped
+        let node = compile(
+            &"country == \"US\" and adView.hasCustomPreferences"
+                .parse()
+                .expect("Should parse the rule"),
+        );
+        assert!(matches!(node, Node::Logic { short_circuit: false, .. }));
+    }
+}
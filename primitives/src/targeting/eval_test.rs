@@ -3,9 +3,11 @@ use chrono::{TimeZone, Utc};
 use super::*;
 use crate::{
     targeting::input,
+    token::{PriceSource, StaticPriceProvider, TokenInfo, TokenRegistry},
     util::tests::prep_db::{DUMMY_CHANNEL, DUMMY_IPFS, IDS},
     BalancesMap,
 };
+use std::sync::Arc;
 
 fn get_default_input() -> Input {
     let input_balances = BalancesMap::default();
@@ -30,6 +32,7 @@ fn get_default_input() -> Input {
         balances: None,
         ad_unit_id: Some(DUMMY_IPFS[0].clone()),
         ad_slot: None,
+        deposit_asset_price: None,
     };
 
     // Set the Channel, Balances and AdUnit for the Input
@@ -1375,19 +1378,373 @@ mod string_and_array {
             boost: 1.0,
             price: Default::default(),
         };
-        for (key, value) in &*DEPOSIT_ASSETS_MAP {
+        let tokens = vec![
+            ("0x89d24A6b4CcB1B6fAA2625fE562bDD9a23260359", 18, 1.0),
+            ("0xdac17f958d2ee523a2206206994597c13d831ec7", 6, 1.0),
+            ("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", 18, 400.0),
+        ];
+        let token_registry = TokenRegistry::new(
+            tokens
+                .iter()
+                .map(|(address, decimals, usd)| TokenInfo {
+                    address: address.to_string(),
+                    symbol: String::new(),
+                    decimals: *decimals,
+                    price_source: PriceSource::Static { usd: *usd },
+                })
+                .collect(),
+            Arc::new(StaticPriceProvider),
+        );
+
+        for (address, decimals, usd) in tokens.iter() {
             let mut asset_channel = DUMMY_CHANNEL.clone();
-            asset_channel.deposit_asset = key.to_string();
-            let input = get_default_input().with_channel(asset_channel);
+            asset_channel.deposit_asset = address.to_string();
+            let input = get_default_input()
+                .with_channel(asset_channel)
+                .with_deposit_asset_price(&token_registry);
 
-            let amount_crypto = BigNum::from(100).mul(value);
+            let amount_crypto = BigNum::from(10u64.pow((*decimals).into())).mul(&BigNum::from(100));
             let amount_usd = Some(Value::Number(
-                Number::from_f64(100.0).expect("should create a float"),
+                Number::from_f64(100.0 * usd).expect("should create a float"),
             ));
             let rule = Rule::Function(Function::new_get_price_in_usd(Rule::Value(Value::BigNum(
                 amount_crypto,
             ))));
             assert_eq!(Ok(amount_usd), rule.eval(&input, &mut output));
         }
+
+        // a token which is not in the registry
+        let mut unknown_asset_channel = DUMMY_CHANNEL.clone();
+        unknown_asset_channel.deposit_asset = "0x0000000000000000000000000000000000000001".into();
+        let input = get_default_input()
+            .with_channel(unknown_asset_channel)
+            .with_deposit_asset_price(&token_registry);
+        let rule = Rule::Function(Function::new_get_price_in_usd(Rule::Value(Value::BigNum(
+            BigNum::from(100),
+        ))));
+        assert_eq!(Err(Error::TypeError), rule.eval(&input, &mut output));
+    }
+
+    #[test]
+    fn test_eq_ignore_case_eval() {
+        let input = get_default_input();
+        let mut output = Output {
+            show: true,
+            boost: 1.0,
+            price: Default::default(),
+        };
+
+        let cases = [
+            ("BG", "bg", true),
+            ("Straße", "STRASSE", false),
+            ("Ünï", "üNÏ", true),
+        ];
+
+        for (lhs, rhs, expected) in cases.iter() {
+            let rule = Rule::Function(Function::new_eq_ignore_case(
+                Value::new_string(lhs),
+                Value::new_string(rhs),
+            ));
+            assert_eq!(
+                Ok(Some(Value::Bool(*expected))),
+                rule.eval(&input, &mut output)
+            );
+        }
+
+        let not_strings = Rule::Function(Function::new_eq_ignore_case(
+            Value::new_string("1"),
+            Value::new_number(1),
+        ));
+        assert_eq!(Err(Error::TypeError), not_strings.eval(&input, &mut output));
+    }
+
+    #[test]
+    fn test_regex_match_eval() {
+        let input = get_default_input();
+        let mut output = Output {
+            show: true,
+            boost: 1.0,
+            price: Default::default(),
+        };
+
+        let cases = [
+            ("news.example.com", r"\.example\.com$", true),
+            ("example.com.evil.org", r"\.example\.com$", false),
+            ("ad_slot_id Value", r"^ad_slot_\w+", true),
+        ];
+
+        for (string, regex, expected) in cases.iter() {
+            let rule = Rule::Function(Function::new_regex_match(
+                Value::new_string(string),
+                Value::new_string(regex),
+            ));
+            assert_eq!(
+                Ok(Some(Value::Bool(*expected))),
+                rule.eval(&input, &mut output)
+            );
+        }
+
+        // the regex of a variable
+        let rule = Rule::Function(Function::new_regex_match(
+            Value::new_string("ad_slot_id Value"),
+            Function::new_get("adSlotId"),
+        ));
+        assert_eq!(Ok(Some(Value::Bool(true))), rule.eval(&input, &mut output));
+
+        let invalid_regex = Rule::Function(Function::new_regex_match(
+            Value::new_string("string"),
+            Value::new_string("(unclosed"),
+        ));
+        assert_eq!(
+            Err(Error::TypeError),
+            invalid_regex.eval(&input, &mut output)
+        );
+
+        // the valid regexes are compiled once and kept for the next evaluations
+        let cache = REGEX_CACHE.lock().expect("Should lock the regex cache");
+        assert!(cache.contains_key(r"\.example\.com$"));
+        // the regex of the variable too
+        assert!(cache.contains_key("ad_slot_id Value"));
+        assert!(!cache.contains_key("(unclosed"));
+    }
+}
+
+mod time_and_sampling {
+    use super::*;
+
+    #[test]
+    fn test_hour_of_day_eval() {
+        // 2020-11-06 12:00:00 UTC
+        let input = get_default_input();
+        let mut output = Output {
+            show: true,
+            boost: 1.0,
+            price: Default::default(),
+        };
+
+        let cases = [
+            ("UTC", 12),
+            ("Europe/Sofia", 14),
+            ("America/New_York", 7),
+            ("Asia/Kolkata", 17),
+        ];
+
+        for (timezone, expected) in cases.iter() {
+            let rule = Rule::Function(Function::new_hour_of_day(
+                Function::new_get("secondsSinceEpoch"),
+                Value::new_string(timezone),
+            ));
+            assert_eq!(
+                Ok(Some(Value::new_number(*expected))),
+                rule.eval(&input, &mut output),
+                "in {}",
+                timezone
+            );
+        }
+
+        // the daylight saving time is applied: 2020-07-01 12:00:00 UTC
+        let summer = Rule::Function(Function::new_hour_of_day(
+            Value::new_number(1_593_604_800),
+            Value::new_string("Europe/Sofia"),
+        ));
+        assert_eq!(
+            Ok(Some(Value::new_number(15))),
+            summer.eval(&input, &mut output)
+        );
+
+        let unknown_timezone = Rule::Function(Function::new_hour_of_day(
+            Function::new_get("secondsSinceEpoch"),
+            Value::new_string("Mars/Olympus"),
+        ));
+        assert_eq!(
+            Err(Error::TypeError),
+            unknown_timezone.eval(&input, &mut output)
+        );
+    }
+
+    #[test]
+    fn test_day_of_week_eval() {
+        // Friday, 2020-11-06 12:00:00 UTC
+        let input = get_default_input();
+        let mut output = Output {
+            show: true,
+            boost: 1.0,
+            price: Default::default(),
+        };
+
+        let cases = [
+            ("UTC", 5),
+            // already Saturday
+            ("Pacific/Kiritimati", 6),
+        ];
+
+        for (timezone, expected) in cases.iter() {
+            let rule = Rule::Function(Function::new_day_of_week(
+                Function::new_get("secondsSinceEpoch"),
+                Value::new_string(timezone),
+            ));
+            assert_eq!(
+                Ok(Some(Value::new_number(*expected))),
+                rule.eval(&input, &mut output),
+                "in {}",
+                timezone
+            );
+        }
+
+        // Sunday is 0, like in JS
+        let sunday = Rule::Function(Function::new_day_of_week(
+            Value::new_number(1_604_836_800),
+            Value::new_string("UTC"),
+        ));
+        assert_eq!(
+            Ok(Some(Value::new_number(0))),
+            sunday.eval(&input, &mut output)
+        );
+    }
+
+    #[test]
+    fn test_hash_bucket_eval() {
+        let input = get_default_input();
+        let mut output = Output {
+            show: true,
+            boost: 1.0,
+            price: Default::default(),
+        };
+
+        // the buckets of the 32-bit FNV-1a hashes, which are the same in the JS implementation
+        let cases = [
+            ("", 100, 61),
+            ("a", 100, 20),
+            ("user-2", 100, 57),
+            ("user-2", 2, 1),
+        ];
+
+        for (string, buckets, expected) in cases.iter() {
+            let rule = Rule::Function(Function::new_hash_bucket(
+                Value::new_string(string),
+                Value::new_number(*buckets),
+            ));
+            assert_eq!(
+                Ok(Some(Value::new_number(*expected))),
+                rule.eval(&input, &mut output)
+            );
+        }
+
+        // a 10% sample
+        let sample = Rule::Function(Function::new_lt(
+            Function::new_hash_bucket(Value::new_string("user-1"), Value::new_number(100)),
+            Value::new_number(10),
+        ));
+        assert_eq!(
+            Ok(Some(Value::Bool(true))),
+            sample.eval(&input, &mut output)
+        );
+
+        let no_buckets = Rule::Function(Function::new_hash_bucket(
+            Value::new_string("user-1"),
+            Value::new_number(0),
+        ));
+        assert_eq!(Err(Error::TypeError), no_buckets.eval(&input, &mut output));
+    }
+}
+
+mod trace {
+    use super::*;
+    use crate::targeting::{
+        text::parse_rules,
+        trace::{OutputChange, StepResult},
+    };
+
+    fn output() -> Output {
+        Output {
+            show: true,
+            boost: 1.0,
+            price: vec![("IMPRESSION".to_string(), BigNum::from(100))]
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn traces_the_evaluated_functions_and_the_output_changes() {
+        let input = get_default_input();
+        let mut output = output();
+        let rules = parse_rules(
+            r#"
+            if country in ["bg", "ro"] then set price.IMPRESSION = 1000n;
+            onlyShowIf(adView.secondsSinceCampaignImpression > 900);
+            set boost = 2.0
+            "#,
+        )
+        .expect("Should parse the rules");
+
+        let trace = eval_with_trace(&rules, &input, &mut output);
+
+        assert_eq!(
+            2,
+            trace.rules.len(),
+            "Should stop at the rule which hides the ad"
+        );
+        assert_eq!(Some(1), trace.hidden_by_rule);
+        assert!(!output.show);
+
+        let pricing = &trace.rules[0];
+        assert_eq!(StepResult::Value(None), pricing.result);
+        assert_eq!(2, pricing.args.len());
+        assert_eq!(r#"country in ["bg", "ro"]"#, pricing.args[0].rule);
+        assert_eq!(
+            StepResult::Value(Some(Value::Bool(true))),
+            pricing.args[0].result
+        );
+        assert_eq!(
+            Some(OutputChange {
+                variable: "price.IMPRESSION".to_string(),
+                before: Some(Value::BigNum(BigNum::from(100))),
+                after: Some(Value::BigNum(BigNum::from(1000))),
+            }),
+            pricing.args[1].output_change
+        );
+        assert!(!pricing.hides_the_ad());
+
+        let hiding = &trace.rules[1];
+        assert!(hiding.hides_the_ad());
+        assert_eq!(
+            "adView.secondsSinceCampaignImpression > 900",
+            hiding.args[0].rule
+        );
+        assert_eq!(
+            StepResult::Value(Some(Value::Bool(false))),
+            hiding.args[0].result
+        );
+    }
+
+    #[test]
+    fn traces_the_errors_and_the_values() {
+        let input = get_default_input();
+        let mut output = output();
+        let rules = vec![
+            Rule::Value(Value::Bool(true)),
+            Function::new_add(Value::new_string("1"), Value::Bool(false)).into(),
+        ];
+
+        let trace = eval_with_trace(&rules, &input, &mut output);
+
+        assert_eq!(None, trace.hidden_by_rule);
+        assert_eq!("true", trace.rules[0].rule);
+        assert_eq!(
+            StepResult::Value(Some(Value::Bool(true))),
+            trace.rules[0].result
+        );
+        assert_eq!(
+            StepResult::Error(Error::TypeError.to_string()),
+            trace.rules[1].result
+        );
+        assert_eq!(
+            serde_json::json!({
+                "rule": "\"1\" + false",
+                "error": "TypeError: Wrong type",
+            }),
+            serde_json::to_value(&trace.rules[1]).expect("Should serialize")
+        );
     }
 }
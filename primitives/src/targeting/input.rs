@@ -1,5 +1,8 @@
 use super::{Error, Value};
-use crate::{ToETHChecksum, ValidatorId, IPFS};
+use crate::{
+    token::{TokenPrice, TokenRegistry},
+    ToETHChecksum, ValidatorId, IPFS,
+};
 use chrono::{serde::ts_seconds, DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -42,6 +45,9 @@ pub struct Input {
     /// adSlot scope, accessible on Supermarket and AdView
     #[serde(flatten, with = "adslot_prefix")]
     pub ad_slot: Option<AdSlot>,
+    /// The price of the channel `deposit_asset`, used by `getPriceInUsd`
+    #[serde(skip)]
+    pub deposit_asset_price: Option<TokenPrice>,
 }
 
 impl Input {
@@ -61,6 +67,22 @@ impl Input {
         self
     }
 
+    /// Sets the price of the `deposit_asset` of the Channel Getter,
+    /// so it should be called after `with_channel` or `with_market_channel`
+    pub fn with_deposit_asset_price(mut self, token_registry: &TokenRegistry) -> Self {
+        let deposit_asset = match &self.channel {
+            Some(Get::Getter(channel::Getter::Full(full_channel))) => {
+                Some(&full_channel.channel.deposit_asset)
+            }
+            Some(Get::Getter(channel::Getter::Market(channel))) => Some(&channel.deposit_asset),
+            _ => None,
+        };
+        self.deposit_asset_price =
+            deposit_asset.and_then(|deposit_asset| token_registry.price(deposit_asset));
+
+        self
+    }
+
     pub fn with_balances(mut self, balances: crate::BalancesMap) -> Self {
         self.balances = Some(Get::Getter(balances::Getter {
             balances,
@@ -507,6 +529,7 @@ mod test {
                 hostname: "adex.network".into(),
                 alexa_rank: Some(2.0),
             }),
+            deposit_asset_price: None,
         };
 
         let ser_actual_json = serde_json::to_value(full_input.clone()).expect("Should serialize");
@@ -0,0 +1,865 @@
+//! A textual syntax for the targeting `Rule`s, which compiles to the same `Rule`s as their JSON.
+//!
+//! ```text
+//! if country in ["US", "CA"] then set price.IMPRESSION = bn("1000");
+//! onlyShowIf(adView.secondsSinceCampaignImpression > 900 and not adView.hasCustomPreferences);
+//! // comments run until the end of the line
+//! set boost = if startsWith(adSlotType, "legacy_") then 0.5 else boost * 2
+//! ```
+//!
+//! From the lowest to the highest precedence, the expressions are:
+//! - `if c then a`, `if c then a else b`, `unless c then a` (`ifNot`) and `set variable = a`
+//! - `a or b`, `a xor b` and `a and b`
+//! - `not a`
+//! - `a < b`, `a <= b`, `a > b`, `a >= b`, `a == b`, `a != b`, `value in array` and `value nin array`
+//! - `a + b`, `a - b`, `a * b`, `a / b` and `a % b`
+//! - `array[index]` (`at`)
+//! - literals: `true`, `false`, numbers, `"strings"`, arrays of literals `[1, "two"]` and BigNums `1000n`
+//! - variables (`get`) such as `adSlot.categories`, or `get("variable")` for names which are not identifiers
+//! - the rest of the functions, called by their JSON name: `max(a, b)`, `min(a, b)`, `mulDiv(a, b, c)`,
+//!   `between(start, end, value)`, `split(a, b)`, `startsWith(a, b)`, `endsWith(a, b)`, `intersects(a, b)`,
+//!   `hourOfDay(seconds, timezone)`, `dayOfWeek(seconds, timezone)`, `eqIgnoreCase(a, b)`,
+//!   `regexMatch(string, regex)`, `hashBucket(string, buckets)`,
+//!   `onlyShowIf(a)`, `getPriceInUsd(a)`, `do(a)` and `bn(literal)`
+//!
+//! The rules are separated by `;`.
+use super::{Function, Rule, Rules, Value};
+use crate::BigNum;
+use serde_json::Number;
+use std::{fmt, str::FromStr};
+
+#[cfg(test)]
+#[path = "text_test.rs"]
+mod test;
+
+const KEYWORDS: [&str; 13] = [
+    "if", "then", "else", "unless", "set", "or", "xor", "and", "not", "in", "nin", "true", "false",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(text: &str, offset: usize, message: impl Into<String>) -> Self {
+        let before = &text[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rsplit('\n')
+            .next()
+            .map(|line| line.chars().count())
+            .unwrap_or_default()
+            + 1;
+
+        Self {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses the `;` separated rules
+pub fn parse_rules(text: &str) -> Result<Rules, ParseError> {
+    let mut parser = Parser::new(text)?;
+    let mut rules = vec![];
+
+    loop {
+        while parser.eat_symbol(";") {}
+
+        if parser.peek() == &Token::Eof {
+            break;
+        }
+
+        rules.push(parser.expr()?);
+
+        if !parser.eat_symbol(";") && parser.peek() != &Token::Eof {
+            return Err(parser.error("expected `;` after the rule"));
+        }
+    }
+
+    Ok(Rules(rules))
+}
+
+/// Prints each rule on its own line, followed by a `;`
+pub fn print_rules(rules: &[Rule]) -> String {
+    rules.iter().map(|rule| format!("{};\n", rule)).collect()
+}
+
+impl FromStr for Rule {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser::new(text)?;
+        let rule = parser.expr()?;
+
+        match parser.peek() {
+            Token::Eof => Ok(rule),
+            _ => Err(parser.error("expected the end of the rule")),
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut text = String::new();
+        print(self, STATEMENT, &mut text);
+
+        f.write_str(&text)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(String),
+    BigNum(String),
+    String(String),
+    Symbol(&'static str),
+    Eof,
+}
+
+const SYMBOLS: [&str; 18] = [
+    "==", "!=", "<=", ">=", "(", ")", "[", "]", ",", ";", "=", "<", ">", "+", "-", "*", "/", "%",
+];
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Whether the variable can be written without `get("...")`,
+/// i.e. it's made of `.` separated words and it's not a keyword
+fn is_identifier(variable: &str) -> bool {
+    let starts_like_identifier = variable.chars().next().map(is_ident_start).unwrap_or(false);
+
+    starts_like_identifier
+        && variable
+            .split('.')
+            .all(|segment| !segment.is_empty() && segment.chars().all(is_ident_char))
+        && !KEYWORDS.contains(&variable)
+}
+
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+
+    while let Some(&(offset, c)) = chars.peek() {
+        let rest = &text[offset..];
+
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        if rest.starts_with("//") {
+            while let Some(&(_, c)) = chars.peek() {
+                if c == '\n' {
+                    break;
+                }
+                chars.next();
+            }
+            continue;
+        }
+
+        let token = if is_ident_start(c) {
+            let mut end = offset;
+            while let Some(&(index, c)) = chars.peek() {
+                let continues_path = c == '.'
+                    && text[index + 1..]
+                        .chars()
+                        .next()
+                        .map(is_ident_char)
+                        .unwrap_or(false);
+
+                if is_ident_char(c) || continues_path {
+                    end = index + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+
+            Token::Ident(text[offset..end].to_string())
+        } else if c.is_ascii_digit() {
+            let number_len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '+' || c == '-'))
+                .unwrap_or(rest.len());
+            let mut number = &rest[..number_len];
+            // `+` and `-` are part of the number only in the exponent, e.g. `1e-3`
+            if let Some(sign) = number.find(|c: char| c == '+' || c == '-') {
+                let in_exponent = number[..sign].ends_with(|c: char| c == 'e' || c == 'E');
+                if !in_exponent {
+                    number = &number[..sign];
+                }
+            }
+            for _ in number.chars() {
+                chars.next();
+            }
+
+            match number.strip_suffix('n') {
+                Some(big_num) if big_num.chars().all(|c| c.is_ascii_digit()) => {
+                    Token::BigNum(big_num.to_string())
+                }
+                _ => Token::Number(number.to_string()),
+            }
+        } else if c == '"' {
+            chars.next();
+            let mut end = None;
+            while let Some((index, c)) = chars.next() {
+                match c {
+                    '\\' => {
+                        chars.next();
+                    }
+                    '"' => {
+                        end = Some(index + 1);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            let end = end.ok_or_else(|| ParseError::new(text, offset, "unterminated string"))?;
+            let string = serde_json::from_str(&text[offset..end])
+                .map_err(|_| ParseError::new(text, offset, "invalid string"))?;
+
+            Token::String(string)
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(*symbol))
+                .ok_or_else(|| {
+                    ParseError::new(text, offset, format!("unexpected character `{}`", c))
+                })?;
+            for _ in symbol.chars() {
+                chars.next();
+            }
+
+            Token::Symbol(symbol)
+        };
+
+        tokens.push((token, offset));
+    }
+
+    tokens.push((Token::Eof, text.len()));
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Result<Self, ParseError> {
+        Ok(Self {
+            text,
+            tokens: tokenize(text)?,
+            position: 0,
+        })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].0
+    }
+
+    fn peek_next(&self) -> &Token {
+        self.tokens
+            .get(self.position + 1)
+            .map(|(token, _)| token)
+            .unwrap_or(&Token::Eof)
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.position].0.clone();
+        if token != Token::Eof {
+            self.position += 1;
+        }
+
+        token
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        ParseError::new(self.text, self.tokens[self.position].1, message)
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        match self.peek() {
+            Token::Symbol(next) if *next == symbol => {
+                self.advance();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Token::Ident(next) if next == keyword => {
+                self.advance();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), ParseError> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{}`", symbol)))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{}`", keyword)))
+        }
+    }
+
+    fn expr(&mut self) -> Result<Rule, ParseError> {
+        if self.eat_keyword("if") {
+            let condition = self.expr()?;
+            self.expect_keyword("then")?;
+            let then = self.expr()?;
+
+            let function = if self.eat_keyword("else") {
+                Function::new_if_else(condition, then, self.expr()?)
+            } else {
+                Function::new_if(condition, then)
+            };
+
+            Ok(function.into())
+        } else if self.eat_keyword("unless") {
+            let condition = self.expr()?;
+            self.expect_keyword("then")?;
+
+            Ok(Function::new_if_not(condition, self.expr()?).into())
+        } else if self.eat_keyword("set") {
+            let error = self.error("expected the variable to set");
+            let variable = match self.advance() {
+                Token::Ident(variable) if !KEYWORDS.contains(&variable.as_str()) => variable,
+                Token::String(variable) => variable,
+                _ => return Err(error),
+            };
+            self.expect_symbol("=")?;
+
+            Ok(Function::Set(variable, Box::new(self.expr()?)).into())
+        } else {
+            self.or()
+        }
+    }
+
+    fn or(&mut self) -> Result<Rule, ParseError> {
+        let mut rule = self.xor()?;
+        while self.eat_keyword("or") {
+            rule = Function::new_or(rule, self.xor()?).into();
+        }
+
+        Ok(rule)
+    }
+
+    fn xor(&mut self) -> Result<Rule, ParseError> {
+        let mut rule = self.and()?;
+        while self.eat_keyword("xor") {
+            rule = Function::new_xor(rule, self.and()?).into();
+        }
+
+        Ok(rule)
+    }
+
+    fn and(&mut self) -> Result<Rule, ParseError> {
+        let mut rule = self.not()?;
+        while self.eat_keyword("and") {
+            rule = Function::new_and(rule, self.not()?).into();
+        }
+
+        Ok(rule)
+    }
+
+    fn not(&mut self) -> Result<Rule, ParseError> {
+        if self.eat_keyword("not") {
+            Ok(Function::new_not(self.not()?).into())
+        } else {
+            self.comparison()
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Rule, ParseError> {
+        let lhs = self.additive()?;
+
+        let function: fn(Rule, Rule) -> Function = match self.peek() {
+            Token::Symbol("<") => Function::new_lt,
+            Token::Symbol("<=") => Function::new_lte,
+            Token::Symbol(">") => Function::new_gt,
+            Token::Symbol(">=") => Function::new_gte,
+            Token::Symbol("==") => Function::new_eq,
+            Token::Symbol("!=") => Function::new_neq,
+            // `value in array` is `in(array, value)`
+            Token::Ident(keyword) if keyword == "in" => {
+                |value: Rule, array: Rule| Function::new_in(array, value)
+            }
+            Token::Ident(keyword) if keyword == "nin" => {
+                |value: Rule, array: Rule| Function::new_nin(array, value)
+            }
+            _ => return Ok(lhs),
+        };
+        self.advance();
+
+        Ok(function(lhs, self.additive()?).into())
+    }
+
+    fn additive(&mut self) -> Result<Rule, ParseError> {
+        let mut rule = self.multiplicative()?;
+        loop {
+            let function: fn(Rule, Rule) -> Function = match self.peek() {
+                Token::Symbol("+") => Function::new_add,
+                Token::Symbol("-") => Function::new_sub,
+                _ => return Ok(rule),
+            };
+            self.advance();
+
+            rule = function(rule, self.multiplicative()?).into();
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Rule, ParseError> {
+        let mut rule = self.postfix()?;
+        loop {
+            let function: fn(Rule, Rule) -> Function = match self.peek() {
+                Token::Symbol("*") => Function::new_mul,
+                Token::Symbol("/") => Function::new_div,
+                Token::Symbol("%") => Function::new_mod,
+                _ => return Ok(rule),
+            };
+            self.advance();
+
+            rule = function(rule, self.postfix()?).into();
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Rule, ParseError> {
+        let mut rule = self.primary()?;
+        while self.eat_symbol("[") {
+            let index = self.expr()?;
+            self.expect_symbol("]")?;
+
+            rule = Function::new_at(rule, index).into();
+        }
+
+        Ok(rule)
+    }
+
+    fn primary(&mut self) -> Result<Rule, ParseError> {
+        if self.eat_symbol("(") {
+            let rule = self.expr()?;
+            self.expect_symbol(")")?;
+
+            return Ok(rule);
+        }
+
+        match self.peek().clone() {
+            Token::Ident(name)
+                if self.peek_next() == &Token::Symbol("(")
+                    && !KEYWORDS.contains(&name.as_str()) =>
+            {
+                let offset = self.tokens[self.position].1;
+                self.advance();
+                self.call(&name, offset)
+            }
+            Token::Ident(name) if name != "true" && name != "false" => {
+                if KEYWORDS.contains(&name.as_str()) {
+                    return Err(self.error(format!("unexpected `{}`", name)));
+                }
+                self.advance();
+
+                Ok(Function::Get(name).into())
+            }
+            _ => Ok(Rule::Value(self.value()?)),
+        }
+    }
+
+    /// A literal value
+    fn value(&mut self) -> Result<Value, ParseError> {
+        let offset = self.tokens[self.position].1;
+        let error = self.error("expected a value");
+
+        let value = match self.advance() {
+            Token::Ident(name) if name == "true" => Value::Bool(true),
+            Token::Ident(name) if name == "false" => Value::Bool(false),
+            Token::Number(number) => Value::Number(self.number(&number, offset)?),
+            Token::Symbol("-") => match self.advance() {
+                Token::Number(number) => {
+                    Value::Number(self.number(&format!("-{}", number), offset)?)
+                }
+                _ => return Err(error),
+            },
+            Token::BigNum(big_num) => Value::BigNum(
+                big_num
+                    .parse::<BigNum>()
+                    .map_err(|_| ParseError::new(self.text, offset, "invalid BigNum"))?,
+            ),
+            Token::String(string) => Value::String(string),
+            Token::Symbol("[") => {
+                let mut array = vec![];
+                while !self.eat_symbol("]") {
+                    array.push(self.value()?);
+
+                    if !self.eat_symbol(",") {
+                        self.expect_symbol("]")?;
+                        break;
+                    }
+                }
+
+                Value::Array(array)
+            }
+            _ => return Err(error),
+        };
+
+        Ok(value)
+    }
+
+    fn number(&self, number: &str, offset: usize) -> Result<Number, ParseError> {
+        serde_json::from_str(number)
+            .map_err(|_| ParseError::new(self.text, offset, format!("invalid number `{}`", number)))
+    }
+
+    /// Parses the arguments of the function `name`, which starts at `offset`
+    fn call(&mut self, name: &str, offset: usize) -> Result<Rule, ParseError> {
+        self.expect_symbol("(")?;
+
+        let function = match name {
+            "bn" => Function::Bn(self.value()?),
+            "get" => match self.peek().clone() {
+                Token::String(variable) => {
+                    self.advance();
+                    Function::Get(variable)
+                }
+                _ => return Err(self.error("expected the variable name as a string")),
+            },
+            _ => {
+                let mut args = vec![];
+                while !self.eat_symbol(")") {
+                    args.push(self.expr()?);
+
+                    if !self.eat_symbol(",") {
+                        self.expect_symbol(")")?;
+                        break;
+                    }
+                }
+
+                return self.function(name, offset, args).map(Rule::Function);
+            }
+        };
+        self.expect_symbol(")")?;
+
+        Ok(function.into())
+    }
+
+    fn function(&self, name: &str, offset: usize, args: Vec<Rule>) -> Result<Function, ParseError> {
+        let expected = match name {
+            "onlyShowIf" | "getPriceInUsd" | "do" => 1,
+            "max" | "min" | "split" | "startsWith" | "endsWith" | "intersects" | "hourOfDay"
+            | "dayOfWeek" | "eqIgnoreCase" | "regexMatch" | "hashBucket" => 2,
+            "mulDiv" | "between" => 3,
+            _ => {
+                let message = format!("unknown function `{}`", name);
+                return Err(ParseError::new(self.text, offset, message));
+            }
+        };
+
+        if args.len() != expected {
+            let message = format!(
+                "`{}` expects {} argument(s), found {}",
+                name,
+                expected,
+                args.len()
+            );
+            return Err(ParseError::new(self.text, offset, message));
+        }
+
+        let mut args = args.into_iter();
+        let mut arg = || args.next().expect("The number of arguments is checked");
+
+        let function = match name {
+            "onlyShowIf" => Function::new_only_show_if(arg()),
+            "getPriceInUsd" => Function::new_get_price_in_usd(arg()),
+            "do" => Function::new_do(arg()),
+            "max" => Function::new_max(arg(), arg()),
+            "min" => Function::new_min(arg(), arg()),
+            "split" => Function::new_split(arg(), arg()),
+            "startsWith" => Function::new_starts_with(arg(), arg()),
+            "endsWith" => Function::new_ends_with(arg(), arg()),
+            "intersects" => Function::new_intersects(arg(), arg()),
+            "hourOfDay" => Function::new_hour_of_day(arg(), arg()),
+            "dayOfWeek" => Function::new_day_of_week(arg(), arg()),
+            "eqIgnoreCase" => Function::new_eq_ignore_case(arg(), arg()),
+            "regexMatch" => Function::new_regex_match(arg(), arg()),
+            "hashBucket" => Function::new_hash_bucket(arg(), arg()),
+            "mulDiv" => Function::new_muldiv(arg(), arg(), arg()),
+            _ => Function::new_between(arg(), arg(), arg()),
+        };
+
+        Ok(function)
+    }
+}
+
+// The precedences of the expressions, from the lowest to the highest
+const STATEMENT: u8 = 0;
+const OR: u8 = 1;
+const XOR: u8 = 2;
+const AND: u8 = 3;
+const NOT: u8 = 4;
+const COMPARISON: u8 = 5;
+const ADDITIVE: u8 = 6;
+const MULTIPLICATIVE: u8 = 7;
+const POSTFIX: u8 = 8;
+const PRIMARY: u8 = 9;
+
+fn precedence(rule: &Rule) -> u8 {
+    let function = match rule {
+        Rule::Value(_) => return PRIMARY,
+        Rule::Function(function) => function,
+    };
+
+    match function {
+        Function::If(..) | Function::IfNot(..) | Function::IfElse(..) | Function::Set(..) => {
+            STATEMENT
+        }
+        Function::Or(..) => OR,
+        Function::Xor(..) => XOR,
+        Function::And(..) => AND,
+        Function::Not(..) => NOT,
+        Function::Lt(..)
+        | Function::Lte(..)
+        | Function::Gt(..)
+        | Function::Gte(..)
+        | Function::Eq(..)
+        | Function::Neq(..)
+        | Function::In(..)
+        | Function::Nin(..) => COMPARISON,
+        Function::Add(..) | Function::Sub(..) => ADDITIVE,
+        Function::Mul(..) | Function::Div(..) | Function::Mod(..) => MULTIPLICATIVE,
+        Function::At(..) => POSTFIX,
+        Function::MulDiv(..)
+        | Function::Max(..)
+        | Function::Min(..)
+        | Function::Between(..)
+        | Function::Split(..)
+        | Function::StartsWith(..)
+        | Function::EndsWith(..)
+        | Function::OnlyShowIf(..)
+        | Function::GetPriceInUsd(..)
+        | Function::Intersects(..)
+        | Function::HourOfDay(..)
+        | Function::DayOfWeek(..)
+        | Function::EqIgnoreCase(..)
+        | Function::RegexMatch(..)
+        | Function::HashBucket(..)
+        | Function::Do(..)
+        | Function::Get(..)
+        | Function::Bn(..) => PRIMARY,
+    }
+}
+
+/// Whether the printed rule ends with an `if` without an `else`,
+/// which would take the `else` of an outer `if` when parsed
+fn ends_with_open_if(rule: &Rule) -> bool {
+    match rule {
+        Rule::Function(Function::If(..)) => true,
+        Rule::Function(Function::IfNot(_, then)) => ends_with_open_if(then),
+        Rule::Function(Function::IfElse(_, _, otherwise)) => ends_with_open_if(otherwise),
+        Rule::Function(Function::Set(_, value)) => ends_with_open_if(value),
+        _ => false,
+    }
+}
+
+/// Prints the rule, in parentheses if its precedence is lower than `min_precedence`
+fn print(rule: &Rule, min_precedence: u8, text: &mut String) {
+    if precedence(rule) < min_precedence {
+        text.push('(');
+        print(rule, STATEMENT, text);
+        text.push(')');
+
+        return;
+    }
+
+    let function = match rule {
+        Rule::Value(value) => return print_value(value, text),
+        Rule::Function(function) => function,
+    };
+
+    match function {
+        Function::If(condition, then) => {
+            text.push_str("if ");
+            print(condition, OR, text);
+            text.push_str(" then ");
+            print(then, STATEMENT, text);
+        }
+        Function::IfNot(condition, then) => {
+            text.push_str("unless ");
+            print(condition, OR, text);
+            text.push_str(" then ");
+            print(then, STATEMENT, text);
+        }
+        Function::IfElse(condition, then, otherwise) => {
+            text.push_str("if ");
+            print(condition, OR, text);
+            text.push_str(" then ");
+            if ends_with_open_if(then) {
+                text.push('(');
+                print(then, STATEMENT, text);
+                text.push(')');
+            } else {
+                print(then, STATEMENT, text);
+            }
+            text.push_str(" else ");
+            print(otherwise, STATEMENT, text);
+        }
+        Function::Set(variable, value) => {
+            text.push_str("set ");
+            if is_identifier(variable) {
+                text.push_str(variable);
+            } else {
+                print_string(variable, text);
+            }
+            text.push_str(" = ");
+            print(value, STATEMENT, text);
+        }
+        Function::Or(lhs, rhs) => print_binary(lhs, " or ", rhs, OR, XOR, text),
+        Function::Xor(lhs, rhs) => print_binary(lhs, " xor ", rhs, XOR, AND, text),
+        Function::And(lhs, rhs) => print_binary(lhs, " and ", rhs, AND, NOT, text),
+        Function::Not(rule) => {
+            text.push_str("not ");
+            print(rule, NOT, text);
+        }
+        Function::Lt(lhs, rhs) => print_binary(lhs, " < ", rhs, ADDITIVE, ADDITIVE, text),
+        Function::Lte(lhs, rhs) => print_binary(lhs, " <= ", rhs, ADDITIVE, ADDITIVE, text),
+        Function::Gt(lhs, rhs) => print_binary(lhs, " > ", rhs, ADDITIVE, ADDITIVE, text),
+        Function::Gte(lhs, rhs) => print_binary(lhs, " >= ", rhs, ADDITIVE, ADDITIVE, text),
+        Function::Eq(lhs, rhs) => print_binary(lhs, " == ", rhs, ADDITIVE, ADDITIVE, text),
+        Function::Neq(lhs, rhs) => print_binary(lhs, " != ", rhs, ADDITIVE, ADDITIVE, text),
+        Function::In(array, value) => print_binary(value, " in ", array, ADDITIVE, ADDITIVE, text),
+        Function::Nin(array, value) => {
+            print_binary(value, " nin ", array, ADDITIVE, ADDITIVE, text)
+        }
+        Function::Add(lhs, rhs) => print_binary(lhs, " + ", rhs, ADDITIVE, MULTIPLICATIVE, text),
+        Function::Sub(lhs, rhs) => print_binary(lhs, " - ", rhs, ADDITIVE, MULTIPLICATIVE, text),
+        Function::Mul(lhs, rhs) => print_binary(lhs, " * ", rhs, MULTIPLICATIVE, POSTFIX, text),
+        Function::Div(lhs, rhs) => print_binary(lhs, " / ", rhs, MULTIPLICATIVE, POSTFIX, text),
+        Function::Mod(lhs, rhs) => print_binary(lhs, " % ", rhs, MULTIPLICATIVE, POSTFIX, text),
+        Function::At(array, index) => {
+            print(array, POSTFIX, text);
+            text.push('[');
+            print(index, STATEMENT, text);
+            text.push(']');
+        }
+        Function::MulDiv(first, second, third) => {
+            print_call("mulDiv", &[first, second, third], text)
+        }
+        Function::Between(first, second, third) => {
+            print_call("between", &[first, second, third], text)
+        }
+        Function::Max(lhs, rhs) => print_call("max", &[lhs, rhs], text),
+        Function::Min(lhs, rhs) => print_call("min", &[lhs, rhs], text),
+        Function::Split(lhs, rhs) => print_call("split", &[lhs, rhs], text),
+        Function::StartsWith(lhs, rhs) => print_call("startsWith", &[lhs, rhs], text),
+        Function::EndsWith(lhs, rhs) => print_call("endsWith", &[lhs, rhs], text),
+        Function::Intersects(lhs, rhs) => print_call("intersects", &[lhs, rhs], text),
+        Function::HourOfDay(lhs, rhs) => print_call("hourOfDay", &[lhs, rhs], text),
+        Function::DayOfWeek(lhs, rhs) => print_call("dayOfWeek", &[lhs, rhs], text),
+        Function::EqIgnoreCase(lhs, rhs) => print_call("eqIgnoreCase", &[lhs, rhs], text),
+        Function::RegexMatch(lhs, rhs) => print_call("regexMatch", &[lhs, rhs], text),
+        Function::HashBucket(lhs, rhs) => print_call("hashBucket", &[lhs, rhs], text),
+        Function::OnlyShowIf(rule) => print_call("onlyShowIf", &[rule], text),
+        Function::GetPriceInUsd(rule) => print_call("getPriceInUsd", &[rule], text),
+        Function::Do(rule) => print_call("do", &[rule], text),
+        Function::Get(variable) => {
+            if is_identifier(variable) {
+                text.push_str(variable);
+            } else {
+                text.push_str("get(");
+                print_string(variable, text);
+                text.push(')');
+            }
+        }
+        Function::Bn(value) => {
+            text.push_str("bn(");
+            print_value(value, text);
+            text.push(')');
+        }
+    }
+}
+
+/// Prints `lhs` and `rhs` with the precedences they need on each side of the operator
+fn print_binary(
+    lhs: &Rule,
+    operator: &str,
+    rhs: &Rule,
+    lhs_min: u8,
+    rhs_min: u8,
+    text: &mut String,
+) {
+    print(lhs, lhs_min, text);
+    text.push_str(operator);
+    print(rhs, rhs_min, text);
+}
+
+fn print_call<R: AsRef<Rule>>(name: &str, args: &[R], text: &mut String) {
+    text.push_str(name);
+    text.push('(');
+    for (index, arg) in args.iter().enumerate() {
+        if index > 0 {
+            text.push_str(", ");
+        }
+        print(arg.as_ref(), STATEMENT, text);
+    }
+    text.push(')');
+}
+
+fn print_value(value: &Value, text: &mut String) {
+    match value {
+        Value::Bool(bool) => text.push_str(if *bool { "true" } else { "false" }),
+        Value::Number(number) => text.push_str(&number.to_string()),
+        Value::String(string) => print_string(string, text),
+        Value::Array(array) => {
+            text.push('[');
+            for (index, value) in array.iter().enumerate() {
+                if index > 0 {
+                    text.push_str(", ");
+                }
+                print_value(value, text);
+            }
+            text.push(']');
+        }
+        Value::BigNum(big_num) => {
+            text.push_str(&big_num.to_string());
+            text.push('n');
+        }
+    }
+}
+
+fn print_string(string: &str, text: &mut String) {
+    text.push_str(&serde_json::to_string(string).expect("Should serialize a string"));
+}
@@ -0,0 +1,428 @@
+use super::*;
+use crate::BigNum;
+use serde_json::{json, Number};
+
+/// Prints the rule and parses it back
+fn assert_round_trip(rule: Rule) {
+    let text = rule.to_string();
+    let parsed = text
+        .parse::<Rule>()
+        .unwrap_or_else(|err| panic!("Should parse `{}`: {}", text, err));
+
+    assert_eq!(rule, parsed, "`{}` should parse to the printed rule", text);
+}
+
+fn parse(text: &str) -> Rule {
+    text.parse()
+        .unwrap_or_else(|err| panic!("Should parse `{}`: {}", text, err))
+}
+
+fn float(number: f64) -> Value {
+    Value::Number(Number::from_f64(number).expect("should create float number"))
+}
+
+fn big_num(number: u64) -> Value {
+    Value::BigNum(BigNum::from(number))
+}
+
+mod parser {
+    use super::*;
+
+    #[test]
+    fn parses_the_rules_of_the_json_syntax() {
+        let text = r#"
+            // only for North America
+            if country in ["US", "CA"] then set price.IMPRESSION = bn("1000");
+            onlyShowIf(adView.secondsSinceCampaignImpression > 900 and not adView.hasCustomPreferences);
+        "#;
+
+        let expected = json!([
+            {"if": [
+                {"in": [["US", "CA"], {"get": "country"}]},
+                {"set": ["price.IMPRESSION", {"bn": "1000"}]}
+            ]},
+            {"onlyShowIf": [
+                {"and": [
+                    {"gt": [{"get": "adView.secondsSinceCampaignImpression"}, 900]},
+                    {"not": [{"get": "adView.hasCustomPreferences"}]}
+                ]}
+            ]}
+        ]);
+        let expected: Rules =
+            serde_json::from_value(expected).expect("Should deserialize the rules");
+
+        assert_eq!(expected, parse_rules(text).expect("Should parse the rules"));
+    }
+
+    #[test]
+    fn parses_no_rules() {
+        assert_eq!(Rules(vec![]), parse_rules("").expect("Should parse"));
+        assert_eq!(
+            Rules(vec![]),
+            parse_rules(" ; // nothing\n").expect("Should parse")
+        );
+    }
+
+    #[test]
+    fn operators_have_precedence() {
+        let multiplication_first = Function::new_add(
+            Value::new_number(1),
+            Function::new_mul(Value::new_number(2), Value::new_number(3)),
+        );
+        assert_eq!(Rule::from(multiplication_first), parse("1 + 2 * 3"));
+
+        let left_associative = Function::new_sub(
+            Function::new_sub(Value::new_number(5), Value::new_number(2)),
+            Value::new_number(1),
+        );
+        assert_eq!(Rule::from(left_associative), parse("5 - 2 - 1"));
+
+        let and_before_or = Function::new_or(
+            Function::Get("a".to_string()),
+            Function::new_and(
+                Function::Get("b".to_string()),
+                Function::Get("c".to_string()),
+            ),
+        );
+        assert_eq!(Rule::from(and_before_or), parse("a or b and c"));
+
+        let comparison_before_not = Function::new_not(Function::new_eq(
+            Function::Get("a".to_string()),
+            Value::Bool(true),
+        ));
+        assert_eq!(Rule::from(comparison_before_not), parse("not a == true"));
+
+        let parenthesized = Function::new_mul(
+            Function::new_add(Value::new_number(1), Value::new_number(2)),
+            Value::new_number(3),
+        );
+        assert_eq!(Rule::from(parenthesized), parse("(1 + 2) * 3"));
+    }
+
+    #[test]
+    fn else_belongs_to_the_closest_if() {
+        let rule = Function::new_if(
+            Function::Get("a".to_string()),
+            Function::new_if_else(
+                Function::Get("b".to_string()),
+                Value::new_number(1),
+                Value::new_number(2),
+            ),
+        );
+
+        assert_eq!(Rule::from(rule), parse("if a then if b then 1 else 2"));
+    }
+
+    #[test]
+    fn parses_literals() {
+        assert_eq!(Rule::Value(float(-1.5)), parse("-1.5"));
+        assert_eq!(Rule::Value(float(100.0)), parse("1e2"));
+        assert_eq!(Rule::Value(Value::new_number(-7)), parse("-7"));
+        assert_eq!(Rule::Value(big_num(1000)), parse("1000n"));
+        assert_eq!(
+            Rule::Value(Value::new_string("quote \" and\nnew line")),
+            parse(r#""quote \" and\nnew line""#)
+        );
+        assert_eq!(
+            Rule::Value(Value::Array(vec![
+                Value::Bool(true),
+                Value::new_number(1),
+                Value::Array(vec![]),
+            ])),
+            parse("[true, 1, []]")
+        );
+    }
+
+    #[test]
+    fn variables_which_are_not_identifiers_use_get() {
+        assert_eq!(Rule::from(Function::new_get("in")), parse(r#"get("in")"#));
+        assert_eq!(
+            Rule::from(Function::new_set("with space", Value::Bool(true))),
+            parse(r#"set "with space" = true"#)
+        );
+    }
+
+    #[test]
+    fn errors_have_the_line_and_column() {
+        let error = parse_rules("onlyShowIf(true);\nset = 1").expect_err("Should fail");
+        assert_eq!((2, 5), (error.line, error.column));
+        assert_eq!(
+            "line 2, column 5: expected the variable to set",
+            error.to_string()
+        );
+
+        let error = "1 +".parse::<Rule>().expect_err("Should fail");
+        assert_eq!(
+            (1, 4, "expected a value"),
+            (error.line, error.column, error.message.as_str())
+        );
+
+        let error = "\"unterminated".parse::<Rule>().expect_err("Should fail");
+        assert_eq!((1, 1), (error.line, error.column));
+
+        let error = "1 @ 2".parse::<Rule>().expect_err("Should fail");
+        assert_eq!(
+            "line 1, column 3: unexpected character `@`",
+            error.to_string()
+        );
+
+        let error = "1 2".parse::<Rule>().expect_err("Should fail");
+        assert_eq!("expected the end of the rule", error.message);
+
+        let error = parse_rules("true false").expect_err("Should fail");
+        assert_eq!("expected `;` after the rule", error.message);
+    }
+
+    #[test]
+    fn functions_are_checked() {
+        let error = "unknown(1)".parse::<Rule>().expect_err("Should fail");
+        assert_eq!("unknown function `unknown`", error.message);
+
+        let error = "1 + max(1)".parse::<Rule>().expect_err("Should fail");
+        assert_eq!(
+            "line 1, column 5: `max` expects 2 argument(s), found 1",
+            error.to_string()
+        );
+
+        let error = "bn(get(\"a\"))".parse::<Rule>().expect_err("Should fail");
+        assert_eq!("expected a value", error.message);
+
+        let error = "1 < 2 < 3".parse::<Rule>().expect_err("Should fail");
+        assert_eq!("expected the end of the rule", error.message);
+    }
+}
+
+mod printer {
+    use super::*;
+
+    #[test]
+    fn prints_the_rules() {
+        let rules = vec![
+            Function::new_if(
+                Function::new_in(
+                    Value::Array(vec![Value::new_string("US"), Value::new_string("CA")]),
+                    Function::new_get("country"),
+                ),
+                Function::new_set(
+                    "price.IMPRESSION",
+                    Function::new_bn(Value::new_string("1000")),
+                ),
+            )
+            .into(),
+            Function::new_only_show_if(Function::new_gt(
+                Function::new_get("adView.secondsSinceCampaignImpression"),
+                Value::new_number(900),
+            ))
+            .into(),
+        ];
+
+        assert_eq!(
+            "if country in [\"US\", \"CA\"] then set price.IMPRESSION = bn(\"1000\");\nonlyShowIf(adView.secondsSinceCampaignImpression > 900);\n",
+            print_rules(&rules)
+        );
+    }
+
+    #[test]
+    fn parenthesizes_only_when_needed() {
+        let rule = Function::new_mul(
+            Function::new_add(Value::new_number(1), Value::new_number(2)),
+            Function::new_mul(Value::new_number(3), Value::new_number(4)),
+        );
+        assert_eq!("(1 + 2) * (3 * 4)", Rule::from(rule).to_string());
+
+        let rule = Function::new_add(
+            Function::new_add(Value::new_number(1), Value::new_number(2)),
+            Function::new_mul(Value::new_number(3), Value::new_number(4)),
+        );
+        assert_eq!("1 + 2 + 3 * 4", Rule::from(rule).to_string());
+
+        let rule = Function::new_if_else(
+            Function::new_get("a"),
+            Function::new_if(Function::new_get("b"), Value::new_number(1)),
+            Value::new_number(2),
+        );
+        assert_eq!(
+            "if a then (if b then 1) else 2",
+            Rule::from(rule).to_string()
+        );
+    }
+}
+
+/// Every `Function` of `eval_test.rs` prints to text which parses back to the same `Rule`
+mod round_trip {
+    use super::*;
+
+    fn math_operands() -> Vec<(Value, Value)> {
+        vec![
+            (big_num(10), big_num(4)),
+            (Value::new_number(10), Value::new_number(4)),
+            (float(10.5), float(-4.25)),
+            (big_num(10), Value::new_number(4)),
+        ]
+    }
+
+    #[test]
+    fn math_functions() {
+        let functions: [fn(Value, Value) -> Function; 11] = [
+            Function::new_div,
+            Function::new_mul,
+            Function::new_mod,
+            Function::new_add,
+            Function::new_sub,
+            Function::new_min,
+            Function::new_max,
+            Function::new_lt,
+            Function::new_lte,
+            Function::new_gt,
+            Function::new_gte,
+        ];
+
+        for function in functions.iter() {
+            for (lhs, rhs) in math_operands() {
+                assert_round_trip(function(lhs, rhs).into());
+            }
+        }
+
+        assert_round_trip(
+            Function::new_between(
+                big_num(10),
+                big_num(100),
+                Function::new_get("adView.secondsSinceCampaignImpression"),
+            )
+            .into(),
+        );
+        assert_round_trip(
+            Function::new_muldiv(big_num(10), Value::new_number(5), Value::new_number(2)).into(),
+        );
+    }
+
+    #[test]
+    fn nested_math_functions() {
+        let rule = Function::new_sub(
+            Value::new_number(1),
+            Function::new_sub(
+                Function::new_mod(Value::new_number(7), Value::new_number(3)),
+                Function::new_div(
+                    Value::new_number(8),
+                    Function::new_mul(Value::new_number(2), Value::new_number(2)),
+                ),
+            ),
+        );
+
+        assert_round_trip(rule.into());
+    }
+
+    #[test]
+    fn control_flow_and_logic() {
+        let (a, b, c) = (
+            Function::new_get("a"),
+            Function::new_get("b"),
+            Function::new_get("c"),
+        );
+
+        assert_round_trip(Function::new_if_not(Value::Bool(false), big_num(100)).into());
+        assert_round_trip(
+            Function::new_if_else(Value::Bool(true), big_num(100), big_num(200)).into(),
+        );
+        assert_round_trip(
+            Function::new_or(a.clone(), Function::new_or(b.clone(), c.clone())).into(),
+        );
+        assert_round_trip(
+            Function::new_xor(Function::new_or(a.clone(), b.clone()), c.clone()).into(),
+        );
+        assert_round_trip(
+            Function::new_and(a.clone(), Function::new_xor(b.clone(), c.clone())).into(),
+        );
+        assert_round_trip(Function::new_not(Function::new_not(a.clone())).into());
+        assert_round_trip(Function::new_eq(Function::new_not(a.clone()), b.clone()).into());
+        assert_round_trip(Function::new_only_show_if(Value::Bool(false)).into());
+        assert_round_trip(
+            Function::new_do(Function::new_set("price.IMPRESSION", big_num(10))).into(),
+        );
+        assert_round_trip(
+            Function::new_if(
+                Function::new_if_else(a.clone(), b.clone(), c.clone()),
+                Function::new_if_else(
+                    a.clone(),
+                    Function::new_if_not(
+                        b.clone(),
+                        Function::new_set("d", Function::new_if(c.clone(), a.clone())),
+                    ),
+                    b.clone(),
+                ),
+            )
+            .into(),
+        );
+        assert_round_trip(
+            Function::new_and(a, Function::new_set("d", Function::new_if(b, c))).into(),
+        );
+    }
+
+    #[test]
+    fn equality() {
+        let values = vec![
+            Value::Array(vec![Value::new_number(1), Value::new_number(2)]),
+            Value::new_string("string"),
+            Value::Bool(true),
+            float(1.5),
+            big_num(1),
+        ];
+
+        for lhs in values.iter() {
+            for rhs in values.iter() {
+                assert_round_trip(Function::new_eq(lhs.clone(), rhs.clone()).into());
+                assert_round_trip(Function::new_neq(lhs.clone(), rhs.clone()).into());
+            }
+        }
+    }
+
+    #[test]
+    fn strings_and_arrays() {
+        let array = Value::Array(vec![Value::new_number(1), Value::new_number(2)]);
+
+        assert_round_trip(Function::new_in(array.clone(), Value::new_number(1)).into());
+        assert_round_trip(Function::new_nin(array.clone(), Value::new_number(3)).into());
+        assert_round_trip(Function::new_at(array.clone(), Value::new_number(1)).into());
+        assert_round_trip(
+            Function::new_at(
+                Function::new_at(array.clone(), Value::new_number(0)),
+                Value::new_number(1),
+            )
+            .into(),
+        );
+        assert_round_trip(
+            Function::new_at(
+                Function::new_split(Value::new_string("one, two"), Value::new_string(", ")),
+                Function::new_add(Value::new_number(0), Value::new_number(1)),
+            )
+            .into(),
+        );
+        assert_round_trip(
+            Function::new_starts_with(Value::new_string("test string"), Value::new_string("test"))
+                .into(),
+        );
+        assert_round_trip(
+            Function::new_ends_with(
+                Value::new_string("test string"),
+                Value::new_string("string"),
+            )
+            .into(),
+        );
+        assert_round_trip(
+            Function::new_intersects(array, Function::new_get("adSlot.categories")).into(),
+        );
+    }
+
+    #[test]
+    fn variables_and_big_nums() {
+        assert_round_trip(Function::new_get("adSlotId").into());
+        assert_round_trip(Function::new_get("not").into());
+        assert_round_trip(Function::new_get("1st").into());
+        assert_round_trip(Function::new_set("with space", Value::new_string("é")).into());
+        assert_round_trip(Function::new_bn(Value::new_string("1000")).into());
+        assert_round_trip(Function::new_bn(Value::new_number(100)).into());
+        assert_round_trip(Function::new_bn(big_num(100)).into());
+        assert_round_trip(Function::new_bn(Value::Bool(true)).into());
+        assert_round_trip(Function::new_get_price_in_usd(big_num(1_000_000)).into());
+    }
+}
@@ -0,0 +1,161 @@
+//! Explains the evaluation of the targeting `Rule`s,
+//! i.e. which `Rule` hid the ad or changed its price and why.
+//! See `eval_with_trace`.
+use super::{Error, Function, Output, Rule, Value};
+use serde::Serialize;
+
+/// The evaluation of the `Rule`s by `eval_with_trace`
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Trace {
+    /// The step of each evaluated `Rule`, in order.
+    /// The `Rule`s after the one that hides the ad are not evaluated
+    pub rules: Vec<TraceStep>,
+    /// The index of the `Rule` after which `show` became `false`
+    pub hidden_by_rule: Option<usize>,
+}
+
+/// An evaluated `Function`, or a `Value` if it's a whole `Rule`
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceStep {
+    /// The evaluated `Rule` in the syntax of `targeting::text`
+    pub rule: String,
+    /// The evaluated arguments which are `Function`s, in the order of evaluation.
+    /// `Value` arguments can be seen in the `rule`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<TraceStep>,
+    #[serde(flatten)]
+    pub result: StepResult,
+    /// The `Output` variable changed by `set`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_change: Option<OutputChange>,
+}
+
+impl TraceStep {
+    fn new(rule: &Rule, result: &Result<Option<Value>, Error>) -> Self {
+        let result = match result {
+            Ok(value) => StepResult::Value(value.clone()),
+            Err(error) => StepResult::Error(error.to_string()),
+        };
+
+        Self {
+            rule: rule.to_string(),
+            args: vec![],
+            result,
+            output_change: None,
+        }
+    }
+
+    /// Whether this step or any of its arguments changed `show` to `false`
+    pub fn hides_the_ad(&self) -> bool {
+        let hides_the_ad = self
+            .output_change
+            .as_ref()
+            .map(OutputChange::hides_the_ad)
+            .unwrap_or(false);
+
+        hides_the_ad || self.args.iter().any(TraceStep::hides_the_ad)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum StepResult {
+    /// `set` and the `if`s which aren't taken don't return a value
+    Value(Option<Value>),
+    Error(String),
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputChange {
+    pub variable: String,
+    /// `None` if the variable had no value, e.g. a `price.{eventType}` without pricing bounds
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+impl OutputChange {
+    pub fn hides_the_ad(&self) -> bool {
+        self.variable == "show"
+            && self.before != Some(Value::Bool(false))
+            && self.after == Some(Value::Bool(false))
+    }
+}
+
+/// The `Function` which is being evaluated
+#[derive(Debug)]
+struct Frame {
+    args: Vec<TraceStep>,
+    /// The variable of a `set` and its value before it
+    set: Option<(String, Option<Value>)>,
+}
+
+/// Collects the steps of the evaluated `Function`s when it's enabled,
+/// otherwise the evaluation doesn't pay for the tracing
+#[derive(Debug, Default)]
+pub(super) struct Tracer {
+    pub(super) enabled: bool,
+    stack: Vec<Frame>,
+    /// The steps of the evaluated `Rule`s
+    steps: Vec<TraceStep>,
+}
+
+impl Tracer {
+    pub(super) fn enabled() -> Self {
+        Self {
+            enabled: true,
+            ..Self::default()
+        }
+    }
+
+    pub(super) fn enter(&mut self, output: &Output, function: &Function) {
+        let set = match function {
+            Function::Set(variable, _) => Some((variable.clone(), output.try_get(variable).ok())),
+            _ => None,
+        };
+
+        self.stack.push(Frame { args: vec![], set });
+    }
+
+    pub(super) fn exit(
+        &mut self,
+        output: &Output,
+        rule: &Rule,
+        result: &Result<Option<Value>, Error>,
+    ) {
+        let frame = self
+            .stack
+            .pop()
+            .expect("Should exit only the entered functions");
+
+        let mut step = TraceStep::new(rule, result);
+        step.args = frame.args;
+        step.output_change = match frame.set {
+            Some((variable, before)) if result.is_ok() => Some(OutputChange {
+                after: output.try_get(&variable).ok(),
+                variable,
+                before,
+            }),
+            _ => None,
+        };
+
+        match self.stack.last_mut() {
+            Some(parent) => parent.args.push(step),
+            None => self.steps.push(step),
+        }
+    }
+
+    /// Returns the step of the evaluated `Rule`
+    pub(super) fn rule_step(
+        &mut self,
+        rule: &Rule,
+        result: &Result<Option<Value>, Error>,
+    ) -> TraceStep {
+        // `Value`s are not traced as they don't need evaluation
+        self.steps
+            .pop()
+            .unwrap_or_else(|| TraceStep::new(rule, result))
+    }
+}
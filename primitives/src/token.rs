@@ -0,0 +1,332 @@
+//! The tokens which can be used as a channel `deposit_asset`,
+//! with their decimals and the source of their price in USD.
+use crate::{BigNum, Config};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock, Weak},
+    thread,
+    time::{Duration, SystemTime},
+};
+use thiserror::Error;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TokenInfo {
+    pub address: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub price_source: PriceSource,
+}
+
+impl TokenInfo {
+    /// The amount of the smallest unit of the token in one whole token, i.e. `10^decimals`
+    pub fn precision(&self) -> BigNum {
+        (0..self.decimals).fold(BigNum::from(1), |precision, _| precision * BigNum::from(10))
+    }
+}
+
+/// The stablecoins which were priced at $1 before the tokens were configurable,
+/// used when no `tokens` are configured
+pub fn default_tokens() -> Vec<TokenInfo> {
+    [
+        ("0x89d24A6b4CcB1B6fAA2625fE562bDD9a23260359", "SAI", 18),
+        ("0x6B175474E89094C44Da98b954EedeAC495271d0F", "DAI", 18),
+        ("0xdac17f958d2ee523a2206206994597c13d831ec7", "USDT", 6),
+        ("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "USDC", 6),
+    ]
+    .iter()
+    .map(|&(address, symbol, decimals)| TokenInfo {
+        address: address.to_string(),
+        symbol: symbol.to_string(),
+        decimals,
+        price_source: PriceSource::Static { usd: 1.0 },
+    })
+    .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PriceSource {
+    /// A fixed price, e.g. for stablecoins
+    Static { usd: f64 },
+    /// The price is read by the `PriceProvider` using the token symbol
+    Feed,
+}
+
+/// The price of a token, together with its decimals,
+/// needed to convert an amount of it to USD
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenPrice {
+    pub decimals: u8,
+    pub usd: f64,
+}
+
+impl TokenPrice {
+    pub fn amount_in_usd(&self, amount: &BigNum) -> Option<f64> {
+        let amount = amount.to_f64()?;
+        let usd = amount / 10_f64.powi(self.decimals.into()) * self.usd;
+
+        if usd.is_finite() {
+            Some(usd)
+        } else {
+            None
+        }
+    }
+}
+
+pub trait PriceProvider: fmt::Debug + Send + Sync {
+    /// Returns `None` if the price of the token is unknown
+    fn price_in_usd(&self, token: &TokenInfo) -> Option<f64>;
+}
+
+/// Only knows the prices of the tokens with `PriceSource::Static`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StaticPriceProvider;
+
+impl PriceProvider for StaticPriceProvider {
+    fn price_in_usd(&self, token: &TokenInfo) -> Option<f64> {
+        match token.price_source {
+            PriceSource::Static { usd } => Some(usd),
+            PriceSource::Feed => None,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PriceFeedError {
+    #[error("Reading the price feed file: {0}")]
+    Io(#[from] io::Error),
+    #[error("Parsing the price feed file: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// How often the modification time of the price feed file is checked
+const FEED_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default)]
+struct FeedPrices {
+    modified: Option<SystemTime>,
+    prices: HashMap<String, f64>,
+}
+
+/// Reads the prices of the tokens with `PriceSource::Feed` from a JSON file of `{ "SYMBOL": price }`,
+/// which is kept up to date by an external process.
+/// A background thread reads the file again whenever its modification time changes,
+/// so looking up a price never touches the file system.
+/// If the file can't be read or parsed, the last known prices are kept.
+#[derive(Debug)]
+pub struct FilePriceProvider {
+    path: PathBuf,
+    feed: Arc<RwLock<FeedPrices>>,
+}
+
+impl FilePriceProvider {
+    /// Fails if the file can't be read or parsed initially
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, PriceFeedError> {
+        let path = path.into();
+        let feed = Arc::new(RwLock::new(read_feed(&path)?));
+
+        let (watched_path, watched_feed) = (path.clone(), Arc::downgrade(&feed));
+        thread::Builder::new()
+            .name("price-feed".to_string())
+            .spawn(move || watch_feed(&watched_path, watched_feed))?;
+
+        Ok(Self { path, feed })
+    }
+
+    fn feed_price(&self, symbol: &str) -> Option<f64> {
+        self.feed
+            .read()
+            .expect("Should lock the price feed")
+            .prices
+            .get(symbol)
+            .copied()
+    }
+}
+
+/// Reloads the feed every `FEED_CHECK_INTERVAL` until the `FilePriceProvider` is dropped
+fn watch_feed(path: &Path, feed: Weak<RwLock<FeedPrices>>) {
+    loop {
+        thread::sleep(FEED_CHECK_INTERVAL);
+
+        match feed.upgrade() {
+            Some(feed) => reload_feed(path, &feed),
+            None => break,
+        }
+    }
+}
+
+/// Reads the file again if its modification time has changed
+fn reload_feed(path: &Path, feed: &RwLock<FeedPrices>) {
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+
+    let is_stale =
+        modified.is_some() && feed.read().expect("Should lock the price feed").modified != modified;
+
+    if is_stale {
+        if let Ok(prices) = read_feed(path) {
+            *feed.write().expect("Should lock the price feed") = prices;
+        }
+    }
+}
+
+fn read_feed(path: &Path) -> Result<FeedPrices, PriceFeedError> {
+    let modified = fs::metadata(path)?.modified().ok();
+    let prices = serde_json::from_str(&fs::read_to_string(path)?)?;
+
+    Ok(FeedPrices { modified, prices })
+}
+
+impl PriceProvider for FilePriceProvider {
+    fn price_in_usd(&self, token: &TokenInfo) -> Option<f64> {
+        match token.price_source {
+            PriceSource::Static { usd } => Some(usd),
+            PriceSource::Feed => self.feed_price(&token.symbol),
+        }
+    }
+}
+
+/// The known tokens by their (case-insensitive) address
+#[derive(Debug, Clone)]
+pub struct TokenRegistry {
+    tokens: HashMap<String, TokenInfo>,
+    price_provider: Arc<dyn PriceProvider>,
+}
+
+impl TokenRegistry {
+    pub fn new(tokens: Vec<TokenInfo>, price_provider: Arc<dyn PriceProvider>) -> Self {
+        Self {
+            tokens: tokens
+                .into_iter()
+                .map(|token| (token.address.to_lowercase(), token))
+                .collect(),
+            price_provider,
+        }
+    }
+
+    /// Uses the `FilePriceProvider` if `price_feed_file` is configured,
+    /// otherwise the `StaticPriceProvider`
+    pub fn from_config(config: &Config) -> Result<Self, PriceFeedError> {
+        let price_provider: Arc<dyn PriceProvider> = match &config.price_feed_file {
+            Some(file) => Arc::new(FilePriceProvider::open(file)?),
+            None => Arc::new(StaticPriceProvider),
+        };
+
+        Ok(Self::new(config.tokens.clone(), price_provider))
+    }
+
+    pub fn token(&self, address: &str) -> Option<&TokenInfo> {
+        self.tokens.get(&address.to_lowercase())
+    }
+
+    /// Returns `None` if the token is not registered or its price is unknown
+    pub fn price(&self, address: &str) -> Option<TokenPrice> {
+        let token = self.token(address)?;
+
+        self.price_provider
+            .price_in_usd(token)
+            .map(|usd| TokenPrice {
+                decimals: token.decimals,
+                usd,
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn token(symbol: &str, decimals: u8, price_source: PriceSource) -> TokenInfo {
+        TokenInfo {
+            address: format!("0x{:0>40}", symbol.len()),
+            symbol: symbol.to_string(),
+            decimals,
+            price_source,
+        }
+    }
+
+    #[test]
+    fn converts_amounts_with_the_token_decimals_and_price() {
+        let price = TokenPrice {
+            decimals: 6,
+            usd: 2.5,
+        };
+
+        assert_eq!(Some(250.0), price.amount_in_usd(&BigNum::from(100_000_000)));
+        assert_eq!(Some(0.5), price.amount_in_usd(&BigNum::from(200_000)));
+    }
+
+    #[test]
+    fn precision_is_ten_to_the_decimals() {
+        let dai = token("DAI", 18, PriceSource::Static { usd: 1.0 });
+
+        assert_eq!(BigNum::from(10u64.pow(18)), dai.precision());
+    }
+
+    #[test]
+    fn registry_finds_tokens_by_any_address_case() {
+        let mut usdt = token("USDT", 6, PriceSource::Static { usd: 1.0 });
+        usdt.address = "0xdAC17F958D2ee523a2206206994597C13D831ec7".to_string();
+        let registry = TokenRegistry::new(vec![usdt], Arc::new(StaticPriceProvider));
+
+        let expected = Some(TokenPrice {
+            decimals: 6,
+            usd: 1.0,
+        });
+        assert_eq!(
+            expected,
+            registry.price("0xdac17f958d2ee523a2206206994597c13d831ec7")
+        );
+        assert_eq!(
+            None,
+            registry.price("0x89d24A6b4CcB1B6fAA2625fE562bDD9a23260359")
+        );
+    }
+
+    #[test]
+    fn static_provider_does_not_know_feed_prices() {
+        let weth = token("WETH", 18, PriceSource::Feed);
+
+        assert_eq!(None, StaticPriceProvider.price_in_usd(&weth));
+    }
+
+    #[test]
+    fn file_provider_reads_the_feed_and_falls_back_to_static_prices() {
+        let path = std::env::temp_dir().join(format!(
+            "adex-price-feed-{}-{}.json",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        fs::write(&path, r#"{"WETH": 400.5}"#).expect("Should write the price feed");
+
+        let provider = FilePriceProvider::open(&path).expect("Should read the price feed");
+        let weth = token("WETH", 18, PriceSource::Feed);
+        let wbtc = token("WBTC", 8, PriceSource::Feed);
+        let dai = token("DAI", 18, PriceSource::Static { usd: 1.0 });
+
+        assert_eq!(Some(400.5), provider.price_in_usd(&weth));
+        assert_eq!(None, provider.price_in_usd(&wbtc));
+        assert_eq!(Some(1.0), provider.price_in_usd(&dai));
+
+        // an unparsable feed keeps the last known prices
+        fs::write(&path, "not json").expect("Should write the price feed");
+        provider.feed.write().unwrap().modified = None;
+        reload_feed(&provider.path, &provider.feed);
+        assert_eq!(Some(400.5), provider.price_in_usd(&weth));
+
+        // the prices are only updated when the feed is reloaded
+        fs::write(&path, r#"{"WETH": 410.0, "WBTC": 13000.0}"#)
+            .expect("Should write the price feed");
+        provider.feed.write().unwrap().modified = None;
+        assert_eq!(Some(400.5), provider.price_in_usd(&weth));
+        reload_feed(&provider.path, &provider.feed);
+        assert_eq!(Some(410.0), provider.price_in_usd(&weth));
+        assert_eq!(Some(13000.0), provider.price_in_usd(&wbtc));
+
+        fs::remove_file(&path).expect("Should remove the price feed");
+    }
+}
@@ -1,7 +1,7 @@
 use crate::{
     channel::{Pricing, PricingBounds},
     targeting::Rules,
-    AdUnit, BigNum, Channel, ChannelId, ChannelSpec, EventSubmission, SpecValidators,
+    AdUnit, BigNum, ChainId, Channel, ChannelId, ChannelSpec, EventSubmission, SpecValidators,
     ValidatorDesc, ValidatorId, IPFS,
 };
 use chrono::{TimeZone, Utc};
@@ -62,6 +62,7 @@ lazy_static! {
 
         Channel {
             id: ChannelId::from_hex("061d5e2a67d0a9a10f1c732bca12a676d83f79663a396f7d87b3e30b9b411088").expect("prep_db: failed to deserialize channel id"),
+            chain_id: ChainId::MAINNET,
             creator: ValidatorId::try_from("033ed90e0fec3f3ea1c9b005c724d704501e0196").expect("Should be valid ValidatorId"),
             deposit_asset: "0x89d24A6b4CcB1B6fAA2625fE562bDD9a23260359".to_string(),
             deposit_amount: 1_000.into(),
@@ -82,6 +83,7 @@ lazy_static! {
                 withdraw_period_start: Utc.timestamp_millis(4_073_414_400_000),
                 ad_units: vec![],
                 pricing_bounds: Some(PricingBounds {impression: None, click: Some(Pricing { max: 0.into(), min: 0.into()})}),
+                signature_scheme: None,
             },
             exhausted: Default::default(),
         }
@@ -0,0 +1,2 @@
+DROP TABLE chain_sync;
+ALTER TABLE channels DROP COLUMN chain_state;
@@ -0,0 +1,13 @@
+-- The state of the channel in the AdExCore contract, NULL until the chain watcher syncs it
+ALTER TABLE channels ADD COLUMN chain_state VARCHAR(16);
+
+CREATE INDEX idx_channels_chain_state ON channels (chain_state);
+
+-- The last block synced by the chain watcher for each AdExCore contract
+CREATE TABLE chain_sync
+(
+    core_address VARCHAR(42) NOT NULL,
+    last_block   BIGINT      NOT NULL,
+
+    PRIMARY KEY (core_address)
+);
@@ -0,0 +1,6 @@
+ALTER TABLE chain_sync DROP CONSTRAINT chain_sync_pkey;
+DELETE FROM chain_sync WHERE chain_id <> 1;
+ALTER TABLE chain_sync DROP COLUMN chain_id;
+ALTER TABLE chain_sync ADD PRIMARY KEY (core_address);
+
+ALTER TABLE channels DROP COLUMN chain_id;
@@ -0,0 +1,9 @@
+-- The chain of the AdExCore contract the channel is opened with, the existing channels are on mainnet
+ALTER TABLE channels ADD COLUMN chain_id BIGINT NOT NULL DEFAULT 1;
+
+CREATE INDEX idx_channels_chain_id ON channels (chain_id);
+
+-- The last synced block is kept for each chain as well
+ALTER TABLE chain_sync ADD COLUMN chain_id BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE chain_sync DROP CONSTRAINT chain_sync_pkey;
+ALTER TABLE chain_sync ADD PRIMARY KEY (chain_id, core_address);
@@ -0,0 +1,18 @@
+-- The hourly rollup of the events by ad unit, ad slot, country and hostname,
+-- flushed from the short-lived Redis buffer of the analytics recorder
+CREATE TABLE analytics_dimensions
+(
+    time       TIMESTAMP(2) WITH TIME ZONE NOT NULL,
+    channel_id VARCHAR(66)                 NOT NULL REFERENCES channels (id) ON DELETE RESTRICT,
+    publisher  VARCHAR(42)                 NOT NULL,
+    event_type VARCHAR(255)                NOT NULL,
+    dimension  VARCHAR(32)                 NOT NULL,
+    value      VARCHAR(255)                NOT NULL,
+    count      BIGINT                      NOT NULL DEFAULT 0,
+    -- in whole tokens of the channel deposit asset
+    payout     DOUBLE PRECISION            NOT NULL DEFAULT 0,
+    PRIMARY KEY (channel_id, publisher, event_type, dimension, value, time)
+);
+
+CREATE INDEX idx_analytics_dimensions_publisher ON analytics_dimensions (publisher, event_type, time);
+CREATE INDEX idx_analytics_dimensions_time ON analytics_dimensions (time);
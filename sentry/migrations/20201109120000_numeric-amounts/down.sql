@@ -0,0 +1,6 @@
+ALTER TABLE event_aggregates
+    ALTER COLUMN count TYPE VARCHAR USING count::VARCHAR,
+    ALTER COLUMN payout TYPE VARCHAR USING payout::VARCHAR;
+
+ALTER TABLE channels
+    ALTER COLUMN deposit_amount TYPE VARCHAR(255) USING deposit_amount::VARCHAR(255);
@@ -0,0 +1,21 @@
+-- The amounts are stored as NUMERIC, so they can be summed in Postgres,
+-- 78 digits are enough for any uint256 amount.
+-- The conversion fails instead of rounding or dropping any amount which isn't a non-negative integer.
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM event_aggregates WHERE count !~ '^[0-9]{1,78}$' OR payout !~ '^[0-9]{1,78}$') THEN
+        RAISE EXCEPTION 'event_aggregates has a count or payout which is not a non-negative integer';
+    END IF;
+
+    IF EXISTS (SELECT 1 FROM channels WHERE deposit_amount !~ '^[0-9]{1,78}$') THEN
+        RAISE EXCEPTION 'channels has a deposit_amount which is not a non-negative integer';
+    END IF;
+END
+$$;
+
+ALTER TABLE event_aggregates
+    ALTER COLUMN count TYPE NUMERIC(78, 0) USING count::NUMERIC(78, 0),
+    ALTER COLUMN payout TYPE NUMERIC(78, 0) USING payout::NUMERIC(78, 0);
+
+ALTER TABLE channels
+    ALTER COLUMN deposit_amount TYPE NUMERIC(78, 0) USING deposit_amount::NUMERIC(78, 0);
@@ -0,0 +1,15 @@
+-- The daily summaries of the event aggregates which were approved by both validators
+-- and are older than the retention period, the raw aggregates are deleted once compacted
+CREATE TABLE event_aggregates_daily
+(
+    channel_id VARCHAR(66)    NOT NULL REFERENCES channels (id) ON DELETE RESTRICT,
+    day        DATE           NOT NULL,
+    event_type VARCHAR(255)   NOT NULL,
+    -- NULL for the totals of the channel, as in event_aggregates
+    earner     VARCHAR(255),
+    count      NUMERIC(78, 0) NOT NULL,
+    payout     NUMERIC(78, 0) NOT NULL
+);
+
+CREATE UNIQUE INDEX idx_event_aggregates_daily_unique ON event_aggregates_daily (channel_id, day, event_type, COALESCE(earner, ''));
+CREATE INDEX idx_event_aggregates_daily_day ON event_aggregates_daily (day);
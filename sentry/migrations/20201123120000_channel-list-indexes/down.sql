@@ -0,0 +1,4 @@
+DROP INDEX idx_channels_creator;
+DROP INDEX idx_channels_deposit_asset;
+DROP INDEX idx_channels_spec_created_id;
+DROP INDEX idx_channels_deposit_amount_id;
@@ -0,0 +1,5 @@
+-- The filters and the keyset pagination of the channel list
+CREATE INDEX idx_channels_creator ON channels (creator);
+CREATE INDEX idx_channels_deposit_asset ON channels (deposit_asset);
+CREATE INDEX idx_channels_spec_created_id ON channels ((spec ->> 'created') DESC, id DESC);
+CREATE INDEX idx_channels_deposit_amount_id ON channels (deposit_amount DESC, id DESC);
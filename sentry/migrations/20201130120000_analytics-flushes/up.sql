@@ -0,0 +1,9 @@
+-- The batches of the analytics buffer which are already stored in `analytics_dimensions`,
+-- so a batch flushed again after a failure or by another sentry is skipped
+CREATE TABLE analytics_flushes
+(
+    batch_id VARCHAR(64)                 NOT NULL PRIMARY KEY,
+    flushed  TIMESTAMP(2) WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_analytics_flushes_flushed ON analytics_flushes (flushed);
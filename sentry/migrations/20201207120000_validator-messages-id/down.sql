@@ -0,0 +1,3 @@
+DROP INDEX idx_validator_messages_channel_id_received_id;
+
+ALTER TABLE validator_messages DROP COLUMN id;
@@ -0,0 +1,4 @@
+-- The id orders the messages received at the same time, for the keyset pagination of the messages
+ALTER TABLE validator_messages ADD COLUMN id BIGSERIAL NOT NULL;
+
+CREATE INDEX idx_validator_messages_channel_id_received_id ON validator_messages (channel_id, received DESC, id DESC);
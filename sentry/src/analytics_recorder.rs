@@ -1,21 +1,94 @@
-use crate::epoch;
+//! Records the dimensions of the impression and click events in an hourly Redis buffer,
+//! which is periodically flushed to the `analytics_dimensions` Postgres table.
+use crate::db::analytics::{insert_analytics_dimensions, DimensionKey, DimensionValue};
+use crate::db::DbPool;
 use crate::payout::get_payout;
 use crate::Session;
+use bb8::RunError;
+use chrono::{DateTime, TimeZone, Utc};
+use primitives::analytics::AnalyticsDimension;
 use primitives::sentry::Event;
-use primitives::sentry::{ChannelReport, PublisherReport};
+use primitives::targeting::CompiledRules;
+use primitives::token::{TokenInfo, TokenRegistry};
 use primitives::{BigNum, Channel};
 use redis::aio::MultiplexedConnection;
-use redis::pipe;
-use slog::{error, Logger};
+use redis::{pipe, Pipeline, RedisError};
+use slog::{error, info, Logger};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::delay_for;
+
+/// The Redis hash in which the recorded events are buffered until they are flushed
+const BUFFER_KEY: &str = "analyticsBuffer";
+/// The buffer is renamed while flushing, so the new events don't get lost
+const FLUSHING_KEY: &str = "analyticsBuffer:flushing";
+/// Only a single sentry flushes the buffer at a time
+const FLUSH_LOCK_KEY: &str = "analyticsBuffer:lock";
+/// In milliseconds
+const FLUSH_LOCK_TTL: usize = 60_000;
+/// The field of the flushing buffer with its id, which is kept until the buffer is stored,
+/// so flushing the same buffer again doesn't store its events twice
+const BATCH_ID_FIELD: &str = "batchId";
+/// Releases the lock only if it's still held with the same token,
+/// since it might have expired and been acquired by another sentry
+const UNLOCK_SCRIPT: &str =
+    "if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('DEL', KEYS[1]) else return 0 end";
+/// Deletes the flushing buffer only if it's still the same batch
+const DELETE_BATCH_SCRIPT: &str = "if redis.call('HGET', KEYS[1], ARGV[1]) == ARGV[2] then return redis.call('DEL', KEYS[1]) else return 0 end";
+/// The buffer is dropped if it isn't flushed for a day, e.g. when Postgres is down
+const BUFFER_TTL: usize = 24 * 60 * 60;
+/// The length (in characters) of the `value` column, the longer values come from invalid events
+/// and are not recorded, since they would fail the whole flushed batch
+const MAX_VALUE_LENGTH: usize = 255;
+
+#[derive(Debug)]
+pub enum Error {
+    Redis(RedisError),
+    Postgres(RunError<bb8_postgres::tokio_postgres::Error>),
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Redis(err) => write!(f, "Redis: {}", err),
+            Error::Postgres(err) => write!(f, "Postgres: {}", err),
+        }
+    }
+}
+
+impl From<RedisError> for Error {
+    fn from(err: RedisError) -> Self {
+        Error::Redis(err)
+    }
+}
+
+impl From<RunError<bb8_postgres::tokio_postgres::Error>> for Error {
+    fn from(err: RunError<bb8_postgres::tokio_postgres::Error>) -> Self {
+        Error::Postgres(err)
+    }
+}
 
 pub async fn record(
     mut conn: MultiplexedConnection,
+    token_registry: TokenRegistry,
     channel: Channel,
+    targeting_rules: Arc<CompiledRules>,
     session: Session,
     events: Vec<Event>,
     logger: Logger,
 ) {
     let mut db = pipe();
+    let now = Utc::now();
+    // the pay amounts are recorded in whole tokens,
+    // for unknown tokens we fallback to the 18 decimals of DAI
+    let divisor = token_registry
+        .token(&channel.deposit_asset)
+        .map(TokenInfo::precision)
+        .unwrap_or_else(|| BigNum::from(10u64.pow(18)));
 
     events
         .iter()
@@ -33,9 +106,7 @@ pub async fn record(
                 ad_slot,
                 referrer,
             } => {
-                let divisor = BigNum::from(10u64.pow(18));
-
-                let pay_amount = match get_payout(&logger, &channel, event, &session) {
+                let pay_amount = match get_payout(&logger, &token_registry, &channel, &targeting_rules, event, &session) {
                     Ok(Some((_, payout))) => payout.div_floor(&divisor)
                         .to_f64()
                         .expect("Should always have a payout in f64 after division"),
@@ -47,81 +118,311 @@ pub async fn record(
                     },
                 };
 
-                if let Some(ad_unit) = ad_unit {
-                    db.zincr(
-                        format!("{}:{}:{}", PublisherReport::AdUnit, event, publisher),
-                        ad_unit,
-                        1,
-                    )
-                    .ignore();
-                    db.zincr(
-                        format!("{}:{}:{}", ChannelReport::AdUnit, event, publisher),
-                        ad_unit,
-                        1,
-                    )
-                    .ignore();
-                }
-
-                if let Some(ad_slot) = ad_slot {
-                    db.zincr(
-                        format!("{}:{}:{}", PublisherReport::AdSlot, event, publisher),
-                        ad_slot,
-                        1,
-                    )
-                    .ignore();
-                    db.zincr(
-                        format!("{}:{}:{}", PublisherReport::AdSlotPay, event, publisher),
-                        ad_slot,
-                        pay_amount,
-                    )
-                    .ignore();
-                }
+                let hour = hour_bucket(now);
 
-                if let Some(country) = &session.country {
-                    db.zincr(
-                        format!(
-                            "{}:{}:{}:{}",
-                            PublisherReport::Country,
-                            epoch().floor(),
-                            event,
-                            publisher
-                        ),
-                        country,
-                        1,
-                    )
-                    .ignore();
-                }
+                for (dimension, value) in dimensions(ad_unit, ad_slot, referrer, &session) {
+                    let key = DimensionKey {
+                        time: hour,
+                        channel_id: channel.id,
+                        publisher: *publisher,
+                        event_type: event.to_string(),
+                        dimension,
+                        value,
+                    };
 
-                let hostname = (referrer.as_ref())
-                    .or_else(|| session.referrer_header.as_ref())
-                    .map(|rf| rf.split('/').nth(2).map(ToString::to_string))
-                    .flatten();
-
-                if let Some(hostname) = &hostname {
-                    db.zincr(
-                        format!("{}:{}:{}", PublisherReport::Hostname, event, publisher),
-                        hostname,
-                        1,
-                    )
-                    .ignore();
-                    db.zincr(
-                        format!("{}:{}:{}", ChannelReport::Hostname, event, channel.id),
-                        hostname,
-                        1,
-                    )
-                    .ignore();
-                    db.zincr(
-                        format!("{}:{}:{}", ChannelReport::HostnamePay, event, channel.id),
-                        hostname,
-                        1,
-                    )
-                    .ignore();
+                    buffer(&mut db, &key, pay_amount);
                 }
             }
             _ => {}
         });
 
+    db.expire(BUFFER_KEY, BUFFER_TTL).ignore();
+
     if let Err(err) = db.query_async::<_, Option<String>>(&mut conn).await {
         error!(&logger, "Redis Database error: {}", err; "module" => "analytics-recorder");
     }
 }
+
+fn hour_bucket(time: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.timestamp(time.timestamp() - time.timestamp().rem_euclid(3600), 0)
+}
+
+/// The recorded dimensions of the event, without the missing and the too long values
+fn dimensions(
+    ad_unit: &Option<String>,
+    ad_slot: &Option<String>,
+    referrer: &Option<String>,
+    session: &Session,
+) -> Vec<(AnalyticsDimension, String)> {
+    vec![
+        (AnalyticsDimension::AdUnit, ad_unit.clone()),
+        (AnalyticsDimension::AdSlot, ad_slot.clone()),
+        (AnalyticsDimension::Country, session.country.clone()),
+        (AnalyticsDimension::Hostname, hostname(referrer, session)),
+    ]
+    .into_iter()
+    .filter_map(|(dimension, value)| Some((dimension, value.filter(|v| is_storable(v))?)))
+    .collect()
+}
+
+fn is_storable(value: &str) -> bool {
+    value.chars().count() <= MAX_VALUE_LENGTH
+}
+
+fn hostname(referrer: &Option<String>, session: &Session) -> Option<String> {
+    referrer
+        .as_ref()
+        .or_else(|| session.referrer_header.as_ref())
+        .and_then(|rf| rf.split('/').nth(2).map(ToString::to_string))
+}
+
+fn buffer(db: &mut Pipeline, key: &DimensionKey, pay_amount: f64) {
+    let field = serde_json::to_string(key).expect("Should serialize the DimensionKey");
+
+    db.hincr(BUFFER_KEY, format!("count:{}", field), 1).ignore();
+    db.hincr(BUFFER_KEY, format!("payout:{}", field), pay_amount)
+        .ignore();
+}
+
+/// Parses the `count:` and `payout:` fields of the buffer, invalid fields are skipped
+fn parse_buffer(
+    logger: &Logger,
+    fields: HashMap<String, String>,
+) -> HashMap<DimensionKey, DimensionValue> {
+    let mut dimensions: HashMap<DimensionKey, DimensionValue> = HashMap::new();
+    let parse_key = |key: &str| {
+        serde_json::from_str::<DimensionKey>(key)
+            .ok()
+            .filter(|key| is_storable(&key.value))
+    };
+
+    for (field, amount) in fields {
+        let parsed = if let Some(key) = field.strip_prefix("count:") {
+            parse_key(key)
+                .zip(amount.parse::<i64>().ok())
+                .map(|(key, count)| dimensions.entry(key).or_default().count += count)
+        } else if let Some(key) = field.strip_prefix("payout:") {
+            parse_key(key)
+                .zip(amount.parse::<f64>().ok())
+                .map(|(key, payout)| dimensions.entry(key).or_default().payout += payout)
+        } else {
+            None
+        };
+
+        if parsed.is_none() {
+            error!(logger, "Skipping an invalid analytics buffer field"; "field" => field, "amount" => amount, "module" => "analytics-recorder");
+        }
+    }
+
+    dimensions
+}
+
+/// Stores the buffered events in Postgres and returns the number of stored rows.
+/// If a previous flush failed, its buffer is stored first and the new events wait for the next flush.
+pub async fn flush(
+    logger: &Logger,
+    redis: &MultiplexedConnection,
+    pool: &DbPool,
+) -> Result<u64, Error> {
+    let mut redis = redis.clone();
+    let lock_token = unique_id();
+
+    let locked: Option<String> = redis::cmd("SET")
+        .arg(FLUSH_LOCK_KEY)
+        .arg(&lock_token)
+        .arg("NX")
+        .arg("PX")
+        .arg(FLUSH_LOCK_TTL)
+        .query_async(&mut redis)
+        .await?;
+
+    if locked.is_none() {
+        return Ok(0);
+    }
+
+    let result = flush_locked(logger, &mut redis, pool).await;
+
+    redis::cmd("EVAL")
+        .arg(UNLOCK_SCRIPT)
+        .arg(1)
+        .arg(FLUSH_LOCK_KEY)
+        .arg(&lock_token)
+        .query_async::<_, ()>(&mut redis)
+        .await?;
+
+    result
+}
+
+async fn flush_locked(
+    logger: &Logger,
+    redis: &mut MultiplexedConnection,
+    pool: &DbPool,
+) -> Result<u64, Error> {
+    let flushing: bool = redis::cmd("EXISTS")
+        .arg(FLUSHING_KEY)
+        .query_async(redis)
+        .await?;
+
+    if !flushing {
+        let buffered: bool = redis::cmd("EXISTS")
+            .arg(BUFFER_KEY)
+            .query_async(redis)
+            .await?;
+
+        if !buffered {
+            return Ok(0);
+        }
+
+        // doesn't replace a buffer which another sentry started flushing in the meantime
+        redis::cmd("RENAMENX")
+            .arg(BUFFER_KEY)
+            .arg(FLUSHING_KEY)
+            .query_async::<_, ()>(redis)
+            .await?;
+    }
+
+    redis::cmd("HSETNX")
+        .arg(FLUSHING_KEY)
+        .arg(BATCH_ID_FIELD)
+        .arg(unique_id())
+        .query_async::<_, ()>(redis)
+        .await?;
+
+    let mut fields: HashMap<String, String> = redis::cmd("HGETALL")
+        .arg(FLUSHING_KEY)
+        .query_async(redis)
+        .await?;
+    // the buffer was flushed and deleted by another sentry in the meantime
+    let batch_id = match fields.remove(BATCH_ID_FIELD) {
+        Some(batch_id) => batch_id,
+        None => return Ok(0),
+    };
+
+    let inserted =
+        insert_analytics_dimensions(pool, &batch_id, &parse_buffer(logger, fields)).await?;
+
+    redis::cmd("EVAL")
+        .arg(DELETE_BATCH_SCRIPT)
+        .arg(1)
+        .arg(FLUSHING_KEY)
+        .arg(BATCH_ID_FIELD)
+        .arg(&batch_id)
+        .query_async::<_, ()>(redis)
+        .await?;
+
+    Ok(inserted)
+}
+
+/// A random id for the flush lock and the flushed batches
+fn unique_id() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// Flushes the analytics buffer every `analytics_flush_interval`
+pub async fn flush_loop(
+    redis: MultiplexedConnection,
+    pool: DbPool,
+    analytics_flush_interval: u32,
+    logger: Logger,
+) {
+    let interval = Duration::from_millis(analytics_flush_interval as u64);
+
+    loop {
+        match flush(&logger, &redis, &pool).await {
+            Ok(inserted) if inserted > 0 => {
+                info!(&logger, "Flushed the analytics buffer"; "rows" => inserted, "module" => "analytics-recorder")
+            }
+            Ok(_) => {}
+            Err(err) => {
+                error!(&logger, "Flushing the analytics buffer failed: {}", &err; "module" => "analytics-recorder")
+            }
+        }
+
+        delay_for(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use primitives::util::tests::discard_logger;
+    use primitives::util::tests::prep_db::{DUMMY_CHANNEL, IDS};
+
+    #[test]
+    fn hour_bucket_truncates_to_the_hour() {
+        let time = Utc.ymd(2020, 11, 2).and_hms(14, 35, 12);
+
+        assert_eq!(Utc.ymd(2020, 11, 2).and_hms(14, 0, 0), hour_bucket(time));
+    }
+
+    #[test]
+    fn parses_the_buffer_fields() {
+        let key = DimensionKey {
+            time: Utc.ymd(2020, 11, 2).and_hms(14, 0, 0),
+            channel_id: DUMMY_CHANNEL.id,
+            publisher: IDS["publisher"],
+            event_type: "IMPRESSION".to_string(),
+            dimension: AnalyticsDimension::Country,
+            value: "BG".to_string(),
+        };
+        let json = serde_json::to_string(&key).expect("Should serialize");
+        // buffered by a sentry which didn't drop the too long values
+        let too_long = serde_json::to_string(&DimensionKey {
+            dimension: AnalyticsDimension::Hostname,
+            value: "a".repeat(MAX_VALUE_LENGTH + 1),
+            ..key.clone()
+        })
+        .expect("Should serialize");
+
+        let fields = vec![
+            (format!("count:{}", json), "3".to_string()),
+            (format!("payout:{}", json), "0.25".to_string()),
+            ("count:invalid".to_string(), "1".to_string()),
+            (format!("count:{}", too_long), "1".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        let dimensions = parse_buffer(&discard_logger(), fields);
+
+        assert_eq!(1, dimensions.len());
+        assert_eq!(
+            Some(&DimensionValue {
+                count: 3,
+                payout: 0.25
+            }),
+            dimensions.get(&key)
+        );
+    }
+
+    #[test]
+    fn drops_the_too_long_dimension_values() {
+        let session = Session {
+            ip: None,
+            country: Some("BG".to_string()),
+            referrer_header: None,
+            os: None,
+        };
+        let ad_unit = Some("QmcUVX7fvoLMM93uN2bD3wGTH8MXSxeL8hojYfL2Lhp7mR".to_string());
+        let hostname = format!("{}.com", "a".repeat(MAX_VALUE_LENGTH));
+        let referrer = Some(format!("https://{}/page", hostname));
+
+        assert_eq!(
+            vec![
+                (AnalyticsDimension::AdUnit, ad_unit.clone().unwrap()),
+                (AnalyticsDimension::Country, "BG".to_string()),
+            ],
+            dimensions(&ad_unit, &None, &referrer, &session)
+        );
+
+        let referrer = Some("https://adex.network/page".to_string());
+        assert_eq!(
+            vec![
+                (AnalyticsDimension::AdUnit, ad_unit.clone().unwrap()),
+                (AnalyticsDimension::Country, "BG".to_string()),
+                (AnalyticsDimension::Hostname, "adex.network".to_string()),
+            ],
+            dimensions(&ad_unit, &None, &referrer, &session)
+        );
+    }
+}
@@ -0,0 +1,124 @@
+//! Syncs the on-chain state of the channels, following the channel logs of the core contract
+//! of each chain from its `sync_from_block` on.
+use crate::db::{
+    get_sync_cursor, get_unsynced_channels, update_channel_chain_state, update_sync_cursor, DbPool,
+};
+use bb8::RunError;
+use primitives::adapter::{Adapter, AdapterErrorKind, Error as AdapterError};
+use primitives::{config::ChainConfig, Config};
+use slog::{error, info, Logger};
+use std::fmt;
+use std::time::Duration;
+use tokio::time::delay_for;
+
+#[derive(Debug)]
+pub enum Error<AE: AdapterErrorKind> {
+    Adapter(AdapterError<AE>),
+    Postgres(RunError<bb8_postgres::tokio_postgres::Error>),
+}
+
+impl<AE: AdapterErrorKind> std::error::Error for Error<AE> {}
+
+impl<AE: AdapterErrorKind> fmt::Display for Error<AE> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Adapter(err) => write!(f, "Adapter: {}", err),
+            Error::Postgres(err) => write!(f, "Postgres: {}", err),
+        }
+    }
+}
+
+impl<AE: AdapterErrorKind> From<AdapterError<AE>> for Error<AE> {
+    fn from(err: AdapterError<AE>) -> Self {
+        Error::Adapter(err)
+    }
+}
+
+impl<AE: AdapterErrorKind> From<RunError<bb8_postgres::tokio_postgres::Error>> for Error<AE> {
+    fn from(err: RunError<bb8_postgres::tokio_postgres::Error>) -> Self {
+        Error::Postgres(err)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SyncStatus {
+    /// The number of channel logs applied
+    pub logs: usize,
+    /// The number of channels whose state was looked up, as none of their logs were synced
+    pub looked_up: usize,
+    /// The last synced block, `None` if there were no new confirmed blocks
+    pub last_block: Option<u64>,
+}
+
+/// Syncs the channels of every chain every `ethereum_sync_interval`
+pub async fn watch<A: Adapter + 'static>(adapter: A, config: Config, pool: DbPool, logger: Logger) {
+    let interval = Duration::from_millis(config.ethereum_sync_interval as u64);
+
+    loop {
+        // a failing chain doesn't hold back the sync of the others
+        for chain in config.chains.iter() {
+            match sync(&adapter, &config, chain, &pool).await {
+                Ok(status) => {
+                    if status.logs > 0 || status.looked_up > 0 {
+                        info!(&logger, "Synced the on-chain channel states"; "chain_id" => %chain.chain_id, "status" => ?status, "module" => "chain_watcher");
+                    }
+                }
+                Err(err) => {
+                    error!(&logger, "Syncing the on-chain channel states failed: {}", &err; "chain_id" => %chain.chain_id, "module" => "chain_watcher")
+                }
+            }
+        }
+
+        delay_for(interval).await;
+    }
+}
+
+/// Applies the channel logs of the `chain` confirmed blocks since the last synced one.
+/// Afterwards, the states of the channels on the `chain` without any synced logs are looked up,
+/// e.g. channels opened before its `sync_from_block`.
+/// Channels which were never opened end up in the `Unknown` state and are hidden from the channel list.
+pub async fn sync<A: Adapter>(
+    adapter: &A,
+    config: &Config,
+    chain: &ChainConfig,
+    pool: &DbPool,
+) -> Result<SyncStatus, Error<A::AdapterError>> {
+    // the cursor is kept for each core contract, so changing it starts the sync over
+    let core_address = format!("0x{}", hex::encode(chain.core_address));
+    let mut status = SyncStatus::default();
+
+    let mut from_block = get_sync_cursor(pool, chain.chain_id, &core_address)
+        .await?
+        .map(|last_block| last_block + 1)
+        .unwrap_or(chain.sync_from_block);
+
+    loop {
+        let channel_logs = adapter.channel_logs(chain.chain_id, from_block).await?;
+
+        for log in channel_logs.logs.iter() {
+            update_channel_chain_state(pool, &log.channel_id, log.event.state()).await?;
+        }
+        status.logs += channel_logs.logs.len();
+
+        match channel_logs.to_block {
+            Some(to_block) => {
+                update_sync_cursor(pool, chain.chain_id, &core_address, to_block).await?;
+                status.last_block = Some(to_block);
+                from_block = to_block + 1;
+            }
+            None => break,
+        }
+    }
+
+    let unsynced = get_unsynced_channels(pool, chain.chain_id, config.channels_find_limit).await?;
+    if !unsynced.is_empty() {
+        let states = adapter.channel_states(chain.chain_id, &unsynced).await?;
+
+        for (channel_id, state) in states.iter() {
+            update_channel_chain_state(pool, channel_id, *state).await?;
+        }
+        status.looked_up = states.len();
+    }
+
+    Ok(status)
+}
@@ -0,0 +1,204 @@
+//! Real-time updates of the channels, streamed to the clients as Server-Sent Events.
+//!
+//! The updates are published on a Redis Pub/Sub channel and every sentry replica
+//! forwards them to its own streams, so a client receives the updates stored by any replica.
+//! The updates published while a replica reconnects to Redis are missed,
+//! so the clients should reload the channels whenever they (re)connect to the stream.
+use crate::db::redis_pubsub_connection;
+use futures::StreamExt;
+use hyper::body::{Bytes, Sender};
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
+use hyper::{Body, Response};
+use primitives::sentry::ChannelUpdate;
+use primitives::ChannelId;
+use redis::aio::MultiplexedConnection;
+use redis::RedisError;
+use slog::{error, Logger};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, Receiver, RecvError};
+use tokio::time::{delay_for, interval};
+
+/// The maximum number of channels of a single stream
+pub const MAX_CHANNELS: usize = 100;
+/// The Redis Pub/Sub channel of the updates
+const REDIS_CHANNEL: &str = "channelUpdates";
+/// The number of updates buffered for each stream,
+/// a stream which falls further behind is closed
+const BUFFERED_UPDATES: usize = 1024;
+/// A comment is sent to the idle streams so the proxies keep them open
+/// and the disconnected clients are noticed
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+const KEEP_ALIVE_COMMENT: &[u8] = b": keep-alive\n\n";
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// The updates received from Redis, shared by all of the streams of this sentry
+#[derive(Clone)]
+pub struct ChannelUpdates {
+    sender: broadcast::Sender<Arc<ChannelUpdate>>,
+}
+
+impl Default for ChannelUpdates {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(BUFFERED_UPDATES);
+
+        Self { sender }
+    }
+}
+
+impl ChannelUpdates {
+    /// Streams the updates of the `channels` as Server-Sent Events
+    pub fn stream(&self, logger: Logger, channels: Vec<ChannelId>) -> Response<Body> {
+        let receiver = self.sender.subscribe();
+        let (sender, body) = Body::channel();
+
+        tokio::spawn(send_updates(receiver, channels, sender, logger));
+
+        Response::builder()
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(body)
+            .expect("Should build the stream response")
+    }
+}
+
+/// Publishes the update to the streams of every sentry.
+/// A failure is only logged, since the update itself is already stored.
+pub async fn publish(redis: &MultiplexedConnection, logger: &Logger, update: &ChannelUpdate) {
+    let payload = match serde_json::to_string(update) {
+        Ok(payload) => payload,
+        Err(err) => {
+            error!(logger, "Serializing the channel update failed: {}", err; "module" => "channel_updates");
+            return;
+        }
+    };
+
+    let published = redis::cmd("PUBLISH")
+        .arg(REDIS_CHANNEL)
+        .arg(payload)
+        .query_async::<_, u64>(&mut redis.clone())
+        .await;
+
+    if let Err(err) = published {
+        error!(logger, "Publishing the channel update failed: {}", err; "module" => "channel_updates");
+    }
+}
+
+/// Forwards the updates published on Redis to the streams of this sentry,
+/// reconnecting whenever the subscription fails
+pub async fn subscribe_loop(updates: ChannelUpdates, logger: Logger) {
+    loop {
+        match subscribe(&updates, &logger).await {
+            Ok(()) => {
+                error!(&logger, "The subscription to the channel updates was closed"; "module" => "channel_updates")
+            }
+            Err(err) => {
+                error!(&logger, "Subscribing to the channel updates failed: {}", err; "module" => "channel_updates")
+            }
+        }
+
+        delay_for(RECONNECT_DELAY).await;
+    }
+}
+
+async fn subscribe(updates: &ChannelUpdates, logger: &Logger) -> Result<(), RedisError> {
+    let mut pubsub = redis_pubsub_connection().await?;
+    pubsub.subscribe(REDIS_CHANNEL).await?;
+
+    let mut messages = pubsub.on_message();
+    while let Some(message) = messages.next().await {
+        let payload: String = message.get_payload()?;
+
+        match serde_json::from_str::<ChannelUpdate>(&payload) {
+            Ok(update) => {
+                // it fails only when there are no streams
+                let _ = updates.sender.send(Arc::new(update));
+            }
+            Err(err) => {
+                error!(logger, "Invalid channel update: {}", err; "module" => "channel_updates")
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn send_updates(
+    mut receiver: Receiver<Arc<ChannelUpdate>>,
+    channels: Vec<ChannelId>,
+    mut sender: Sender,
+    logger: Logger,
+) {
+    let mut keep_alive = interval(KEEP_ALIVE_INTERVAL);
+
+    loop {
+        let received = tokio::select! {
+            _ = keep_alive.tick() => None,
+            received = receiver.recv() => Some(received),
+        };
+
+        let event = match received {
+            None => Bytes::from_static(KEEP_ALIVE_COMMENT),
+            Some(Ok(update)) if channels.contains(update.channel_id()) => {
+                match encode_event(&update) {
+                    Ok(event) => event.into(),
+                    Err(err) => {
+                        error!(&logger, "Serializing the channel update failed: {}", err; "module" => "channel_updates");
+                        continue;
+                    }
+                }
+            }
+            Some(Ok(_)) => continue,
+            // the stream fell behind, the client reconnects and reloads the channels
+            Some(Err(RecvError::Lagged(_))) | Some(Err(RecvError::Closed)) => break,
+        };
+
+        // the client has disconnected
+        if sender.send_data(event).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// An event with the JSON of the update as its data, the JSON has no line breaks
+fn encode_event(update: &ChannelUpdate) -> Result<String, serde_json::Error> {
+    serde_json::to_string(update).map(|json| format!("data: {}\n\n", json))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use primitives::util::tests::prep_db::{DUMMY_CHANNEL, IDS};
+
+    #[test]
+    fn encodes_the_updates_as_events() {
+        let update = ChannelUpdate::Exhausted {
+            channel_id: DUMMY_CHANNEL.id,
+            validator: IDS["leader"],
+        };
+
+        assert_eq!(
+            format!(
+                "data: {{\"type\":\"exhausted\",\"channelId\":\"{}\",\"validator\":\"{}\"}}\n\n",
+                DUMMY_CHANNEL.id, IDS["leader"]
+            ),
+            encode_event(&update).expect("Should encode")
+        );
+
+        let update = ChannelUpdate::EventAggregate {
+            channel_id: DUMMY_CHANNEL.id,
+            created: Utc.timestamp(1_600_000_000, 0),
+        };
+        let event = encode_event(&update).expect("Should encode");
+        let data = event
+            .strip_prefix("data: ")
+            .and_then(|event| event.strip_suffix("\n\n"))
+            .expect("Should be a single data field");
+
+        assert_eq!(
+            update,
+            serde_json::from_str(data).expect("Should deserialize the update")
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! Compacts the event aggregates which are covered by a `NewState` approved by both validators
+//! and are older than the retention period into daily summaries.
+//!
+//! The validator workers fetch the event aggregates after the `lastEvAggr` of their latest `Accounting`,
+//! so only the aggregates before the `Accounting`s of both validators are compacted.
+//! The analytics read both the event aggregates and their daily summaries.
+use crate::db::event_aggregate::{
+    channels_with_aggregates_before, compact_event_aggregates, latest_accounting,
+    latest_approve_state, latest_new_state,
+};
+use crate::db::{get_channel_by_id, DbPool};
+use bb8::RunError;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use primitives::validator::MessageTypes;
+use primitives::{Channel, Config};
+use slog::{error, info, Logger};
+use std::time::Duration;
+use tokio::time::delay_for;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CompactionStatus {
+    /// The number of channels whose aggregates were compacted
+    pub channels: usize,
+    /// The number of compacted event aggregates
+    pub aggregates: u64,
+}
+
+/// Compacts the event aggregates every `event_aggregates_compaction_interval`
+pub async fn compaction_loop(pool: DbPool, config: Config, logger: Logger) {
+    let interval = Duration::from_millis(config.event_aggregates_compaction_interval as u64);
+
+    loop {
+        match compact(&pool, &config, Utc::now()).await {
+            Ok(status) => {
+                if status.aggregates > 0 {
+                    info!(&logger, "Compacted the approved event aggregates"; "status" => ?status, "module" => "compaction");
+                }
+            }
+            Err(err) => {
+                error!(&logger, "Compacting the event aggregates failed: {}", &err; "module" => "compaction")
+            }
+        }
+
+        delay_for(interval).await;
+    }
+}
+
+/// Compacts the approved event aggregates of every channel which are older than the retention period
+pub async fn compact(
+    pool: &DbPool,
+    config: &Config,
+    now: DateTime<Utc>,
+) -> Result<CompactionStatus, RunError<bb8_postgres::tokio_postgres::Error>> {
+    let retained_from = now - ChronoDuration::days(config.event_aggregates_retention_days.into());
+    let mut status = CompactionStatus::default();
+
+    for channel_id in channels_with_aggregates_before(pool, retained_from).await? {
+        let channel = match get_channel_by_id(pool, &channel_id).await? {
+            Some(channel) => channel,
+            None => continue,
+        };
+
+        let approved_until = match approved_until(pool, &channel).await? {
+            Some(approved_until) => approved_until,
+            None => continue,
+        };
+
+        // the aggregates created at `retained_from` are retained
+        let until = approved_until.min(retained_from - ChronoDuration::milliseconds(1));
+        let compacted = compact_event_aggregates(pool, &channel.id, until).await?;
+
+        if compacted > 0 {
+            status.channels += 1;
+            status.aggregates += compacted;
+        }
+    }
+
+    Ok(status)
+}
+
+/// The time of the last event aggregate which is both in the last approved `NewState`
+/// and before the latest `Accounting` of each of the validators.
+/// `None` if there is no approved `NewState` yet.
+async fn approved_until(
+    pool: &DbPool,
+    channel: &Channel,
+) -> Result<Option<DateTime<Utc>>, RunError<bb8_postgres::tokio_postgres::Error>> {
+    let state_root = match latest_approve_state(pool, channel).await? {
+        Some(approve_state) => match approve_state.msg {
+            MessageTypes::ApproveState(approve_state) => approve_state.state_root,
+            _ => return Ok(None),
+        },
+        None => return Ok(None),
+    };
+
+    let new_state = match latest_new_state(pool, channel, &state_root).await? {
+        Some(new_state) => new_state,
+        None => return Ok(None),
+    };
+
+    // the `NewState` is produced from the leader's `Accounting` before it
+    let leader = channel.spec.validators.leader();
+    let approved_accounting =
+        match latest_accounting(pool, &channel.id, &leader.id, Some(new_state.received)).await? {
+            Some(accounting) => accounting,
+            None => return Ok(None),
+        };
+
+    let mut until = approved_accounting.last_event_aggregate;
+    for validator in channel.spec.validators.iter() {
+        match latest_accounting(pool, &channel.id, &validator.id, None).await? {
+            Some(accounting) => until = until.min(accounting.last_event_aggregate),
+            // the validator still needs all of the aggregates
+            None => return Ok(None),
+        }
+    }
+
+    Ok(Some(until))
+}
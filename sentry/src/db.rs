@@ -1,23 +1,29 @@
 use bb8::Pool;
+use bb8_postgres::tokio_postgres::types::ToSql;
 use bb8_postgres::tokio_postgres::NoTls;
 use bb8_postgres::PostgresConnectionManager;
-use redis::aio::MultiplexedConnection;
+use redis::aio::{MultiplexedConnection, PubSub};
 use redis::RedisError;
 use std::env;
 
 use lazy_static::lazy_static;
 
 pub mod analytics;
+mod chain_sync;
 mod channel;
 pub mod event_aggregate;
 mod validator_message;
 
+pub use self::chain_sync::*;
 pub use self::channel::*;
 pub use self::event_aggregate::*;
 pub use self::validator_message::*;
 
 pub type DbPool = Pool<PostgresConnectionManager<NoTls>>;
 
+/// Owned statement parameters, for statements which outlive the request, e.g. streamed exports
+pub type SqlParams = Vec<Box<dyn ToSql + Send + Sync>>;
+
 lazy_static! {
     static ref REDIS_URL: String =
         env::var("REDIS_URL").unwrap_or_else(|_| String::from("redis://127.0.0.1:6379"));
@@ -41,7 +47,22 @@ pub async fn redis_connection() -> Result<MultiplexedConnection, RedisError> {
     Ok(connection)
 }
 
+/// A dedicated connection for subscribing to the Redis Pub/Sub channels,
+/// since a subscribed connection can't be used for any other commands
+pub async fn redis_pubsub_connection() -> Result<PubSub, RedisError> {
+    let client = redis::Client::open(REDIS_URL.as_str())?;
+    let connection = client.get_async_connection().await?;
+    Ok(connection.into_pubsub())
+}
+
 pub async fn postgres_connection() -> Result<DbPool, bb8_postgres::tokio_postgres::Error> {
+    postgres_connection_to(POSTGRES_DB.as_deref()).await
+}
+
+/// Connects to the provided database or if `None`, to the default database of the `POSTGRES_USER`
+pub async fn postgres_connection_to(
+    database: Option<&str>,
+) -> Result<DbPool, bb8_postgres::tokio_postgres::Error> {
     let mut config = bb8_postgres::tokio_postgres::Config::new();
 
     config
@@ -49,8 +70,8 @@ pub async fn postgres_connection() -> Result<DbPool, bb8_postgres::tokio_postgre
         .password(POSTGRES_PASSWORD.as_str())
         .host(POSTGRES_HOST.as_str())
         .port(*POSTGRES_PORT);
-    if let Some(db) = POSTGRES_DB.clone() {
-        config.dbname(&db);
+    if let Some(db) = database {
+        config.dbname(db);
     }
     let pg_mgr = PostgresConnectionManager::new(config, NoTls);
 
@@ -58,6 +79,11 @@ pub async fn postgres_connection() -> Result<DbPool, bb8_postgres::tokio_postgre
 }
 
 pub async fn setup_migrations(environment: &str) {
+    setup_database_migrations(environment, &POSTGRES_DB.as_ref().unwrap_or(&POSTGRES_USER)).await
+}
+
+/// Applies the migrations to the provided database
+pub async fn setup_database_migrations(environment: &str, database: &str) {
     use migrant_lib::{Config, Direction, Migrator, Settings};
 
     let settings = Settings::configure_postgres()
@@ -65,7 +91,7 @@ pub async fn setup_migrations(environment: &str) {
         .database_password(POSTGRES_PASSWORD.as_str())
         .database_host(POSTGRES_HOST.as_str())
         .database_port(*POSTGRES_PORT)
-        .database_name(&POSTGRES_DB.as_ref().unwrap_or(&POSTGRES_USER))
+        .database_name(database)
         .build()
         .expect("Should build migration settings");
 
@@ -87,6 +113,14 @@ pub async fn setup_migrations(environment: &str) {
     let mut migrations = vec![
         make_migration!("20190806011140_initial-tables"),
         make_migration!("20200625092729_channel-targeting-rules"),
+        make_migration!("20201019120000_channel-chain-state"),
+        make_migration!("20201026120000_channel-chain-id"),
+        make_migration!("20201102120000_analytics-dimensions"),
+        make_migration!("20201109120000_numeric-amounts"),
+        make_migration!("20201116120000_event-aggregates-daily"),
+        make_migration!("20201123120000_channel-list-indexes"),
+        make_migration!("20201130120000_analytics-flushes"),
+        make_migration!("20201207120000_validator-messages-id"),
     ];
 
     if environment == "development" {
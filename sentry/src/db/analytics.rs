@@ -1,16 +1,16 @@
-use crate::db::DbPool;
-use crate::epoch;
+use crate::db::{DbPool, SqlParams};
 use crate::Auth;
 use bb8::RunError;
 use bb8_postgres::tokio_postgres::types::ToSql;
-use chrono::Utc;
-use primitives::analytics::{AnalyticsData, AnalyticsQuery, ANALYTICS_QUERY_LIMIT};
+use chrono::{serde::ts_seconds, DateTime, Utc};
+use primitives::analytics::{
+    AdvancedAnalyticsQuery, AnalyticsData, AnalyticsDimension, AnalyticsQuery, TimeFrame,
+    ANALYTICS_QUERY_LIMIT,
+};
 use primitives::sentry::{AdvancedAnalyticsResponse, ChannelReport, PublisherReport};
 use primitives::{ChannelId, ValidatorId};
-use redis::aio::MultiplexedConnection;
-use redis::cmd;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::error::Error;
 
 pub enum AnalyticsType {
     Advertiser { auth: Auth },
@@ -40,6 +40,9 @@ pub async fn advertiser_channel_ids(
     .await
 }
 
+/// The event aggregates together with the compacted daily summaries, which are at the start of their day in UTC
+const EVENT_AGGREGATES_WITH_DAILY: &str = "(SELECT channel_id, created, event_type, earner, count, payout FROM event_aggregates UNION ALL SELECT channel_id, day::timestamp AT TIME ZONE 'UTC' AS created, event_type, earner, count, payout FROM event_aggregates_daily)";
+
 fn metric_to_column(metric: &str) -> String {
     match metric {
         "eventCounts" => "count".to_string(),
@@ -50,22 +53,70 @@ fn metric_to_column(metric: &str) -> String {
 
 pub async fn get_analytics(
     query: AnalyticsQuery,
+    time_frame: &TimeFrame,
     pool: &DbPool,
     analytics_type: AnalyticsType,
     segment_by_channel: bool,
     channel_id: Option<&ChannelId>,
 ) -> Result<Vec<AnalyticsData>, RunError<bb8_postgres::tokio_postgres::Error>> {
+    let applied_limit = query.limit.min(ANALYTICS_QUERY_LIMIT);
+    let (sql_query, params) = analytics_statement(
+        &query,
+        time_frame,
+        &analytics_type,
+        segment_by_channel,
+        channel_id,
+        Some(applied_limit),
+    );
+
+    // execute query
+    pool.run(move |connection| async move {
+        let params: Vec<&(dyn ToSql + Sync)> = params
+            .iter()
+            .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+            .collect();
+
+        match connection.prepare(&sql_query).await {
+            Ok(stmt) => match connection.query(&stmt, &params).await {
+                Ok(rows) => {
+                    let analytics: Vec<AnalyticsData> =
+                        rows.iter().map(AnalyticsData::from).collect();
+                    Ok((analytics, connection))
+                }
+                Err(e) => Err((e, connection)),
+            },
+            Err(e) => Err((e, connection)),
+        }
+    })
+    .await
+}
+
+/// The statement of the analytics query and its parameters.
+/// Without a `limit`, all of the rows in the time frame are returned, e.g. for exports
+pub fn analytics_statement(
+    query: &AnalyticsQuery,
+    time_frame: &TimeFrame,
+    analytics_type: &AnalyticsType,
+    segment_by_channel: bool,
+    channel_id: Option<&ChannelId>,
+    limit: Option<u32>,
+) -> (String, SqlParams) {
     // converts metric to column
     let metric = metric_to_column(&query.metric);
 
-    let mut params = Vec::<&(dyn ToSql + Sync)>::new();
-    let applied_limit = query.limit.min(ANALYTICS_QUERY_LIMIT);
-    let (interval, period) = get_time_frame(&query.timeframe);
-    let time_limit = Utc::now().timestamp() - period;
-
-    let mut where_clauses = vec![format!("created > to_timestamp({})", time_limit)];
+    let mut params: SqlParams = vec![
+        Box::new(time_frame.start),
+        Box::new(time_frame.end),
+        Box::new(time_frame.timezone.name().to_string()),
+    ];
+    let mut where_clauses = vec!["created >= $1".to_string(), "created < $2".to_string()];
+    // the buckets are aligned in the local time of the timezone and converted back to UTC
+    let time_clause = format!(
+        "extract(epoch from (to_timestamp(floor(extract(epoch from (created AT TIME ZONE $3)) / {bucket}) * {bucket}) AT TIME ZONE 'UTC') AT TIME ZONE $3)::float8 as time",
+        bucket = time_frame.bucket
+    );
 
-    params.push(&query.event_type);
+    params.push(Box::new(query.event_type.clone()));
 
     where_clauses.extend(vec![
         format!("event_type = ${}", params.len()),
@@ -86,26 +137,17 @@ pub async fn get_analytics(
                 ));
             }
 
-            format!(
-                "SUM({}::numeric)::varchar as value, (extract(epoch from created) - (MOD( CAST (extract(epoch from created) AS NUMERIC), {}))) as time", 
-                metric, interval
-            )
+            format!("SUM({})::varchar as value, {}", metric, time_clause)
         }
         AnalyticsType::Global => {
             where_clauses.push("earner IS NULL".to_string());
 
-            format!(
-                "SUM({}::numeric)::varchar as value, (extract(epoch from created) - (MOD( CAST (extract(epoch from created) AS NUMERIC), {}))) as time", 
-                metric, interval
-            )
+            format!("SUM({})::varchar as value, {}", metric, time_clause)
         }
         AnalyticsType::Publisher { auth } => {
             where_clauses.push(format!("earner = '{}'", auth.uid));
 
-            format!(
-                "SUM({}::numeric)::varchar as value, (extract(epoch from created) - (MOD( CAST (extract(epoch from created) AS NUMERIC), {}))) as time", 
-                metric, interval
-            )
+            format!("SUM({})::varchar as value, {}", metric, time_clause)
         }
     };
 
@@ -114,22 +156,184 @@ pub async fn get_analytics(
         group_clause = format!("{}, channel_id", group_clause);
     }
 
+    let limit_clause = limit
+        .map(|limit| format!(" LIMIT {}", limit))
+        .unwrap_or_default();
+
     let sql_query = format!(
-        "SELECT {} FROM event_aggregates WHERE {} GROUP BY {} LIMIT {}",
+        "SELECT {} FROM {} AS event_aggregates WHERE {} GROUP BY {} ORDER BY time{}",
         select_clause,
+        EVENT_AGGREGATES_WITH_DAILY,
         where_clauses.join(" AND "),
         group_clause,
-        applied_limit,
+        limit_clause,
+    );
+
+    (sql_query, params)
+}
+
+/// An hourly bucket of the events of a channel and publisher with the same dimension value
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DimensionKey {
+    #[serde(with = "ts_seconds")]
+    pub time: DateTime<Utc>,
+    pub channel_id: ChannelId,
+    pub publisher: ValidatorId,
+    pub event_type: String,
+    pub dimension: AnalyticsDimension,
+    pub value: String,
+}
+
+/// The events count and their payout in whole tokens
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DimensionValue {
+    pub count: i64,
+    pub payout: f64,
+}
+
+/// Adds the counts and payouts to the ones already stored for the same hourly buckets,
+/// unless the batch with the `batch_id` was already stored
+pub async fn insert_analytics_dimensions(
+    pool: &DbPool,
+    batch_id: &str,
+    dimensions: &HashMap<DimensionKey, DimensionValue>,
+) -> Result<u64, RunError<bb8_postgres::tokio_postgres::Error>> {
+    if dimensions.is_empty() {
+        return Ok(0);
+    }
+
+    let mut times = Vec::with_capacity(dimensions.len());
+    let mut channel_ids = Vec::with_capacity(dimensions.len());
+    let mut publishers = Vec::with_capacity(dimensions.len());
+    let mut event_types = Vec::with_capacity(dimensions.len());
+    let mut dimension_names = Vec::with_capacity(dimensions.len());
+    let mut values = Vec::with_capacity(dimensions.len());
+    let mut counts = Vec::with_capacity(dimensions.len());
+    let mut payouts = Vec::with_capacity(dimensions.len());
+
+    for (key, value) in dimensions {
+        times.push(key.time);
+        channel_ids.push(key.channel_id);
+        publishers.push(key.publisher);
+        event_types.push(key.event_type.clone());
+        dimension_names.push(key.dimension.as_str());
+        values.push(key.value.clone());
+        counts.push(value.count);
+        payouts.push(value.payout);
+    }
+    let batch_id = batch_id.to_string();
+
+    pool
+        .run(move |connection| {
+            async move {
+                // a single statement, so either the batch is recorded and all of its buckets are updated or nothing is.
+                // An already recorded batch is skipped and the batches are remembered for longer than the buffer is kept
+                let statement = "WITH batch AS (INSERT INTO analytics_flushes (batch_id) VALUES ($9) ON CONFLICT DO NOTHING RETURNING batch_id), expired AS (DELETE FROM analytics_flushes WHERE flushed < NOW() - INTERVAL '2 days') INSERT INTO analytics_dimensions (time, channel_id, publisher, event_type, dimension, value, count, payout) SELECT * FROM UNNEST($1::timestamptz[], $2::varchar[], $3::varchar[], $4::varchar[], $5::varchar[], $6::varchar[], $7::bigint[], $8::float8[]) WHERE EXISTS (SELECT 1 FROM batch) ON CONFLICT (channel_id, publisher, event_type, dimension, value, time) DO UPDATE SET count = analytics_dimensions.count + EXCLUDED.count, payout = analytics_dimensions.payout + EXCLUDED.payout";
+                match connection.prepare(statement).await {
+                    Ok(stmt) => match connection.execute(&stmt, &[&times, &channel_ids, &publishers, &event_types, &dimension_names, &values, &counts, &payouts, &batch_id]).await {
+                        Ok(row_count) => Ok((row_count, connection)),
+                        Err(e) => Err((e, connection)),
+                    },
+                    Err(e) => Err((e, connection)),
+                }
+            }
+        })
+        .await
+}
+
+/// The totals of a dimension value over the hourly buckets in the range
+#[derive(Debug, Clone, PartialEq)]
+struct DimensionTotal {
+    channel_id: Option<ChannelId>,
+    dimension: AnalyticsDimension,
+    value: String,
+    count: i64,
+    payout: f64,
+}
+
+impl DimensionTotal {
+    fn report_value(&self, is_payout: bool) -> f64 {
+        if is_payout {
+            self.payout
+        } else {
+            self.count as f64
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum TotalsOf<'a> {
+    Publisher(&'a ValidatorId),
+    Channels(&'a [ChannelId]),
+}
+
+async fn get_dimension_totals(
+    pool: &DbPool,
+    query: &AdvancedAnalyticsQuery,
+    totals_of: TotalsOf<'_>,
+) -> Result<Vec<DimensionTotal>, RunError<bb8_postgres::tokio_postgres::Error>> {
+    let channel_ids = match totals_of {
+        TotalsOf::Channels(channel_ids) => Some(channel_ids.to_vec()),
+        TotalsOf::Publisher(_) => None,
+    };
+    let dimension = query.dimension.map(|dimension| dimension.as_str());
+
+    let mut params = Vec::<&(dyn ToSql + Sync)>::new();
+    params.push(&query.event_type);
+    let mut where_clauses = vec![format!("event_type = ${}", params.len())];
+    let mut group_clause = "dimension, value".to_string();
+
+    if let TotalsOf::Publisher(publisher) = totals_of {
+        params.push(publisher);
+        where_clauses.push(format!("publisher = ${}", params.len()));
+    }
+    if let Some(channel_ids) = &channel_ids {
+        params.push(channel_ids);
+        where_clauses.push(format!("channel_id = ANY(${})", params.len()));
+        group_clause = format!("channel_id, {}", group_clause);
+    }
+
+    if let Some(start) = &query.start {
+        params.push(start);
+        where_clauses.push(format!("time >= ${}", params.len()));
+    }
+    if let Some(end) = &query.end {
+        params.push(end);
+        where_clauses.push(format!("time < ${}", params.len()));
+    }
+    if let Some(dimension) = &dimension {
+        params.push(dimension);
+        where_clauses.push(format!("dimension = ${}", params.len()));
+    }
+
+    let statement = format!(
+        "SELECT {}, SUM(count)::bigint AS count, SUM(payout) AS payout FROM analytics_dimensions WHERE {} GROUP BY {}",
+        group_clause,
+        where_clauses.join(" AND "),
+        group_clause
     );
 
-    // execute query
     pool.run(move |connection| async move {
-        match connection.prepare(&sql_query).await {
+        match connection.prepare(&statement).await {
             Ok(stmt) => match connection.query(&stmt, &params).await {
                 Ok(rows) => {
-                    let analytics: Vec<AnalyticsData> =
-                        rows.iter().map(AnalyticsData::from).collect();
-                    Ok((analytics, connection))
+                    let totals = rows
+                        .iter()
+                        .filter_map(|row| {
+                            let dimension = row.get::<_, &str>("dimension").parse().ok()?;
+
+                            Some(DimensionTotal {
+                                channel_id: row.try_get("channel_id").ok(),
+                                dimension,
+                                value: row.get("value"),
+                                count: row.get("count"),
+                                payout: row.get("payout"),
+                            })
+                        })
+                        .collect();
+
+                    Ok((totals, connection))
                 }
                 Err(e) => Err((e, connection)),
             },
@@ -139,50 +343,21 @@ pub async fn get_analytics(
     .await
 }
 
-fn get_time_frame(timeframe: &str) -> (i64, i64) {
-    let minute = 60 * 1000;
-    let hour = 60 * minute;
-    let day = 24 * hour;
-
-    match timeframe {
-        "year" => (30 * day, 365 * day),
-        "month" => (day, 30 * day),
-        "week" => (6 * hour, 7 * day),
-        "day" => (hour, day),
-        "hour" => (minute, hour),
-        _ => (hour, day),
-    }
-}
-
-async fn stat_pair(
-    mut conn: MultiplexedConnection,
-    key: &str,
-) -> Result<HashMap<String, f64>, Box<dyn Error>> {
-    let data = cmd("ZRANGE")
-        .arg(key)
-        .arg(0 as u64)
-        .arg(-1 as i64)
-        .arg("WITHSCORES")
-        .query_async::<_, Vec<String>>(&mut conn)
-        .await?;
-
-    Ok(data
-        .chunks(2)
-        .map(|chunk: &[String]| {
-            (
-                chunk[0].clone(),
-                chunk[1].parse::<f64>().expect("should parse value"),
-            )
-        })
-        .collect())
-}
-
+/// The reports of the `publisher` and of the `channel_ids` in the range of the query,
+/// only for the dimension of the query, if it's set
 pub async fn get_advanced_reports(
-    redis: &MultiplexedConnection,
-    event_type: &str,
+    pool: &DbPool,
+    query: &AdvancedAnalyticsQuery,
     publisher: &ValidatorId,
     channel_ids: &[ChannelId],
-) -> Result<AdvancedAnalyticsResponse, Box<dyn Error>> {
+) -> Result<AdvancedAnalyticsResponse, RunError<bb8_postgres::tokio_postgres::Error>> {
+    let in_query = |dimension: AnalyticsDimension| {
+        query
+            .dimension
+            .map(|query_dimension| query_dimension == dimension)
+            .unwrap_or(true)
+    };
+
     let publisher_reports = [
         PublisherReport::AdUnit,
         PublisherReport::AdSlot,
@@ -191,24 +366,22 @@ pub async fn get_advanced_reports(
         PublisherReport::Hostname,
     ];
 
-    let mut publisher_stats: HashMap<PublisherReport, HashMap<String, f64>> = HashMap::new();
-
-    for publisher_report in publisher_reports.iter() {
-        let pair = match publisher_report {
-            PublisherReport::Country => format!(
-                "{}:{}:{}:{}",
-                epoch().floor(),
-                publisher_report,
-                event_type,
-                publisher
-            ),
-            _ => format!("{}:{}:{}", publisher_report, event_type, publisher),
-        };
-        let result = stat_pair(redis.clone(), &pair).await?;
-        publisher_stats.insert(publisher_report.clone(), result);
-    }
+    let publisher_totals =
+        get_dimension_totals(pool, query, TotalsOf::Publisher(publisher)).await?;
+
+    let publisher_stats = publisher_reports
+        .iter()
+        .filter(|report| in_query(report.dimension()))
+        .map(|report| {
+            let stats = publisher_totals
+                .iter()
+                .filter(|total| total.dimension == report.dimension())
+                .map(|total| (total.value.clone(), total.report_value(report.is_payout())))
+                .collect();
 
-    let mut by_channel_stats = HashMap::new();
+            (report.clone(), stats)
+        })
+        .collect();
 
     let channel_reports = [
         ChannelReport::AdUnit,
@@ -216,20 +389,35 @@ pub async fn get_advanced_reports(
         ChannelReport::HostnamePay,
     ];
 
-    for channel_id in channel_ids {
-        let mut channel_stat = HashMap::new();
+    let channels_totals = if channel_ids.is_empty() {
+        vec![]
+    } else {
+        get_dimension_totals(pool, query, TotalsOf::Channels(channel_ids)).await?
+    };
 
-        for channel_report in channel_reports.iter() {
-            let result = stat_pair(
-                redis.clone(),
-                &format!("{}:{}:{}", channel_report, event_type, channel_id),
-            )
-            .await?;
-            channel_stat.insert(channel_report.clone(), result);
-        }
+    let by_channel_stats = channel_ids
+        .iter()
+        .map(|channel_id| {
+            let channel_stats: HashMap<ChannelReport, HashMap<String, f64>> = channel_reports
+                .iter()
+                .filter(|report| in_query(report.dimension()))
+                .map(|report| {
+                    let stats = channels_totals
+                        .iter()
+                        .filter(|total| {
+                            total.channel_id.as_ref() == Some(channel_id)
+                                && total.dimension == report.dimension()
+                        })
+                        .map(|total| (total.value.clone(), total.report_value(report.is_payout())))
+                        .collect();
 
-        by_channel_stats.insert(channel_id.to_owned(), channel_stat);
-    }
+                    (report.clone(), stats)
+                })
+                .collect();
+
+            (*channel_id, channel_stats)
+        })
+        .collect();
 
     Ok(AdvancedAnalyticsResponse {
         publisher_stats,
@@ -0,0 +1,108 @@
+use crate::db::DbPool;
+use bb8::RunError;
+use primitives::adapter::ChannelState;
+use primitives::{ChainId, ChannelId};
+
+/// The last block synced for the core contract on the chain
+pub async fn get_sync_cursor(
+    pool: &DbPool,
+    chain_id: ChainId,
+    core_address: &str,
+) -> Result<Option<u64>, RunError<bb8_postgres::tokio_postgres::Error>> {
+    pool.run(move |connection| async move {
+        match connection
+            .prepare(
+                "SELECT last_block FROM chain_sync WHERE chain_id = $1 AND core_address = $2 LIMIT 1",
+            )
+            .await
+        {
+            Ok(select) => match connection.query(&select, &[&chain_id, &core_address]).await {
+                Ok(results) => {
+                    let last_block = results
+                        .get(0)
+                        .map(|row| row.get::<_, i64>("last_block") as u64);
+
+                    Ok((last_block, connection))
+                }
+                Err(e) => Err((e, connection)),
+            },
+            Err(e) => Err((e, connection)),
+        }
+    })
+    .await
+}
+
+pub async fn update_sync_cursor(
+    pool: &DbPool,
+    chain_id: ChainId,
+    core_address: &str,
+    last_block: u64,
+) -> Result<bool, RunError<bb8_postgres::tokio_postgres::Error>> {
+    pool.run(move |connection| async move {
+        let last_block = last_block as i64;
+        match connection.prepare("INSERT INTO chain_sync (chain_id, core_address, last_block) VALUES ($1, $2, $3) ON CONFLICT (chain_id, core_address) DO UPDATE SET last_block = EXCLUDED.last_block").await {
+            Ok(stmt) => match connection.execute(&stmt, &[&chain_id, &core_address, &last_block]).await {
+                Ok(row) => Ok((row == 1, connection)),
+                Err(e) => Err((e, connection)),
+            },
+            Err(e) => Err((e, connection)),
+        }
+    })
+    .await
+}
+
+pub async fn update_channel_chain_state(
+    pool: &DbPool,
+    channel_id: &ChannelId,
+    state: ChannelState,
+) -> Result<bool, RunError<bb8_postgres::tokio_postgres::Error>> {
+    pool.run(move |connection| async move {
+        let state = chain_state_name(state);
+        match connection
+            .prepare("UPDATE channels SET chain_state = $1 WHERE id = $2")
+            .await
+        {
+            Ok(stmt) => match connection.execute(&stmt, &[&state, &channel_id]).await {
+                Ok(row) => Ok((row == 1, connection)),
+                Err(e) => Err((e, connection)),
+            },
+            Err(e) => Err((e, connection)),
+        }
+    })
+    .await
+}
+
+/// The channels on the chain whose on-chain state was never synced
+pub async fn get_unsynced_channels(
+    pool: &DbPool,
+    chain_id: ChainId,
+    limit: u32,
+) -> Result<Vec<ChannelId>, RunError<bb8_postgres::tokio_postgres::Error>> {
+    pool.run(move |connection| async move {
+        let statement = format!(
+            "SELECT id FROM channels WHERE chain_id = $1 AND chain_state IS NULL LIMIT {}",
+            limit
+        );
+        match connection.prepare(&statement).await {
+            Ok(select) => match connection.query(&select, &[&chain_id]).await {
+                Ok(rows) => {
+                    let channels = rows.iter().map(|row| row.get("id")).collect();
+
+                    Ok((channels, connection))
+                }
+                Err(e) => Err((e, connection)),
+            },
+            Err(e) => Err((e, connection)),
+        }
+    })
+    .await
+}
+
+/// The `chain_state` column value of the `ChannelState`
+pub fn chain_state_name(state: ChannelState) -> &'static str {
+    match state {
+        ChannelState::Unknown => "Unknown",
+        ChannelState::Active => "Active",
+        ChannelState::Expired => "Expired",
+    }
+}
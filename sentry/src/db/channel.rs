@@ -1,11 +1,11 @@
 use crate::db::DbPool;
 use bb8::RunError;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use primitives::validator::MessageTypes;
 use primitives::{Channel, ChannelId, ValidatorId};
 use std::str::FromStr;
 
-pub use list_channels::list_channels;
+pub use list_channels::{list_channels, list_channels_after};
 
 pub async fn get_channel_by_id(
     pool: &DbPool,
@@ -14,7 +14,7 @@ pub async fn get_channel_by_id(
     pool
         .run(move |connection| {
             async move {
-                match connection.prepare("SELECT id, creator, deposit_asset, deposit_amount, valid_until, targeting_rules, spec FROM channels WHERE id = $1 LIMIT 1").await {
+                match connection.prepare("SELECT id, chain_id, creator, deposit_asset, deposit_amount, valid_until, targeting_rules, spec, exhausted FROM channels WHERE id = $1 LIMIT 1").await {
                     Ok(select) => match connection.query(&select, &[&id]).await {
                         Ok(results) => Ok((results.get(0).map(Channel::from), connection)),
                         Err(e) => Err((e, connection)),
@@ -35,7 +35,7 @@ pub async fn get_channel_by_id_and_validator(
         .run(move |connection| {
             async move {
                 let validator = serde_json::Value::from_str(&format!(r#"[{{"id": "{}"}}]"#, validator_id)).expect("Not a valid json");
-                let query = "SELECT id, creator, deposit_asset, deposit_amount, valid_until, targeting_rules, spec FROM channels WHERE id = $1 AND spec->'validators' @> $2 LIMIT 1";
+                let query = "SELECT id, chain_id, creator, deposit_asset, deposit_amount, valid_until, targeting_rules, spec, exhausted FROM channels WHERE id = $1 AND spec->'validators' @> $2 LIMIT 1";
                 match connection.prepare(query).await {
                     Ok(select) => {
                         match connection.query(&select, &[&id, &validator]).await {
@@ -57,8 +57,8 @@ pub async fn insert_channel(
     pool
         .run(move |connection| {
             async move {
-                    match connection.prepare("INSERT INTO channels (id, creator, deposit_asset, deposit_amount, valid_until, targeting_rules, spec) values ($1, $2, $3, $4, $5, $6, $7)").await {
-                    Ok(stmt) => match connection.execute(&stmt, &[&channel.id, &channel.creator, &channel.deposit_asset, &channel.deposit_amount, &channel.valid_until, &channel.targeting_rules, &channel.spec]).await {
+                    match connection.prepare("INSERT INTO channels (id, chain_id, creator, deposit_asset, deposit_amount, valid_until, targeting_rules, spec) values ($1, $2, $3, $4, $5, $6, $7, $8)").await {
+                    Ok(stmt) => match connection.execute(&stmt, &[&channel.id, &channel.chain_id, &channel.creator, &channel.deposit_asset, &channel.deposit_amount, &channel.valid_until, &channel.targeting_rules, &channel.spec]).await {
                         Ok(row) => {
                             let inserted = row == 1;
                             Ok((inserted, connection))
@@ -72,20 +72,21 @@ pub async fn insert_channel(
         .await
 }
 
+/// Returns the `received` of the message as it's stored
 pub async fn insert_validator_messages(
     pool: &DbPool,
     channel: &Channel,
     from: &ValidatorId,
     validator_message: &MessageTypes,
-) -> Result<bool, RunError<bb8_postgres::tokio_postgres::Error>> {
+) -> Result<DateTime<Utc>, RunError<bb8_postgres::tokio_postgres::Error>> {
     pool
         .run(move | connection| {
             async move {
-                match connection.prepare("INSERT INTO validator_messages (channel_id, \"from\", msg, received) values ($1, $2, $3, $4)").await {
-                    Ok(stmt) => match connection.execute(&stmt, &[&channel.id, &from, &validator_message, &Utc::now()]).await {
+                match connection.prepare("INSERT INTO validator_messages (channel_id, \"from\", msg, received) values ($1, $2, $3, $4) RETURNING received").await {
+                    Ok(stmt) => match connection.query_one(&stmt, &[&channel.id, &from, &validator_message, &Utc::now()]).await {
                         Ok(row) => {
-                            let inserted = row == 1;
-                            Ok((inserted, connection))
+                            let received = row.get("received");
+                            Ok((received, connection))
                         },
                         Err(e) => Err((e, connection)),
                     },
@@ -120,12 +121,13 @@ pub async fn update_exhausted_channel(
 }
 
 mod list_channels {
-    use crate::db::DbPool;
+    use crate::db::{DbPool, SqlParams};
     use bb8::RunError;
     use bb8_postgres::tokio_postgres::types::{accepts, FromSql, ToSql, Type};
-    use chrono::{DateTime, Utc};
+    use primitives::sentry::channel_list::{ChannelListCursor, ChannelListQuery, ChannelListSort};
     use primitives::sentry::ChannelListResponse;
-    use primitives::{Channel, ValidatorId};
+    use primitives::Channel;
+    use serde_json::json;
     use std::error::Error;
     use std::str::FromStr;
 
@@ -141,46 +143,23 @@ mod list_channels {
         accepts!(VARCHAR, TEXT);
     }
 
+    /// A page of the channels matching the filters of the `query`, in the order of its `sort`.
+    /// The page starts after the `cursor` of the query if it's set, otherwise `skip` channels are skipped
     pub async fn list_channels(
         pool: &DbPool,
         skip: u64,
         limit: u32,
-        creator: &Option<String>,
-        validator: &Option<ValidatorId>,
-        valid_until_ge: &DateTime<Utc>,
+        query: &ChannelListQuery,
     ) -> Result<ChannelListResponse, RunError<bb8_postgres::tokio_postgres::Error>> {
-        let validator = validator.as_ref().map(|validator_id| {
-            serde_json::Value::from_str(&format!(r#"[{{"id": "{}"}}]"#, validator_id))
-                .expect("Not a valid json")
-        });
-        let (where_clauses, params) =
-            channel_list_query_params(creator, validator.as_ref(), valid_until_ge);
-        let total_count_params = (where_clauses.clone(), params.clone());
-
-        let channels = pool
-            .run(move |connection| {
-                async move {
-                    // To understand why we use Order by, see Postgres Documentation: https://www.postgresql.org/docs/8.1/queries-limit.html
-                    let statement = format!("SELECT id, creator, deposit_asset, deposit_amount, valid_until, targeting_rules, spec FROM channels WHERE {} ORDER BY spec->>'created' DESC LIMIT {} OFFSET {}", where_clauses.join(" AND "), limit, skip);
-                    match connection.prepare(&statement).await {
-                        Ok(stmt) => {
-                            match connection.query(&stmt, params.as_slice()).await {
-                                Ok(rows) => {
-                                    let channels = rows.iter().map(Channel::from).collect();
-
-                                    Ok((channels, connection))
-                                },
-                                Err(e) => Err((e, connection)),
-                            }
-                        },
-                        Err(e) => Err((e, connection)),
-                    }
-                }
-            })
-            .await?;
+        let (mut where_clauses, mut params) = channel_list_query_params(query);
+        let (total_count_where_clauses, total_count_params) = channel_list_query_params(query);
+        add_cursor_params(&mut where_clauses, &mut params, query.cursor.as_ref());
+
+        let statement = format!("SELECT id, chain_id, creator, deposit_asset, deposit_amount, valid_until, targeting_rules, spec, exhausted FROM channels WHERE {} ORDER BY {} LIMIT {} OFFSET {}", where_clauses.join(" AND "), order_by(query.sort), limit, skip);
+        let channels = query_channels(pool, statement, params).await?;
 
         let total_count =
-            list_channels_total_count(&pool, (&total_count_params.0, total_count_params.1)).await?;
+            list_channels_total_count(&pool, total_count_where_clauses, total_count_params).await?;
 
         // fast ceil for total_pages
         let total_pages = if total_count == 0 {
@@ -189,19 +168,78 @@ mod list_channels {
             1 + ((total_count - 1) / limit as u64)
         };
 
+        // a full page might be followed by more channels
+        let next = if channels.len() == limit as usize {
+            channels
+                .last()
+                .map(|channel| ChannelListCursor::after(query.sort, channel))
+        } else {
+            None
+        };
+
         Ok(ChannelListResponse {
             total_pages,
             total: total_pages,
             page: skip / limit as u64,
             channels,
+            statuses: Default::default(),
+            next,
         })
     }
 
-    async fn list_channels_total_count<'a>(
+    /// At most `limit` channels matching the filters of `list_channels` after the `cursor`,
+    /// ignoring the cursor of the query and without counting the total.
+    /// Used for scanning the channels in batches, when they are further filtered by their `Status`
+    pub async fn list_channels_after(
         pool: &DbPool,
-        (where_clauses, params): (&'a [String], Vec<&'a (dyn ToSql + Sync)>),
+        query: &ChannelListQuery,
+        cursor: Option<&ChannelListCursor>,
+        limit: u32,
+    ) -> Result<Vec<Channel>, RunError<bb8_postgres::tokio_postgres::Error>> {
+        let (mut where_clauses, mut params) = channel_list_query_params(query);
+        add_cursor_params(&mut where_clauses, &mut params, cursor);
+
+        let statement = format!("SELECT id, chain_id, creator, deposit_asset, deposit_amount, valid_until, targeting_rules, spec, exhausted FROM channels WHERE {} ORDER BY {} LIMIT {}", where_clauses.join(" AND "), order_by(query.sort), limit);
+
+        query_channels(pool, statement, params).await
+    }
+
+    async fn query_channels(
+        pool: &DbPool,
+        statement: String,
+        params: SqlParams,
+    ) -> Result<Vec<Channel>, RunError<bb8_postgres::tokio_postgres::Error>> {
+        pool.run(move |connection| async move {
+            let params: Vec<&(dyn ToSql + Sync)> = params
+                .iter()
+                .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+                .collect();
+
+            match connection.prepare(&statement).await {
+                Ok(stmt) => match connection.query(&stmt, params.as_slice()).await {
+                    Ok(rows) => {
+                        let channels = rows.iter().map(Channel::from).collect();
+
+                        Ok((channels, connection))
+                    }
+                    Err(e) => Err((e, connection)),
+                },
+                Err(e) => Err((e, connection)),
+            }
+        })
+        .await
+    }
+
+    async fn list_channels_total_count(
+        pool: &DbPool,
+        where_clauses: Vec<String>,
+        params: SqlParams,
     ) -> Result<u64, RunError<bb8_postgres::tokio_postgres::Error>> {
         pool.run(move |connection| async move {
+            let params: Vec<&(dyn ToSql + Sync)> = params
+                .iter()
+                .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+                .collect();
             let statement = format!(
                 "SELECT COUNT(id)::varchar FROM channels WHERE {}",
                 where_clauses.join(" AND ")
@@ -217,22 +255,68 @@ mod list_channels {
         .await
     }
 
-    fn channel_list_query_params<'a>(
-        creator: &'a Option<String>,
-        validator: Option<&'a serde_json::Value>,
-        valid_until_ge: &'a DateTime<Utc>,
-    ) -> (Vec<String>, Vec<&'a (dyn ToSql + Sync)>) {
-        let mut where_clauses = vec!["valid_until >= $1".to_string()];
-        let mut params: Vec<&(dyn ToSql + Sync)> = vec![valid_until_ge];
-
-        if let Some(creator) = creator {
-            where_clauses.push(format!("creator = ${}", params.len() + 1));
-            params.push(creator);
+    fn sort_column(sort: ChannelListSort) -> &'static str {
+        match sort {
+            ChannelListSort::Created => "spec->>'created'",
+            ChannelListSort::Deposit => "deposit_amount",
+        }
+    }
+
+    /// The `id` makes the order stable for channels with the same sort value
+    fn order_by(sort: ChannelListSort) -> String {
+        format!("{} DESC, id DESC", sort_column(sort))
+    }
+
+    /// The channels after the `cursor` in the order of its sort
+    fn add_cursor_params(
+        where_clauses: &mut Vec<String>,
+        params: &mut SqlParams,
+        cursor: Option<&ChannelListCursor>,
+    ) {
+        if let Some(cursor) = cursor {
+            let value: Box<dyn ToSql + Send + Sync> = match cursor.sort {
+                // the `created` of the spec is compared as text, like the ordering of its index
+                ChannelListSort::Created => Box::new(cursor.value.to_str_radix(10)),
+                ChannelListSort::Deposit => Box::new(cursor.value.clone()),
+            };
+            params.push(value);
+            params.push(Box::new(cursor.id));
+
+            where_clauses.push(format!(
+                "({}, id) < (${}, ${})",
+                sort_column(cursor.sort),
+                params.len() - 1,
+                params.len()
+            ));
+        }
+    }
+
+    fn channel_list_query_params(query: &ChannelListQuery) -> (Vec<String>, SqlParams) {
+        // hide the channels that were never opened on-chain
+        let mut where_clauses = vec![
+            "valid_until >= $1".to_string(),
+            "chain_state IS DISTINCT FROM 'Unknown'".to_string(),
+        ];
+        let mut params: SqlParams = vec![Box::new(query.valid_until_ge)];
+
+        if let Some(creator) = &query.creator {
+            params.push(Box::new(creator.clone()));
+            where_clauses.push(format!("creator = ${}", params.len()));
+        }
+
+        if let Some(validator) = &query.validator {
+            params.push(Box::new(json!([{ "id": validator }])));
+            where_clauses.push(format!("spec->'validators' @> ${}", params.len()));
+        }
+
+        if let Some(deposit_asset) = &query.deposit_asset {
+            params.push(Box::new(deposit_asset.clone()));
+            where_clauses.push(format!("deposit_asset = ${}", params.len()));
         }
 
-        if let Some(validator) = validator {
-            where_clauses.push(format!("spec->'validators' @> ${}", params.len() + 1));
-            params.push(validator);
+        if let Some(ad_unit_type) = &query.ad_unit_type {
+            params.push(Box::new(json!([{ "type": ad_unit_type }])));
+            where_clauses.push(format!("spec->'adUnits' @> ${}", params.len()));
         }
 
         (where_clauses, params)
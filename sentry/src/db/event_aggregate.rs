@@ -1,14 +1,15 @@
-use crate::db::DbPool;
+use crate::db::{DbPool, SqlParams};
 use bb8::RunError;
 use bb8_postgres::tokio_postgres::binary_copy::BinaryCopyInWriter;
 use bb8_postgres::tokio_postgres::types::{ToSql, Type};
 use bb8_postgres::tokio_postgres::Error;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, SubsecRound, Utc};
 use futures::pin_mut;
 use primitives::sentry::{
     ApproveStateValidatorMessage, EventAggregate, HeartbeatValidatorMessage,
-    NewStateValidatorMessage,
+    NewStateValidatorMessage, ValidatorMessage,
 };
+use primitives::validator::{Accounting, MessageTypes};
 use primitives::BigNum;
 use primitives::{Channel, ChannelId, ValidatorId};
 use std::ops::Add;
@@ -52,6 +53,18 @@ pub async fn latest_new_state(
     .await
 }
 
+/// The balances of the leader's latest `NewState` with the `state_root`, one `earner` and `amount` row per earner
+pub fn balances_statement(channel: &Channel, state_root: &str) -> (String, SqlParams) {
+    let statement = "SELECT balances.key AS earner, balances.value AS amount FROM (SELECT msg FROM validator_messages WHERE channel_id = $1 AND \"from\" = $2 AND msg ->> 'type' = 'NewState' AND msg ->> 'stateRoot' = $3 ORDER BY received DESC LIMIT 1) AS new_state, jsonb_each_text(new_state.msg -> 'balances') AS balances ORDER BY earner".to_string();
+    let params: SqlParams = vec![
+        Box::new(channel.id),
+        Box::new(channel.spec.validators.leader().id),
+        Box::new(state_root.to_string()),
+    ];
+
+    (statement, params)
+}
+
 pub async fn latest_heartbeats(
     pool: &DbPool,
     channel_id: &ChannelId,
@@ -116,13 +129,13 @@ pub async fn list_event_aggregates(
                                     'eventCounts',
                                     jsonb_object_agg(
                                         jsonb_build_object(
-                                            earner, count
+                                            earner, count::text
                                         )
                                     ),
                                     'eventPayouts',
                                     jsonb_object_agg(
                                         jsonb_build_object(
-                                            earner, payout
+                                            earner, payout::text
                                         )
                                     )
                                 )
@@ -160,11 +173,12 @@ struct EventData {
     event_payout: BigNum,
 }
 
+/// Returns the `created` of the event aggregate as it's stored
 pub async fn insert_event_aggregate(
     pool: &DbPool,
     channel_id: &ChannelId,
     event: &EventAggregate,
-) -> Result<bool, RunError<bb8_postgres::tokio_postgres::Error>> {
+) -> Result<DateTime<Utc>, RunError<bb8_postgres::tokio_postgres::Error>> {
     let mut data: Vec<EventData> = Vec::new();
 
     for (event_type, aggr) in &event.events {
@@ -206,9 +220,10 @@ pub async fn insert_event_aggregate(
                     Err(e) => return Err((e, connection))
                 };
 
-                let created = Utc::now(); // time discrepancy
+                // Postgres stores microseconds
+                let created = Utc::now().trunc_subsecs(6);
 
-                let writer = BinaryCopyInWriter::new(sink, &[Type::VARCHAR, Type::TIMESTAMPTZ, Type::VARCHAR, Type::VARCHAR, Type::VARCHAR, Type::VARCHAR]);
+                let writer = BinaryCopyInWriter::new(sink, &[Type::VARCHAR, Type::TIMESTAMPTZ, Type::VARCHAR, Type::NUMERIC, Type::NUMERIC, Type::VARCHAR]);
                 pin_mut!(writer);
                 for item in data {
                     if let Err(e) = writer.as_mut().write(&[&item.id, &created, &item.event_type, &item.event_count, &item.event_payout, &item.earner]).await {
@@ -223,7 +238,7 @@ pub async fn insert_event_aggregate(
                         if let Err(e) = writer.finish().await {
                             return Err((e, connection));
                         };
-                        Ok((true, connection))
+                        Ok((created, connection))
                     }
                 }
             }
@@ -232,3 +247,92 @@ pub async fn insert_event_aggregate(
 
     Ok(result)
 }
+
+/// The latest `Accounting` of the `validator`, received at or before `received_before` if it's set
+pub async fn latest_accounting(
+    pool: &DbPool,
+    channel_id: &ChannelId,
+    validator: &ValidatorId,
+    received_before: Option<DateTime<Utc>>,
+) -> Result<Option<Accounting>, RunError<bb8_postgres::tokio_postgres::Error>> {
+    pool
+    .run(move |connection| {
+        async move {
+            match connection.prepare("SELECT \"from\", msg, received FROM validator_messages WHERE channel_id = $1 AND \"from\" = $2 AND msg ->> 'type' = 'Accounting' AND ($3::timestamptz IS NULL OR received <= $3) ORDER BY received DESC LIMIT 1").await {
+                Ok(select) => match connection.query(&select, &[&channel_id, &validator, &received_before]).await {
+                    Ok(rows) => {
+                        let accounting = rows.get(0).map(ValidatorMessage::from).and_then(|message| match message.msg {
+                            MessageTypes::Accounting(accounting) => Some(accounting),
+                            _ => None,
+                        });
+
+                        Ok((accounting, connection))
+                    },
+                    Err(e) => Err((e, connection)),
+                },
+                Err(e) => Err((e, connection)),
+            }
+        }
+    })
+    .await
+}
+
+/// The channels with event aggregates created before `created_before`
+pub async fn channels_with_aggregates_before(
+    pool: &DbPool,
+    created_before: DateTime<Utc>,
+) -> Result<Vec<ChannelId>, RunError<bb8_postgres::tokio_postgres::Error>> {
+    pool.run(move |connection| async move {
+        match connection
+            .prepare("SELECT DISTINCT channel_id FROM event_aggregates WHERE created < $1")
+            .await
+        {
+            Ok(select) => match connection.query(&select, &[&created_before]).await {
+                Ok(rows) => Ok((
+                    rows.iter().map(|row| row.get("channel_id")).collect(),
+                    connection,
+                )),
+                Err(e) => Err((e, connection)),
+            },
+            Err(e) => Err((e, connection)),
+        }
+    })
+    .await
+}
+
+/// Moves the event aggregates of the channel created at or before `until`
+/// into the daily summaries, in a single statement so no event is lost or counted twice.
+/// Returns the number of compacted aggregates.
+pub async fn compact_event_aggregates(
+    pool: &DbPool,
+    channel_id: &ChannelId,
+    until: DateTime<Utc>,
+) -> Result<u64, RunError<bb8_postgres::tokio_postgres::Error>> {
+    pool
+    .run(move |connection| {
+        async move {
+            let statement = "
+                WITH compacted AS (
+                    DELETE FROM event_aggregates WHERE channel_id = $1 AND created <= $2
+                    RETURNING created, event_type, earner, count, payout
+                ), daily AS (
+                    INSERT INTO event_aggregates_daily (channel_id, day, event_type, earner, count, payout)
+                    SELECT $1, (created AT TIME ZONE 'UTC')::date AS day, event_type, earner, SUM(count), SUM(payout)
+                    FROM compacted GROUP BY day, event_type, earner
+                    ON CONFLICT (channel_id, day, event_type, COALESCE(earner, ''))
+                    DO UPDATE SET count = event_aggregates_daily.count + EXCLUDED.count, payout = event_aggregates_daily.payout + EXCLUDED.payout
+                )
+                SELECT COUNT(*) AS compacted FROM compacted
+            ";
+
+            match connection.prepare(statement).await {
+                Ok(stmt) => match connection.query_one(&stmt, &[&channel_id, &until]).await {
+                    Ok(row) => Ok((row.get::<_, i64>("compacted") as u64, connection)),
+                    Err(e) => Err((e, connection)),
+                },
+                Err(e) => Err((e, connection)),
+            }
+        }
+    })
+    .await
+}
@@ -1,16 +1,28 @@
 use crate::db::DbPool;
 use bb8::RunError;
 use bb8_postgres::tokio_postgres::types::ToSql;
-use primitives::sentry::ValidatorMessage;
+use chrono::{DateTime, Utc};
+use primitives::sentry::{validator_message_list::ValidatorMessagesCursor, ValidatorMessage};
 use primitives::{ChannelId, ValidatorId};
+use std::collections::HashMap;
 
+/// A page of the latest messages received before `before` and after `after`, newest first,
+/// which starts after the `cursor` if it's set.
+/// The cursor of the next page is returned if there are more messages.
+#[allow(clippy::too_many_arguments)]
 pub async fn get_validator_messages(
     pool: &DbPool,
     channel_id: &ChannelId,
     validator_id: &Option<ValidatorId>,
     message_types: &[String],
+    before: &Option<DateTime<Utc>>,
+    after: &Option<DateTime<Utc>>,
+    cursor: &Option<ValidatorMessagesCursor>,
     limit: u64,
-) -> Result<Vec<ValidatorMessage>, RunError<bb8_postgres::tokio_postgres::Error>> {
+) -> Result<
+    (Vec<ValidatorMessage>, Option<ValidatorMessagesCursor>),
+    RunError<bb8_postgres::tokio_postgres::Error>,
+> {
     let mut where_clauses: Vec<String> = vec!["channel_id = $1".to_string()];
     let mut params: Vec<&(dyn ToSql + Sync)> = vec![&channel_id];
 
@@ -21,15 +33,83 @@ pub async fn get_validator_messages(
 
     add_message_types_params(&mut where_clauses, &mut params, message_types);
 
+    if let Some(before) = before {
+        where_clauses.push(format!("received < ${}", params.len() + 1));
+        params.push(before);
+    }
+
+    if let Some(after) = after {
+        where_clauses.push(format!("received > ${}", params.len() + 1));
+        params.push(after);
+    }
+
+    if let Some(cursor) = cursor {
+        where_clauses.push(format!(
+            "(received, id) < (${}, ${})",
+            params.len() + 1,
+            params.len() + 2
+        ));
+        params.push(&cursor.received);
+        params.push(&cursor.id);
+    }
+
     pool
         .run(move |connection| {
             async move {
-                let statement = format!(r#"SELECT "from", msg, received FROM validator_messages WHERE {} ORDER BY received DESC LIMIT {}"#, where_clauses.join(" AND "), limit);
+                // one more message than the `limit`, to find out if there is a next page
+                let statement = format!(r#"SELECT id, "from", msg, received FROM validator_messages WHERE {} ORDER BY received DESC, id DESC LIMIT {}"#, where_clauses.join(" AND "), limit + 1);
                 match connection.prepare(&statement).await {
                     Ok(select) => match connection.query(&select, params.as_slice()).await {
-                        Ok(results) => {
+                        Ok(mut results) => {
+                            let has_more = results.len() as u64 > limit;
+                            results.truncate(limit as usize);
+
+                            let next = match results.last() {
+                                Some(last) if has_more => Some(ValidatorMessagesCursor {
+                                    received: last.get("received"),
+                                    id: last.get("id"),
+                                }),
+                                _ => None,
+                            };
                             let messages = results.iter().map(ValidatorMessage::from).collect();
-                            Ok((messages, connection))},
+
+                            Ok(((messages, next), connection))
+                        }
+                        Err(e) => Err((e, connection)),
+                    },
+                    Err(e) => Err((e, connection)),
+                }
+            }
+        })
+        .await
+}
+
+/// The latest `NewState`, `ApproveState`, `RejectState` & `Heartbeat` of every validator
+/// for each of the given channels, used to determine their `Status`
+pub async fn get_status_messages(
+    pool: &DbPool,
+    channel_ids: &[ChannelId],
+) -> Result<HashMap<ChannelId, Vec<ValidatorMessage>>, RunError<bb8_postgres::tokio_postgres::Error>>
+{
+    let channel_ids = channel_ids.to_vec();
+
+    pool
+        .run(move |connection| {
+            async move {
+                let statement = r#"SELECT DISTINCT ON (channel_id, "from", msg->>'type') channel_id, "from", msg, received FROM validator_messages WHERE channel_id = ANY($1) AND msg->>'type' IN ('NewState', 'ApproveState', 'RejectState', 'Heartbeat') ORDER BY channel_id, "from", msg->>'type', received DESC"#;
+                match connection.prepare(statement).await {
+                    Ok(select) => match connection.query(&select, &[&channel_ids]).await {
+                        Ok(results) => {
+                            let mut messages: HashMap<ChannelId, Vec<ValidatorMessage>> = HashMap::new();
+                            for row in results.iter() {
+                                messages
+                                    .entry(row.get("channel_id"))
+                                    .or_default()
+                                    .push(ValidatorMessage::from(row));
+                            }
+
+                            Ok((messages, connection))
+                        }
                         Err(e) => Err((e, connection)),
                     },
                     Err(e) => Err((e, connection)),
@@ -1,9 +1,11 @@
 use crate::access::check_access;
 use crate::access::Error as AccessError;
+use crate::channel_updates;
 use crate::db::event_aggregate::insert_event_aggregate;
 use crate::db::get_channel_by_id;
 use crate::db::DbPool;
 use crate::event_reducer;
+use crate::payout::channel_targeting_rules;
 use crate::Application;
 use crate::ResponseError;
 use crate::Session;
@@ -12,8 +14,10 @@ use async_std::sync::RwLock;
 use chrono::Utc;
 use lazy_static::lazy_static;
 use primitives::adapter::Adapter;
-use primitives::sentry::{Event, EventAggregate};
+use primitives::sentry::{ChannelUpdate, Event, EventAggregate};
+use primitives::targeting::CompiledRules;
 use primitives::{Channel, ChannelId};
+use redis::aio::MultiplexedConnection;
 use slog::{error, Logger};
 use std::collections::HashMap;
 use std::env;
@@ -29,6 +33,8 @@ lazy_static! {
 struct Record {
     channel: Channel,
     aggregate: EventAggregate,
+    /// The targeting rules of the channel, compiled once for all of its events
+    targeting_rules: Arc<CompiledRules>,
 }
 
 type Recorder = Arc<RwLock<HashMap<ChannelId, Record>>>;
@@ -46,21 +52,42 @@ pub fn new_aggr(channel_id: &ChannelId) -> EventAggregate {
     }
 }
 
-async fn store(db: &DbPool, channel_id: &ChannelId, logger: &Logger, recorder: Recorder) {
+async fn store(
+    db: &DbPool,
+    redis: &MultiplexedConnection,
+    channel_id: &ChannelId,
+    logger: &Logger,
+    recorder: Recorder,
+) {
     let mut channel_recorder = recorder.write().await;
+    let mut update = None;
     let record: Option<&Record> = channel_recorder.get(channel_id);
     if let Some(data) = record {
-        if let Err(e) = insert_event_aggregate(&db, &channel_id, &data.aggregate).await {
-            error!(&logger, "{}", e; "module" => "event_aggregator", "in" => "store");
-        } else {
-            // reset aggr record
-            let record = Record {
-                channel: data.channel.to_owned(),
-                aggregate: new_aggr(&channel_id),
-            };
-            channel_recorder.insert(channel_id.to_owned(), record);
+        match insert_event_aggregate(&db, &channel_id, &data.aggregate).await {
+            Err(e) => error!(&logger, "{}", e; "module" => "event_aggregator", "in" => "store"),
+            Ok(created) => {
+                update = Some(ChannelUpdate::EventAggregate {
+                    channel_id: channel_id.to_owned(),
+                    created,
+                });
+
+                // reset aggr record
+                let record = Record {
+                    channel: data.channel.to_owned(),
+                    aggregate: new_aggr(&channel_id),
+                    targeting_rules: data.targeting_rules.clone(),
+                };
+                channel_recorder.insert(channel_id.to_owned(), record);
+            }
         }
     }
+
+    // the events of all channels are recorded with the lock, so it's not held while publishing
+    drop(channel_recorder);
+
+    if let Some(update) = update {
+        channel_updates::publish(redis, logger, &update).await;
+    }
 }
 
 impl EventAggregator {
@@ -89,9 +116,11 @@ impl EventAggregator {
 
                 let withdraw_period_start = channel.spec.withdraw_period_start;
                 let channel_id = channel.id;
+                let targeting_rules = CompiledRules::new(channel_targeting_rules(&channel));
                 let record = Record {
                     channel,
                     aggregate: new_aggr(&channel_id),
+                    targeting_rules: Arc::new(targeting_rules),
                 };
 
                 // insert into
@@ -102,6 +131,7 @@ impl EventAggregator {
                 // the channel events to database
                 if aggr_throttle > 0 {
                     let recorder = recorder.clone();
+                    let redis = redis.clone();
                     tokio::spawn(async move {
                         loop {
                             // break loop if the
@@ -114,7 +144,7 @@ impl EventAggregator {
                             }
 
                             delay_for(Duration::from_millis(aggr_throttle as u64)).await;
-                            store(&dbpool, &channel_id, &logger, recorder.clone()).await;
+                            store(&dbpool, &redis, &channel_id, &logger, recorder.clone()).await;
                         }
                     });
                 }
@@ -146,7 +176,9 @@ impl EventAggregator {
         events.iter().for_each(|ev| {
             match event_reducer::reduce(
                 &app.logger,
+                &app.token_registry,
                 &record.channel,
+                &record.targeting_rules,
                 &mut record.aggregate,
                 ev,
                 &session,
@@ -161,7 +193,9 @@ impl EventAggregator {
         if ANALYTICS_RECORDER.is_some() {
             tokio::spawn(analytics_recorder::record(
                 redis.clone(),
+                app.token_registry.clone(),
                 record.channel.clone(),
+                record.targeting_rules.clone(),
                 session.clone(),
                 events.to_owned().to_vec(),
                 app.logger.clone(),
@@ -173,7 +207,14 @@ impl EventAggregator {
         drop(channel_recorder);
 
         if aggr_throttle == 0 {
-            store(&app.pool, &channel_id, &app.logger, recorder.clone()).await;
+            store(
+                &app.pool,
+                &app.redis,
+                &channel_id,
+                &app.logger,
+                recorder.clone(),
+            )
+            .await;
         }
 
         Ok(())
@@ -1,13 +1,17 @@
 use crate::{payout::get_payout, Session};
 use primitives::{
     sentry::{AggregateEvents, Event, EventAggregate},
+    targeting::CompiledRules,
+    token::TokenRegistry,
     BigNum, Channel, ValidatorId,
 };
 use slog::Logger;
 
 pub(crate) fn reduce(
     logger: &Logger,
+    token_registry: &TokenRegistry,
     channel: &Channel,
+    targeting_rules: &CompiledRules,
     initial_aggr: &mut EventAggregate,
     ev: &Event,
     session: &Session,
@@ -16,7 +20,14 @@ pub(crate) fn reduce(
     match ev {
         Event::Impression { publisher, .. } => {
             let impression = initial_aggr.events.get(&event_type);
-            let payout = get_payout(logger, &channel, &ev, session)?;
+            let payout = get_payout(
+                logger,
+                token_registry,
+                &channel,
+                targeting_rules,
+                &ev,
+                session,
+            )?;
             let merge = merge_payable_event(
                 impression,
                 payout.unwrap_or_else(|| (*publisher, Default::default())),
@@ -26,7 +37,14 @@ pub(crate) fn reduce(
         }
         Event::Click { publisher, .. } => {
             let clicks = initial_aggr.events.get(&event_type);
-            let payout = get_payout(logger, &channel, &ev, session)?;
+            let payout = get_payout(
+                logger,
+                token_registry,
+                &channel,
+                targeting_rules,
+                &ev,
+                session,
+            )?;
             let merge = merge_payable_event(
                 clicks,
                 payout.unwrap_or_else(|| (*publisher, Default::default())),
@@ -76,20 +94,24 @@ fn merge_payable_event(
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::payout::channel_targeting_rules;
     use chrono::Utc;
     use primitives::util::tests::{
         discard_logger,
         prep_db::{DUMMY_CHANNEL, IDS},
     };
-    use primitives::BigNum;
+    use primitives::{token::StaticPriceProvider, BigNum};
+    use std::sync::Arc;
 
     #[test]
     fn test_reduce() {
         let logger = discard_logger();
+        let token_registry = TokenRegistry::new(vec![], Arc::new(StaticPriceProvider));
         let mut channel: Channel = DUMMY_CHANNEL.clone();
         channel.deposit_amount = 100.into();
         // make immutable again
         let channel = channel;
+        let targeting_rules = CompiledRules::new(channel_targeting_rules(&channel));
 
         let mut event_aggr = EventAggregate {
             channel_id: channel.id,
@@ -112,8 +134,16 @@ mod test {
         };
 
         for i in 0..101 {
-            reduce(&logger, &channel, &mut event_aggr, &event, &session)
-                .expect(&format!("Should be able to reduce event #{}", i));
+            reduce(
+                &logger,
+                &token_registry,
+                &channel,
+                &targeting_rules,
+                &mut event_aggr,
+                &event,
+                &session,
+            )
+            .expect(&format!("Should be able to reduce event #{}", i));
         }
 
         assert_eq!(event_aggr.channel_id, channel.id);
@@ -0,0 +1,368 @@
+//! CSV and newline-delimited JSON exports, negotiated with the `Accept` header of the request.
+//! The rows are fetched from Postgres in chunks, so large exports aren't kept in memory.
+//! Each export holds a pooled connection while it's streamed, so only a few exports run at a time
+//! and an export is aborted when the client stops reading it.
+use crate::db::{DbPool, SqlParams};
+use crate::ResponseError;
+use bb8::RunError;
+use bb8_postgres::tokio_postgres::{types::ToSql, Client, Row};
+use hyper::body::{Bytes, Sender};
+use hyper::header::{ACCEPT, CONTENT_TYPE};
+use hyper::{Body, Request, Response};
+use primitives::analytics::AnalyticsData;
+use primitives::sentry::Balance;
+use serde::Serialize;
+use slog::{error, Logger};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::timeout;
+
+/// The maximum number of exports streamed at a time by a sentry,
+/// fewer than the connections of the pool, so the other requests can still be served
+const MAX_CONCURRENT_EXPORTS: usize = 4;
+/// The number of rows fetched from Postgres at once
+const CHUNK_ROWS: i32 = 1_000;
+/// An export is aborted if the client doesn't read the next row in time
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    /// The first supported media type of the `Accept` header, `Json` if there is none
+    pub fn from_request(req: &Request<Body>) -> Self {
+        let accept = req
+            .headers()
+            .get(ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .unwrap_or_default();
+
+        accept
+            .split(',')
+            .filter_map(|media_type| {
+                match media_type.split(';').next().unwrap_or_default().trim() {
+                    "application/json" => Some(ExportFormat::Json),
+                    "text/csv" => Some(ExportFormat::Csv),
+                    "application/x-ndjson" => Some(ExportFormat::Ndjson),
+                    _ => None,
+                }
+            })
+            .next()
+            .unwrap_or(ExportFormat::Json)
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+}
+
+/// A row of an export, serialized as a JSON object for NDJSON
+pub trait ExportRow: Serialize {
+    /// The header of the CSV export
+    const COLUMNS: &'static [&'static str];
+
+    /// The CSV fields, in the order of the `COLUMNS`
+    fn fields(&self) -> Vec<String>;
+}
+
+impl ExportRow for AnalyticsData {
+    const COLUMNS: &'static [&'static str] = &["time", "value", "channelId"];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.time.to_string(),
+            self.value.clone(),
+            self.channel_id
+                .map(|channel_id| channel_id.to_string())
+                .unwrap_or_default(),
+        ]
+    }
+}
+
+impl ExportRow for Balance {
+    const COLUMNS: &'static [&'static str] = &["earner", "amount"];
+
+    fn fields(&self) -> Vec<String> {
+        vec![self.earner.to_string(), self.amount.to_str_radix(10)]
+    }
+}
+
+/// A CSV record as in RFC 4180, the fields with separators, quotes or line breaks are quoted
+fn csv_record<T: AsRef<str>>(fields: &[T]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+
+            if field.contains(|c: char| matches!(c, ',' | '"' | '\r' | '\n')) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+
+    format!("{}\r\n", fields.join(","))
+}
+
+fn encode_row<T: ExportRow>(format: ExportFormat, row: &T) -> Result<String, serde_json::Error> {
+    match format {
+        ExportFormat::Csv => Ok(csv_record(&row.fields())),
+        ExportFormat::Json | ExportFormat::Ndjson => {
+            serde_json::to_string(row).map(|json| format!("{}\n", json))
+        }
+    }
+}
+
+/// The number of the running exports of this sentry
+#[derive(Debug, Clone, Default)]
+pub struct Exports {
+    running: Arc<AtomicUsize>,
+}
+
+impl Exports {
+    /// `None` if there are already `MAX_CONCURRENT_EXPORTS` running
+    fn start(&self) -> Option<RunningExport> {
+        let started = self
+            .running
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |running| {
+                if running < MAX_CONCURRENT_EXPORTS {
+                    Some(running + 1)
+                } else {
+                    None
+                }
+            })
+            .is_ok();
+
+        if started {
+            Some(RunningExport {
+                running: self.running.clone(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// The export is counted as running until this is dropped
+struct RunningExport {
+    running: Arc<AtomicUsize>,
+}
+
+impl Drop for RunningExport {
+    fn drop(&mut self) {
+        self.running.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug)]
+enum Error {
+    Postgres(bb8_postgres::tokio_postgres::Error),
+    Json(serde_json::Error),
+    Body(hyper::Error),
+    SendTimedOut,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Postgres(err) => write!(f, "Postgres: {}", err),
+            Error::Json(err) => write!(f, "Json: {}", err),
+            Error::Body(err) => write!(f, "Body: {}", err),
+            Error::SendTimedOut => write!(f, "the client didn't read the export in time"),
+        }
+    }
+}
+
+impl From<bb8_postgres::tokio_postgres::Error> for Error {
+    fn from(err: bb8_postgres::tokio_postgres::Error) -> Self {
+        Error::Postgres(err)
+    }
+}
+
+/// Streams the rows of the `statement` in the export `format`.
+/// The response is sent before the statement is executed,
+/// so a failing export is aborted instead of returning an error status.
+pub fn stream_rows<T>(
+    exports: &Exports,
+    logger: Logger,
+    pool: DbPool,
+    format: ExportFormat,
+    statement: String,
+    params: SqlParams,
+) -> Result<Response<Body>, ResponseError>
+where
+    T: ExportRow + for<'a> From<&'a Row> + Send + 'static,
+{
+    let running_export = exports.start().ok_or_else(|| {
+        ResponseError::TooManyRequests("Too many running exports, try again later".into())
+    })?;
+    let (mut sender, body) = Body::channel();
+
+    tokio::spawn(async move {
+        let _running_export = running_export;
+        let result = pool
+            .run(move |mut connection| async move {
+                let sent =
+                    send_rows::<T>(&mut connection, format, &statement, &params, &mut sender).await;
+
+                match sent {
+                    Ok(()) => Ok(((), connection)),
+                    Err(err) => {
+                        sender.abort();
+                        Err((err, connection))
+                    }
+                }
+            })
+            .await;
+
+        match result {
+            Ok(()) => {}
+            Err(RunError::User(err)) => {
+                error!(&logger, "Streaming the export failed: {}", err; "module" => "export")
+            }
+            Err(RunError::TimedOut) => {
+                error!(&logger, "Streaming the export failed: timed out getting a connection"; "module" => "export")
+            }
+        }
+    });
+
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, format.content_type())
+        .body(body)
+        .expect("Should build the export response"))
+}
+
+/// The rows are fetched in chunks of `CHUNK_ROWS` through a portal, i.e. a server-side cursor,
+/// which only exists in a transaction. Nothing is written, so the transaction is never committed
+async fn send_rows<T>(
+    connection: &mut Client,
+    format: ExportFormat,
+    statement: &str,
+    params: &SqlParams,
+    sender: &mut Sender,
+) -> Result<(), Error>
+where
+    T: ExportRow + for<'a> From<&'a Row>,
+{
+    let params: Vec<&(dyn ToSql + Sync)> = params
+        .iter()
+        .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+        .collect();
+    let transaction = connection.transaction().await?;
+    let portal = transaction.bind(statement, &params).await?;
+
+    if format == ExportFormat::Csv {
+        send(sender, csv_record(T::COLUMNS)).await?;
+    }
+
+    loop {
+        let rows = transaction.query_portal(&portal, CHUNK_ROWS).await?;
+
+        for row in rows.iter() {
+            send(
+                sender,
+                encode_row(format, &T::from(row)).map_err(Error::Json)?,
+            )
+            .await?;
+        }
+
+        if rows.len() < CHUNK_ROWS as usize {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+async fn send(sender: &mut Sender, data: impl Into<Bytes>) -> Result<(), Error> {
+    match timeout(SEND_TIMEOUT, sender.send_data(data.into())).await {
+        Ok(sent) => sent.map_err(Error::Body),
+        Err(_) => Err(Error::SendTimedOut),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use primitives::util::tests::prep_db::IDS;
+
+    #[test]
+    fn negotiates_the_export_format() {
+        let request = |accept: Option<&str>| {
+            let mut builder = Request::builder();
+            if let Some(accept) = accept {
+                builder = builder.header(ACCEPT, accept);
+            }
+
+            builder.body(Body::empty()).expect("Should build Request")
+        };
+
+        assert_eq!(
+            ExportFormat::Json,
+            ExportFormat::from_request(&request(None))
+        );
+        assert_eq!(
+            ExportFormat::Json,
+            ExportFormat::from_request(&request(Some("*/*")))
+        );
+        assert_eq!(
+            ExportFormat::Csv,
+            ExportFormat::from_request(&request(Some("text/csv; charset=utf-8")))
+        );
+        assert_eq!(
+            ExportFormat::Ndjson,
+            ExportFormat::from_request(&request(Some(
+                "text/html, application/x-ndjson, application/json"
+            )))
+        );
+    }
+
+    #[test]
+    fn encodes_the_rows() {
+        let balance = Balance {
+            earner: IDS["publisher"],
+            amount: 1_000.into(),
+        };
+
+        assert_eq!("earner,amount\r\n", csv_record(Balance::COLUMNS));
+        assert_eq!(
+            format!("{},1000\r\n", IDS["publisher"]),
+            encode_row(ExportFormat::Csv, &balance).expect("Should encode")
+        );
+        assert_eq!(
+            format!(
+                "{{\"earner\":\"{}\",\"amount\":\"1000\"}}\n",
+                IDS["publisher"]
+            ),
+            encode_row(ExportFormat::Ndjson, &balance).expect("Should encode")
+        );
+        assert_eq!(
+            "\"a,b\",\"say \"\"hi\"\"\"\r\n",
+            csv_record(&["a,b", "say \"hi\""])
+        );
+    }
+
+    #[test]
+    fn limits_the_running_exports() {
+        let exports = Exports::default();
+
+        let running: Vec<_> = (0..MAX_CONCURRENT_EXPORTS)
+            .map(|_| exports.start().expect("Should start the export"))
+            .collect();
+        assert!(exports.start().is_none());
+
+        drop(running);
+        assert!(exports.start().is_some());
+    }
+}
@@ -1,12 +1,15 @@
 #![deny(clippy::all)]
 #![deny(rust_2018_idioms)]
 
+use crate::channel_updates::ChannelUpdates;
 use crate::db::DbPool;
 use crate::event_aggregator::EventAggregator;
-use crate::routes::channel::channel_status;
+use crate::export::Exports;
+use crate::routes::channel::{channel_balances, channel_status, explain_targeting};
 use crate::routes::event_aggregate::list_channel_event_aggregates;
+use crate::routes::units_for_slot::get_units_for_slot;
 use crate::routes::validator_message::{extract_params, list_validator_messages};
-use chrono::Utc;
+use crate::supermarket::Supermarket;
 use hyper::{Body, Method, Request, Response, StatusCode};
 use lazy_static::lazy_static;
 use middleware::{
@@ -17,14 +20,15 @@ use middleware::{
 use middleware::{Chain, Middleware};
 use primitives::adapter::Adapter;
 use primitives::sentry::ValidationErrorResponse;
+use primitives::token::TokenRegistry;
 use primitives::{Config, ValidatorId};
 use redis::aio::MultiplexedConnection;
 use regex::Regex;
 use routes::analytics::{advanced_analytics, advertiser_analytics, analytics, publisher_analytics};
 use routes::cfg::config;
 use routes::channel::{
-    channel_list, channel_validate, create_channel, create_validator_messages, insert_events,
-    last_approved,
+    channel_list, channel_updates, channel_validate, create_channel, create_validator_messages,
+    insert_events, last_approved,
 };
 use slog::Logger;
 use std::collections::HashMap;
@@ -35,28 +39,38 @@ pub mod routes {
     pub mod cfg;
     pub mod channel;
     pub mod event_aggregate;
+    pub mod units_for_slot;
     pub mod validator_message;
 }
 
 pub mod access;
 pub mod analytics_recorder;
+pub mod chain_watcher;
+pub mod channel_updates;
+pub mod compaction;
 pub mod db;
 pub mod event_aggregator;
 pub mod event_reducer;
+pub mod export;
 pub mod payout;
+pub mod status;
+pub mod supermarket;
 
 lazy_static! {
     static ref CHANNEL_GET_BY_ID: Regex =
         Regex::new(r"^/channel/0x([a-zA-Z0-9]{64})/?$").expect("The regex should be valid");
     static ref LAST_APPROVED_BY_CHANNEL_ID: Regex = Regex::new(r"^/channel/0x([a-zA-Z0-9]{64})/last-approved/?$").expect("The regex should be valid");
     static ref CHANNEL_STATUS_BY_CHANNEL_ID: Regex = Regex::new(r"^/channel/0x([a-zA-Z0-9]{64})/status/?$").expect("The regex should be valid");
+    static ref CHANNEL_BALANCES_BY_CHANNEL_ID: Regex = Regex::new(r"^/channel/0x([a-zA-Z0-9]{64})/balances/?$").expect("The regex should be valid");
     // Only the initial Regex to be matched.
     static ref CHANNEL_VALIDATOR_MESSAGES: Regex = Regex::new(r"^/channel/0x([a-zA-Z0-9]{64})/validator-messages(/.*)?$").expect("The regex should be valid");
     static ref CHANNEL_EVENTS_AGGREGATES: Regex = Regex::new(r"^/channel/0x([a-zA-Z0-9]{64})/events-aggregates/?$").expect("The regex should be valid");
     static ref ANALYTICS_BY_CHANNEL_ID: Regex = Regex::new(r"^/analytics/0x([a-zA-Z0-9]{64})/?$").expect("The regex should be valid");
     static ref ADVERTISER_ANALYTICS_BY_CHANNEL_ID: Regex = Regex::new(r"^/analytics/for-advertiser/0x([a-zA-Z0-9]{64})/?$").expect("The regex should be valid");
     static ref PUBLISHER_ANALYTICS_BY_CHANNEL_ID: Regex = Regex::new(r"^/analytics/for-publisher/0x([a-zA-Z0-9]{64})/?$").expect("The regex should be valid");
+    static ref CHANNEL_TARGETING_EXPLAIN: Regex = Regex::new(r"^/channel/0x([a-zA-Z0-9]{64})/targeting/explain/?$").expect("The regex should be valid");
     static ref CREATE_EVENTS_BY_CHANNEL_ID: Regex = Regex::new(r"^/channel/0x([a-zA-Z0-9]{64})/events/?$").expect("The regex should be valid");
+    static ref UNITS_FOR_SLOT: Regex = Regex::new(r"^/units-for-slot/([a-zA-Z0-9]+)/?$").expect("The regex should be valid");
 }
 
 #[derive(Debug)]
@@ -79,25 +93,36 @@ pub struct Application<A: Adapter> {
     pub redis: MultiplexedConnection,
     pub pool: DbPool,
     pub config: Config,
+    pub token_registry: TokenRegistry,
     pub event_aggregator: EventAggregator,
+    pub supermarket: Supermarket,
+    pub channel_updates: ChannelUpdates,
+    pub exports: Exports,
 }
 
 impl<A: Adapter + 'static> Application<A> {
     pub fn new(
         adapter: A,
         config: Config,
+        token_registry: TokenRegistry,
         logger: Logger,
         redis: MultiplexedConnection,
         pool: DbPool,
-    ) -> Self {
-        Self {
+    ) -> Result<Self, reqwest::Error> {
+        let supermarket = Supermarket::new(config.supermarket.as_ref())?;
+
+        Ok(Self {
             adapter,
             config,
+            token_registry,
             logger,
             redis,
             pool,
             event_aggregator: Default::default(),
-        }
+            supermarket,
+            channel_updates: Default::default(),
+            exports: Default::default(),
+        })
     }
 
     pub async fn handle_routing(&self, req: Request<Body>) -> Response<Body> {
@@ -117,6 +142,7 @@ impl<A: Adapter + 'static> Application<A> {
             ("/cfg", &Method::GET) => config(req, &self).await,
             ("/channel", &Method::POST) => create_channel(req, &self).await,
             ("/channel/list", &Method::GET) => channel_list(req, &self).await,
+            ("/channel/updates", &Method::GET) => channel_updates(req, &self).await,
             ("/channel/validate", &Method::POST) => channel_validate(req, &self).await,
 
             ("/analytics", &Method::GET) => analytics(req, &self).await,
@@ -153,6 +179,9 @@ impl<A: Adapter + 'static> Application<A> {
             // This is important becuase it prevents us from doing
             // expensive regex matching for routes without /channel
             (path, _) if path.starts_with("/channel") => channels_router(req, &self).await,
+            (path, &Method::GET) if path.starts_with("/units-for-slot") => {
+                units_for_slot_router(req, &self).await
+            }
             _ => Err(ResponseError::NotFound),
         }
         .unwrap_or_else(map_response_error);
@@ -221,6 +250,22 @@ async fn analytics_router<A: Adapter + 'static>(
     }
 }
 
+async fn units_for_slot_router<A: Adapter + 'static>(
+    mut req: Request<Body>,
+    app: &Application<A>,
+) -> Result<Response<Body>, ResponseError> {
+    if let Some(caps) = UNITS_FOR_SLOT.captures(req.uri().path()) {
+        let param = RouteParams(vec![caps
+            .get(1)
+            .map_or("".to_string(), |m| m.as_str().to_string())]);
+        req.extensions_mut().insert(param);
+
+        get_units_for_slot(req, app).await
+    } else {
+        Err(ResponseError::NotFound)
+    }
+}
+
 async fn channels_router<A: Adapter + 'static>(
     mut req: Request<Body>,
     app: &Application<A>,
@@ -254,6 +299,25 @@ async fn channels_router<A: Adapter + 'static>(
 
         req = ChannelLoad.call(req, app).await?;
         channel_status(req, app).await
+    } else if let (Some(caps), &Method::GET) =
+        (CHANNEL_BALANCES_BY_CHANNEL_ID.captures(&path), method)
+    {
+        let param = RouteParams(vec![caps
+            .get(1)
+            .map_or("".to_string(), |m| m.as_str().to_string())]);
+        req.extensions_mut().insert(param);
+
+        req = ChannelLoad.call(req, app).await?;
+        channel_balances(req, app).await
+    } else if let (Some(caps), &Method::POST) = (CHANNEL_TARGETING_EXPLAIN.captures(&path), method)
+    {
+        let param = RouteParams(vec![caps
+            .get(1)
+            .map_or("".to_string(), |m| m.as_str().to_string())]);
+        req.extensions_mut().insert(param);
+
+        req = ChannelLoad.call(req, app).await?;
+        explain_targeting(req, app).await
     } else if let (Some(caps), &Method::GET) = (CHANNEL_VALIDATOR_MESSAGES.captures(&path), method)
     {
         let param = RouteParams(vec![caps
@@ -312,6 +376,8 @@ pub enum ResponseError {
     NotFound,
     BadRequest(String),
     FailedValidation(String),
+    /// A summary message and all of the validation errors
+    FailedValidations(String, Vec<String>),
     Unauthorized,
     Forbidden(String),
     Conflict(String),
@@ -346,7 +412,10 @@ pub fn map_response_error(error: ResponseError) -> Response<Body> {
         ResponseError::Forbidden(e) => bad_response(e, StatusCode::FORBIDDEN),
         ResponseError::Conflict(e) => bad_response(e, StatusCode::CONFLICT),
         ResponseError::TooManyRequests(e) => bad_response(e, StatusCode::TOO_MANY_REQUESTS),
-        ResponseError::FailedValidation(e) => bad_validation_response(e),
+        ResponseError::FailedValidation(e) => bad_validation_response(e.clone(), vec![e]),
+        ResponseError::FailedValidations(message, validation) => {
+            bad_validation_response(message, validation)
+        }
     }
 }
 
@@ -373,11 +442,11 @@ pub fn bad_response(response_body: String, status_code: StatusCode) -> Response<
     response
 }
 
-pub fn bad_validation_response(response_body: String) -> Response<Body> {
+pub fn bad_validation_response(message: String, validation: Vec<String>) -> Response<Body> {
     let error_response = ValidationErrorResponse {
         status_code: 400,
-        message: response_body.clone(),
-        validation: vec![response_body],
+        message,
+        validation,
     };
 
     let body = Body::from(serde_json::to_string(&error_response).expect("serialise err response"));
@@ -406,10 +475,6 @@ pub fn success_response(response_body: String) -> Response<Body> {
     response
 }
 
-pub fn epoch() -> f64 {
-    Utc::now().timestamp() as f64 / 2_628_000_000.0
-}
-
 // @TODO: Make pub(crate)
 #[derive(Debug, Clone)]
 pub struct Session {
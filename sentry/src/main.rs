@@ -3,15 +3,17 @@
 
 use clap::{crate_version, App, Arg};
 
-use adapter::{AdapterTypes, DummyAdapter, EthereumAdapter};
+use adapter::{AdapterTypes, DummyAdapter, EthereumAdapter, RemoteAdapter};
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Error, Server};
-use primitives::adapter::{Adapter, DummyAdapterOptions, KeystoreOptions};
+use primitives::adapter::{Adapter, DummyAdapterOptions, KeystoreOptions, RemoteSignerOptions};
 use primitives::config::configuration;
+use primitives::token::TokenRegistry;
 use primitives::util::tests::prep_db::{AUTH, IDS};
 use primitives::ValidatorId;
 use sentry::db::{postgres_connection, redis_connection, setup_migrations};
-use sentry::Application;
+use sentry::event_aggregator::ANALYTICS_RECORDER;
+use sentry::{analytics_recorder, chain_watcher, channel_updates, compaction, Application};
 use slog::{error, info, Logger};
 use std::convert::TryFrom;
 
@@ -33,15 +35,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("the adapter for authentication and signing")
                 .required(true)
                 .default_value("ethereum")
-                .possible_values(&["ethereum", "dummy"])
+                .possible_values(&["ethereum", "dummy", "remote"])
                 .takes_value(true),
         )
         .arg(
             Arg::with_name("keystoreFile")
                 .long("keystoreFile")
                 .short("k")
-                .help("path to the JSON Ethereum Keystore file")
-                .takes_value(true),
+                .help("path to the JSON Ethereum Keystore file, can be repeated for keeping the previous keys when rotating, the first one is the current key")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
         )
         .arg(
             Arg::with_name("dummyIdentity")
@@ -50,6 +54,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("the identity to use with the dummy adapter")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("remoteSignerUrl")
+                .long("remoteSignerUrl")
+                .help("the JSON-RPC endpoint of the signer used by the remote adapter")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("remoteSignerIdentity")
+                .long("remoteSignerIdentity")
+                .help("path to the PKCS #12 client certificate & key for mutual TLS with the remote signer")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("remoteSignerCa")
+                .long("remoteSignerCa")
+                .help("path to the PEM CA certificate of the remote signer")
+                .takes_value(true),
+        )
         .get_matches();
 
     let environment = std::env::var("ENV").unwrap_or_else(|_| "development".into());
@@ -61,16 +83,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let adapter = match cli.value_of("adapter").unwrap() {
         "ethereum" => {
-            let keystore_file = cli
-                .value_of("keystoreFile")
+            let keystore_files = cli
+                .values_of("keystoreFile")
                 .expect("keystore file is required for the ethereum adapter");
             let keystore_pwd = std::env::var("KEYSTORE_PWD").expect("unable to get keystore pwd");
 
-            let options = KeystoreOptions {
-                keystore_file: keystore_file.to_string(),
-                keystore_pwd,
-            };
-            let ethereum_adapter = EthereumAdapter::init(options, &config)
+            let keystores = keystore_files
+                .map(|keystore_file| KeystoreOptions {
+                    keystore_file: keystore_file.to_string(),
+                    keystore_pwd: keystore_pwd.clone(),
+                })
+                .collect();
+            let ethereum_adapter = EthereumAdapter::init(keystores, &config)
                 .expect("Should initialize ethereum adapter");
 
             AdapterTypes::EthereumAdapter(Box::new(ethereum_adapter))
@@ -83,6 +107,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let options = DummyAdapterOptions {
                 dummy_identity: ValidatorId::try_from(dummy_identity)
                     .expect("failed to parse dummy identity"),
+                dummy_previous_identities: vec![],
                 dummy_auth: IDS.clone(),
                 dummy_auth_tokens: AUTH.clone(),
             };
@@ -90,10 +115,33 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let dummy_adapter = DummyAdapter::init(options, &config);
             AdapterTypes::DummyAdapter(Box::new(dummy_adapter))
         }
+        "remote" => {
+            let signer_url = cli
+                .value_of("remoteSignerUrl")
+                .expect("remote signer url is required for the remote adapter");
+
+            let options = RemoteSignerOptions {
+                signer_url: signer_url.to_string(),
+                client_identity_file: cli
+                    .value_of("remoteSignerIdentity")
+                    .map(ToString::to_string),
+                client_identity_pwd: std::env::var("REMOTE_SIGNER_IDENTITY_PWD")
+                    .unwrap_or_default(),
+                ca_certificate_file: cli.value_of("remoteSignerCa").map(ToString::to_string),
+            };
+            let remote_adapter = RemoteAdapter::init(options, &config)
+                .await
+                .expect("Should initialize remote adapter");
+
+            AdapterTypes::RemoteAdapter(Box::new(remote_adapter))
+        }
         // @TODO exit gracefully
         _ => panic!("We don't have any other adapters implemented yet!"),
     };
 
+    let token_registry =
+        TokenRegistry::from_config(&config).expect("Should load the token registry");
+
     let logger = logger();
     let redis = redis_connection().await?;
     info!(&logger, "Checking connection and applying migrations...");
@@ -104,14 +152,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     match adapter {
         AdapterTypes::EthereumAdapter(adapter) => {
             run(
-                Application::new(*adapter, config, logger, redis, postgres),
+                Application::new(*adapter, config, token_registry, logger, redis, postgres)
+                    .expect("Should create the Application"),
                 port,
             )
             .await
         }
         AdapterTypes::DummyAdapter(adapter) => {
             run(
-                Application::new(*adapter, config, logger, redis, postgres),
+                Application::new(*adapter, config, token_registry, logger, redis, postgres)
+                    .expect("Should create the Application"),
+                port,
+            )
+            .await
+        }
+        AdapterTypes::RemoteAdapter(adapter) => {
+            run(
+                Application::new(*adapter, config, token_registry, logger, redis, postgres)
+                    .expect("Should create the Application"),
                 port,
             )
             .await
@@ -121,10 +179,39 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-/// Starts the `hyper` `Server`.
+/// Starts the chain watcher, the analytics buffer flushing, the event aggregates compaction,
+/// the channel updates subscription and the `hyper` `Server`.
 async fn run<A: Adapter + 'static>(app: Application<A>, port: u16) {
     let addr = ([127, 0, 0, 1], port).into();
     let logger = app.logger.clone();
+
+    tokio::spawn(chain_watcher::watch(
+        app.adapter.clone(),
+        app.config.clone(),
+        app.pool.clone(),
+        logger.clone(),
+    ));
+
+    tokio::spawn(channel_updates::subscribe_loop(
+        app.channel_updates.clone(),
+        logger.clone(),
+    ));
+
+    tokio::spawn(compaction::compaction_loop(
+        app.pool.clone(),
+        app.config.clone(),
+        logger.clone(),
+    ));
+
+    if ANALYTICS_RECORDER.is_some() {
+        tokio::spawn(analytics_recorder::flush_loop(
+            app.redis.clone(),
+            app.pool.clone(),
+            app.config.analytics_flush_interval,
+            logger.clone(),
+        ));
+    }
+
     info!(&logger, "Listening on port {}!", port);
 
     let make_service = make_service_fn(|_| {
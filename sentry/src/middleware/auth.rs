@@ -141,6 +141,7 @@ mod test {
     async fn setup() -> (DummyAdapter, MultiplexedConnection) {
         let adapter_options = DummyAdapterOptions {
             dummy_identity: IDS["leader"],
+            dummy_previous_identities: vec![],
             dummy_auth: IDS.clone(),
             dummy_auth_tokens: AUTH.clone(),
         };
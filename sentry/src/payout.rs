@@ -3,7 +3,8 @@ use chrono::Utc;
 use primitives::{
     sentry::Event,
     targeting::Input,
-    targeting::{eval_with_callback, get_pricing_bounds, input, Error, Output},
+    targeting::{get_pricing_bounds, input, CompiledRules, Error, Output, Rules},
+    token::TokenRegistry,
     BigNum, Channel, ValidatorId,
 };
 use slog::{error, Logger};
@@ -11,7 +12,24 @@ use std::cmp::{max, min};
 
 type Result = std::result::Result<Option<(ValidatorId, BigNum)>, Error>;
 
-pub fn get_payout(logger: &Logger, channel: &Channel, event: &Event, session: &Session) -> Result {
+/// The targeting rules of the channel, which can be updated, take precedence over the ones of its spec
+pub fn channel_targeting_rules(channel: &Channel) -> &Rules {
+    if !channel.targeting_rules.is_empty() {
+        &channel.targeting_rules
+    } else {
+        &channel.spec.targeting_rules
+    }
+}
+
+/// The `targeting_rules` are the compiled `channel_targeting_rules`
+pub fn get_payout(
+    logger: &Logger,
+    token_registry: &TokenRegistry,
+    channel: &Channel,
+    targeting_rules: &CompiledRules,
+    event: &Event,
+    session: &Session,
+) -> Result {
     let event_type = event.to_string();
 
     match event {
@@ -27,12 +45,6 @@ pub fn get_payout(logger: &Logger, channel: &Channel, event: &Event, session: &S
             ad_slot,
             ..
         } => {
-            let targeting_rules = if !channel.targeting_rules.is_empty() {
-                channel.targeting_rules.clone()
-            } else {
-                channel.spec.targeting_rules.clone()
-            };
-
             let pricing = get_pricing_bounds(&channel, &event_type);
 
             if targeting_rules.is_empty() {
@@ -66,8 +78,10 @@ pub fn get_payout(logger: &Logger, channel: &Channel, event: &Event, session: &S
                     balances: None,
                     // TODO: Check this one as well!
                     ad_slot: None,
+                    deposit_asset_price: None,
                 }
-                .with_channel(channel.clone());
+                .with_channel(channel.clone())
+                .with_deposit_asset_price(token_registry);
 
                 let mut output = Output {
                     show: true,
@@ -79,7 +93,7 @@ pub fn get_payout(logger: &Logger, channel: &Channel, event: &Event, session: &S
 
                 let on_type_error = |error, rule| error!(logger, "Rule evaluation error for {:?}", channel.id; "error" => ?error, "rule" => ?rule);
 
-                eval_with_callback(&targeting_rules, &input, &mut output, Some(on_type_error));
+                targeting_rules.eval_with_callback(&input, &mut output, Some(on_type_error));
 
                 if output.show {
                     let price = match output.price.get(&event_type) {
@@ -107,6 +121,16 @@ mod test {
         discard_logger,
         prep_db::{DUMMY_CHANNEL, IDS},
     };
+    use primitives::{
+        targeting::{Function, Rules, Value},
+        token::{PriceSource, StaticPriceProvider, TokenInfo},
+    };
+    use serde_json::Number;
+    use std::sync::Arc;
+
+    fn token_registry() -> TokenRegistry {
+        TokenRegistry::new(vec![], Arc::new(StaticPriceProvider))
+    }
 
     #[test]
     fn get_event_payouts_pricing_bounds_impression_event() {
@@ -138,7 +162,15 @@ mod test {
             os: None,
         };
 
-        let payout = get_payout(&logger, &channel, &event, &session).expect("Should be OK");
+        let payout = get_payout(
+            &logger,
+            &token_registry(),
+            &channel,
+            &CompiledRules::new(channel_targeting_rules(&channel)),
+            &event,
+            &session,
+        )
+        .expect("Should be OK");
 
         let expected_option = Some((IDS["leader"], 8.into()));
         assert_eq!(expected_option, payout, "pricingBounds: impression event");
@@ -173,7 +205,15 @@ mod test {
             os: None,
         };
 
-        let payout = get_payout(&logger, &channel, &event, &session).expect("Should be OK");
+        let payout = get_payout(
+            &logger,
+            &token_registry(),
+            &channel,
+            &CompiledRules::new(channel_targeting_rules(&channel)),
+            &event,
+            &session,
+        )
+        .expect("Should be OK");
 
         let expected_option = Some((IDS["leader"], 23.into()));
         assert_eq!(expected_option, payout, "pricingBounds: click event");
@@ -203,8 +243,89 @@ mod test {
             os: None,
         };
 
-        let payout = get_payout(&logger, &channel, &event, &session).expect("Should be OK");
+        let payout = get_payout(
+            &logger,
+            &token_registry(),
+            &channel,
+            &CompiledRules::new(channel_targeting_rules(&channel)),
+            &event,
+            &session,
+        )
+        .expect("Should be OK");
 
         assert_eq!(None, payout, "pricingBounds: click event");
     }
+
+    #[test]
+    fn get_event_payouts_uses_the_deposit_asset_price_in_usd() {
+        let logger = discard_logger();
+        let mut channel = DUMMY_CHANNEL.clone();
+        channel.spec.min_per_impression = 8.into();
+        channel.spec.max_per_impression = 64.into();
+        // only show if 1 whole token is worth at least $1.5
+        channel.targeting_rules = Rules(vec![Function::new_only_show_if(Function::new_gte(
+            Function::new_get_price_in_usd(Value::BigNum(10u64.pow(18).into())),
+            Value::Number(Number::from_f64(1.5).expect("Should create a float")),
+        ))
+        .into()]);
+
+        let event = Event::Impression {
+            publisher: IDS["leader"],
+            ad_unit: None,
+            ad_slot: None,
+            referrer: None,
+        };
+
+        let session = Session {
+            ip: None,
+            country: None,
+            referrer_header: None,
+            os: None,
+        };
+
+        let registry_with_price = |usd| {
+            let token = TokenInfo {
+                address: channel.deposit_asset.clone(),
+                symbol: "TOKEN".into(),
+                decimals: 18,
+                price_source: PriceSource::Static { usd },
+            };
+
+            TokenRegistry::new(vec![token], Arc::new(StaticPriceProvider))
+        };
+
+        let payout = get_payout(
+            &logger,
+            &registry_with_price(2.0),
+            &channel,
+            &CompiledRules::new(channel_targeting_rules(&channel)),
+            &event,
+            &session,
+        )
+        .expect("Should be OK");
+        assert_eq!(Some((IDS["leader"], 8.into())), payout, "token is worth $2");
+
+        let payout = get_payout(
+            &logger,
+            &registry_with_price(1.0),
+            &channel,
+            &CompiledRules::new(channel_targeting_rules(&channel)),
+            &event,
+            &session,
+        )
+        .expect("Should be OK");
+        assert_eq!(None, payout, "token is worth $1");
+
+        // the rule fails with a TypeError for an unknown deposit asset and it's skipped
+        let payout = get_payout(
+            &logger,
+            &token_registry(),
+            &channel,
+            &CompiledRules::new(channel_targeting_rules(&channel)),
+            &event,
+            &session,
+        )
+        .expect("Should be OK");
+        assert_eq!(Some((IDS["leader"], 8.into())), payout, "unknown token");
+    }
 }
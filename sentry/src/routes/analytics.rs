@@ -1,12 +1,20 @@
 use crate::{
-    db::analytics::{advertiser_channel_ids, get_advanced_reports, get_analytics, AnalyticsType},
+    db::analytics::{
+        advertiser_channel_ids, analytics_statement, get_advanced_reports, get_analytics,
+        AnalyticsType,
+    },
+    export::{stream_rows, ExportFormat},
     success_response, Application, Auth, ResponseError, RouteParams,
 };
+use chrono::{DateTime, Duration, Utc};
 use hyper::{Body, Request, Response};
 use primitives::{
     adapter::Adapter,
-    analytics::{AnalyticsQuery, AnalyticsResponse},
-    ChannelId,
+    analytics::{
+        AdvancedAnalyticsQuery, AnalyticsData, AnalyticsQuery, AnalyticsResponse, TimeFrame,
+        ANALYTICS_QUERY_LIMIT,
+    },
+    ChannelId, Config,
 };
 use redis::aio::MultiplexedConnection;
 use slog::{error, Logger};
@@ -23,15 +31,18 @@ pub async fn publisher_analytics<A: Adapter>(
 
     let analytics_type = AnalyticsType::Publisher { auth };
 
-    process_analytics(req, app, analytics_type)
-        .await
-        .map(success_response)
+    analytics_response(req, app, analytics_type).await
 }
 
 pub async fn analytics<A: Adapter>(
     req: Request<Body>,
     app: &Application<A>,
 ) -> Result<Response<Body>, ResponseError> {
+    let format = ExportFormat::from_request(&req);
+    if format != ExportFormat::Json {
+        return export_analytics(req, app, AnalyticsType::Global, format);
+    }
+
     let request_uri = req.uri().to_string();
     let redis = app.redis.clone();
 
@@ -70,9 +81,60 @@ pub async fn advertiser_analytics<A: Adapter>(
         auth: sess.ok_or(ResponseError::Unauthorized)?.to_owned(),
     };
 
-    process_analytics(req, app, analytics_type)
-        .await
-        .map(success_response)
+    analytics_response(req, app, analytics_type).await
+}
+
+/// The analytics as JSON or as a CSV or NDJSON export, depending on the `Accept` header
+async fn analytics_response<A: Adapter>(
+    req: Request<Body>,
+    app: &Application<A>,
+    analytics_type: AnalyticsType,
+) -> Result<Response<Body>, ResponseError> {
+    match ExportFormat::from_request(&req) {
+        ExportFormat::Json => process_analytics(req, app, analytics_type)
+            .await
+            .map(success_response),
+        format => export_analytics(req, app, analytics_type, format),
+    }
+}
+
+/// Streams all of the analytics in the time frame of the query.
+/// The `limit` is only applied to the public global analytics
+fn export_analytics<A: Adapter>(
+    req: Request<Body>,
+    app: &Application<A>,
+    analytics_type: AnalyticsType,
+    format: ExportFormat,
+) -> Result<Response<Body>, ResponseError> {
+    let query = serde_urlencoded::from_str::<AnalyticsQuery>(&req.uri().query().unwrap_or(""))?;
+    query
+        .is_valid()
+        .map_err(|e| ResponseError::BadRequest(e.to_string()))?;
+    let time_frame = time_frame(&query, &app.config, Utc::now())?;
+
+    let channel_id = req.extensions().get::<ChannelId>();
+    let limit = match analytics_type {
+        AnalyticsType::Global => Some(query.limit.min(ANALYTICS_QUERY_LIMIT)),
+        _ => None,
+    };
+
+    let (statement, params) = analytics_statement(
+        &query,
+        &time_frame,
+        &analytics_type,
+        query.segment_by_channel.is_some(),
+        channel_id,
+        limit,
+    );
+
+    stream_rows::<AnalyticsData>(
+        &app.exports,
+        app.logger.clone(),
+        app.pool.clone(),
+        format,
+        statement,
+        params,
+    )
 }
 
 pub async fn process_analytics<A: Adapter>(
@@ -84,6 +146,7 @@ pub async fn process_analytics<A: Adapter>(
     query
         .is_valid()
         .map_err(|e| ResponseError::BadRequest(e.to_string()))?;
+    let time_frame = time_frame(&query, &app.config, Utc::now())?;
 
     let channel_id = req.extensions().get::<ChannelId>();
 
@@ -93,6 +156,7 @@ pub async fn process_analytics<A: Adapter>(
 
     let aggr = get_analytics(
         query,
+        &time_frame,
         &app.pool,
         analytics_type,
         segment_channel,
@@ -106,6 +170,31 @@ pub async fn process_analytics<A: Adapter>(
         .map_err(|_| ResponseError::BadRequest("error occurred; try again later".to_string()))
 }
 
+/// The event aggregates older than the retention period might be compacted into daily summaries
+/// at UTC midnight, so a time frame reaching them can only have whole day buckets in UTC
+fn time_frame(
+    query: &AnalyticsQuery,
+    config: &Config,
+    now: DateTime<Utc>,
+) -> Result<TimeFrame, ResponseError> {
+    let time_frame = query
+        .time_frame(now)
+        .map_err(|e| ResponseError::BadRequest(e.to_string()))?;
+
+    let compacted_before = now - Duration::days(config.event_aggregates_retention_days.into());
+    let whole_days = time_frame.bucket % Duration::days(1).num_seconds() == 0
+        && time_frame.timezone.name() == "UTC";
+
+    if time_frame.start < compacted_before && !whole_days {
+        return Err(ResponseError::BadRequest(format!(
+            "the events before {} are summarized by day, so their buckets should be whole days in UTC",
+            compacted_before.to_rfc3339()
+        )));
+    }
+
+    Ok(time_frame)
+}
+
 pub async fn advanced_analytics<A: Adapter>(
     req: Request<Body>,
     app: &Application<A>,
@@ -113,16 +202,13 @@ pub async fn advanced_analytics<A: Adapter>(
     let auth = req.extensions().get::<Auth>().expect("auth is required");
     let advertiser_channels = advertiser_channel_ids(&app.pool, &auth.uid).await?;
 
-    let query = serde_urlencoded::from_str::<AnalyticsQuery>(&req.uri().query().unwrap_or(""))?;
+    let query =
+        serde_urlencoded::from_str::<AdvancedAnalyticsQuery>(&req.uri().query().unwrap_or(""))?;
+    query
+        .is_valid()
+        .map_err(|e| ResponseError::BadRequest(e.to_string()))?;
 
-    let response = get_advanced_reports(
-        &app.redis,
-        &query.event_type,
-        &auth.uid,
-        &advertiser_channels,
-    )
-    .await
-    .map_err(|_| ResponseError::BadRequest("error occurred; try again later".to_string()))?;
+    let response = get_advanced_reports(&app.pool, &query, &auth.uid, &advertiser_channels).await?;
 
     Ok(success_response(serde_json::to_string(&response)?))
 }
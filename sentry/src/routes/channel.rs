@@ -1,8 +1,14 @@
-use crate::db::event_aggregate::{latest_approve_state, latest_heartbeats, latest_new_state};
+use crate::channel_updates::{self, MAX_CHANNELS};
+use crate::db::event_aggregate::{
+    balances_statement, latest_approve_state, latest_heartbeats, latest_new_state,
+};
 use crate::db::{
     get_channel_by_id, insert_channel, insert_validator_messages, list_channels,
-    update_exhausted_channel,
+    list_channels_after, update_exhausted_channel,
 };
+use crate::export::{stream_rows, ExportFormat};
+use crate::payout::channel_targeting_rules;
+use crate::status::get_statuses;
 use crate::{success_response, Application, Auth, ResponseError, RouteParams, Session};
 use bb8::RunError;
 use bb8_postgres::tokio_postgres::error;
@@ -12,23 +18,33 @@ use hyper::{Body, Request, Response};
 use primitives::{
     adapter::Adapter,
     sentry::{
-        channel_list::{ChannelListQuery, LastApprovedQuery},
-        Event, LastApproved, LastApprovedResponse, SuccessResponse,
+        channel_list::{ChannelListCursor, ChannelListQuery, LastApprovedQuery},
+        ApproveStateValidatorMessage, Balance, ChannelBalancesResponse, ChannelListResponse,
+        ChannelUpdate, ChannelValidationResponse, Event, LastApproved, LastApprovedResponse,
+        SuccessResponse, TargetingExplainRequest,
     },
+    supermarket::{Status, StatusKind},
+    targeting::{check::check_channel, eval_with_trace, trace::Trace, Output},
     validator::MessageTypes,
     Channel, ChannelId,
 };
 use slog::error;
 use std::collections::HashMap;
 
+/// The number of channels loaded at once when listing the channels by their status
+const STATUS_SCAN_BATCH: u32 = 100;
+/// The maximum number of channels scanned by a single request for the channels with a status
+const MAX_STATUS_SCAN: usize = 1_000;
+
 pub async fn channel_status<A: Adapter>(
     req: Request<Body>,
-    _: &Application<A>,
+    app: &Application<A>,
 ) -> Result<Response<Body>, ResponseError> {
     use serde::Serialize;
     #[derive(Serialize)]
     struct ChannelStatusResponse<'a> {
         channel: &'a Channel,
+        status: Status,
     }
 
     let channel = req
@@ -36,7 +52,101 @@ pub async fn channel_status<A: Adapter>(
         .get::<Channel>()
         .expect("Request should have Channel");
 
-    let response = ChannelStatusResponse { channel };
+    let status = get_statuses(&app.pool, app.config.heartbeat_time, &[channel])
+        .await?
+        .remove(&channel.id)
+        .expect("Should have the status of the channel");
+
+    let response = ChannelStatusResponse { channel, status };
+
+    Ok(success_response(serde_json::to_string(&response)?))
+}
+
+/// `GET /channel/:id/balances`
+///
+/// The balances of the last approved `NewState` of the channel,
+/// as JSON or as a CSV or NDJSON export of the earners, depending on the `Accept` header
+pub async fn channel_balances<A: Adapter>(
+    req: Request<Body>,
+    app: &Application<A>,
+) -> Result<Response<Body>, ResponseError> {
+    let channel = req
+        .extensions()
+        .get::<Channel>()
+        .expect("Request should have Channel");
+
+    let state_root = match latest_approve_state(&app.pool, channel).await? {
+        Some(ApproveStateValidatorMessage {
+            msg: MessageTypes::ApproveState(approve_state),
+            ..
+        }) => Some(approve_state.state_root),
+        _ => None,
+    };
+
+    let format = ExportFormat::from_request(&req);
+    if format != ExportFormat::Json {
+        // without an approved state there are no balances and only the CSV header is sent
+        let (statement, params) =
+            balances_statement(channel, state_root.as_deref().unwrap_or_default());
+
+        return stream_rows::<Balance>(
+            &app.exports,
+            app.logger.clone(),
+            app.pool.clone(),
+            format,
+            statement,
+            params,
+        );
+    }
+
+    let new_state = match state_root {
+        Some(state_root) => latest_new_state(&app.pool, channel, &state_root).await?,
+        None => None,
+    };
+    let balances = match new_state.map(|new_state| new_state.msg) {
+        Some(MessageTypes::NewState(new_state)) => new_state.balances,
+        _ => Default::default(),
+    };
+
+    Ok(success_response(serde_json::to_string(
+        &ChannelBalancesResponse { balances },
+    )?))
+}
+
+/// Evaluates the targeting rules of the channel against the `Input` of the request
+/// and returns the `Output` together with the trace of the evaluation
+pub async fn explain_targeting<A: Adapter>(
+    req: Request<Body>,
+    app: &Application<A>,
+) -> Result<Response<Body>, ResponseError> {
+    use serde::Serialize;
+    #[derive(Serialize)]
+    struct TargetingExplainResponse {
+        output: Output,
+        trace: Trace,
+    }
+
+    let channel = req
+        .extensions()
+        .get::<Channel>()
+        .expect("Request should have Channel")
+        .to_owned();
+
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let request = serde_json::from_slice::<TargetingExplainRequest>(&body)
+        .map_err(|e| ResponseError::FailedValidation(e.to_string()))?;
+
+    // the same rules as the ones used for the payouts
+    let targeting_rules = channel_targeting_rules(&channel);
+
+    let input = request
+        .input
+        .with_channel(channel.clone())
+        .with_deposit_asset_price(&app.token_registry);
+    let mut output = Output::from(&channel);
+
+    let trace = eval_with_trace(targeting_rules, &input, &mut output);
+    let response = TargetingExplainResponse { output, trace };
 
     Ok(success_response(serde_json::to_string(&response)?))
 }
@@ -50,6 +160,8 @@ pub async fn create_channel<A: Adapter>(
     let channel = serde_json::from_slice::<Channel>(&body)
         .map_err(|e| ResponseError::FailedValidation(e.to_string()))?;
 
+    let warnings = check_targeting_rules(&channel)?;
+
     if let Err(e) = app.adapter.validate_channel(&channel).await {
         return Err(ResponseError::BadRequest(e.to_string()));
     }
@@ -70,7 +182,10 @@ pub async fn create_channel<A: Adapter>(
         _ => Ok(()),
     }?;
 
-    let create_response = SuccessResponse { success: true };
+    let create_response = ChannelValidationResponse {
+        success: true,
+        warnings,
+    };
 
     Ok(success_response(serde_json::to_string(&create_response)?))
 }
@@ -80,35 +195,192 @@ pub async fn channel_list<A: Adapter>(
     app: &Application<A>,
 ) -> Result<Response<Body>, ResponseError> {
     let query = serde_urlencoded::from_str::<ChannelListQuery>(&req.uri().query().unwrap_or(""))?;
-    let skip = query
-        .page
-        .checked_mul(app.config.channels_find_limit.into())
-        .ok_or_else(|| ResponseError::BadRequest("Page and/or limit is too large".into()))?;
-
-    let list_response = list_channels(
-        &app.pool,
-        skip,
-        app.config.channels_find_limit,
-        &query.creator,
-        &query.validator,
-        &query.valid_until_ge,
-    )
-    .await?;
+    if let Some(cursor) = &query.cursor {
+        if cursor.sort != query.sort {
+            return Err(ResponseError::BadRequest(format!(
+                "The cursor is for the {} sort",
+                cursor.sort
+            )));
+        }
+    }
+
+    // the pages after a cursor start right after it
+    let skip = match query.cursor {
+        Some(_) => 0,
+        None => query
+            .page
+            .checked_mul(app.config.channels_find_limit.into())
+            .ok_or_else(|| ResponseError::BadRequest("Page and/or limit is too large".into()))?,
+    };
+
+    let list_response = match query.status {
+        Some(status_kind) => list_channels_by_status(app, &query, status_kind, skip).await?,
+        None => {
+            let mut list_response =
+                list_channels(&app.pool, skip, app.config.channels_find_limit, &query).await?;
+            let channels = list_response.channels.iter().collect::<Vec<_>>();
+            list_response.statuses =
+                get_statuses(&app.pool, app.config.heartbeat_time, &channels).await?;
+
+            list_response
+        }
+    };
 
     Ok(success_response(serde_json::to_string(&list_response)?))
 }
 
+/// The status is not stored, so the channels are scanned in batches of `STATUS_SCAN_BATCH`
+/// until the page is filled, but at most `MAX_STATUS_SCAN` channels are scanned by a request.
+/// When the scan stops before the page is filled, the page is shorter and its `next` cursor
+/// continues the scan, so `total_pages` only counts the scanned channels and one more page.
+async fn list_channels_by_status<A: Adapter>(
+    app: &Application<A>,
+    query: &ChannelListQuery,
+    status_kind: StatusKind,
+    skip: u64,
+) -> Result<ChannelListResponse, ResponseError> {
+    let limit = app.config.channels_find_limit as usize;
+    let skip = skip as usize;
+
+    let mut matching = vec![];
+    let mut statuses = HashMap::new();
+    let mut scan_cursor = query.cursor.clone();
+    let mut scanned = 0;
+    let mut scanned_all = false;
+
+    while matching.len() <= skip + limit && scanned < MAX_STATUS_SCAN {
+        let batch =
+            list_channels_after(&app.pool, query, scan_cursor.as_ref(), STATUS_SCAN_BATCH).await?;
+        scanned += batch.len();
+        scanned_all = batch.len() < STATUS_SCAN_BATCH as usize;
+        if let Some(last) = batch.last() {
+            scan_cursor = Some(ChannelListCursor::after(query.sort, last));
+        }
+
+        let batch_refs = batch.iter().collect::<Vec<_>>();
+        let mut batch_statuses =
+            get_statuses(&app.pool, app.config.heartbeat_time, &batch_refs).await?;
+        batch_statuses.retain(|_, status| status.kind() == status_kind);
+
+        matching.extend(
+            batch
+                .into_iter()
+                .filter(|channel| batch_statuses.contains_key(&channel.id)),
+        );
+        statuses.extend(batch_statuses);
+
+        if scanned_all {
+            break;
+        }
+    }
+
+    if !scanned_all && matching.len() <= skip {
+        return Err(ResponseError::BadRequest(
+            "Page is too large for the status filter, use the next cursor instead".into(),
+        ));
+    }
+
+    // fast ceil for total_pages
+    let scanned_pages = if matching.is_empty() {
+        1
+    } else {
+        1 + ((matching.len() - 1) / limit)
+    };
+    // there is at least one more page after a stopped scan
+    let total_pages = if scanned_all {
+        scanned_pages as u64
+    } else {
+        scanned_pages as u64 + 1
+    };
+
+    let has_more = matching.len() > skip + limit;
+    let channels = matching
+        .into_iter()
+        .skip(skip)
+        .take(limit)
+        .collect::<Vec<_>>();
+    statuses.retain(|channel_id, _| channels.iter().any(|channel| &channel.id == channel_id));
+
+    let next = if has_more {
+        channels
+            .last()
+            .map(|channel| ChannelListCursor::after(query.sort, channel))
+    } else if !scanned_all {
+        scan_cursor
+    } else {
+        None
+    };
+
+    Ok(ChannelListResponse {
+        total_pages,
+        total: total_pages,
+        page: query.page,
+        channels,
+        statuses,
+        next,
+    })
+}
+
+/// `GET /channel/updates?channels=0x...,0x...`
+///
+/// Streams the real-time updates of the channels as Server-Sent Events
+pub async fn channel_updates<A: Adapter>(
+    req: Request<Body>,
+    app: &Application<A>,
+) -> Result<Response<Body>, ResponseError> {
+    use serde::Deserialize;
+    #[derive(Deserialize)]
+    struct ChannelUpdatesQuery {
+        /// Comma-separated channel ids
+        channels: String,
+    }
+
+    let query = serde_urlencoded::from_str::<ChannelUpdatesQuery>(req.uri().query().unwrap_or(""))?;
+
+    let channels = query
+        .channels
+        .split(',')
+        .map(|channel_id| channel_id.trim().parse::<ChannelId>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| ResponseError::BadRequest("invalid channel id".to_string()))?;
+
+    if channels.len() > MAX_CHANNELS {
+        return Err(ResponseError::BadRequest(format!(
+            "at most {} channels can be streamed",
+            MAX_CHANNELS
+        )));
+    }
+
+    Ok(app.channel_updates.stream(app.logger.clone(), channels))
+}
+
 pub async fn channel_validate<A: Adapter>(
     req: Request<Body>,
     _: &Application<A>,
 ) -> Result<Response<Body>, ResponseError> {
     let body = hyper::body::to_bytes(req.into_body()).await?;
-    let _channel = serde_json::from_slice::<Channel>(&body)
+    let channel = serde_json::from_slice::<Channel>(&body)
         .map_err(|e| ResponseError::FailedValidation(e.to_string()))?;
-    let create_response = SuccessResponse { success: true };
+    let warnings = check_targeting_rules(&channel)?;
+    let create_response = ChannelValidationResponse {
+        success: true,
+        warnings,
+    };
     Ok(success_response(serde_json::to_string(&create_response)?))
 }
 
+/// Rejects channels with targeting rules which would fail when evaluated
+/// and returns the warnings of the valid ones
+fn check_targeting_rules(channel: &Channel) -> Result<Vec<String>, ResponseError> {
+    match check_channel(channel) {
+        Ok(warnings) => Ok(warnings.iter().map(ToString::to_string).collect()),
+        Err(errors) => Err(ResponseError::FailedValidations(
+            "channel targeting rules are invalid".to_string(),
+            errors.iter().map(ToString::to_string).collect(),
+        )),
+    }
+}
+
 pub async fn last_approved<A: Adapter>(
     req: Request<Body>,
     app: &Application<A>,
@@ -250,14 +522,30 @@ pub async fn create_validator_messages<A: Adapter + 'static>(
     match channel.spec.validators.find(&session.uid) {
         None => Err(ResponseError::Unauthorized),
         _ => {
-            try_join_all(messages.iter().map(|message| {
+            let received_messages = try_join_all(messages.iter().map(|message| {
                 insert_validator_messages(&app.pool, &channel, &session.uid, &message)
             }))
             .await?;
 
+            for (received, message) in received_messages.into_iter().zip(messages) {
+                let update = ChannelUpdate::ValidatorMessage {
+                    channel_id: channel.id,
+                    from: session.uid,
+                    received,
+                    msg: message.clone(),
+                };
+                channel_updates::publish(&app.redis, &app.logger, &update).await;
+            }
+
             if channel_is_exhausted {
                 if let Some(validator_index) = channel.spec.validators.find_index(&session.uid) {
                     update_exhausted_channel(&app.pool, &channel, validator_index).await?;
+
+                    let update = ChannelUpdate::Exhausted {
+                        channel_id: channel.id,
+                        validator: session.uid,
+                    };
+                    channel_updates::publish(&app.redis, &app.logger, &update).await;
                 }
             }
 
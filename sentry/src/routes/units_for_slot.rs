@@ -0,0 +1,84 @@
+use crate::supermarket::units_for_slot;
+use crate::{success_response, Application, ResponseError, RouteParams, Session};
+use chrono::Utc;
+use hyper::{Body, Request, Response};
+use primitives::{adapter::Adapter, IPFS};
+use slog::error;
+use std::convert::TryFrom;
+
+/// `GET /units-for-slot/:adSlot?depositAsset=...&depositAsset=...`
+///
+/// The same response as the market's `units-for-slot`, with the cached campaigns of the `Supermarket`.
+/// Only the `depositAsset`s of the query are used, `pubPrefix` is ignored.
+pub async fn get_units_for_slot<A: Adapter>(
+    req: Request<Body>,
+    app: &Application<A>,
+) -> Result<Response<Body>, ResponseError> {
+    let config = app
+        .config
+        .supermarket
+        .as_ref()
+        .ok_or(ResponseError::NotFound)?;
+
+    let route_params = req
+        .extensions()
+        .get::<RouteParams>()
+        .expect("request should have route params");
+    let ad_slot = IPFS::try_from(route_params.index(0).as_str())
+        .map_err(|_| ResponseError::BadRequest("Invalid AdSlot".to_string()))?;
+
+    // the deposit assets are repeated in the query, which `serde_urlencoded` doesn't support
+    let deposit_assets: Vec<String> =
+        url::form_urlencoded::parse(req.uri().query().unwrap_or("").as_bytes())
+            .filter(|(key, _)| key == "depositAsset")
+            .map(|(_, deposit_asset)| deposit_asset.into_owned())
+            .collect();
+
+    let session = req
+        .extensions()
+        .get::<Session>()
+        .expect("request should have session");
+
+    let ad_slot_response = app
+        .supermarket
+        .fetch_ad_slot(config, &ad_slot)
+        .await?
+        .ok_or(ResponseError::NotFound)?;
+
+    // a missing fallback unit shouldn't prevent showing the campaigns
+    let fallback_unit = match ad_slot_response
+        .slot
+        .fallback_unit
+        .as_ref()
+        .and_then(|fallback_unit| IPFS::try_from(fallback_unit).ok())
+    {
+        Some(fallback_unit) => app
+            .supermarket
+            .fetch_ad_unit(config, &fallback_unit)
+            .await
+            .unwrap_or_else(|err| {
+                error!(&app.logger, "Failed to fetch the fallback unit {}", fallback_unit; "error" => ?err, "module" => "units_for_slot");
+
+                None
+            }),
+        None => None,
+    };
+
+    let campaigns = app
+        .supermarket
+        .campaigns(&app.logger, &app.pool, app.config.heartbeat_time, config)
+        .await?;
+
+    let response = units_for_slot(
+        &app.logger,
+        &app.token_registry,
+        ad_slot_response,
+        fallback_unit,
+        &campaigns,
+        &deposit_assets,
+        session,
+        Utc::now(),
+    );
+
+    Ok(success_response(serde_json::to_string(&response)?))
+}
@@ -1,8 +1,11 @@
 use crate::db::get_validator_messages;
 use crate::{success_response, Application, ResponseError};
+use chrono::{serde::ts_milliseconds_option, DateTime, Utc};
 use hyper::{Body, Request, Response};
 use primitives::adapter::Adapter;
-use primitives::sentry::ValidatorMessageResponse;
+use primitives::sentry::{
+    validator_message_list::ValidatorMessagesCursor, ValidatorMessageResponse,
+};
 use primitives::{Channel, DomainError, ValidatorId};
 use serde::Deserialize;
 use std::convert::TryFrom;
@@ -10,6 +13,14 @@ use std::convert::TryFrom;
 #[derive(Deserialize)]
 pub struct ValidatorMessagesListQuery {
     limit: Option<u64>,
+    /// Only the messages received before it, in milliseconds
+    #[serde(default, with = "ts_milliseconds_option")]
+    before: Option<DateTime<Utc>>,
+    /// Only the messages received after it, in milliseconds
+    #[serde(default, with = "ts_milliseconds_option")]
+    after: Option<DateTime<Utc>>,
+    /// Continues after the `next` of the previous page
+    cursor: Option<ValidatorMessagesCursor>,
 }
 
 pub fn extract_params(from_path: &str) -> Result<(Option<ValidatorId>, Vec<String>), DomainError> {
@@ -61,10 +72,22 @@ pub async fn list_validator_messages<A: Adapter>(
         .unwrap_or(config_limit)
         .min(config_limit);
 
-    let validator_messages =
-        get_validator_messages(&app.pool, &channel.id, validator_id, message_types, limit).await?;
+    let (validator_messages, next) = get_validator_messages(
+        &app.pool,
+        &channel.id,
+        validator_id,
+        message_types,
+        &query.before,
+        &query.after,
+        &query.cursor,
+        limit,
+    )
+    .await?;
 
-    let response = ValidatorMessageResponse { validator_messages };
+    let response = ValidatorMessageResponse {
+        validator_messages,
+        next,
+    };
 
     Ok(success_response(serde_json::to_string(&response)?))
 }
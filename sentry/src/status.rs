@@ -0,0 +1,493 @@
+//! The supermarket `Status` of the channels, derived from the latest validator messages
+//! which this sentry has received.
+//!
+//! In order of precedence, a channel is:
+//! - `Finalized` when it's exhausted, expired or in its withdraw period
+//! - `Initializing` when none of its validators has sent a message yet
+//! - `Unsound` when at least one of its validators is offline,
+//!   the follower doesn't approve the leader's `NewState` (`disconnected`),
+//!   rejected the leader's latest state or reported it as unhealthy
+//! - `Waiting` when there is no `NewState` yet, i.e. there were no payable events
+//! - `Pending` when the latest `NewState` is not approved yet
+//! - `Active` when the latest `NewState` is approved
+use crate::db::{get_status_messages, DbPool};
+use bb8::RunError;
+use chrono::{DateTime, Duration, Utc};
+use primitives::{
+    channel::channel_exhausted,
+    sentry::ValidatorMessage,
+    supermarket::{Finalized, Status},
+    validator::{ApproveState, MessageTypes, NewState, RejectState},
+    Channel, ChannelId,
+};
+use std::collections::HashMap;
+
+/// A validator is online if its latest `Heartbeat` was received in the last 2 `heartbeat_time`s,
+/// i.e. it can miss a single `Heartbeat`.
+/// The same time is given to the follower to approve a `NewState`
+const HEARTBEAT_INTERVALS: i32 = 2;
+
+/// A validator message together with the time it was received
+#[derive(Debug, Clone)]
+pub struct Received<T> {
+    pub msg: T,
+    pub received: DateTime<Utc>,
+}
+
+/// The latest messages of the validators of a channel, which determine its `Status`
+#[derive(Debug, Clone, Default)]
+pub struct StatusMessages {
+    pub leader_heartbeat: Option<DateTime<Utc>>,
+    pub follower_heartbeat: Option<DateTime<Utc>>,
+    /// The leader's latest `NewState`
+    pub new_state: Option<Received<NewState>>,
+    /// The follower's latest `ApproveState`
+    pub approve_state: Option<Received<ApproveState>>,
+    /// The follower's latest `RejectState`
+    pub reject_state: Option<Received<RejectState>>,
+}
+
+impl StatusMessages {
+    /// Keeps the latest message of each kind, ignoring the messages from other validators
+    pub fn new(channel: &Channel, messages: Vec<ValidatorMessage>) -> Self {
+        let leader = &channel.spec.validators.leader().id;
+        let follower = &channel.spec.validators.follower().id;
+        let mut status_messages = Self::default();
+
+        for ValidatorMessage {
+            from,
+            received,
+            msg,
+        } in messages
+        {
+            let is_leader = &from == leader;
+            let is_follower = &from == follower;
+
+            match msg {
+                MessageTypes::Heartbeat(_) if is_leader => {
+                    latest(&mut status_messages.leader_heartbeat, received, received)
+                }
+                MessageTypes::Heartbeat(_) if is_follower => {
+                    latest(&mut status_messages.follower_heartbeat, received, received)
+                }
+                MessageTypes::NewState(msg) if is_leader => latest(
+                    &mut status_messages.new_state,
+                    Received { msg, received },
+                    received,
+                ),
+                MessageTypes::ApproveState(msg) if is_follower => latest(
+                    &mut status_messages.approve_state,
+                    Received { msg, received },
+                    received,
+                ),
+                MessageTypes::RejectState(msg) if is_follower => latest(
+                    &mut status_messages.reject_state,
+                    Received { msg, received },
+                    received,
+                ),
+                _ => {}
+            }
+        }
+
+        status_messages
+    }
+
+    fn approve_received(&self) -> Option<DateTime<Utc>> {
+        self.approve_state.as_ref().map(|approve| approve.received)
+    }
+}
+
+fn latest<T>(current: &mut Option<T>, message: T, received: DateTime<Utc>)
+where
+    T: HasReceived,
+{
+    let is_later = current
+        .as_ref()
+        .map(|current| current.received() < received)
+        .unwrap_or(true);
+
+    if is_later {
+        *current = Some(message);
+    }
+}
+
+trait HasReceived {
+    fn received(&self) -> DateTime<Utc>;
+}
+
+impl HasReceived for DateTime<Utc> {
+    fn received(&self) -> DateTime<Utc> {
+        *self
+    }
+}
+
+impl<T> HasReceived for Received<T> {
+    fn received(&self) -> DateTime<Utc> {
+        self.received
+    }
+}
+
+/// `heartbeat_time` is in milliseconds, see `Config`
+pub fn get_status(
+    channel: &Channel,
+    messages: &StatusMessages,
+    heartbeat_time: u32,
+    now: DateTime<Utc>,
+) -> Status {
+    if is_exhausted(channel, messages) {
+        return Status::Finalized(Finalized::Exhausted);
+    }
+    if now >= channel.valid_until {
+        return Status::Finalized(Finalized::Expired);
+    }
+    if now >= channel.spec.withdraw_period_start {
+        return Status::Finalized(Finalized::Withdraw);
+    }
+
+    if messages.leader_heartbeat.is_none()
+        && messages.follower_heartbeat.is_none()
+        && messages.new_state.is_none()
+    {
+        return Status::Initializing;
+    }
+
+    let threshold = Duration::milliseconds(heartbeat_time.into()) * HEARTBEAT_INTERVALS;
+    let is_recent = |received: &DateTime<Utc>| now - *received <= threshold;
+
+    let offline = !messages.leader_heartbeat.iter().any(is_recent)
+        || !messages.follower_heartbeat.iter().any(is_recent);
+
+    let new_state_root = messages
+        .new_state
+        .as_ref()
+        .map(|new_state| &new_state.msg.state_root);
+    let is_approved = match (new_state_root, &messages.approve_state) {
+        (Some(new_state_root), Some(approve)) => &approve.msg.state_root == new_state_root,
+        _ => false,
+    };
+    // a RejectState after the latest ApproveState
+    let rejected_state = match &messages.reject_state {
+        Some(reject) => messages
+            .approve_received()
+            .map(|approve_received| reject.received > approve_received)
+            .unwrap_or(true),
+        None => false,
+    };
+    let rejected_new_state = match (new_state_root, &messages.reject_state) {
+        (Some(new_state_root), Some(reject)) => &reject.msg.state_root == new_state_root,
+        _ => false,
+    };
+    let disconnected = match &messages.new_state {
+        Some(new_state) => !is_approved && !rejected_new_state && !is_recent(&new_state.received),
+        None => false,
+    };
+    let unhealthy = messages
+        .approve_state
+        .as_ref()
+        .map(|approve| !approve.msg.is_healthy)
+        .unwrap_or(false);
+
+    if offline || disconnected || rejected_state || unhealthy {
+        return Status::Unsound {
+            disconnected,
+            offline,
+            rejected_state,
+            unhealthy,
+        };
+    }
+
+    match &messages.new_state {
+        None => Status::Waiting,
+        Some(_) if is_approved => Status::Active,
+        Some(_) => Status::Pending,
+    }
+}
+
+/// Computes the current `Status` of each channel from its latest validator messages
+pub async fn get_statuses(
+    pool: &DbPool,
+    heartbeat_time: u32,
+    channels: &[&Channel],
+) -> Result<HashMap<ChannelId, Status>, RunError<bb8_postgres::tokio_postgres::Error>> {
+    let channel_ids = channels
+        .iter()
+        .map(|channel| channel.id)
+        .collect::<Vec<_>>();
+    let mut messages = get_status_messages(pool, &channel_ids).await?;
+    let now = Utc::now();
+
+    let statuses = channels
+        .iter()
+        .map(|channel| {
+            let channel_messages = messages.remove(&channel.id).unwrap_or_default();
+            let status_messages = StatusMessages::new(channel, channel_messages);
+
+            (
+                channel.id,
+                get_status(channel, &status_messages, heartbeat_time, now),
+            )
+        })
+        .collect();
+
+    Ok(statuses)
+}
+
+/// Either the channel is marked as exhausted by both validators,
+/// or they both reported it in the latest approved state
+fn is_exhausted(channel: &Channel, messages: &StatusMessages) -> bool {
+    let approved_exhausted = match (&messages.new_state, &messages.approve_state) {
+        (Some(new_state), Some(approve)) => {
+            new_state.msg.state_root == approve.msg.state_root
+                && new_state.msg.exhausted
+                && approve.msg.exhausted
+        }
+        _ => false,
+    };
+
+    channel_exhausted(channel) || approved_exhausted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use primitives::{
+        util::tests::prep_db::{DUMMY_CHANNEL, IDS},
+        validator::Heartbeat,
+    };
+
+    const HEARTBEAT_TIME: u32 = 30_000;
+
+    fn channel(now: DateTime<Utc>) -> Channel {
+        let mut channel = DUMMY_CHANNEL.clone();
+        channel.valid_until = now + Duration::days(30);
+        channel.spec.withdraw_period_start = now + Duration::days(20);
+
+        channel
+    }
+
+    fn new_state(state_root: &str, received: DateTime<Utc>) -> Received<NewState> {
+        Received {
+            msg: NewState {
+                state_root: state_root.to_string(),
+                signature: "signature".to_string(),
+                balances: Default::default(),
+                exhausted: false,
+            },
+            received,
+        }
+    }
+
+    fn approve_state(state_root: &str, received: DateTime<Utc>) -> Received<ApproveState> {
+        Received {
+            msg: ApproveState {
+                state_root: state_root.to_string(),
+                signature: "signature".to_string(),
+                is_healthy: true,
+                exhausted: false,
+            },
+            received,
+        }
+    }
+
+    fn reject_state(state_root: &str, received: DateTime<Utc>) -> Received<RejectState> {
+        Received {
+            msg: RejectState {
+                reason: "InvalidRootHash".to_string(),
+                state_root: state_root.to_string(),
+                ..Default::default()
+            },
+            received,
+        }
+    }
+
+    /// Both validators are online and the latest `NewState` is approved
+    fn active_messages(now: DateTime<Utc>) -> StatusMessages {
+        StatusMessages {
+            leader_heartbeat: Some(now - Duration::seconds(10)),
+            follower_heartbeat: Some(now - Duration::seconds(20)),
+            new_state: Some(new_state("root", now - Duration::minutes(5))),
+            approve_state: Some(approve_state("root", now - Duration::minutes(4))),
+            reject_state: None,
+        }
+    }
+
+    fn unsound(disconnected: bool, offline: bool, rejected_state: bool, unhealthy: bool) -> Status {
+        Status::Unsound {
+            disconnected,
+            offline,
+            rejected_state,
+            unhealthy,
+        }
+    }
+
+    #[test]
+    fn keeps_the_latest_messages_of_the_validators() {
+        let now = Utc::now();
+        let channel = channel(now);
+        let leader = channel.spec.validators.leader().id;
+        let follower = channel.spec.validators.follower().id;
+        let heartbeat = |from, received| ValidatorMessage {
+            from,
+            received,
+            msg: MessageTypes::Heartbeat(Heartbeat::new("signature".into(), "root".into())),
+        };
+        let approve = |from, state_root: &str, received| ValidatorMessage {
+            from,
+            received,
+            msg: MessageTypes::ApproveState(approve_state(state_root, received).msg),
+        };
+
+        let messages = StatusMessages::new(
+            &channel,
+            vec![
+                heartbeat(leader, now - Duration::seconds(5)),
+                heartbeat(leader, now - Duration::seconds(60)),
+                heartbeat(IDS["user"], now),
+                approve(follower, "old", now - Duration::minutes(10)),
+                approve(follower, "new", now - Duration::minutes(1)),
+                // only the follower approves states
+                approve(leader, "leader", now),
+            ],
+        );
+
+        assert_eq!(Some(now - Duration::seconds(5)), messages.leader_heartbeat);
+        assert_eq!(None, messages.follower_heartbeat);
+        assert_eq!(
+            Some("new"),
+            messages
+                .approve_state
+                .as_ref()
+                .map(|approve| approve.msg.state_root.as_str())
+        );
+    }
+
+    #[test]
+    fn finalized_channels() {
+        let now = Utc::now();
+        let messages = active_messages(now);
+
+        let mut expired = channel(now);
+        expired.valid_until = now - Duration::seconds(1);
+        expired.spec.withdraw_period_start = now - Duration::days(1);
+        assert_eq!(
+            Status::Finalized(Finalized::Expired),
+            get_status(&expired, &messages, HEARTBEAT_TIME, now)
+        );
+
+        let mut withdraw = channel(now);
+        withdraw.spec.withdraw_period_start = now - Duration::seconds(1);
+        assert_eq!(
+            Status::Finalized(Finalized::Withdraw),
+            get_status(&withdraw, &messages, HEARTBEAT_TIME, now)
+        );
+
+        let mut exhausted = channel(now);
+        exhausted.exhausted = vec![true, true];
+        assert_eq!(
+            Status::Finalized(Finalized::Exhausted),
+            get_status(&exhausted, &messages, HEARTBEAT_TIME, now)
+        );
+
+        let mut exhausted_messages = active_messages(now);
+        exhausted_messages
+            .new_state
+            .as_mut()
+            .expect("Should have a NewState")
+            .msg
+            .exhausted = true;
+        exhausted_messages
+            .approve_state
+            .as_mut()
+            .expect("Should have an ApproveState")
+            .msg
+            .exhausted = true;
+        assert_eq!(
+            Status::Finalized(Finalized::Exhausted),
+            get_status(&channel(now), &exhausted_messages, HEARTBEAT_TIME, now)
+        );
+    }
+
+    #[test]
+    fn healthy_channels() {
+        let now = Utc::now();
+        let channel = channel(now);
+
+        assert_eq!(
+            Status::Initializing,
+            get_status(&channel, &StatusMessages::default(), HEARTBEAT_TIME, now)
+        );
+
+        assert_eq!(
+            Status::Active,
+            get_status(&channel, &active_messages(now), HEARTBEAT_TIME, now)
+        );
+
+        let waiting = StatusMessages {
+            new_state: None,
+            approve_state: None,
+            ..active_messages(now)
+        };
+        assert_eq!(
+            Status::Waiting,
+            get_status(&channel, &waiting, HEARTBEAT_TIME, now)
+        );
+
+        // a new state which the follower hasn't approved yet
+        let pending = StatusMessages {
+            new_state: Some(new_state("new root", now - Duration::seconds(30))),
+            ..active_messages(now)
+        };
+        assert_eq!(
+            Status::Pending,
+            get_status(&channel, &pending, HEARTBEAT_TIME, now)
+        );
+    }
+
+    #[test]
+    fn unsound_channels() {
+        let now = Utc::now();
+        let channel = channel(now);
+
+        let offline = StatusMessages {
+            follower_heartbeat: Some(now - Duration::seconds(61)),
+            ..active_messages(now)
+        };
+        assert_eq!(
+            unsound(false, true, false, false),
+            get_status(&channel, &offline, HEARTBEAT_TIME, now)
+        );
+
+        // the new state is not approved in 2 heartbeats
+        let disconnected = StatusMessages {
+            new_state: Some(new_state("new root", now - Duration::seconds(90))),
+            ..active_messages(now)
+        };
+        assert_eq!(
+            unsound(true, false, false, false),
+            get_status(&channel, &disconnected, HEARTBEAT_TIME, now)
+        );
+
+        let rejected = StatusMessages {
+            new_state: Some(new_state("new root", now - Duration::seconds(90))),
+            reject_state: Some(reject_state("new root", now - Duration::seconds(80))),
+            ..active_messages(now)
+        };
+        assert_eq!(
+            unsound(false, false, true, false),
+            get_status(&channel, &rejected, HEARTBEAT_TIME, now)
+        );
+
+        let mut unhealthy = active_messages(now);
+        unhealthy
+            .approve_state
+            .as_mut()
+            .expect("Should have an ApproveState")
+            .msg
+            .is_healthy = false;
+        unhealthy.leader_heartbeat = None;
+        assert_eq!(
+            unsound(false, true, false, true),
+            get_status(&channel, &unhealthy, HEARTBEAT_TIME, now)
+        );
+    }
+}
@@ -0,0 +1,643 @@
+//! The built-in supermarket, answering `units-for-slot` for the AdView manager
+//! with the campaigns of this sentry and of the other configured sentries,
+//! instead of going through an external market.
+use crate::db::{list_channels_after, DbPool};
+use crate::payout::channel_targeting_rules;
+use crate::status::get_statuses;
+use crate::Session;
+use async_std::sync::{Mutex, RwLock};
+use bb8::RunError;
+use chrono::{DateTime, Duration, Utc};
+use futures::future::join_all;
+use primitives::{
+    config::SupermarketConfig,
+    market::{AdSlotResponse, AdUnitResponse},
+    sentry::{
+        channel_list::{ChannelListCursor, ChannelListQuery},
+        ChannelListResponse,
+    },
+    supermarket::{
+        units_for_slot::response::{self, Response, UnitsWithPrice},
+        Status, StatusKind,
+    },
+    targeting::{get_pricing_bounds, input, CompiledRules, Input, Output},
+    token::TokenRegistry,
+    AdUnit, Channel, IPFS,
+};
+use reqwest::StatusCode;
+use slog::{error, Logger};
+use std::{
+    cmp::{max, min},
+    error, fmt,
+    sync::Arc,
+};
+use url::Url;
+
+/// The maximum number of channels loaded from this sentry and from each of the other sentries
+const MAX_SENTRY_CHANNELS: usize = 1_000;
+/// The number of channels of this sentry loaded at once
+const CHANNELS_BATCH: u32 = 100;
+
+#[derive(Debug)]
+pub enum Error {
+    Postgres(RunError<bb8_postgres::tokio_postgres::Error>),
+    Request(reqwest::Error),
+}
+
+impl error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Postgres(error) => write!(f, "Postgres: {}", error),
+            Error::Request(error) => write!(f, "Request: {}", error),
+        }
+    }
+}
+
+impl From<RunError<bb8_postgres::tokio_postgres::Error>> for Error {
+    fn from(error: RunError<bb8_postgres::tokio_postgres::Error>) -> Self {
+        Error::Postgres(error)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        Error::Request(error)
+    }
+}
+
+#[derive(Debug)]
+pub struct Campaign {
+    pub channel: Channel,
+    pub status: Status,
+    /// The compiled `channel_targeting_rules`
+    pub targeting_rules: CompiledRules,
+}
+
+impl Campaign {
+    pub fn new(channel: Channel, status: Status) -> Self {
+        let targeting_rules = CompiledRules::new(channel_targeting_rules(&channel));
+
+        Self {
+            channel,
+            status,
+            targeting_rules,
+        }
+    }
+
+    /// Only campaigns which can still pay for events are shown,
+    /// new ones without any payable events yet included
+    pub fn is_active(&self) -> bool {
+        matches!(
+            self.status.kind(),
+            StatusKind::Active | StatusKind::Pending | StatusKind::Waiting
+        )
+    }
+}
+
+type Cached = (DateTime<Utc>, Arc<Vec<Campaign>>);
+
+#[derive(Default, Clone)]
+pub struct Supermarket {
+    client: reqwest::Client,
+    campaigns: Arc<RwLock<Option<Cached>>>,
+    /// Held while aggregating the campaigns, so they are aggregated by a single request at a time
+    aggregating: Arc<Mutex<()>>,
+}
+
+impl Supermarket {
+    /// The requests to the market and the other sentries time out as configured in `config`
+    pub fn new(config: Option<&SupermarketConfig>) -> Result<Self, reqwest::Error> {
+        let client = match config {
+            Some(config) => reqwest::Client::builder()
+                .timeout(std::time::Duration::from_millis(
+                    config.fetch_timeout.into(),
+                ))
+                .connect_timeout(std::time::Duration::from_millis(
+                    config.connect_timeout.into(),
+                ))
+                .build()?,
+            None => Default::default(),
+        };
+
+        Ok(Self {
+            client,
+            ..Default::default()
+        })
+    }
+
+    /// The active campaigns, aggregated again once the cached ones are older than `campaigns_cache_ttl`.
+    /// While they are being aggregated, the other requests get the outdated cached ones
+    pub async fn campaigns(
+        &self,
+        logger: &Logger,
+        pool: &DbPool,
+        heartbeat_time: u32,
+        config: &SupermarketConfig,
+    ) -> Result<Arc<Vec<Campaign>>, Error> {
+        let ttl = Duration::milliseconds(config.campaigns_cache_ttl.into());
+
+        let cached = self.campaigns.read().await.clone();
+        if let Some((fetched, campaigns)) = &cached {
+            if Utc::now() - *fetched < ttl {
+                return Ok(campaigns.clone());
+            }
+        }
+
+        let _aggregating = match (self.aggregating.try_lock(), cached) {
+            (Some(aggregating), _) => aggregating,
+            (None, Some((_, campaigns))) => return Ok(campaigns),
+            (None, None) => self.aggregating.lock().await,
+        };
+        // the campaigns might have been aggregated while waiting for the lock
+        if let Some((fetched, campaigns)) = self.campaigns.read().await.as_ref() {
+            if Utc::now() - *fetched < ttl {
+                return Ok(campaigns.clone());
+            }
+        }
+
+        let campaigns = Arc::new(
+            self.aggregate_campaigns(logger, pool, heartbeat_time, config)
+                .await?,
+        );
+        *self.campaigns.write().await = Some((Utc::now(), campaigns.clone()));
+
+        Ok(campaigns)
+    }
+
+    /// The channels of this sentry come first, a channel found in several sentries is kept once.
+    /// Sentries which fail to respond are skipped
+    async fn aggregate_campaigns(
+        &self,
+        logger: &Logger,
+        pool: &DbPool,
+        heartbeat_time: u32,
+        config: &SupermarketConfig,
+    ) -> Result<Vec<Campaign>, Error> {
+        let mut campaigns: Vec<Campaign> = local_channels(pool, heartbeat_time)
+            .await?
+            .into_iter()
+            .map(|(channel, status)| Campaign::new(channel, status))
+            .collect();
+
+        let sentries_channels = join_all(
+            config
+                .sentry_urls
+                .iter()
+                .map(|sentry_url| self.fetch_sentry_channels(sentry_url)),
+        )
+        .await;
+
+        for (sentry_url, sentry_channels) in config.sentry_urls.iter().zip(sentries_channels) {
+            match sentry_channels {
+                Ok(sentry_channels) => {
+                    for (channel, status) in sentry_channels {
+                        if campaigns
+                            .iter()
+                            .all(|campaign| campaign.channel.id != channel.id)
+                        {
+                            campaigns.push(Campaign::new(channel, status));
+                        }
+                    }
+                }
+                Err(err) => {
+                    error!(logger, "Failed to fetch the channels of {}", sentry_url; "error" => ?err, "module" => "supermarket")
+                }
+            }
+        }
+
+        campaigns.retain(Campaign::is_active);
+
+        Ok(campaigns)
+    }
+
+    /// The pages of the sentry's `/channel/list` up to `MAX_SENTRY_CHANNELS` channels,
+    /// only the channels with a `Status`
+    async fn fetch_sentry_channels(
+        &self,
+        sentry_url: &Url,
+    ) -> Result<Vec<(Channel, Status)>, Error> {
+        // Url adds a trailing `/`
+        let url = format!("{}channel/list", sentry_url);
+        let mut channels = vec![];
+        let mut fetched = 0;
+        let mut cursor: Option<ChannelListCursor> = None;
+
+        loop {
+            let mut request = self.client.get(&url);
+            if let Some(cursor) = &cursor {
+                request = request.query(&[("cursor", cursor.to_string())]);
+            }
+
+            let mut response: ChannelListResponse =
+                request.send().await?.error_for_status()?.json().await?;
+
+            fetched += response.channels.len();
+            cursor = response.next.take();
+            channels.extend(response.channels.into_iter().filter_map(|channel| {
+                response
+                    .statuses
+                    .remove(&channel.id)
+                    .map(|status| (channel, status))
+            }));
+
+            if cursor.is_none() || fetched >= MAX_SENTRY_CHANNELS {
+                break;
+            }
+        }
+
+        Ok(channels)
+    }
+
+    /// `None` if the market doesn't know the ad slot
+    pub async fn fetch_ad_slot(
+        &self,
+        config: &SupermarketConfig,
+        ad_slot: &IPFS,
+    ) -> Result<Option<AdSlotResponse>, Error> {
+        // Url adds a trailing `/`
+        let url = format!("{}slots/{}", config.market_url, ad_slot);
+        let response = self.client.get(&url).send().await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            Ok(None)
+        } else {
+            Ok(Some(response.error_for_status()?.json().await?))
+        }
+    }
+
+    /// `None` if the market doesn't know the ad unit
+    pub async fn fetch_ad_unit(
+        &self,
+        config: &SupermarketConfig,
+        ad_unit: &IPFS,
+    ) -> Result<Option<AdUnit>, Error> {
+        // Url adds a trailing `/`
+        let url = format!("{}units/{}", config.market_url, ad_unit);
+        let response = self.client.get(&url).send().await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            Ok(None)
+        } else {
+            let ad_unit_response: AdUnitResponse = response.error_for_status()?.json().await?;
+
+            Ok(Some(ad_unit_response.unit))
+        }
+    }
+}
+
+/// Up to `MAX_SENTRY_CHANNELS` of the channels of this sentry, only the channels with a `Status`
+async fn local_channels(
+    pool: &DbPool,
+    heartbeat_time: u32,
+) -> Result<Vec<(Channel, Status)>, Error> {
+    let query = ChannelListQuery::default();
+    let mut channels = vec![];
+    let mut scanned = 0;
+    let mut cursor = None;
+
+    loop {
+        let batch = list_channels_after(pool, &query, cursor.as_ref(), CHANNELS_BATCH).await?;
+        let batch_refs = batch.iter().collect::<Vec<_>>();
+        let mut statuses = get_statuses(pool, heartbeat_time, &batch_refs).await?;
+
+        scanned += batch.len();
+        let scanned_all = batch.len() < CHANNELS_BATCH as usize;
+        cursor = batch
+            .last()
+            .map(|channel| ChannelListCursor::after(query.sort, channel));
+
+        channels.extend(
+            batch
+                .into_iter()
+                .filter_map(|channel| statuses.remove(&channel.id).map(|status| (channel, status))),
+        );
+
+        if scanned_all || scanned >= MAX_SENTRY_CHANNELS {
+            break;
+        }
+    }
+
+    Ok(channels)
+}
+
+/// The ad units of the campaigns, which can be shown in the ad slot, with their impression price.
+///
+/// A campaign is considered only if its deposit asset is one of the `deposit_assets` (any, if empty).
+/// The units need to be of the ad slot type and both the campaign and the ad slot targeting rules
+/// are evaluated for each of them. Their price is bound by the campaign pricing
+/// and it needs to be at least the ad slot's `min_per_impression` for the deposit asset.
+#[allow(clippy::too_many_arguments)]
+pub fn units_for_slot(
+    logger: &Logger,
+    token_registry: &TokenRegistry,
+    ad_slot_response: AdSlotResponse,
+    fallback_unit: Option<AdUnit>,
+    campaigns: &[Campaign],
+    deposit_assets: &[String],
+    session: &Session,
+    now: DateTime<Utc>,
+) -> Response {
+    let hostname = slot_hostname(&ad_slot_response);
+    let AdSlotResponse {
+        slot,
+        accepted_referrers,
+        categories,
+        alexa_rank,
+    } = ad_slot_response;
+
+    let targeting_input_base = Input {
+        ad_view: None,
+        global: input::Global {
+            ad_slot_id: slot.ipfs.clone(),
+            ad_slot_type: slot.ad_type.clone(),
+            publisher_id: slot.owner,
+            country: session.country.clone(),
+            event_type: "IMPRESSION".to_string(),
+            seconds_since_epoch: now,
+            user_agent_os: session.os.clone(),
+            user_agent_browser_family: None,
+        },
+        ad_unit_id: None,
+        channel: None,
+        balances: None,
+        ad_slot: Some(input::AdSlot {
+            categories,
+            hostname,
+            alexa_rank,
+        }),
+        deposit_asset_price: None,
+    };
+    let slot_rules = CompiledRules::new(&slot.rules);
+
+    let campaigns = campaigns
+        .iter()
+        .filter(|campaign| {
+            let channel = &campaign.channel;
+
+            (deposit_assets.is_empty() || deposit_assets.contains(&channel.deposit_asset))
+                && channel.valid_until > now
+                && channel
+                    .spec
+                    .active_from
+                    .map(|active_from| active_from <= now)
+                    .unwrap_or(true)
+        })
+        .filter_map(|campaign| {
+            let channel = &campaign.channel;
+            let pricing = get_pricing_bounds(channel, "IMPRESSION");
+            let min_slot_price = slot
+                .min_per_impression
+                .as_ref()
+                .and_then(|min_per_impression| min_per_impression.get(&channel.deposit_asset));
+            let campaign_input = targeting_input_base
+                .clone()
+                .with_channel(channel.clone())
+                .with_deposit_asset_price(token_registry);
+
+            let on_type_error = |error, rule| error!(logger, "Rule evaluation error for {:?}", channel.id; "error" => ?error, "rule" => ?rule, "module" => "supermarket");
+
+            let units_with_price: Vec<UnitsWithPrice> = channel
+                .spec
+                .ad_units
+                .iter()
+                .filter(|ad_unit| ad_unit.ad_type == slot.ad_type && !ad_unit.archived)
+                .filter_map(|ad_unit| {
+                    let input = Input {
+                        ad_unit_id: Some(ad_unit.ipfs.clone()),
+                        ..campaign_input.clone()
+                    };
+                    let mut output = Output {
+                        show: true,
+                        boost: 1.0,
+                        price: vec![("IMPRESSION".to_string(), pricing.min.clone())]
+                            .into_iter()
+                            .collect(),
+                    };
+
+                    campaign
+                        .targeting_rules
+                        .eval_with_callback(&input, &mut output, Some(on_type_error));
+                    if output.show {
+                        slot_rules.eval_with_callback(&input, &mut output, Some(on_type_error));
+                    }
+                    if !output.show {
+                        return None;
+                    }
+
+                    let price = match output.price.get("IMPRESSION") {
+                        Some(output_price) => {
+                            max(pricing.min.clone(), min(pricing.max.clone(), output_price.clone()))
+                        }
+                        None => max(pricing.min.clone(), pricing.max.clone()),
+                    };
+
+                    match min_slot_price {
+                        Some(min_slot_price) if &price < min_slot_price => None,
+                        _ => Some(UnitsWithPrice {
+                            unit: ad_unit.into(),
+                            price,
+                        }),
+                    }
+                })
+                .collect();
+
+            if units_with_price.is_empty() {
+                None
+            } else {
+                Some(response::Campaign {
+                    channel: channel.clone().into(),
+                    targeting_rules: channel_targeting_rules(channel).clone(),
+                    units_with_price,
+                })
+            }
+        })
+        .collect();
+
+    Response {
+        targeting_input_base,
+        accepted_referrers,
+        fallback_unit: fallback_unit.as_ref().map(Into::into),
+        campaigns,
+    }
+}
+
+/// The host of the ad slot website, or of its first accepted referrer
+fn slot_hostname(ad_slot_response: &AdSlotResponse) -> String {
+    ad_slot_response
+        .slot
+        .website
+        .as_deref()
+        .and_then(|website| Url::parse(website).ok())
+        .and_then(|url| url.host_str().map(ToString::to_string))
+        .or_else(|| {
+            ad_slot_response
+                .accepted_referrers
+                .first()
+                .and_then(|referrer| referrer.host_str().map(ToString::to_string))
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use primitives::{
+        targeting::text::parse_rules,
+        token::StaticPriceProvider,
+        util::tests::{
+            discard_logger,
+            prep_db::{DUMMY_AD_UNITS, DUMMY_CHANNEL, IDS},
+        },
+        AdSlot, BigNum,
+    };
+    use std::collections::HashMap;
+
+    fn ad_slot_response(rules: &str) -> AdSlotResponse {
+        AdSlotResponse {
+            slot: AdSlot {
+                ipfs: "QmVwXu9oEgYSsL6G1WZtUQy6dEReqs3Nz9iaW4Cq5QLV8C".to_string(),
+                ad_type: DUMMY_AD_UNITS[0].ad_type.clone(),
+                min_per_impression: None,
+                rules: parse_rules(rules).expect("Should parse the rules").0,
+                fallback_unit: None,
+                owner: IDS["publisher"],
+                created: Utc::now(),
+                title: None,
+                description: None,
+                website: Some("https://adex.network/blog".to_string()),
+                archived: false,
+                modified: None,
+            },
+            accepted_referrers: vec![],
+            categories: vec!["IAB3".to_string()],
+            alexa_rank: None,
+        }
+    }
+
+    fn campaign(targeting_rules: &str) -> Campaign {
+        let mut channel = DUMMY_CHANNEL.clone();
+        channel.valid_until = Utc::now() + Duration::days(1);
+        channel.spec.ad_units = DUMMY_AD_UNITS.to_vec();
+        channel.spec.ad_units[3].ad_type = "legacy_728x90".to_string();
+        channel.targeting_rules = parse_rules(targeting_rules).expect("Should parse the rules");
+
+        Campaign::new(channel, Status::Active)
+    }
+
+    fn session() -> Session {
+        Session {
+            ip: None,
+            country: Some("BG".to_string()),
+            referrer_header: None,
+            os: None,
+        }
+    }
+
+    fn get_units_for_slot(
+        ad_slot_response: AdSlotResponse,
+        campaigns: &[Campaign],
+        deposit_assets: &[String],
+    ) -> Response {
+        units_for_slot(
+            &discard_logger(),
+            &TokenRegistry::new(vec![], Arc::new(StaticPriceProvider)),
+            ad_slot_response,
+            None,
+            campaigns,
+            deposit_assets,
+            &session(),
+            Utc::now(),
+        )
+    }
+
+    #[test]
+    fn targeting_input_base_of_the_slot() {
+        let response = get_units_for_slot(ad_slot_response(""), &[], &[]);
+        let input = response.targeting_input_base;
+
+        assert_eq!(IDS["publisher"], input.global.publisher_id);
+        assert_eq!(Some("BG".to_string()), input.global.country);
+        assert_eq!(
+            Some("adex.network".to_string()),
+            input.ad_slot.map(|ad_slot| ad_slot.hostname)
+        );
+        assert!(response.campaigns.is_empty());
+    }
+
+    #[test]
+    fn only_the_units_of_the_slot_type_are_priced() {
+        let response = get_units_for_slot(
+            ad_slot_response(""),
+            &[campaign("set price.IMPRESSION = bn(\"2\")")],
+            &[],
+        );
+
+        assert_eq!(1, response.campaigns.len());
+        let units_with_price = &response.campaigns[0].units_with_price;
+        assert_eq!(3, units_with_price.len());
+        assert!(units_with_price
+            .iter()
+            .all(|unit_with_price| unit_with_price.unit.id != DUMMY_AD_UNITS[3].ipfs));
+
+        let pricing = get_pricing_bounds(&DUMMY_CHANNEL, "IMPRESSION");
+        let expected_price = max(pricing.min, min(pricing.max, BigNum::from(2)));
+        assert!(units_with_price
+            .iter()
+            .all(|unit_with_price| unit_with_price.price == expected_price));
+    }
+
+    #[test]
+    fn campaign_and_slot_rules_filter_the_units() {
+        let hidden_by_campaign = get_units_for_slot(
+            ad_slot_response(""),
+            &[campaign("onlyShowIf(country == \"US\")")],
+            &[],
+        );
+        assert!(hidden_by_campaign.campaigns.is_empty());
+
+        let shown_by_slot = get_units_for_slot(
+            ad_slot_response(&format!(
+                "onlyShowIf(advertiserId == \"{}\")",
+                DUMMY_CHANNEL.creator.to_hex_prefix_string()
+            )),
+            &[campaign("")],
+            &[],
+        );
+        assert_eq!(1, shown_by_slot.campaigns.len());
+
+        let hidden_by_slot = get_units_for_slot(
+            ad_slot_response("onlyShowIf(adSlot.hostname == \"example.com\")"),
+            &[campaign("")],
+            &[],
+        );
+        assert!(hidden_by_slot.campaigns.is_empty());
+    }
+
+    #[test]
+    fn deposit_asset_and_min_per_impression() {
+        let other_asset = get_units_for_slot(
+            ad_slot_response(""),
+            &[campaign("")],
+            &["0x0000000000000000000000000000000000000000".to_string()],
+        );
+        assert!(other_asset.campaigns.is_empty());
+
+        let mut expensive_slot = ad_slot_response("");
+        let min_per_impression: HashMap<String, BigNum> = vec![(
+            DUMMY_CHANNEL.deposit_asset.clone(),
+            DUMMY_CHANNEL.deposit_amount.clone(),
+        )]
+        .into_iter()
+        .collect();
+        expensive_slot.slot.min_per_impression = Some(min_per_impression);
+
+        let response = get_units_for_slot(
+            expensive_slot,
+            &[campaign("")],
+            &[DUMMY_CHANNEL.deposit_asset.clone()],
+        );
+        assert!(response.campaigns.is_empty());
+    }
+}
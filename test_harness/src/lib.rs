@@ -0,0 +1,351 @@
+//! In-process end-to-end test harness for the validator stack.
+//!
+//! Boots a leader & a follower `Sentry` with the `DummyAdapter` on ephemeral ports,
+//! each one with its own Postgres database, and runs the validator worker ticks against them.
+//! Postgres & Redis are expected to be running locally and are configured
+//! with the same environment variables as `Sentry`.
+#![deny(rust_2018_idioms)]
+#![deny(clippy::all)]
+
+use std::error::Error;
+use std::net::SocketAddr;
+
+use chrono::{Duration, TimeZone, Utc};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::Server;
+use reqwest::Client;
+use slog::{error, Logger};
+
+use adapter::DummyAdapter;
+use primitives::adapter::{Adapter, DummyAdapterOptions};
+use primitives::config::configuration;
+use primitives::sentry::{ChannelListResponse, Event, LastApprovedResponse, SuccessResponse};
+use primitives::token::TokenRegistry;
+use primitives::util::tests::discard_logger;
+use primitives::util::tests::prep_db::{
+    AUTH, DUMMY_CHANNEL, DUMMY_VALIDATOR_FOLLOWER, DUMMY_VALIDATOR_LEADER, IDS,
+};
+use primitives::validator::MessageTypes;
+use primitives::{BalancesMap, Channel, ChannelId, Config, ValidatorDesc, ValidatorId};
+use sentry::db::{postgres_connection_to, redis_connection, setup_database_migrations, DbPool};
+use sentry::Application;
+use validator_worker::{follower, leader, SentryApi};
+
+pub type HarnessResult<T> = Result<T, Box<dyn Error>>;
+
+pub type DummyAdapterError = <DummyAdapter as Adapter>::AdapterError;
+
+/// A validator with its own running `Sentry`
+#[derive(Debug, Clone)]
+pub struct TestValidator {
+    pub id: ValidatorId,
+    /// The token used for authenticating as this validator, see `prep_db::AUTH`
+    pub auth_token: String,
+    pub adapter: DummyAdapter,
+    pub sentry_url: String,
+    pub database: String,
+}
+
+impl TestValidator {
+    /// Starts a `Sentry` for one of the `prep_db` identities, e.g. `leader` or `follower`.
+    /// The `Sentry` database is named `harness_{name}_{identity}` and it's recreated on every start.
+    pub async fn start(
+        name: &str,
+        identity: &str,
+        config: &Config,
+        logger: &Logger,
+    ) -> HarnessResult<Self> {
+        let id = IDS[identity];
+        let database = format!("harness_{}_{}", name, identity);
+
+        recreate_database(&database).await?;
+        // the database is brand new, so there is no need for seeding it
+        setup_database_migrations("production", &database).await;
+
+        let pool = postgres_connection_to(Some(&database)).await?;
+        let redis = redis_connection().await?;
+
+        let options = DummyAdapterOptions {
+            dummy_identity: id,
+            dummy_previous_identities: vec![],
+            dummy_auth: IDS.clone(),
+            dummy_auth_tokens: AUTH.clone(),
+        };
+        let adapter = DummyAdapter::init(options, config);
+
+        let token_registry =
+            TokenRegistry::from_config(config).expect("Should load the token registry");
+        let app = Application::new(
+            adapter.clone(),
+            config.clone(),
+            token_registry,
+            logger.clone(),
+            redis,
+            pool,
+        )?;
+        let address = serve(app)?;
+
+        Ok(Self {
+            id,
+            auth_token: AUTH[identity].clone(),
+            adapter,
+            sentry_url: format!("http://{}", address),
+            database,
+        })
+    }
+
+    /// The pool of the `Sentry` database
+    pub async fn pool(&self) -> HarnessResult<DbPool> {
+        Ok(postgres_connection_to(Some(&self.database)).await?)
+    }
+
+    /// A `GET` request to the `Sentry`, e.g. `/analytics?timeframe=month`.
+    /// If provided, the request is sent with the `auth_token`.
+    pub async fn get(
+        &self,
+        path_and_query: &str,
+        auth_token: Option<&str>,
+    ) -> HarnessResult<reqwest::Response> {
+        let mut request = Client::new().get(&format!("{}{}", self.sentry_url, path_and_query));
+        if let Some(auth_token) = auth_token {
+            request = request.bearer_auth(auth_token);
+        }
+
+        Ok(request.send().await?)
+    }
+
+    /// Validates the `Channel` with `POST /channel/validate`, without creating it
+    pub async fn validate_channel(&self, channel: &Channel) -> HarnessResult<reqwest::Response> {
+        let url = format!("{}/channel/validate", self.sentry_url);
+
+        Ok(Client::new().post(&url).json(channel).send().await?)
+    }
+
+    pub async fn channel_list(&self) -> HarnessResult<ChannelListResponse> {
+        let url = format!("{}/channel/list", self.sentry_url);
+
+        Ok(Client::new()
+            .get(&url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    pub async fn last_approved(
+        &self,
+        channel_id: &ChannelId,
+    ) -> HarnessResult<LastApprovedResponse> {
+        let url = format!(
+            "{}/channel/{}/last-approved?withHeartbeat=true",
+            self.sentry_url, channel_id
+        );
+
+        Ok(Client::new()
+            .get(&url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    /// The balances of the last `NewState` approved by both validators
+    pub async fn approved_balances(
+        &self,
+        channel_id: &ChannelId,
+    ) -> HarnessResult<Option<BalancesMap>> {
+        let balances = self
+            .last_approved(channel_id)
+            .await?
+            .last_approved
+            .and_then(|last_approved| last_approved.new_state)
+            .and_then(|new_state| match new_state.msg {
+                MessageTypes::NewState(new_state) => Some(new_state.balances),
+                _ => None,
+            });
+
+        Ok(balances)
+    }
+}
+
+/// A leader & a follower validator with their running `Sentry`s
+#[derive(Debug)]
+pub struct Setup {
+    pub config: Config,
+    pub logger: Logger,
+    pub leader: TestValidator,
+    pub follower: TestValidator,
+    client: Client,
+}
+
+impl Setup {
+    /// Starts the leader & follower `Sentry`s.
+    /// The `name` should be unique for each test, as it's used for naming the databases.
+    pub async fn new(name: &str) -> HarnessResult<Self> {
+        let config = configuration("development", None).expect("Dev config should be available");
+        let logger = discard_logger();
+
+        let leader = TestValidator::start(name, "leader", &config, &logger).await?;
+        let follower = TestValidator::start(name, "follower", &config, &logger).await?;
+
+        Ok(Self {
+            config,
+            logger,
+            leader,
+            follower,
+            client: Client::new(),
+        })
+    }
+
+    /// A `Channel` validated by the harness validators, which is open for events
+    pub fn channel(&self) -> Channel {
+        let now = Utc.timestamp(Utc::now().timestamp(), 0);
+
+        let leader = ValidatorDesc {
+            url: self.leader.sentry_url.clone(),
+            ..DUMMY_VALIDATOR_LEADER.clone()
+        };
+        let follower = ValidatorDesc {
+            url: self.follower.sentry_url.clone(),
+            ..DUMMY_VALIDATOR_FOLLOWER.clone()
+        };
+
+        let mut channel = Channel {
+            valid_until: now + Duration::days(30),
+            ..DUMMY_CHANNEL.clone()
+        };
+        channel.spec.validators = (leader, follower).into();
+        channel.spec.created = now;
+        channel.spec.withdraw_period_start = now + Duration::days(20);
+
+        channel
+    }
+
+    pub fn validators(&self) -> [&TestValidator; 2] {
+        [&self.leader, &self.follower]
+    }
+
+    /// Creates the `Channel` in the `Sentry`s of both validators
+    pub async fn create_channel(&self, channel: &Channel) -> HarnessResult<()> {
+        for validator in self.validators().iter() {
+            let url = format!("{}/channel", validator.sentry_url);
+            let response = self.client.post(&url).json(channel).send().await?;
+
+            expect_success(response).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Submits the events to the `Sentry`s of both validators.
+    /// If provided, the events are submitted with the `auth_token`.
+    pub async fn submit_events(
+        &self,
+        channel_id: &ChannelId,
+        events: &[Event],
+        auth_token: Option<&str>,
+    ) -> HarnessResult<()> {
+        let body = serde_json::json!({ "events": events });
+
+        for validator in self.validators().iter() {
+            let url = format!("{}/channel/{}/events", validator.sentry_url, channel_id);
+            let mut request = self.client.post(&url).json(&body);
+            if let Some(auth_token) = auth_token {
+                request = request.bearer_auth(auth_token);
+            }
+
+            expect_success(request.send().await?).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn leader_tick(
+        &self,
+        channel: &Channel,
+    ) -> HarnessResult<leader::TickStatus<DummyAdapterError>> {
+        let sentry = self.sentry_api(&self.leader, channel).await?;
+
+        leader::tick(&sentry).await
+    }
+
+    pub async fn follower_tick(
+        &self,
+        channel: &Channel,
+    ) -> HarnessResult<follower::TickStatus<DummyAdapterError>> {
+        let sentry = self.sentry_api(&self.follower, channel).await?;
+
+        follower::tick(&sentry).await
+    }
+
+    async fn sentry_api(
+        &self,
+        validator: &TestValidator,
+        channel: &Channel,
+    ) -> HarnessResult<SentryApi<DummyAdapter>> {
+        Ok(SentryApi::init(
+            validator.adapter.clone(),
+            channel.clone(),
+            &self.config,
+            self.logger.clone(),
+        )
+        .await?)
+    }
+}
+
+async fn expect_success(response: reqwest::Response) -> HarnessResult<()> {
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await?;
+
+        return Err(format!("Sentry responded with {}: {}", status, body).into());
+    }
+
+    match response.json::<SuccessResponse>().await? {
+        SuccessResponse { success: true } => Ok(()),
+        _ => Err("Sentry responded with an unsuccessful response".into()),
+    }
+}
+
+/// Drops the database if it exists and creates it again
+async fn recreate_database(database: &str) -> HarnessResult<()> {
+    let pool: DbPool = postgres_connection_to(None).await?;
+    let client = pool.get().await?;
+
+    client
+        .simple_query(&format!("DROP DATABASE IF EXISTS {}", database))
+        .await?;
+    client
+        .simple_query(&format!("CREATE DATABASE {}", database))
+        .await?;
+
+    Ok(())
+}
+
+/// Serves the `Application` on an ephemeral port and returns its address
+fn serve<A: Adapter + 'static>(app: Application<A>) -> HarnessResult<SocketAddr> {
+    let logger = app.logger.clone();
+
+    let make_service = make_service_fn(move |_| {
+        let server = app.clone();
+        async move {
+            Ok::<_, hyper::Error>(service_fn(move |req| {
+                let server = server.clone();
+                async move { Ok::<_, hyper::Error>(server.handle_routing(req).await) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&([127, 0, 0, 1], 0).into())?.serve(make_service);
+    let address = server.local_addr();
+
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!(&logger, "server error: {}", e; "module" => "test_harness");
+        }
+    });
+
+    Ok(address)
+}
@@ -0,0 +1,55 @@
+use primitives::adapter::ChannelState;
+use sentry::chain_watcher;
+use sentry::db::update_channel_chain_state;
+use test_harness::Setup;
+
+#[tokio::test(threaded_scheduler)]
+async fn channels_never_opened_on_chain_are_hidden_from_the_list() {
+    let setup = Setup::new("chain_sync")
+        .await
+        .expect("Should start the validators");
+
+    let channel = setup.channel();
+    setup
+        .create_channel(&channel)
+        .await
+        .expect("Should create the channel");
+
+    let leader = &setup.leader;
+    let pool = leader.pool().await.expect("Should connect to the database");
+
+    // the dummy adapter has no logs, so the state of the channel is looked up
+    let status = chain_watcher::sync(
+        &leader.adapter,
+        &setup.config,
+        &setup.config.chains[0],
+        &pool,
+    )
+    .await
+    .expect("Should sync the channel states");
+    assert_eq!(None, status.last_block);
+    assert_eq!(1, status.looked_up);
+
+    let list = leader.channel_list().await.expect("Should list channels");
+    let listed: Vec<_> = list.channels.iter().map(|channel| channel.id).collect();
+    assert_eq!(vec![channel.id], listed);
+
+    // e.g. the block opening the channel was reorged out
+    update_channel_chain_state(&pool, &channel.id, ChannelState::Unknown)
+        .await
+        .expect("Should update the channel state");
+
+    let list = leader.channel_list().await.expect("Should list channels");
+    assert!(list.channels.is_empty(), "The channel should be hidden");
+
+    // the channel state is already synced, so it's not looked up again
+    let status = chain_watcher::sync(
+        &leader.adapter,
+        &setup.config,
+        &setup.config.chains[0],
+        &pool,
+    )
+    .await
+    .expect("Should sync the channel states");
+    assert_eq!(0, status.looked_up);
+}
@@ -0,0 +1,172 @@
+use chrono::{Duration, TimeZone, Utc};
+use primitives::sentry::channel_list::{ChannelListCursor, ChannelListQuery, ChannelListSort};
+use primitives::sentry::ChannelListResponse;
+use primitives::{BigNum, Channel, ChannelId};
+use sentry::db::{insert_channel, list_channels, DbPool};
+use test_harness::Setup;
+
+/// A channel with the id `[id; 32]`, created `created` minutes after the others
+fn channel(setup: &Setup, id: u8, created: i64, deposit: u64) -> Channel {
+    let mut channel = setup.channel();
+    channel.id = ChannelId::from([id; 32]);
+    channel.deposit_amount = BigNum::from(deposit);
+    channel.spec.created = Utc.timestamp(1_600_000_000, 0) + Duration::minutes(created);
+
+    channel
+}
+
+async fn insert(pool: &DbPool, channels: &[Channel]) {
+    for channel in channels {
+        insert_channel(pool, channel)
+            .await
+            .expect("Should insert the channel");
+    }
+}
+
+/// The ids of the channels on the pages of `limit` channels after the `cursor`
+async fn pages(
+    pool: &DbPool,
+    sort: ChannelListSort,
+    mut cursor: Option<ChannelListCursor>,
+    limit: u32,
+) -> Vec<Vec<ChannelId>> {
+    let mut pages = vec![];
+
+    loop {
+        let query = ChannelListQuery {
+            sort,
+            cursor: cursor.take(),
+            ..Default::default()
+        };
+        let page = list_channels(pool, 0, limit, &query)
+            .await
+            .expect("Should list the channels");
+        pages.push(page.channels.iter().map(|channel| channel.id).collect());
+
+        match page.next {
+            Some(next) => cursor = Some(next),
+            None => break pages,
+        }
+    }
+}
+
+fn ids(ids: &[u8]) -> Vec<ChannelId> {
+    ids.iter().map(|id| ChannelId::from([*id; 32])).collect()
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn pages_through_the_channels_with_the_same_sort_value() {
+    let setup = Setup::new("channel_list_ties")
+        .await
+        .expect("Should start the validators");
+    let pool = setup
+        .leader
+        .pool()
+        .await
+        .expect("Should connect to Postgres");
+
+    let channels = vec![
+        channel(&setup, 1, 2, 100),
+        channel(&setup, 2, 0, 300),
+        channel(&setup, 3, 2, 100),
+        channel(&setup, 4, 1, 200),
+        channel(&setup, 5, 0, 300),
+        channel(&setup, 6, 1, 100),
+        channel(&setup, 7, 2, 200),
+    ];
+    insert(&pool, &channels).await;
+
+    // the channels with the same value are ordered by their id
+    assert_eq!(
+        vec![ids(&[7, 3]), ids(&[1, 6]), ids(&[4, 5]), ids(&[2])],
+        pages(&pool, ChannelListSort::Created, None, 2).await
+    );
+    assert_eq!(
+        vec![ids(&[5, 2]), ids(&[7, 4]), ids(&[6, 3]), ids(&[1])],
+        pages(&pool, ChannelListSort::Deposit, None, 2).await
+    );
+
+    // the channel statuses don't change the order
+    let by_status: ChannelListResponse = setup
+        .leader
+        .get("/channel/list?status=Initializing", None)
+        .await
+        .expect("Should list the channels")
+        .error_for_status()
+        .expect("Should list the channels")
+        .json()
+        .await
+        .expect("Should parse the channels");
+    assert_eq!(
+        ids(&[7, 3, 1, 6, 4, 5, 2]),
+        by_status
+            .channels
+            .iter()
+            .map(|channel| channel.id)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(None, by_status.next);
+
+    let by_status: ChannelListResponse = setup
+        .leader
+        .get("/channel/list?status=Active", None)
+        .await
+        .expect("Should list the channels")
+        .json()
+        .await
+        .expect("Should parse the channels");
+    assert!(by_status.channels.is_empty());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn the_pages_after_a_cursor_dont_change_when_channels_are_inserted() {
+    let setup = Setup::new("channel_list_inserts")
+        .await
+        .expect("Should start the validators");
+    let pool = setup
+        .leader
+        .pool()
+        .await
+        .expect("Should connect to Postgres");
+
+    let channels = vec![
+        channel(&setup, 1, 2, 100),
+        channel(&setup, 2, 0, 300),
+        channel(&setup, 3, 2, 100),
+        channel(&setup, 4, 1, 200),
+        channel(&setup, 5, 0, 300),
+        channel(&setup, 6, 1, 100),
+        channel(&setup, 7, 2, 200),
+    ];
+    insert(&pool, &channels).await;
+
+    let query = ChannelListQuery::default();
+    let first_page = list_channels(&pool, 0, 3, &query)
+        .await
+        .expect("Should list the channels");
+    assert_eq!(
+        ids(&[7, 3, 1]),
+        first_page
+            .channels
+            .iter()
+            .map(|channel| channel.id)
+            .collect::<Vec<_>>()
+    );
+
+    // a newer channel and one with the same `created` as the end of the page are before it,
+    // the older channel is after it
+    insert(
+        &pool,
+        &[
+            channel(&setup, 8, 3, 100),
+            channel(&setup, 9, 2, 100),
+            channel(&setup, 10, 0, 100),
+        ],
+    )
+    .await;
+
+    assert_eq!(
+        vec![ids(&[6, 4, 10]), ids(&[5, 2])],
+        pages(&pool, ChannelListSort::Created, first_page.next, 3).await
+    );
+}
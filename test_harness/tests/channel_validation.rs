@@ -0,0 +1,66 @@
+use primitives::sentry::{ChannelValidationResponse, ValidationErrorResponse};
+use primitives::targeting::Rules;
+use reqwest::StatusCode;
+use serde_json::json;
+use test_harness::Setup;
+
+fn rules(json: serde_json::Value) -> Rules {
+    serde_json::from_value(json).expect("Should deserialize the rules")
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn rules_without_an_effect_are_only_warnings() {
+    let setup = Setup::new("channel_validation")
+        .await
+        .expect("Should start the validators");
+
+    let mut channel = setup.channel();
+    channel.spec.targeting_rules = rules(json!([
+        { "eq": [{ "get": "country" }, "BG"] },
+        { "onlyShowIf": { "eq": [{ "get": "country" }, "BG"] } },
+    ]));
+
+    let response = setup
+        .leader
+        .validate_channel(&channel)
+        .await
+        .expect("Should validate the channel");
+    assert_eq!(StatusCode::OK, response.status());
+
+    let validation: ChannelValidationResponse =
+        response.json().await.expect("Should parse the response");
+    assert!(validation.success);
+    assert_eq!(
+        vec!["spec.targetingRules[0]: the rule never sets `show` or another output variable, so it has no effect"],
+        validation.warnings
+    );
+
+    // the channel with warnings is still created
+    setup
+        .create_channel(&channel)
+        .await
+        .expect("Should create the channel");
+
+    // the warnings are reported together with the errors of the invalid rules
+    channel.spec.targeting_rules = rules(json!([
+        { "eq": [{ "get": "country" }, "BG"] },
+        { "set": ["boost", true] },
+    ]));
+
+    let response = setup
+        .leader
+        .validate_channel(&channel)
+        .await
+        .expect("Should validate the channel");
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+
+    let validation: ValidationErrorResponse =
+        response.json().await.expect("Should parse the response");
+    assert_eq!(
+        vec![
+            "spec.targetingRules[0]: the rule never sets `show` or another output variable, so it has no effect",
+            "spec.targetingRules[1].set[1]: expected number, found bool",
+        ],
+        validation.validation
+    );
+}
@@ -0,0 +1,210 @@
+use chrono::{Duration, Utc};
+use primitives::sentry::Event;
+use primitives::util::tests::prep_db::{AUTH, IDS};
+use primitives::ChannelId;
+use reqwest::StatusCode;
+use sentry::compaction::compact;
+use sentry::db::DbPool;
+use test_harness::{Setup, TestValidator};
+
+fn impressions(count: usize) -> Vec<Event> {
+    (0..count)
+        .map(|_| Event::Impression {
+            publisher: IDS["publisher"],
+            ad_unit: None,
+            ad_slot: None,
+            referrer: None,
+        })
+        .collect()
+}
+
+/// The number of the event aggregates of the channel which are not compacted
+async fn aggregates_count(pool: &DbPool, channel_id: &ChannelId) -> i64 {
+    let connection = pool.get().await.expect("Should get a connection");
+
+    connection
+        .query_one(
+            "SELECT COUNT(*) FROM event_aggregates WHERE channel_id = $1",
+            &[channel_id],
+        )
+        .await
+        .expect("Should count the event aggregates")
+        .get(0)
+}
+
+/// The publisher's impression counts & payouts by day, including the daily summaries
+async fn publisher_analytics(validator: &TestValidator) -> Vec<serde_json::Value> {
+    let mut analytics = vec![];
+
+    for metric in ["eventCounts", "eventPayouts"].iter() {
+        let path = format!("/analytics/for-publisher?timeframe=month&metric={}", metric);
+        let response = validator
+            .get(&path, Some(&AUTH["publisher"]))
+            .await
+            .expect("Should request the analytics")
+            .error_for_status()
+            .expect("Should get the analytics");
+
+        analytics.push(response.json().await.expect("Should parse the analytics"));
+    }
+
+    analytics
+}
+
+/// A time when all of the aggregates are older than the retention period
+fn after_the_retention_period() -> chrono::DateTime<Utc> {
+    Utc::now() + Duration::days(365)
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn nothing_is_compacted_without_an_approved_new_state() {
+    let setup = Setup::new("compaction_unapproved")
+        .await
+        .expect("Should start the validators");
+    let pool = setup
+        .leader
+        .pool()
+        .await
+        .expect("Should connect to Postgres");
+
+    let channel = setup.channel();
+    setup
+        .create_channel(&channel)
+        .await
+        .expect("Should create the channel");
+    setup
+        .submit_events(&channel.id, &impressions(10), None)
+        .await
+        .expect("Should submit the events");
+
+    // the leader proposes a `NewState`, but the follower doesn't approve it yet
+    setup
+        .leader_tick(&channel)
+        .await
+        .expect("Leader tick should succeed");
+    let aggregates = aggregates_count(&pool, &channel.id).await;
+    assert!(aggregates > 0, "The events should be aggregated");
+
+    let status = compact(&pool, &setup.config, after_the_retention_period())
+        .await
+        .expect("Should compact the event aggregates");
+
+    assert_eq!(0, status.aggregates);
+    assert_eq!(aggregates, aggregates_count(&pool, &channel.id).await);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn only_the_aggregates_accounted_by_both_validators_are_compacted() {
+    let setup = Setup::new("compaction_accounted")
+        .await
+        .expect("Should start the validators");
+    let pool = setup
+        .leader
+        .pool()
+        .await
+        .expect("Should connect to Postgres");
+
+    let channel = setup.channel();
+    setup
+        .create_channel(&channel)
+        .await
+        .expect("Should create the channel");
+
+    setup
+        .submit_events(&channel.id, &impressions(10), None)
+        .await
+        .expect("Should submit the events");
+    setup
+        .leader_tick(&channel)
+        .await
+        .expect("Leader tick should succeed");
+    setup
+        .follower_tick(&channel)
+        .await
+        .expect("Follower tick should succeed");
+    let approved_aggregates = aggregates_count(&pool, &channel.id).await;
+
+    // only the leader's `Accounting` includes the new aggregates
+    // and its `NewState` is not approved yet
+    setup
+        .submit_events(&channel.id, &impressions(5), None)
+        .await
+        .expect("Should submit the events");
+    setup
+        .leader_tick(&channel)
+        .await
+        .expect("Leader tick should succeed");
+    let new_aggregates = aggregates_count(&pool, &channel.id).await - approved_aggregates;
+    assert!(new_aggregates > 0, "The new events should be aggregated");
+
+    let analytics = publisher_analytics(&setup.leader).await;
+
+    let status = compact(&pool, &setup.config, after_the_retention_period())
+        .await
+        .expect("Should compact the event aggregates");
+    assert_eq!(approved_aggregates as u64, status.aggregates);
+    assert_eq!(new_aggregates, aggregates_count(&pool, &channel.id).await);
+    assert_eq!(
+        analytics,
+        publisher_analytics(&setup.leader).await,
+        "The analytics should be the same after the compaction"
+    );
+
+    // the follower accounts for the new aggregates as well and approves the `NewState`
+    setup
+        .follower_tick(&channel)
+        .await
+        .expect("Follower tick should succeed");
+
+    let status = compact(&pool, &setup.config, after_the_retention_period())
+        .await
+        .expect("Should compact the event aggregates");
+    assert_eq!(new_aggregates as u64, status.aggregates);
+    assert_eq!(0, aggregates_count(&pool, &channel.id).await);
+    assert_eq!(
+        analytics,
+        publisher_analytics(&setup.leader).await,
+        "The analytics should be the same after the compaction"
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn the_compacted_range_has_only_whole_day_buckets_in_utc() {
+    let setup = Setup::new("compaction_buckets")
+        .await
+        .expect("Should start the validators");
+
+    let now = Utc::now();
+    let retention = Duration::days(setup.config.event_aggregates_retention_days.into());
+    let compacted = (
+        (now - retention - Duration::days(2)).timestamp_millis(),
+        (now - retention - Duration::days(1)).timestamp_millis(),
+    );
+    let retained = (
+        (now - Duration::days(2)).timestamp_millis(),
+        (now - Duration::days(1)).timestamp_millis(),
+    );
+
+    let cases = vec![
+        (compacted, "bucket=1d", StatusCode::OK),
+        (compacted, "bucket=6h", StatusCode::BAD_REQUEST),
+        (
+            compacted,
+            "bucket=1d&timezone=Europe/Sofia",
+            StatusCode::BAD_REQUEST,
+        ),
+        (retained, "bucket=6h", StatusCode::OK),
+        (retained, "bucket=1d&timezone=Europe/Sofia", StatusCode::OK),
+    ];
+
+    for ((start, end), buckets, expected) in cases {
+        let path = format!("/analytics?start={}&end={}&{}", start, end, buckets);
+        let response = setup
+            .leader
+            .get(&path, None)
+            .await
+            .expect("Should request the analytics");
+
+        assert_eq!(expected, response.status(), "{}", path);
+    }
+}
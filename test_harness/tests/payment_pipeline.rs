@@ -0,0 +1,84 @@
+use primitives::sentry::Event;
+use primitives::util::tests::prep_db::IDS;
+use primitives::validator::MessageTypes;
+use primitives::BigNum;
+use test_harness::Setup;
+use validator_worker::follower::ApproveStateResult;
+use validator_worker::producer;
+
+#[tokio::test(threaded_scheduler)]
+async fn impressions_are_paid_and_approved_by_both_validators() {
+    let setup = Setup::new("payment_pipeline")
+        .await
+        .expect("Should start the validators");
+
+    let channel = setup.channel();
+    setup
+        .create_channel(&channel)
+        .await
+        .expect("Should create the channel");
+
+    let impressions: Vec<Event> = (0..50)
+        .map(|_| Event::Impression {
+            publisher: IDS["publisher"],
+            ad_unit: None,
+            ad_slot: None,
+            referrer: None,
+        })
+        .collect();
+    setup
+        .submit_events(&channel.id, &impressions, None)
+        .await
+        .expect("Should submit the events");
+
+    let leader_tick = setup
+        .leader_tick(&channel)
+        .await
+        .expect("Leader tick should succeed");
+    match &leader_tick.producer_tick {
+        producer::TickStatus::Sent { event_counts, .. } => assert_eq!(&1, event_counts),
+        other => panic!("Expected new Accounting to be sent, got: {:?}", other),
+    }
+    assert!(
+        leader_tick.new_state.is_some(),
+        "Leader should propagate a NewState"
+    );
+
+    let follower_tick = setup
+        .follower_tick(&channel)
+        .await
+        .expect("Follower tick should succeed");
+    match &follower_tick.approve_state {
+        ApproveStateResult::Sent(Some(_)) => {}
+        other => panic!("Expected the NewState to be approved, got: {:?}", other),
+    }
+
+    for validator in setup.validators().iter() {
+        let last_approved = validator
+            .last_approved(&channel.id)
+            .await
+            .expect("Should get last approved")
+            .last_approved
+            .expect("Should have an approved state");
+
+        match last_approved.approve_state.map(|approve| approve.msg) {
+            Some(MessageTypes::ApproveState(approve_state)) => {
+                assert!(approve_state.is_healthy, "ApproveState should be healthy")
+            }
+            other => panic!("Expected an ApproveState, got: {:?}", other),
+        }
+
+        let balances = validator
+            .approved_balances(&channel.id)
+            .await
+            .expect("Should get approved balances")
+            .expect("Should have approved balances");
+
+        // 50 impressions paid with `min_per_impression` of 1 out of the 1 000 deposit,
+        // from which 200 are the validators fees
+        assert_eq!(BigNum::from(50), balances.values().sum::<BigNum>());
+        assert_eq!(BigNum::from(40), balances[&IDS["publisher"]]);
+        assert_eq!(BigNum::from(5), balances[&IDS["leader"]]);
+        assert_eq!(BigNum::from(5), balances[&IDS["follower"]]);
+    }
+}
@@ -0,0 +1,181 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use primitives::sentry::{
+    validator_message_list::ValidatorMessagesCursor, ValidatorMessageResponse,
+};
+use primitives::validator::{Heartbeat, MessageTypes};
+use primitives::{ChannelId, ValidatorId};
+use sentry::db::{get_validator_messages, DbPool};
+use test_harness::{Setup, TestValidator};
+
+/// Inserts a `Heartbeat` with the `signature` received at `received`
+async fn insert_heartbeat(
+    pool: &DbPool,
+    channel_id: &ChannelId,
+    from: &ValidatorId,
+    signature: &str,
+    received: DateTime<Utc>,
+) {
+    let heartbeat = MessageTypes::Heartbeat(Heartbeat {
+        signature: signature.to_string(),
+        state_root: String::new(),
+        timestamp: received,
+    });
+    let connection = pool.get().await.expect("Should get a connection");
+
+    connection
+        .execute(
+            r#"INSERT INTO validator_messages (channel_id, "from", msg, received) VALUES ($1, $2, $3, $4)"#,
+            &[channel_id, from, &heartbeat, &received],
+        )
+        .await
+        .expect("Should insert the Heartbeat");
+}
+
+fn signatures(response: &ValidatorMessageResponse) -> Vec<String> {
+    response
+        .validator_messages
+        .iter()
+        .map(|message| match &message.msg {
+            MessageTypes::Heartbeat(heartbeat) => heartbeat.signature.clone(),
+            other => panic!("Expected a Heartbeat, got: {:?}", other),
+        })
+        .collect()
+}
+
+async fn list_messages(
+    validator: &TestValidator,
+    channel_id: &ChannelId,
+    query: &str,
+) -> ValidatorMessageResponse {
+    let path = format!("/channel/{}/validator-messages?{}", channel_id, query);
+
+    validator
+        .get(&path, None)
+        .await
+        .expect("Should request the validator messages")
+        .error_for_status()
+        .expect("Should list the validator messages")
+        .json()
+        .await
+        .expect("Should parse the validator messages")
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn pages_through_the_messages_received_at_the_same_time() {
+    let setup = Setup::new("validator_messages")
+        .await
+        .expect("Should start the validators");
+    let leader = &setup.leader;
+    let pool = leader.pool().await.expect("Should connect to Postgres");
+
+    let channel = setup.channel();
+    setup
+        .create_channel(&channel)
+        .await
+        .expect("Should create the channel");
+
+    // 3 of the messages are received at the same time
+    let first = Utc.timestamp(Utc::now().timestamp(), 0) - Duration::minutes(10);
+    let second = first + Duration::minutes(1);
+    let third = first + Duration::minutes(2);
+    for (signature, received) in vec![
+        ("a", first),
+        ("b", first),
+        ("c", first),
+        ("d", second),
+        ("e", third),
+    ] {
+        insert_heartbeat(&pool, &channel.id, &leader.id, signature, received).await;
+    }
+
+    // the database pages
+    let heartbeat = vec!["Heartbeat".to_string()];
+    let mut cursor: Option<ValidatorMessagesCursor> = None;
+    let mut pages = vec![];
+    loop {
+        let (messages, next) = get_validator_messages(
+            &pool,
+            &channel.id,
+            &Some(leader.id),
+            &heartbeat,
+            &None,
+            &None,
+            &cursor,
+            2,
+        )
+        .await
+        .expect("Should get the validator messages");
+
+        let page = ValidatorMessageResponse {
+            validator_messages: messages,
+            next: next.clone(),
+        };
+        pages.push(signatures(&page));
+
+        match next {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+    assert_eq!(
+        vec![vec!["e", "d"], vec!["c", "b"], vec!["a"]],
+        pages,
+        "The pages should have no duplicates or gaps"
+    );
+
+    // the route pages
+    let mut query = "limit=2".to_string();
+    let mut pages = vec![];
+    loop {
+        let response = list_messages(leader, &channel.id, &query).await;
+        pages.push(signatures(&response));
+
+        match response.next {
+            Some(next) => query = format!("limit=2&cursor={}", next),
+            None => break,
+        }
+    }
+    assert_eq!(vec![vec!["e", "d"], vec!["c", "b"], vec!["a"]], pages);
+
+    // a full last page has no next page
+    let response = list_messages(leader, &channel.id, "limit=5").await;
+    assert_eq!(vec!["e", "d", "c", "b", "a"], signatures(&response));
+    assert_eq!(None, response.next);
+
+    let before = list_messages(
+        leader,
+        &channel.id,
+        &format!("before={}", second.timestamp_millis()),
+    )
+    .await;
+    assert_eq!(vec!["c", "b", "a"], signatures(&before));
+
+    let after = list_messages(
+        leader,
+        &channel.id,
+        &format!("after={}", first.timestamp_millis()),
+    )
+    .await;
+    assert_eq!(vec!["e", "d"], signatures(&after));
+
+    // the cursor continues within the range
+    let page = list_messages(
+        leader,
+        &channel.id,
+        &format!("limit=1&before={}", third.timestamp_millis()),
+    )
+    .await;
+    assert_eq!(vec!["d"], signatures(&page));
+    let next = page.next.expect("Should have a next page");
+    let page = list_messages(
+        leader,
+        &channel.id,
+        &format!(
+            "limit=1&before={}&cursor={}",
+            third.timestamp_millis(),
+            next
+        ),
+    )
+    .await;
+    assert_eq!(vec!["c"], signatures(&page));
+}
@@ -3,15 +3,15 @@ use std::fmt;
 
 use primitives::adapter::{Adapter, AdapterErrorKind};
 use primitives::validator::{ApproveState, MessageTypes, NewState, RejectState};
-use primitives::{BalancesMap, BigNum};
+use primitives::{BalancesMap, BigNum, Channel, ChannelId, Config};
 
 use crate::core::follower_rules::{get_health, is_valid_transition};
 use crate::heartbeat::{heartbeat, HeartbeatStatus};
 use crate::sentry_interface::{PropagationResult, SentryApi};
-use crate::{get_state_root_hash, producer};
+use crate::{get_channel_state_root_hash, producer};
 use chrono::Utc;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InvalidNewState {
     RootHash,
     Signature,
@@ -104,11 +104,12 @@ async fn on_new_state<'a, A: Adapter + 'static>(
 ) -> Result<ApproveStateResult<A::AdapterError>, Box<dyn Error>> {
     let proposed_balances = new_state.balances.clone();
     let proposed_state_root = new_state.state_root.clone();
-    if proposed_state_root != hex::encode(get_state_root_hash(&iface, &proposed_balances)?) {
+    if !is_valid_state_root(&iface.channel.id, &new_state)? {
         return Ok(on_error(&iface, &new_state, InvalidNewState::RootHash).await);
     }
 
     if !iface.adapter.verify(
+        &iface.channel,
         &iface.channel.spec.validators.leader().id,
         &proposed_state_root,
         &new_state.signature,
@@ -128,16 +129,21 @@ async fn on_new_state<'a, A: Adapter + 'static>(
         _ => Default::default(),
     };
 
-    if !is_valid_transition(&iface.channel, &prev_balances, &proposed_balances) {
-        return Ok(on_error(&iface, &new_state, InvalidNewState::Transition).await);
-    }
-
-    let health = get_health(&iface.channel, balances, &proposed_balances);
-    if health < u64::from(iface.config.health_unsignable_promilles) {
-        return Ok(on_error(&iface, &new_state, InvalidNewState::Health).await);
-    }
+    let health = match check_new_state_balances(
+        &iface.channel,
+        &iface.config,
+        balances,
+        &prev_balances,
+        &proposed_balances,
+    ) {
+        Ok(health) => health,
+        Err(invalid) => return Ok(on_error(&iface, &new_state, invalid).await),
+    };
 
-    let signature = iface.adapter.sign(&new_state.state_root)?;
+    let signature = iface
+        .adapter
+        .sign(&iface.channel, &new_state.state_root)
+        .await?;
     let health_threshold = u64::from(iface.config.health_threshold_promilles);
     let is_healthy = health >= health_threshold;
     let exhausted = proposed_balances.values().sum::<BigNum>() == iface.channel.deposit_amount;
@@ -154,6 +160,37 @@ async fn on_new_state<'a, A: Adapter + 'static>(
     Ok(ApproveStateResult::Sent(Some(propagation_result)))
 }
 
+/// Checks that the `NewState` state root is the one of its proposed balances
+pub(crate) fn is_valid_state_root(
+    channel_id: &ChannelId,
+    new_state: &NewState,
+) -> Result<bool, Box<dyn Error>> {
+    let state_root = get_channel_state_root_hash(channel_id, &new_state.balances)?;
+
+    Ok(new_state.state_root == hex::encode(state_root))
+}
+
+/// Checks the proposed balances against the previously approved ones and our own balances.
+/// Returns the health of the proposed balances if they can be signed.
+pub(crate) fn check_new_state_balances(
+    channel: &Channel,
+    config: &Config,
+    balances: &BalancesMap,
+    prev_balances: &BalancesMap,
+    proposed_balances: &BalancesMap,
+) -> Result<u64, InvalidNewState> {
+    if !is_valid_transition(channel, prev_balances, proposed_balances) {
+        return Err(InvalidNewState::Transition);
+    }
+
+    let health = get_health(channel, balances, proposed_balances);
+    if health < u64::from(config.health_unsignable_promilles) {
+        return Err(InvalidNewState::Health);
+    }
+
+    Ok(health)
+}
+
 async fn on_error<'a, A: Adapter + 'static>(
     iface: &'a SentryApi<A>,
     new_state: &'a NewState,
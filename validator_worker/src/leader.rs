@@ -46,7 +46,7 @@ async fn on_new_accounting<A: Adapter + 'static>(
     let state_root_raw = get_state_root_hash(&iface, &new_accounting.balances)?;
     let state_root = hex::encode(state_root_raw);
 
-    let signature = iface.adapter.sign(&state_root)?;
+    let signature = iface.adapter.sign(&iface.channel, &state_root).await?;
 
     let exhausted =
         new_accounting.balances.values().sum::<BigNum>() == iface.channel.deposit_amount;
@@ -6,7 +6,7 @@ use std::error::Error;
 use adapter::{get_balance_leaf, get_signable_state_root};
 use primitives::adapter::Adapter;
 use primitives::merkle_tree::MerkleTree;
-use primitives::BalancesMap;
+use primitives::{BalancesMap, ChannelId};
 
 pub use self::sentry_interface::{all_channels, SentryApi};
 
@@ -15,6 +15,7 @@ pub mod follower;
 pub mod heartbeat;
 pub mod leader;
 pub mod producer;
+pub mod replay;
 pub mod sentry_interface;
 
 pub mod core {
@@ -26,6 +27,13 @@ pub mod core {
 pub(crate) fn get_state_root_hash<A: Adapter + 'static>(
     iface: &SentryApi<A>,
     balances: &BalancesMap,
+) -> Result<[u8; 32], Box<dyn Error>> {
+    get_channel_state_root_hash(&iface.channel.id, balances)
+}
+
+pub(crate) fn get_channel_state_root_hash(
+    channel_id: &ChannelId,
+    balances: &BalancesMap,
 ) -> Result<[u8; 32], Box<dyn Error>> {
     // Note: MerkleTree takes care of deduplicating and sorting
     let elems: Vec<[u8; 32]> = balances
@@ -35,7 +43,7 @@ pub(crate) fn get_state_root_hash<A: Adapter + 'static>(
 
     let tree = MerkleTree::new(&elems)?;
     // keccak256(channelId, balanceRoot
-    get_signable_state_root(iface.channel.id.as_ref(), &tree.root())
+    get_signable_state_root(channel_id.as_ref(), &tree.root())
 }
 
 #[cfg(test)]
@@ -49,9 +57,10 @@ mod test {
     use primitives::{BalancesMap, Channel};
     use slog::{o, Discard, Logger};
 
-    fn setup_iface(channel: &Channel) -> SentryApi<DummyAdapter> {
+    async fn setup_iface(channel: &Channel) -> SentryApi<DummyAdapter> {
         let adapter_options = DummyAdapterOptions {
             dummy_identity: IDS["leader"].clone(),
+            dummy_previous_identities: vec![],
             dummy_auth: IDS.clone(),
             dummy_auth_tokens: AUTH.clone(),
         };
@@ -59,14 +68,16 @@ mod test {
         let dummy_adapter = DummyAdapter::init(adapter_options, &config);
         let logger = Logger::root(Discard, o!());
 
-        SentryApi::init(dummy_adapter, channel.clone(), &config, logger).expect("should succeed")
+        SentryApi::init(dummy_adapter, channel.clone(), &config, logger)
+            .await
+            .expect("should succeed")
     }
 
-    #[test]
-    fn get_state_root_hash_returns_correct_hash_aligning_with_js_impl() {
+    #[tokio::test]
+    async fn get_state_root_hash_returns_correct_hash_aligning_with_js_impl() {
         let channel = DUMMY_CHANNEL.clone();
 
-        let iface = setup_iface(&channel);
+        let iface = setup_iface(&channel).await;
 
         let balances: BalancesMap = vec![
             (IDS["publisher"].clone(), 1.into()),
@@ -84,11 +95,11 @@ mod test {
         );
     }
 
-    #[test]
-    fn get_state_root_hash_returns_correct_hash_for_fake_channel_aligning_with_js_impl() {
+    #[tokio::test]
+    async fn get_state_root_hash_returns_correct_hash_for_fake_channel_aligning_with_js_impl() {
         let channel = DUMMY_CHANNEL.clone();
 
-        let iface = setup_iface(&channel);
+        let iface = setup_iface(&channel).await;
 
         let balances: BalancesMap = vec![(IDS["publisher"].clone(), 0.into())]
             .into_iter()
@@ -102,4 +113,27 @@ mod test {
             hex::encode(actual_hash)
         );
     }
+
+    #[tokio::test]
+    async fn sentry_api_uses_the_previous_identity_validating_the_channel() {
+        let adapter_options = DummyAdapterOptions {
+            dummy_identity: IDS["tester"],
+            dummy_previous_identities: vec![IDS["follower"]],
+            dummy_auth: IDS.clone(),
+            dummy_auth_tokens: AUTH.clone(),
+        };
+        let config = configuration("development", None).expect("Dev config should be available");
+        let dummy_adapter = DummyAdapter::init(adapter_options, &config);
+        let logger = Logger::root(Discard, o!());
+
+        let iface = SentryApi::init(dummy_adapter, DUMMY_CHANNEL.clone(), &config, logger)
+            .await
+            .expect("should succeed");
+
+        assert_eq!(IDS["follower"], iface.whoami);
+        assert!(iface
+            .propagate_to
+            .iter()
+            .all(|(_, auth_token)| auth_token == &AUTH["follower"]));
+    }
 }
@@ -1,6 +1,7 @@
 #![deny(rust_2018_idioms)]
 #![deny(clippy::all)]
 
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::error::Error;
 use std::time::Duration;
@@ -10,14 +11,17 @@ use futures::future::{join, join_all};
 use tokio::runtime::Runtime;
 use tokio::time::{delay_for, timeout};
 
-use adapter::{AdapterTypes, DummyAdapter, EthereumAdapter};
-use primitives::adapter::{Adapter, DummyAdapterOptions, KeystoreOptions};
+use adapter::{AdapterTypes, DummyAdapter, EthereumAdapter, RemoteAdapter};
+use primitives::adapter::{
+    Adapter, ChannelState, DummyAdapterOptions, KeystoreOptions, RemoteSignerOptions,
+};
 use primitives::config::{configuration, Config};
 use primitives::util::tests::prep_db::{AUTH, IDS};
-use primitives::{Channel, ChannelId, SpecValidator, ValidatorId};
-use slog::{error, info, Logger};
+use primitives::{ChainId, Channel, ChannelId, SpecValidator, ValidatorId};
+use slog::{error, info, warn, Logger};
 use std::fmt::Debug;
 use validator_worker::error::{Error as ValidatorWorkerError, TickError};
+use validator_worker::replay::{load_dump, replay_channel};
 use validator_worker::{all_channels, follower, leader, SentryApi};
 
 #[derive(Debug, Clone)]
@@ -42,15 +46,17 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .help("the adapter for authentication and signing")
                 .required(true)
                 .default_value("ethereum")
-                .possible_values(&["ethereum", "dummy"])
+                .possible_values(&["ethereum", "dummy", "remote"])
                 .takes_value(true),
         )
         .arg(
             Arg::with_name("keystoreFile")
                 .long("keystoreFile")
                 .short("k")
-                .help("path to the JSON Ethereum Keystore file")
-                .takes_value(true),
+                .help("path to the JSON Ethereum Keystore file, can be repeated for keeping the previous keys when rotating, the first one is the current key")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
         )
         .arg(
             Arg::with_name("dummyIdentity")
@@ -59,6 +65,24 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .help("the identity to use with the dummy adapter")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("remoteSignerUrl")
+                .long("remoteSignerUrl")
+                .help("the JSON-RPC endpoint of the signer used by the remote adapter")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("remoteSignerIdentity")
+                .long("remoteSignerIdentity")
+                .help("path to the PKCS #12 client certificate & key for mutual TLS with the remote signer")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("remoteSignerCa")
+                .long("remoteSignerCa")
+                .help("path to the PEM CA certificate of the remote signer")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("sentryUrl")
                 .long("sentryUrl")
@@ -75,6 +99,13 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .takes_value(false)
                 .help("runs the validator in single-tick mode and exit"),
         )
+        .arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .short("r")
+                .help("dry-runs the validator on a recorded JSON dump of channels, event aggregates & validator messages and prints the differences")
+                .takes_value(true),
+        )
         .get_matches();
 
     let environment = std::env::var("ENV").unwrap_or_else(|_| "development".into());
@@ -83,18 +114,27 @@ fn main() -> Result<(), Box<dyn Error>> {
     let sentry_url = cli.value_of("sentryUrl").expect("sentry url missing");
     let is_single_tick = cli.is_present("singleTick");
 
+    if let Some(dump_file) = cli.value_of("replay") {
+        return run_replay(dump_file, &config);
+    }
+
+    // Create the runtime
+    let mut rt = Runtime::new()?;
+
     let adapter = match cli.value_of("adapter").unwrap() {
         "ethereum" => {
-            let keystore_file = cli
-                .value_of("keystoreFile")
+            let keystore_files = cli
+                .values_of("keystoreFile")
                 .expect("unable to get keystore file");
             let keystore_pwd = std::env::var("KEYSTORE_PWD").expect("unable to get keystore pwd");
-            let keystore_options = KeystoreOptions {
-                keystore_file: keystore_file.to_string(),
-                keystore_pwd,
-            };
+            let keystores = keystore_files
+                .map(|keystore_file| KeystoreOptions {
+                    keystore_file: keystore_file.to_string(),
+                    keystore_pwd: keystore_pwd.clone(),
+                })
+                .collect();
             AdapterTypes::EthereumAdapter(Box::new(
-                EthereumAdapter::init(keystore_options, &config).expect("failed to init adapter"),
+                EthereumAdapter::init(keystores, &config).expect("failed to init adapter"),
             ))
         }
         "dummy" => {
@@ -103,11 +143,30 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .expect("unable to get dummyIdentity");
             let options = DummyAdapterOptions {
                 dummy_identity: ValidatorId::try_from(dummy_identity)?,
+                dummy_previous_identities: vec![],
                 dummy_auth: IDS.clone(),
                 dummy_auth_tokens: AUTH.clone(),
             };
             AdapterTypes::DummyAdapter(Box::new(DummyAdapter::init(options, &config)))
         }
+        "remote" => {
+            let signer_url = cli
+                .value_of("remoteSignerUrl")
+                .expect("unable to get remote signer url");
+            let options = RemoteSignerOptions {
+                signer_url: signer_url.to_string(),
+                client_identity_file: cli
+                    .value_of("remoteSignerIdentity")
+                    .map(ToString::to_string),
+                client_identity_pwd: std::env::var("REMOTE_SIGNER_IDENTITY_PWD")
+                    .unwrap_or_default(),
+                ca_certificate_file: cli.value_of("remoteSignerCa").map(ToString::to_string),
+            };
+            AdapterTypes::RemoteAdapter(Box::new(
+                rt.block_on(RemoteAdapter::init(options, &config))
+                    .expect("failed to init adapter"),
+            ))
+        }
         // @TODO exit gracefully
         _ => panic!("We don't have any other adapters implemented yet!"),
     };
@@ -115,16 +174,31 @@ fn main() -> Result<(), Box<dyn Error>> {
     let logger = logger();
 
     match adapter {
-        AdapterTypes::EthereumAdapter(ethadapter) => {
-            run(is_single_tick, &sentry_url, &config, *ethadapter, &logger)
-        }
-        AdapterTypes::DummyAdapter(dummyadapter) => {
-            run(is_single_tick, &sentry_url, &config, *dummyadapter, &logger)
-        }
+        AdapterTypes::EthereumAdapter(ethadapter) => rt.block_on(run(
+            is_single_tick,
+            &sentry_url,
+            &config,
+            *ethadapter,
+            &logger,
+        )),
+        AdapterTypes::DummyAdapter(dummyadapter) => rt.block_on(run(
+            is_single_tick,
+            &sentry_url,
+            &config,
+            *dummyadapter,
+            &logger,
+        )),
+        AdapterTypes::RemoteAdapter(remoteadapter) => rt.block_on(run(
+            is_single_tick,
+            &sentry_url,
+            &config,
+            *remoteadapter,
+            &logger,
+        )),
     }
 }
 
-fn run<A: Adapter + 'static>(
+async fn run<A: Adapter + 'static>(
     is_single_tick: bool,
     sentry_url: &str,
     config: &Config,
@@ -132,7 +206,7 @@ fn run<A: Adapter + 'static>(
     logger: &Logger,
 ) -> Result<(), Box<dyn Error>> {
     // unlock adapter
-    adapter.unlock()?;
+    adapter.unlock().await?;
 
     let args = Args {
         sentry_url: sentry_url.to_owned(),
@@ -140,13 +214,51 @@ fn run<A: Adapter + 'static>(
         adapter,
     };
 
-    // Create the runtime
-    let mut rt = Runtime::new()?;
-
     if is_single_tick {
-        rt.block_on(iterate_channels(args, &logger));
+        iterate_channels(args, &logger).await;
     } else {
-        rt.block_on(infinite(args, &logger));
+        infinite(args, &logger).await;
+    }
+
+    Ok(())
+}
+
+fn run_replay(dump_file: &str, config: &Config) -> Result<(), Box<dyn Error>> {
+    let channel_dumps = load_dump(dump_file)?;
+    let channels_size = channel_dumps.len();
+    let mut differences = 0;
+    let mut failures = 0;
+
+    for channel_dump in channel_dumps.iter() {
+        match replay_channel(channel_dump, config) {
+            Ok(channel_replay) => {
+                if channel_replay.has_differences() {
+                    differences += 1;
+                }
+                println!("{}", channel_replay);
+            }
+            Err(err) => {
+                failures += 1;
+                eprintln!(
+                    "channel {}: failed to replay: {}",
+                    channel_dump.channel.id, err
+                );
+            }
+        }
+    }
+
+    println!(
+        "Replayed {} channels, {} with differences, {} failed",
+        channels_size, differences, failures
+    );
+
+    // a non-zero exit status, so the replay can be used as a check
+    if differences > 0 || failures > 0 {
+        return Err(format!(
+            "{} channels with differences, {} failed to replay",
+            differences, failures
+        )
+        .into());
     }
 
     Ok(())
@@ -161,7 +273,7 @@ async fn infinite<A: Adapter + 'static>(args: Args<A>, logger: &Logger) {
 }
 
 async fn iterate_channels<A: Adapter + 'static>(args: Args<A>, logger: &Logger) {
-    let result = all_channels(&args.sentry_url, args.adapter.whoami()).await;
+    let result = all_channels(&args.sentry_url, args.adapter.identities()).await;
 
     let channels = match result {
         Ok(channels) => channels,
@@ -171,6 +283,7 @@ async fn iterate_channels<A: Adapter + 'static>(args: Args<A>, logger: &Logger)
         }
     };
 
+    let channels = opened_channels(&args.adapter, channels, logger).await;
     let channels_size = channels.len();
 
     let tick_results = join_all(
@@ -191,20 +304,57 @@ async fn iterate_channels<A: Adapter + 'static>(args: Args<A>, logger: &Logger)
     }
 }
 
+/// Skips the channels which were never opened on their chain, looking up their states in bulk.
+/// The `Expired` channels are still ticked, so their last events are accounted and approved.
+/// If the states on a chain can't be looked up, all of its channels are kept.
+async fn opened_channels<A: Adapter + 'static>(
+    adapter: &A,
+    channels: Vec<Channel>,
+    logger: &Logger,
+) -> Vec<Channel> {
+    let mut channel_ids: HashMap<ChainId, Vec<ChannelId>> = HashMap::new();
+    for channel in channels.iter() {
+        channel_ids
+            .entry(channel.chain_id)
+            .or_default()
+            .push(channel.id);
+    }
+
+    let mut states = HashMap::new();
+    for (chain_id, channel_ids) in channel_ids.iter() {
+        match adapter.channel_states(*chain_id, channel_ids).await {
+            Ok(chain_states) => states.extend(chain_states),
+            Err(e) => {
+                error!(logger, "Failed to get the on-chain channel states"; "chain_id" => %chain_id, "error" => ?e, "main" => "opened_channels")
+            }
+        }
+    }
+
+    channels
+        .into_iter()
+        .filter(|channel| match states.get(&channel.id) {
+            Some(ChannelState::Unknown) => {
+                warn!(logger, "Skipping channel which was never opened on-chain"; "channel" => %channel.id, "main" => "opened_channels");
+                false
+            }
+            _ => true,
+        })
+        .collect()
+}
+
 async fn validator_tick<A: Adapter + 'static>(
     adapter: A,
     channel: Channel,
     config: &Config,
     logger: &Logger,
 ) -> Result<(ChannelId, Box<dyn Debug>), ValidatorWorkerError<A::AdapterError>> {
-    let whoami = *adapter.whoami();
-
     // Cloning the `Logger` is cheap, see documentation for more info
     let sentry = SentryApi::init(adapter, channel.clone(), &config, logger.clone())
+        .await
         .map_err(ValidatorWorkerError::SentryApi)?;
     let duration = Duration::from_millis(config.validator_tick_timeout as u64);
 
-    match channel.spec.validators.find(&whoami) {
+    match channel.spec.validators.find(&sentry.whoami) {
         Some(SpecValidator::Leader(_)) => match timeout(duration, leader::tick(&sentry)).await {
             Err(timeout_e) => Err(ValidatorWorkerError::LeaderTick(
                 channel.id,
@@ -0,0 +1,522 @@
+//! Replays a recorded dump of Sentry data through the leader & follower logic.
+//!
+//! Nothing is signed nor propagated, which allows checking how changes to
+//! `merge_aggrs`, the fees or the follower rules affect real channels.
+use std::error::Error;
+use std::fmt;
+use std::fs;
+
+use chrono::{TimeZone, Utc};
+use serde::Deserialize;
+
+use primitives::sentry::{EventAggregate, ValidatorMessage};
+use primitives::validator::{Accounting, MessageTypes, NewState};
+use primitives::{BalancesMap, BigNum, Channel, ChannelId, Config, ValidatorId};
+
+use crate::core::events::merge_aggrs;
+use crate::follower::{check_new_state_balances, is_valid_state_root, InvalidNewState};
+use crate::get_channel_state_root_hash;
+
+/// Everything recorded from a Sentry for a single channel
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelDump {
+    pub channel: Channel,
+    #[serde(default)]
+    pub event_aggregates: Vec<EventAggregate>,
+    #[serde(default)]
+    pub validator_messages: Vec<ValidatorMessage>,
+}
+
+/// Reads a JSON array of [`ChannelDump`]s
+pub fn load_dump(path: &str) -> Result<Vec<ChannelDump>, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+
+    Ok(serde_json::from_str(&contents)?)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceDiff {
+    pub address: ValidatorId,
+    pub recorded: Option<BigNum>,
+    pub replayed: Option<BigNum>,
+}
+
+/// Returns all the addresses which have a different balance in `replayed` than in `recorded`
+pub fn diff_balances(recorded: &BalancesMap, replayed: &BalancesMap) -> Vec<BalanceDiff> {
+    let mut addresses: Vec<&ValidatorId> = recorded
+        .iter()
+        .chain(replayed.iter())
+        .map(|(address, _)| address)
+        .collect();
+    addresses.sort();
+    addresses.dedup();
+
+    addresses
+        .into_iter()
+        .filter_map(|address| {
+            let recorded = recorded.get(address);
+            let replayed = replayed.get(address);
+
+            if recorded == replayed {
+                None
+            } else {
+                Some(BalanceDiff {
+                    address: *address,
+                    recorded: recorded.cloned(),
+                    replayed: replayed.cloned(),
+                })
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct AccountingReplay {
+    /// The latest `Accounting` recorded by the validator, if any
+    pub recorded: Option<Accounting>,
+    /// The `Accounting` replayed from the same event aggregates as the recorded one.
+    /// If nothing was recorded, all event aggregates are replayed.
+    pub replayed: Accounting,
+    /// The recorded `NewState` state root for the recorded balances, only available for the leader
+    pub recorded_state_root: Option<String>,
+    pub replayed_state_root: String,
+}
+
+impl AccountingReplay {
+    pub fn balances_diff(&self) -> Vec<BalanceDiff> {
+        match &self.recorded {
+            Some(recorded) => diff_balances(&recorded.balances, &self.replayed.balances),
+            None => vec![],
+        }
+    }
+
+    pub fn has_differences(&self) -> bool {
+        let state_root_differs = self
+            .recorded_state_root
+            .as_ref()
+            .map_or(false, |state_root| state_root != &self.replayed_state_root);
+
+        state_root_differs || !self.balances_diff().is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Approve { is_healthy: bool },
+    Reject { reason: String },
+}
+
+impl From<InvalidNewState> for Verdict {
+    fn from(invalid: InvalidNewState) -> Self {
+        Verdict::Reject {
+            reason: invalid.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::Approve { is_healthy: true } => write!(f, "ApproveState (healthy)"),
+            Verdict::Approve { is_healthy: false } => write!(f, "ApproveState (unhealthy)"),
+            Verdict::Reject { reason } => write!(f, "RejectState ({})", reason),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FollowerReplay {
+    pub accounting: AccountingReplay,
+    /// The state root of the latest leader `NewState`, if any
+    pub state_root: Option<String>,
+    /// The recorded response of the follower to the latest `NewState`
+    pub recorded: Option<Verdict>,
+    /// The response of the follower rules to the latest `NewState`
+    pub replayed: Option<Verdict>,
+}
+
+impl FollowerReplay {
+    pub fn has_differences(&self) -> bool {
+        let verdict_differs = self.recorded.is_some() && self.recorded != self.replayed;
+
+        verdict_differs || self.accounting.has_differences()
+    }
+}
+
+#[derive(Debug)]
+pub struct ChannelReplay {
+    pub channel: ChannelId,
+    pub leader: AccountingReplay,
+    pub follower: FollowerReplay,
+}
+
+impl ChannelReplay {
+    pub fn has_differences(&self) -> bool {
+        self.leader.has_differences() || self.follower.has_differences()
+    }
+}
+
+/// Deterministically replays the leader & follower ticks of a channel from its recorded data.
+///
+/// The replayed `Accounting` of each validator covers the same event aggregates as its latest
+/// recorded `Accounting`. The latest leader `NewState` is then checked with the follower rules
+/// against the follower's replayed balances. Signatures are not verified.
+pub fn replay_channel(
+    dump: &ChannelDump,
+    config: &Config,
+) -> Result<ChannelReplay, Box<dyn Error>> {
+    let channel = &dump.channel;
+
+    let mut messages: Vec<&ValidatorMessage> = dump.validator_messages.iter().collect();
+    messages.sort_by_key(|message| message.received);
+
+    let leader_id = channel.spec.validators.leader().id;
+    let follower_id = channel.spec.validators.follower().id;
+
+    let new_states: Vec<&NewState> = messages
+        .iter()
+        .filter(|message| message.from == leader_id)
+        .filter_map(|message| match &message.msg {
+            MessageTypes::NewState(new_state) => Some(new_state),
+            _ => None,
+        })
+        .collect();
+
+    let mut leader = replay_accounting(dump, &messages, &leader_id)?;
+    leader.recorded_state_root = leader.recorded.as_ref().and_then(|recorded| {
+        new_states
+            .iter()
+            .rev()
+            .find(|new_state| new_state.balances == recorded.balances)
+            .map(|new_state| new_state.state_root.clone())
+    });
+
+    let follower_accounting = replay_accounting(dump, &messages, &follower_id)?;
+
+    let follower_verdict = |state_root: &str| {
+        messages
+            .iter()
+            .rev()
+            .filter(|message| message.from == follower_id)
+            .find_map(|message| match &message.msg {
+                MessageTypes::ApproveState(approve) if approve.state_root == state_root => {
+                    Some(Verdict::Approve {
+                        is_healthy: approve.is_healthy,
+                    })
+                }
+                MessageTypes::RejectState(reject) if reject.state_root == state_root => {
+                    Some(Verdict::Reject {
+                        reason: reject.reason.clone(),
+                    })
+                }
+                _ => None,
+            })
+    };
+
+    let (state_root, recorded, replayed) = match new_states.split_last() {
+        Some((new_state, previous)) => {
+            // the last approved balances before this `NewState`
+            let prev_balances = previous
+                .iter()
+                .rev()
+                .find(|prev| {
+                    matches!(
+                        follower_verdict(&prev.state_root),
+                        Some(Verdict::Approve { .. })
+                    )
+                })
+                .map(|prev| prev.balances.clone())
+                .unwrap_or_default();
+
+            let replayed = if !is_valid_state_root(&channel.id, new_state)? {
+                Verdict::from(InvalidNewState::RootHash)
+            } else {
+                match check_new_state_balances(
+                    channel,
+                    config,
+                    &follower_accounting.replayed.balances,
+                    &prev_balances,
+                    &new_state.balances,
+                ) {
+                    Ok(health) => Verdict::Approve {
+                        is_healthy: health >= u64::from(config.health_threshold_promilles),
+                    },
+                    Err(invalid) => Verdict::from(invalid),
+                }
+            };
+
+            (
+                Some(new_state.state_root.clone()),
+                follower_verdict(&new_state.state_root),
+                Some(replayed),
+            )
+        }
+        None => (None, None, None),
+    };
+
+    Ok(ChannelReplay {
+        channel: channel.id,
+        leader,
+        follower: FollowerReplay {
+            accounting: follower_accounting,
+            state_root,
+            recorded,
+            replayed,
+        },
+    })
+}
+
+fn replay_accounting(
+    dump: &ChannelDump,
+    messages: &[&ValidatorMessage],
+    validator: &ValidatorId,
+) -> Result<AccountingReplay, Box<dyn Error>> {
+    let recorded = messages
+        .iter()
+        .rev()
+        .filter(|message| &message.from == validator)
+        .find_map(|message| match &message.msg {
+            MessageTypes::Accounting(accounting) => Some(accounting.clone()),
+            _ => None,
+        });
+
+    let until = recorded
+        .as_ref()
+        .map(|accounting| accounting.last_event_aggregate);
+    let mut aggregates: Vec<EventAggregate> = dump
+        .event_aggregates
+        .iter()
+        .filter(|aggr| until.map_or(true, |until| aggr.created <= until))
+        .cloned()
+        .collect();
+    aggregates.sort_by_key(|aggr| aggr.created);
+
+    let empty = Accounting {
+        last_event_aggregate: Utc.timestamp(0, 0),
+        balances_before_fees: Default::default(),
+        balances: Default::default(),
+    };
+
+    let replayed = merge_aggrs(&empty, &aggregates, &dump.channel)?;
+    let replayed_state_root = hex::encode(get_channel_state_root_hash(
+        &dump.channel.id,
+        &replayed.balances,
+    )?);
+
+    Ok(AccountingReplay {
+        recorded,
+        replayed,
+        recorded_state_root: None,
+        replayed_state_root,
+    })
+}
+
+fn fmt_accounting(
+    f: &mut fmt::Formatter<'_>,
+    name: &str,
+    replay: &AccountingReplay,
+) -> fmt::Result {
+    let display_balance = |balance: &Option<BigNum>| {
+        balance
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_else(|| "-".to_string())
+    };
+
+    match &replay.recorded {
+        Some(_) => writeln!(f, "  {} accounting:", name)?,
+        None => writeln!(f, "  {} accounting: nothing recorded", name)?,
+    }
+
+    for diff in replay.balances_diff() {
+        writeln!(
+            f,
+            "    balance {}: {} -> {}",
+            diff.address,
+            display_balance(&diff.recorded),
+            display_balance(&diff.replayed)
+        )?;
+    }
+
+    match &replay.recorded_state_root {
+        Some(recorded) if recorded != &replay.replayed_state_root => writeln!(
+            f,
+            "    state root: {} -> {}",
+            recorded, replay.replayed_state_root
+        ),
+        _ => writeln!(f, "    state root: {}", replay.replayed_state_root),
+    }
+}
+
+impl fmt::Display for ChannelReplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = if self.has_differences() {
+            "differences found"
+        } else {
+            "no differences"
+        };
+        writeln!(f, "channel {}: {}", self.channel, status)?;
+
+        fmt_accounting(f, "leader", &self.leader)?;
+        fmt_accounting(f, "follower", &self.follower.accounting)?;
+
+        match (&self.follower.state_root, &self.follower.replayed) {
+            (Some(state_root), Some(replayed)) => {
+                let recorded = self
+                    .follower
+                    .recorded
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_else(|| "no response".to_string());
+
+                write!(
+                    f,
+                    "  follower on NewState {}: {} -> {}",
+                    state_root, recorded, replayed
+                )
+            }
+            _ => write!(f, "  follower: no NewState recorded"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use primitives::config::configuration;
+    use primitives::sentry::AggregateEvents;
+    use primitives::util::tests::prep_db::{DUMMY_CHANNEL, IDS};
+    use primitives::validator::ApproveState;
+
+    fn gen_ev_aggr(seconds: i64, count: u64, recipient: &ValidatorId) -> EventAggregate {
+        let aggregate_events = AggregateEvents {
+            event_counts: Some(vec![(*recipient, count.into())].into_iter().collect()),
+            event_payouts: vec![(*recipient, (count * 10).into())]
+                .into_iter()
+                .collect(),
+        };
+
+        EventAggregate {
+            channel_id: DUMMY_CHANNEL.id,
+            created: Utc.timestamp(seconds, 0),
+            events: vec![("IMPRESSION".to_string(), aggregate_events)]
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    fn message(from: &ValidatorId, seconds: i64, msg: MessageTypes) -> ValidatorMessage {
+        ValidatorMessage {
+            from: *from,
+            received: Utc.timestamp(seconds, 0),
+            msg,
+        }
+    }
+
+    fn setup_dump(recorded_balances: Option<BalancesMap>) -> ChannelDump {
+        let channel = DUMMY_CHANNEL.clone();
+        let leader = channel.spec.validators.leader().id;
+        let follower = channel.spec.validators.follower().id;
+
+        let event_aggregates = vec![
+            gen_ev_aggr(1_000, 5, &IDS["publisher"]),
+            gen_ev_aggr(2_000, 3, &IDS["publisher2"]),
+        ];
+
+        let empty = Accounting {
+            last_event_aggregate: Utc.timestamp(0, 0),
+            balances_before_fees: Default::default(),
+            balances: Default::default(),
+        };
+        let mut accounting =
+            merge_aggrs(&empty, &event_aggregates, &channel).expect("Should merge aggregates");
+        if let Some(balances) = recorded_balances {
+            accounting.balances = balances;
+        }
+
+        let state_root = hex::encode(
+            get_channel_state_root_hash(&channel.id, &accounting.balances)
+                .expect("Should get state root"),
+        );
+
+        let validator_messages = vec![
+            message(&leader, 3_000, MessageTypes::Accounting(accounting.clone())),
+            message(
+                &leader,
+                3_001,
+                MessageTypes::NewState(NewState {
+                    state_root: state_root.clone(),
+                    signature: "signature".to_string(),
+                    balances: accounting.balances.clone(),
+                    exhausted: false,
+                }),
+            ),
+            message(&follower, 3_002, MessageTypes::Accounting(accounting)),
+            message(
+                &follower,
+                3_003,
+                MessageTypes::ApproveState(ApproveState {
+                    state_root,
+                    signature: "signature".to_string(),
+                    is_healthy: true,
+                    exhausted: false,
+                }),
+            ),
+        ];
+
+        ChannelDump {
+            channel,
+            event_aggregates,
+            validator_messages,
+        }
+    }
+
+    #[test]
+    fn replays_recorded_channel_without_differences() {
+        let config = configuration("development", None).expect("Dev config should be available");
+        let dump = setup_dump(None);
+
+        let replay = replay_channel(&dump, &config).expect("Should replay channel");
+
+        assert!(!replay.has_differences(), "{}", replay);
+        assert_eq!(
+            Some(Verdict::Approve { is_healthy: true }),
+            replay.follower.replayed
+        );
+        assert_eq!(replay.follower.recorded, replay.follower.replayed);
+        assert_eq!(
+            replay.leader.recorded_state_root.as_ref(),
+            Some(&replay.leader.replayed_state_root)
+        );
+    }
+
+    #[test]
+    fn replay_reports_balances_and_state_root_differences() {
+        let config = configuration("development", None).expect("Dev config should be available");
+        let recorded_balances: BalancesMap =
+            vec![(IDS["publisher"], 100.into())].into_iter().collect();
+        let dump = setup_dump(Some(recorded_balances));
+
+        let replay = replay_channel(&dump, &config).expect("Should replay channel");
+
+        assert!(replay.has_differences());
+        assert_ne!(
+            replay.leader.recorded_state_root.as_ref(),
+            Some(&replay.leader.replayed_state_root)
+        );
+
+        let diff = replay.leader.balances_diff();
+        let publisher = diff
+            .iter()
+            .find(|diff| diff.address == IDS["publisher"])
+            .expect("publisher balance should differ");
+        assert_eq!(Some(BigNum::from(100)), publisher.recorded);
+
+        let publisher2 = diff
+            .iter()
+            .find(|diff| diff.address == IDS["publisher2"])
+            .expect("publisher2 balance should differ");
+        assert_eq!(None, publisher2.recorded);
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::time::Duration;
 
@@ -20,6 +20,8 @@ pub type PropagationResult<AE> = Result<ValidatorId, (ValidatorId, Error<AE>)>;
 #[derive(Debug, Clone)]
 pub struct SentryApi<T: Adapter> {
     pub adapter: T,
+    /// The identity of the adapter which validates the channel
+    pub whoami: ValidatorId,
     pub validator_url: String,
     pub client: Client,
     pub logger: Logger,
@@ -75,7 +77,7 @@ impl<AE: AdapterErrorKind> fmt::Display for Error<AE> {
 }
 
 impl<A: Adapter + 'static> SentryApi<A> {
-    pub fn init(
+    pub async fn init(
         adapter: A,
         channel: Channel,
         config: &Config,
@@ -87,25 +89,23 @@ impl<A: Adapter + 'static> SentryApi<A> {
             .map_err(Error::BuildingClient)?;
 
         // validate that we are to validate the channel
-        match channel.spec.validators.find(adapter.whoami()) {
+        let whoami = *adapter.whoami(&channel);
+        match channel.spec.validators.find(&whoami) {
             Some(ref spec_validator) => {
                 let validator = spec_validator.validator();
                 let validator_url = format!("{}/channel/{}", validator.url, channel.id);
 
-                let propagate_to = channel
-                    .spec
-                    .validators
-                    .iter()
-                    .map(|validator| {
-                        adapter
-                            .get_auth(&validator.id)
-                            .map(|auth| (validator.to_owned(), auth))
-                            .map_err(Error::ValidatorAuthentication)
-                    })
-                    .collect::<Result<Vec<_>, _>>()?;
+                let propagate_to = try_join_all(channel.spec.validators.iter().map(|validator| {
+                    adapter
+                        .get_auth(&channel, &validator.id)
+                        .map_ok(move |auth| (validator.to_owned(), auth))
+                        .map_err(Error::ValidatorAuthentication)
+                }))
+                .await?;
 
                 Ok(Self {
                     adapter,
+                    whoami,
                     validator_url,
                     client,
                     logger,
@@ -117,7 +117,7 @@ impl<A: Adapter + 'static> SentryApi<A> {
             None => Err(Error::MissingWhoamiInChannelValidators {
                 channel: channel.id,
                 validators: channel.spec.validators.iter().map(|v| v.id).collect(),
-                whoami: *adapter.whoami(),
+                whoami,
             }),
         }
     }
@@ -165,8 +165,7 @@ impl<A: Adapter + 'static> SentryApi<A> {
         &self,
         message_types: &[&str],
     ) -> Result<Option<MessageTypes>, Error<A::AdapterError>> {
-        self.get_latest_msg(self.adapter.whoami(), message_types)
-            .await
+        self.get_latest_msg(&self.whoami, message_types).await
     }
 
     pub async fn get_last_approved(&self) -> Result<LastApprovedResponse, Error<A::AdapterError>> {
@@ -196,7 +195,8 @@ impl<A: Adapter + 'static> SentryApi<A> {
     ) -> Result<EventAggregateResponse, Error<A::AdapterError>> {
         let auth_token = self
             .adapter
-            .get_auth(self.adapter.whoami())
+            .get_auth(&self.channel, &self.whoami)
+            .await
             .map_err(Error::ValidatorAuthentication)?;
 
         let url = format!(
@@ -245,7 +245,28 @@ async fn propagate_to<A: Adapter>(
     Ok(validator.id)
 }
 
+/// The channels validated by any of the `identities`
 pub async fn all_channels(
+    sentry_url: &str,
+    identities: &[ValidatorId],
+) -> Result<Vec<Channel>, reqwest::Error> {
+    let channels = try_join_all(
+        identities
+            .iter()
+            .map(|identity| validator_channels(sentry_url, identity)),
+    )
+    .await?;
+
+    // a channel can be validated by more than one of the identities
+    let mut channel_ids = HashSet::new();
+    Ok(channels
+        .into_iter()
+        .flatten()
+        .filter(|channel| channel_ids.insert(channel.id))
+        .collect())
+}
+
+async fn validator_channels(
     sentry_url: &str,
     whoami: &ValidatorId,
 ) -> Result<Vec<Channel>, reqwest::Error> {